@@ -0,0 +1,116 @@
+//! Latest-value collection at 8, 64 and 512 entity × field values. The decode benches compare decoding
+//! each value on its own (building an error for every missing one) with the batched `decode_latest`
+//! path; the live bench runs the whole `LatestValuesQuery` against an embedded hostengine when one is
+//! available. Run with `cargo bench --features testing`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_dcgm::dcgm_bindings::bindings::*;
+use rust_dcgm::dcgm_bindings::entity::Entity;
+use rust_dcgm::dcgm_bindings::latest::{decode_latest, LatestValuesQuery};
+use rust_dcgm::dcgm_bindings::samples::{decode_field_value_v2, FieldValue, Sample};
+use rust_dcgm::dcgm_bindings::testing::field_value_v2;
+use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+use rust_dcgm::dcgm_bindings::{DcgmLibSafe, Mode};
+
+const SCALES: [(u32, u16); 3] = [(1, 8), (8, 8), (8, 64)];
+
+const FIELDS: [u32; 8] = [
+    DCGM_FI_DEV_GPU_TEMP, DCGM_FI_DEV_POWER_USAGE, DCGM_FI_DEV_GPU_UTIL, DCGM_FI_DEV_MEM_COPY_UTIL,
+    DCGM_FI_DEV_SM_CLOCK, DCGM_FI_DEV_MEM_CLOCK, DCGM_FI_DEV_FB_USED, DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION,
+];
+
+/// `gpus × fields` raw values as DCGM returns them; every eighth is not watched.
+fn raw_values(gpus: u32, fields: u16) -> Vec<dcgmFieldValue_v2> {
+    let mut values = Vec::new();
+    for gpu in 0..gpus {
+        for field in 0..fields {
+            let mut v = if field % 2 == 0 {
+                field_value_v2(Entity::gpu(gpu), 100 + field, DCGM_FT_INT64, 1_700_000_000_000_000, &FieldValue::Int64(field as i64))
+            } else {
+                field_value_v2(Entity::gpu(gpu), 100 + field, DCGM_FT_DOUBLE, 1_700_000_000_000_000, &FieldValue::Double(field as f64))
+            };
+            if field % 8 == 7 {
+                v.status = dcgmReturn_enum_DCGM_ST_NOT_WATCHED;
+            }
+            values.push(v);
+        }
+    }
+    values
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (gpus, fields) in SCALES {
+        let n = gpus as u64 * fields as u64;
+        let values = raw_values(gpus, fields);
+        group.throughput(Throughput::Elements(n));
+        group.bench_with_input(BenchmarkId::new("per_value", n), &values, |b, values| {
+            b.iter(|| {
+                let samples: Vec<Sample> = values.iter().filter_map(|v| decode_field_value_v2(v).ok()).collect();
+                black_box(samples)
+            })
+        });
+        let mut out = Vec::with_capacity(values.len());
+        group.bench_with_input(BenchmarkId::new("batched", n), &values, |b, values| {
+            b.iter(|| {
+                out.clear();
+                black_box(decode_latest(values, &mut out))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn live(c: &mut Criterion) {
+    let mut dcgm = match DcgmLibSafe::new(Mode::Embedded, &[]) {
+        Ok(dcgm) => dcgm,
+        Err(e) => {
+            eprintln!("Skipping live latest-value benches: {e}");
+            return;
+        }
+    };
+    let gpus: Vec<Entity> = dcgm.getAllSupportedDevices().unwrap_or_default().into_iter().map(Entity::gpu).collect();
+    if gpus.is_empty() {
+        eprintln!("Skipping live latest-value benches: no GPUs");
+        return;
+    }
+    let fields: Vec<u16> = FIELDS.iter().map(|&f| f as u16).collect();
+    let watch = dcgm.watch_all_gpus(&fields, &WatchOptions::default()).expect("watch fields");
+    let _ = dcgm.updateAllFields();
+
+    let mut group = c.benchmark_group("live");
+    for (scale_gpus, scale_fields) in SCALES {
+        // Repeat the real GPUs and fields to reach the requested scale.
+        let entities: Vec<Entity> = gpus.iter().copied().cycle().take(scale_gpus as usize).collect();
+        let query_fields: Vec<u16> = fields.iter().copied().cycle().take(scale_fields as usize).collect();
+        let n = (entities.len() * query_fields.len()) as u64;
+        group.throughput(Throughput::Elements(n));
+        let mut query = LatestValuesQuery::new(&entities, &query_fields);
+        let mut out = Vec::new();
+        group.bench_function(BenchmarkId::new("batched_query", n), |b| {
+            b.iter(|| {
+                out.clear();
+                black_box(query.collect_into(&mut dcgm, &mut out).unwrap())
+            })
+        });
+        group.bench_function(BenchmarkId::new("per_entity", n), |b| {
+            b.iter(|| {
+                let mut samples = Vec::new();
+                for entity in &entities {
+                    let mut fields = query_fields.clone();
+                    let values = dcgm.entityGetLatestValues(entity.id as i32, entity.group, &mut fields).unwrap();
+                    samples.extend(values.iter().filter_map(|v| {
+                        rust_dcgm::dcgm_bindings::samples::decode_field_value_v1(entity.group, entity.id, v).ok()
+                    }));
+                }
+                black_box(samples)
+            })
+        });
+    }
+    group.finish();
+    let _ = dcgm.unwatch(watch);
+    let _ = dcgm.shutdown();
+}
+
+criterion_group!(benches, decode, live);
+criterion_main!(benches);
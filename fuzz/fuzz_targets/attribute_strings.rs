@@ -0,0 +1,15 @@
+//! Strings read out of DCGM structs: fixed-size char arrays that need not be NUL-terminated or UTF-8,
+//! the raw build info string and PCI bus ids.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_dcgm::dcgm_bindings::compat::DcgmVersion;
+use rust_dcgm::dcgm_bindings::testing::{attribute_string, sysfs_bus_id};
+use std::os::raw::c_char;
+
+fuzz_target!(|data: &[u8]| {
+    let chars: Vec<c_char> = data.iter().map(|&b| b as c_char).collect();
+    let text = attribute_string(&chars);
+    let _ = DcgmVersion::parse_build_info(&text);
+    let _ = sysfs_bus_id(&text);
+});
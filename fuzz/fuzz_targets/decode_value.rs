@@ -0,0 +1,26 @@
+//! Field value decoding over raw `dcgmFieldValue_v1`/`_v2` structs whose every byte (type, status,
+//! entity, the value union) comes from the fuzzer, as a misbehaving driver or hostengine could send.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_dcgm::dcgm_bindings::bindings::*;
+use rust_dcgm::dcgm_bindings::entity::EntityGroup;
+use rust_dcgm::dcgm_bindings::init::Zeroable;
+use rust_dcgm::dcgm_bindings::latest::decode_latest;
+use rust_dcgm::dcgm_bindings::samples::{decode_field_value_v1, decode_field_value_v2};
+
+fn overlay<T: Zeroable>(data: &[u8]) -> T {
+    let mut value = T::zeroed();
+    let n = data.len().min(std::mem::size_of::<T>());
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), &mut value as *mut T as *mut u8, n) };
+    value
+}
+
+fuzz_target!(|data: &[u8]| {
+    let v1: dcgmFieldValue_v1 = overlay(data);
+    let _ = decode_field_value_v1(EntityGroup::Gpu, 0, &v1);
+    let v2: dcgmFieldValue_v2 = overlay(data);
+    let _ = decode_field_value_v2(&v2);
+    let mut samples = Vec::new();
+    decode_latest(std::slice::from_ref(&v2), &mut samples);
+});
@@ -0,0 +1,13 @@
+//! NVVS results: `dcgmi diag --json` output and saved diag reports.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_dcgm::dcgm_bindings::diag::DiagReport;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else { return };
+    if let Ok(report) = DiagReport::from_dcgmi_json(text) {
+        let _ = report.diff(&report);
+    }
+    let _ = serde_json::from_str::<DiagReport>(text);
+});
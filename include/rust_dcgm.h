@@ -0,0 +1,54 @@
+/*
+ * C interface of rust-dcgm, built with
+ * `cargo rustc --release --lib --features capi --crate-type cdylib` (librust_dcgm.so).
+ *
+ * Every function returns a dcgmReturn_t code from dcgm_structs.h: DCGM_ST_OK (0) on success, else
+ * the code libdcgm failed with or the closest one. rdcgm_last_error() has the message of the last
+ * failure on the calling thread. JSON strings handed out are owned by the caller and released with
+ * rdcgm_string_free().
+ */
+#ifndef RUST_DCGM_H
+#define RUST_DCGM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct rdcgm_client rdcgm_client;
+
+/*
+ * Connects to nv-hostengine at address (host[:port], or a socket path when unix_socket is non-zero),
+ * or starts a hostengine inside the process when address is NULL.
+ */
+int rdcgm_connect(const char *address, int unix_socket, rdcgm_client **out);
+
+/* Removes the client's watches and groups, disconnects and frees it. NULL is ignored. */
+int rdcgm_disconnect(rdcgm_client *client);
+
+/*
+ * Live values of count fields (DCGM_FI_* ids) on every supported GPU, as a JSON array of samples:
+ * [{"entity_group": "Gpu", "entity_id": 0, "field_id": 150, "timestamp": ..., "value": ...}, ...]
+ */
+int rdcgm_snapshot(rdcgm_client *client, const uint16_t *fields, size_t count, char **json_out);
+
+/*
+ * Health report of group (e.g. DCGM_GROUP_ALL_GPUS) as JSON. Non-zero systems (dcgmHealthSystems_t
+ * bits, DCGM_HEALTH_WATCH_ALL for all) are enabled on the group first, replacing the health watches
+ * set there; zero checks the systems already enabled.
+ */
+int rdcgm_health_check(rdcgm_client *client, uintptr_t group, uint32_t systems, char **json_out);
+
+/* Message of the last failed call on this thread, valid until the next call on the thread. */
+const char *rdcgm_last_error(void);
+
+/* Frees a string returned by this library. NULL is ignored. */
+void rdcgm_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUST_DCGM_H */
@@ -0,0 +1,601 @@
+use clap::Args;
+use rust_dcgm::dcgm_bindings::alerts::{Alert, AlertMonitor, WebhookConfig, WebhookSink};
+use rust_dcgm::dcgm_bindings::daemon::{Backend, Collection, Collector, DaemonConfig, HistogramSettings, SinkConfig};
+use rust_dcgm::dcgm_bindings::signals;
+use rust_dcgm::dcgm_bindings::systemd::Notifier;
+use rust_dcgm::dcgm_bindings::timing::Phase;
+use rust_dcgm::dcgm_bindings::exporter::{Exporter, LatestSamples};
+use rust_dcgm::dcgm_bindings::fuse::{FuseMount, TelemetryTree};
+use rust_dcgm::dcgm_bindings::histogram::HistogramAggregator;
+use rust_dcgm::dcgm_bindings::hotplug::{EntityWatcher, DEFAULT_ENTITY_POLL_INTERVAL};
+use rust_dcgm::dcgm_bindings::http::{HttpConfig, MetricsPage, MetricsServer};
+use rust_dcgm::dcgm_bindings::kube_events::{KubeEvents, KubeEventsConfig};
+#[cfg(feature = "k8s")]
+use rust_dcgm::dcgm_bindings::probe::GrpcHealthServer;
+use rust_dcgm::dcgm_bindings::probe::ProbeState;
+use rust_dcgm::dcgm_bindings::rollup::RollupEngine;
+use rust_dcgm::dcgm_bindings::samples::{Sample, SampleKey};
+use rust_dcgm::dcgm_bindings::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
+use rust_dcgm::dcgm_bindings::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    /// Config file (.toml, .yaml or .yml)
+    #[arg(long, short = 'c')]
+    pub config: PathBuf,
+    /// Validate the config and exit
+    #[arg(long)]
+    pub check: bool,
+    /// Collect every group once, write the sinks and exit
+    #[arg(long)]
+    pub once: bool,
+    /// Also reload when the config file changes on disk, not only on SIGHUP
+    #[arg(long)]
+    pub watch_config: bool,
+}
+
+pub fn connect(config: &DaemonConfig) -> Result<DcgmLibSafe, DCGMError> {
+    let c = &config.connection;
+    if c.embedded {
+        DcgmLibSafe::new(Mode::Embedded, &[])
+    } else {
+        DcgmLibSafe::new(Mode::Standalone, &[&c.host, if c.unix_socket { "1" } else { "0" }])
+    }
+}
+
+/// Writes `contents` next to `path` and renames it over, so readers never see a partial file.
+fn replace_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+fn append_json_lines<T: Serialize>(path: &Path, lines: &[T]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::OpenOptions::new().create(true).append(true).open(path)?);
+    for line in lines {
+        serde_json::to_writer(&mut file, line)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()
+}
+
+/// The aggregator of one `[[histograms]]` entry, with the ids of its fields.
+struct Histogram {
+    settings: HistogramSettings,
+    fields: Vec<u16>,
+    aggregator: HistogramAggregator,
+}
+
+impl Histogram {
+    fn new(settings: &HistogramSettings) -> Self {
+        let fields = settings.fields.iter()
+            .filter_map(|field| field.resolve().map_err(|e| tracing::warn!("No histogram of {field}: {e}")).ok())
+            .collect();
+        Histogram { settings: settings.clone(), fields, aggregator: HistogramAggregator::new(settings.aggregator_config()) }
+    }
+}
+
+/// The open windows of a json_lines sink with `rollup` set.
+struct Rollups {
+    path: PathBuf,
+    engine: RollupEngine,
+}
+
+impl Rollups {
+    fn of(config: &DaemonConfig) -> Vec<Rollups> {
+        config.sinks.iter()
+            .filter_map(|s| match s {
+                SinkConfig::JsonLines { path, rollup: Some(window) } => {
+                    Some(Rollups { path: path.clone(), engine: RollupEngine::new(*window) })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Appends the windows that are still open, e.g. before exiting.
+    fn finish(&mut self) {
+        if let Err(e) = append_json_lines(&self.path, &self.engine.flush_all()) {
+            tracing::warn!("Failed to write the rollups of {}: {e}", self.path.display());
+        }
+    }
+}
+
+pub(crate) struct Sinks {
+    exporter: Exporter,
+    latest: LatestSamples,
+    histograms: Vec<Histogram>,
+    rollups: Vec<Rollups>,
+    /// Served by every HTTP sink.
+    page: MetricsPage,
+    servers: Vec<MetricsServer>,
+    /// Served by every FUSE sink.
+    tree: TelemetryTree,
+    mounts: Vec<FuseMount>,
+    webhooks: Vec<WebhookSink>,
+    kube_events: Vec<KubeEvents>,
+}
+
+impl Sinks {
+    pub(crate) fn new(dcgm: &mut DcgmLibSafe, config: &DaemonConfig, collector: &Collector) -> Result<Self, DCGMError> {
+        let mut exporter = Exporter::new(config.exporter_config());
+        exporter.load_from_dcgm(dcgm, &collector.fields())?;
+        Ok(Sinks::with_exporter(exporter, config))
+    }
+
+    pub(crate) fn with_exporter(exporter: Exporter, config: &DaemonConfig) -> Self {
+        Sinks { exporter, latest: LatestSamples::new(Some(config.stale_after())),
+                histograms: config.histograms.iter().map(Histogram::new).collect(), rollups: Rollups::of(config),
+                page: MetricsPage::default(), servers: Vec::new(),
+                tree: TelemetryTree::default(), mounts: Vec::new(), webhooks: Vec::new(),
+                kube_events: Vec::new() }
+    }
+
+    /// Starts a server for every HTTP sink, mounts every FUSE sink and starts every webhook and
+    /// Kubernetes events sink of `config` that is not running yet, and stops the ones that are no
+    /// longer configured.
+    pub(crate) fn start_servers(&mut self, config: &DaemonConfig) -> Result<(), DCGMError> {
+        let mountpoints: Vec<&PathBuf> = config.sinks.iter()
+            .filter_map(|s| if let SinkConfig::Fuse { mountpoint } = s { Some(mountpoint) } else { None })
+            .collect();
+        self.mounts.retain(|mount| mountpoints.iter().any(|m| m.as_path() == mount.mountpoint()));
+        for mountpoint in mountpoints {
+            if !self.mounts.iter().any(|mount| mount.mountpoint() == mountpoint.as_path()) {
+                self.mounts.push(FuseMount::mount(mountpoint, self.tree.clone())?);
+            }
+        }
+        let wanted: Vec<&HttpConfig> = config.sinks.iter()
+            .filter_map(|s| if let SinkConfig::Http(http) = s { Some(http) } else { None })
+            .collect();
+        self.servers.retain(|server| wanted.contains(&server.config()));
+        for http in wanted {
+            if !self.servers.iter().any(|server| server.config() == http) {
+                self.servers.push(MetricsServer::start(http, self.page.clone())?);
+            }
+        }
+        let webhooks: Vec<&WebhookConfig> = config.sinks.iter()
+            .filter_map(|s| if let SinkConfig::Webhook(webhook) = s { Some(webhook) } else { None })
+            .collect();
+        self.webhooks.retain(|sink| webhooks.contains(&sink.config()));
+        for webhook in webhooks {
+            if !self.webhooks.iter().any(|sink| sink.config() == webhook) {
+                self.webhooks.push(WebhookSink::start(webhook)?);
+            }
+        }
+        let publishers: Vec<&KubeEventsConfig> = config.sinks.iter()
+            .filter_map(|s| if let SinkConfig::KubernetesEvents(events) = s { Some(events) } else { None })
+            .collect();
+        self.kube_events.retain(|sink| publishers.contains(&sink.config()));
+        for events in publishers {
+            if !self.kube_events.iter().any(|sink| sink.config() == events) {
+                self.kube_events.push(KubeEvents::start(events)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches to `new`, keeping the HTTP servers that are still configured so their listeners stay open,
+    /// the alert sinks so their deduplication carries over and the windows of unchanged histograms and
+    /// rollups. Open rollup windows of sinks that changed or went away are written out.
+    pub(crate) fn replace(&mut self, mut new: Sinks, config: &DaemonConfig) {
+        for histogram in &mut new.histograms {
+            if let Some(old) = self.histograms.iter_mut().find(|old| old.settings == histogram.settings) {
+                std::mem::swap(&mut histogram.aggregator, &mut old.aggregator);
+            }
+        }
+        for rollups in &mut new.rollups {
+            let same = |old: &&mut Rollups| old.path == rollups.path && old.engine.window() == rollups.engine.window();
+            if let Some(old) = self.rollups.iter_mut().find(same) {
+                std::mem::swap(&mut rollups.engine, &mut old.engine);
+            }
+        }
+        for old in &mut self.rollups {
+            old.finish();
+        }
+        new.page = self.page.clone();
+        new.servers = std::mem::take(&mut self.servers);
+        new.tree = self.tree.clone();
+        new.mounts = std::mem::take(&mut self.mounts);
+        new.webhooks = std::mem::take(&mut self.webhooks);
+        new.kube_events = std::mem::take(&mut self.kube_events);
+        let now = now_micros();
+        new.latest.update(self.latest.current(now));
+        *self = new;
+        if let Err(e) = self.start_servers(config) {
+            tracing::error!("Failed to start an HTTP, FUSE or alert sink: {e}");
+        }
+    }
+
+    pub(crate) fn write(&mut self, config: &DaemonConfig, samples: Vec<Sample>) {
+        let fresh = samples.clone();
+        for histogram in &mut self.histograms {
+            histogram.aggregator.record_all(fresh.iter().filter(|s| histogram.fields.contains(&s.field_id)));
+        }
+        for rollups in &mut self.rollups {
+            rollups.engine.record_all(&fresh);
+        }
+        self.latest.update(samples);
+        let now = now_micros();
+        let current = self.latest.current(now);
+        // histograms of stale series go with them
+        let live: HashSet<SampleKey> = current.iter().map(Sample::key).collect();
+        let snapshots: Vec<_> = self.histograms.iter()
+            .flat_map(|h| h.aggregator.snapshots())
+            .filter(|s| live.contains(&s.key))
+            .collect();
+        // windows ending before this only get samples that are stale on arrival
+        let settled = now - config.stale_after().as_micros() as i64;
+        let mut rendered = None;
+        let mut render = |exporter: &Exporter| {
+            rendered.get_or_insert_with(|| exporter.render(&current) + &exporter.render_histograms(&snapshots)).clone()
+        };
+        for sink in &config.sinks {
+            let result = match sink {
+                SinkConfig::Stdout => {
+                    print!("{}", render(&self.exporter));
+                    Ok(())
+                }
+                SinkConfig::PrometheusFile { path } => replace_file(path, &render(&self.exporter)),
+                SinkConfig::JsonLines { path, rollup: None } => append_json_lines(path, &fresh),
+                SinkConfig::JsonLines { path, rollup: Some(_) } => match self.rollups.iter_mut().find(|r| r.path == *path) {
+                    Some(rollups) => append_json_lines(path, &rollups.engine.flush(settled)),
+                    None => Ok(()),
+                },
+                SinkConfig::Http(_) | SinkConfig::Fuse { .. } | SinkConfig::Webhook(_)
+                | SinkConfig::KubernetesEvents(_) => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to write sink {sink:?}: {e}");
+            }
+        }
+        if !self.servers.is_empty() {
+            self.page.publish(render(&self.exporter));
+        }
+        if !self.mounts.is_empty() {
+            self.tree.publish(&self.exporter, &current);
+        }
+    }
+
+    /// Writes the open rollup windows; the sinks are not written again afterwards.
+    pub(crate) fn finish(&mut self) {
+        for rollups in &mut self.rollups {
+            rollups.finish();
+        }
+    }
+
+    pub(crate) fn alert(&self, alert: &Alert) {
+        for webhook in &self.webhooks {
+            webhook.send(alert);
+        }
+        for events in &self.kube_events {
+            events.send(alert);
+        }
+    }
+}
+
+fn now_micros() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64
+}
+
+pub fn run(args: &DaemonArgs, json: bool) -> Result<i32, DCGMError> {
+    let mut config = DaemonConfig::load(&args.config)?;
+    if args.check {
+        if json {
+            super::print_json(&serde_json::json!({
+                "config": args.config, "ok": true, "groups": config.groups.len(), "sinks": config.sinks.len(),
+            }))?;
+        } else {
+            println!("{}: ok, {} group(s), {} sink(s)", args.config.display(), config.groups.len(), config.sinks.len());
+        }
+        return Ok(0);
+    }
+    if args.once {
+        // a single collection has nothing to spread out
+        config.phase = Phase::None;
+        config.jitter = Duration::ZERO;
+    }
+
+    let probe = ProbeState::new(config.stale_after());
+    // up before connecting, so probes see a daemon that is starting rather than a dead one
+    let _health = serve_health(&config, &probe)?;
+    let mut dcgm = match config.connection.backend {
+        Backend::Nvml => return nvml::run(config, args, &probe),
+        Backend::Dcgm => connect(&config)?,
+        Backend::Auto => match connect(&config) {
+            Ok(dcgm) => dcgm,
+            Err(e) if cfg!(feature = "nvml-fallback") => {
+                tracing::warn!("DCGM is unavailable ({e}), falling back to NVML");
+                return nvml::run(config, args, &probe);
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    let _ = dcgm.install_signal_cleanup();
+    probe.set_connected(true);
+    let mut notifier = Notifier::from_env();
+    let result = collect(&mut dcgm, config, args, &mut notifier, &probe);
+    probe.set_connected(false);
+    notifier.stopping();
+    match dcgm.shutdown_graceful(DEFAULT_SHUTDOWN_TIMEOUT) {
+        Ok(report) if !report.is_clean() => tracing::warn!("Shutdown was not clean: {report}"),
+        Ok(_) => (),
+        Err(e) => tracing::warn!("Failed to disconnect from DCGM: {e}"),
+    }
+    result.map(|_| 0)
+}
+
+/// Starts the gRPC health endpoint when one is configured; it stops when the handle is dropped.
+#[cfg(feature = "k8s")]
+fn serve_health(config: &DaemonConfig, probe: &ProbeState) -> Result<Option<GrpcHealthServer>, DCGMError> {
+    config.health.as_ref().map(|health| GrpcHealthServer::start(health.listen, probe.clone())).transpose()
+}
+
+/// Config validation rejects a health endpoint without the k8s feature.
+#[cfg(not(feature = "k8s"))]
+fn serve_health(_config: &DaemonConfig, _probe: &ProbeState) -> Result<Option<()>, DCGMError> {
+    Ok(None)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Re-reads the config and moves the watches over. A config that fails to load leaves everything as it was.
+fn reload(dcgm: &mut DcgmLibSafe, args: &DaemonArgs, config: &mut DaemonConfig, collector: &mut Collector,
+          sinks: &mut Sinks, probe: &ProbeState) {
+    let new = match DaemonConfig::load(&args.config) {
+        Ok(new) => new,
+        Err(e) => {
+            tracing::error!("Keeping the current config, reload failed: {e}");
+            return;
+        }
+    };
+    if new.connection != config.connection {
+        tracing::warn!("Connection settings changed; they take effect on restart only");
+    }
+    if new.health != config.health {
+        tracing::warn!("Health endpoint settings changed; they take effect on restart only");
+    }
+    probe.set_stale_after(new.stale_after());
+    let summary = collector.reload(dcgm, &new);
+    if !summary.is_empty() {
+        tracing::info!("Config reloaded: {summary}");
+    }
+    match Sinks::new(dcgm, &new, collector) {
+        Ok(new_sinks) => sinks.replace(new_sinks, &new),
+        Err(e) => tracing::error!("Keeping the current sinks, reload failed: {e}"),
+    }
+    *config = new;
+}
+
+/// Diffs the GPU and MIG entities and moves the groups and GPU labels over to what changed.
+fn follow_entities(dcgm: &mut DcgmLibSafe, watcher: &mut EntityWatcher, config: &DaemonConfig, collector: &mut Collector,
+                   sinks: &mut Sinks) {
+    let events = match watcher.poll(dcgm) {
+        Ok(events) => events,
+        Err(e) => {
+            tracing::warn!("Failed to list GPU entities: {e}");
+            return;
+        }
+    };
+    if events.is_empty() {
+        return;
+    }
+    for event in &events {
+        tracing::info!("{event}");
+    }
+    if let Err(e) = collector.apply_entity_events(dcgm, &events) {
+        tracing::error!("Failed to update the groups: {e}");
+    }
+    match Sinks::new(dcgm, config, collector) {
+        Ok(new_sinks) => sinks.replace(new_sinks, config),
+        Err(e) => tracing::error!("Keeping the current sinks: {e}"),
+    }
+}
+
+/// Starts the alert sources once a webhook or Kubernetes events sink is configured, stops them when
+/// none is, and hands what they report to those sinks.
+fn forward_alerts(dcgm: &mut DcgmLibSafe, config: &DaemonConfig, monitor: &mut Option<AlertMonitor>, sinks: &Sinks) {
+    let webhooks: Vec<&WebhookConfig> = config.sinks.iter()
+        .filter_map(|s| if let SinkConfig::Webhook(webhook) = s { Some(webhook) } else { None })
+        .collect();
+    let kube_events = config.sinks.iter().any(|s| matches!(s, SinkConfig::KubernetesEvents(_)));
+    if webhooks.is_empty() && !kube_events {
+        *monitor = None;
+        return;
+    }
+    if monitor.is_none() {
+        let policy = kube_events || webhooks.iter().any(|w| w.policy);
+        let health = (kube_events || webhooks.iter().any(|w| w.health)).then_some(config.interval);
+        match AlertMonitor::start(dcgm, policy, health) {
+            Ok(started) => *monitor = Some(started),
+            Err(e) => {
+                tracing::error!("Failed to watch for policy violations and health changes: {e}");
+                return;
+            }
+        }
+    }
+    let Some(monitor) = monitor else { return };
+    match monitor.poll(dcgm) {
+        Ok(alerts) => alerts.iter().for_each(|alert| sinks.alert(alert)),
+        Err(e) => tracing::warn!("Failed to check for alerts: {e}"),
+    }
+}
+
+fn collect(dcgm: &mut DcgmLibSafe, mut config: DaemonConfig, args: &DaemonArgs, notifier: &mut Notifier,
+           probe: &ProbeState) -> Result<(), DCGMError> {
+    let mut collector = Collector::start(dcgm, &config).dcgm_context("while watching the configured groups")?;
+    let result = (|| {
+        let mut sinks = Sinks::new(dcgm, &config, &collector).dcgm_context("while setting up the sinks")?;
+        sinks.start_servers(&config)?;
+        let mut monitor = None;
+        if args.once {
+            dcgm.updateAllFields()?;
+            let Collection { samples, mut failed } = collector.collect_due(dcgm, Instant::now());
+            sinks.write(&config, samples);
+            sinks.finish();
+            forward_alerts(dcgm, &config, &mut monitor, &sinks);
+            let first = (!failed.is_empty()).then(|| failed.remove(0));
+            for (group, e) in failed {
+                tracing::warn!("Failed to read group '{group}': {e}");
+            }
+            return first.map_or(Ok(()), |(group, e)| Err(e.context(format!("group '{group}'"))));
+        }
+        signals::install_reload_handler()?;
+        let mut last_modified = modified(&args.config);
+        let mut watcher = EntityWatcher::new(dcgm).dcgm_context("while listing GPU entities")?;
+        let mut next_entity_poll = Instant::now() + DEFAULT_ENTITY_POLL_INTERVAL;
+        let step = notifier.keep_alive_interval().unwrap_or(Duration::MAX).min(Duration::from_millis(500));
+        notifier.ready(&status(&collector));
+        loop {
+            let Collection { samples, failed } = collector.collect_due(dcgm, Instant::now());
+            if failed.is_empty() || !samples.is_empty() {
+                probe.collected();
+            }
+            sinks.write(&config, samples);
+            let mut lost = None;
+            for (group, e) in failed {
+                if e.kind == DCGMErrorKind::Disconnected {
+                    lost = Some(e);
+                } else {
+                    tracing::warn!("Failed to read group '{group}': {e}");
+                }
+            }
+            if let Some(e) = lost {
+                // shut down, e.g. by the signal cleanup
+                if dcgm.is_disconnected() {
+                    return Err(e);
+                }
+                tracing::error!("Lost the connection to DCGM: {e}");
+                probe.set_connected(false);
+                // the policy registration went with the connection
+                monitor = None;
+                reconnect(dcgm, &mut collector, notifier)?;
+                probe.set_connected(true);
+                continue;
+            }
+            forward_alerts(dcgm, &config, &mut monitor, &sinks);
+            // Sleep in short steps so a SIGHUP or config edit is picked up promptly.
+            let next = collector.next_due().unwrap_or_else(|| Instant::now() + config.interval);
+            while Instant::now() < next {
+                std::thread::sleep(next.saturating_duration_since(Instant::now()).min(step));
+                notifier.keep_alive();
+                let mut requested = signals::take_reload_request();
+                if args.watch_config {
+                    let now_modified = modified(&args.config);
+                    if now_modified != last_modified {
+                        last_modified = now_modified;
+                        requested = true;
+                    }
+                }
+                if requested {
+                    notifier.reloading();
+                    reload(dcgm, args, &mut config, &mut collector, &mut sinks, probe);
+                    // restarted with the sources the reloaded webhooks ask for
+                    monitor = None;
+                    notifier.ready(&status(&collector));
+                    break;
+                }
+                if Instant::now() >= next_entity_poll {
+                    follow_entities(dcgm, &mut watcher, &config, &mut collector, &mut sinks);
+                    next_entity_poll = Instant::now() + DEFAULT_ENTITY_POLL_INTERVAL;
+                }
+            }
+        }
+    })();
+    collector.stop(dcgm);
+    result
+}
+
+/// Reconnects with backoff until the hostengine is back, then points the collector at the groups the
+/// reconnect restored. Embedded hostengines cannot be reconnected to, that error is returned.
+fn reconnect(dcgm: &mut DcgmLibSafe, collector: &mut Collector, notifier: &mut Notifier) -> Result<(), DCGMError> {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match dcgm.reconnect() {
+            Ok(event) => {
+                collector.remap(&event);
+                return Ok(());
+            }
+            Err(e) if e.kind == DCGMErrorKind::NotSupported => return Err(e),
+            Err(e) => tracing::warn!("Reconnecting to DCGM failed, retrying in {backoff:?}: {e}"),
+        }
+        let resume = Instant::now() + backoff;
+        while Instant::now() < resume {
+            std::thread::sleep(resume.saturating_duration_since(Instant::now()).min(Duration::from_millis(500)));
+            notifier.keep_alive();
+        }
+        backoff = (backoff * 2).min(Duration::from_secs(60));
+    }
+}
+
+fn status(collector: &Collector) -> String {
+    format!("Collecting {} group(s)", collector.groups().len())
+}
+
+#[cfg(feature = "nvml-fallback")]
+mod nvml {
+    use super::{DaemonArgs, Sinks};
+    use rust_dcgm::dcgm_bindings::daemon::DaemonConfig;
+    use rust_dcgm::dcgm_bindings::exporter::Exporter;
+    use rust_dcgm::dcgm_bindings::nvml::{NvmlBackend, NvmlCollector, NVML_FIELDS};
+    use rust_dcgm::dcgm_bindings::probe::ProbeState;
+    use rust_dcgm::dcgm_bindings::systemd::Notifier;
+    use rust_dcgm::dcgm_bindings::DCGMError;
+    use std::time::{Duration, Instant};
+
+    /// Collects the groups with the reduced NVML backend. There are no watches or groups to set up,
+    /// so config reloads and GPU changes are only picked up on restart.
+    pub(super) fn run(config: DaemonConfig, args: &DaemonArgs, probe: &ProbeState) -> Result<i32, DCGMError> {
+        let nvml = NvmlBackend::new()?;
+        probe.set_connected(true);
+        let mut collector = NvmlCollector::start(&nvml, &config)?;
+        let mut exporter = Exporter::new(config.exporter_config());
+        for gpu in nvml.gpus()? {
+            exporter.set_identity(nvml.identity(gpu)?);
+        }
+        let fields = collector.fields();
+        for (id, tag) in NVML_FIELDS.iter().filter(|(id, _)| fields.contains(id)) {
+            exporter.set_metric_name(*id, &format!("DCGM_FI_{}", tag.to_uppercase()));
+        }
+        let mut sinks = Sinks::with_exporter(exporter, &config);
+        sinks.start_servers(&config)?;
+        let mut notifier = Notifier::from_env();
+        let step = notifier.keep_alive_interval().unwrap_or(Duration::MAX);
+        if !args.once {
+            notifier.ready("Collecting with the NVML backend");
+        }
+        loop {
+            let samples = collector.collect_due(&nvml, Instant::now())?;
+            probe.collected();
+            sinks.write(&config, samples);
+            if args.once {
+                sinks.finish();
+                return Ok(0);
+            }
+            let next = collector.next_due().unwrap_or_else(|| Instant::now() + config.interval);
+            while Instant::now() < next {
+                std::thread::sleep(next.saturating_duration_since(Instant::now()).min(step));
+                notifier.keep_alive();
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "nvml-fallback"))]
+mod nvml {
+    use super::DaemonArgs;
+    use rust_dcgm::dcgm_bindings::daemon::DaemonConfig;
+    use rust_dcgm::dcgm_bindings::probe::ProbeState;
+    use rust_dcgm::dcgm_bindings::DCGMError;
+
+    pub(super) fn run(_config: DaemonConfig, _args: &DaemonArgs, _probe: &ProbeState) -> Result<i32, DCGMError> {
+        Err(DCGMError::not_supported("the NVML backend needs a build with the nvml-fallback feature"))
+    }
+}
@@ -0,0 +1,207 @@
+use clap::Args;
+use rust_dcgm::dcgm_bindings::alerts::Alert;
+use rust_dcgm::dcgm_bindings::bindings::DCGM_GROUP_ALL_GPUS;
+use rust_dcgm::dcgm_bindings::burnin::BurnInOptions;
+use rust_dcgm::dcgm_bindings::daemon::parse_duration;
+use rust_dcgm::dcgm_bindings::diag::{DiagLevel, DiagOptions, DiagParameter, DiagReport, DiagResult};
+use rust_dcgm::dcgm_bindings::kube_events::{KubeEvents, KubeEventsConfig};
+use rust_dcgm::dcgm_bindings::*;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Args, Debug)]
+pub struct DiagArgs {
+    /// Run level: 1/short, 2/medium, 3/long, 4/xlong
+    #[arg(short = 'r', long, default_value = "short")]
+    pub level: DiagLevel,
+    /// Run only the named test; may be repeated. Overrides --level
+    #[arg(short = 't', long = "test")]
+    pub tests: Vec<String>,
+    /// Stop at the first failing test
+    #[arg(long)]
+    pub fail_early: bool,
+    /// Per-test timeout in seconds
+    #[arg(long)]
+    pub timeout: Option<u64>,
+    /// Plugin parameter as plugin.parameter=value, e.g. targeted_stress.test_duration=120; may be repeated
+    #[arg(short = 'p', long = "parameter")]
+    pub parameters: Vec<DiagParameter>,
+    /// Compare with a report saved by --save-baseline; the exit status then only reflects new failures
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+    /// Save the report for later --baseline comparisons
+    #[arg(long)]
+    pub save_baseline: Option<PathBuf>,
+    /// Publish failures as Kubernetes Events on this node ($NODE_NAME); needs the k8s feature and an
+    /// in-cluster service account
+    #[arg(long)]
+    pub kubernetes_events: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct BurnInArgs {
+    /// How long to keep running diagnostics, e.g. 2h; a started run is always finished
+    #[arg(short = 'd', long, value_parser = parse_duration)]
+    pub duration: Duration,
+    /// Run level of the diagnostics: 3/long or 4/xlong
+    #[arg(short = 'r', long, default_value = "long")]
+    pub level: DiagLevel,
+    /// Plugin parameter as plugin.parameter=value; may be repeated
+    #[arg(short = 'p', long = "parameter")]
+    pub parameters: Vec<DiagParameter>,
+    /// How often health, temperature and power are checked
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub interval: Duration,
+    /// Fail GPUs that get hotter than this, in degrees C
+    #[arg(long)]
+    pub max_temperature: Option<f64>,
+    /// Fail GPUs that draw more than this, in W
+    #[arg(long)]
+    pub max_power: Option<f64>,
+}
+
+/// Spins on stderr until dropped. Stays quiet when stderr is not a terminal.
+struct Spinner {
+    done: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Spinner {
+    fn start(label: String) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = std::io::stderr().is_terminal().then(|| {
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let started = std::time::Instant::now();
+                for frame in ['|', '/', '-', '\\'].iter().cycle() {
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    eprint!("\r{frame} {label} ({}s)", started.elapsed().as_secs());
+                    let _ = std::io::stderr().flush();
+                    std::thread::sleep(Duration::from_millis(120));
+                }
+                eprint!("\r\x1b[2K");
+            })
+        });
+        Spinner { done, thread }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn colored(result: DiagResult, color: bool) -> String {
+    let code = match result {
+        DiagResult::Pass => "32",
+        DiagResult::Warn => "33",
+        DiagResult::Fail => "31",
+        DiagResult::Skip | DiagResult::NotRun => "2",
+    };
+    if color {
+        format!("\x1b[{code}m{:<8}\x1b[0m", result.to_string())
+    } else {
+        format!("{:<8}", result.to_string())
+    }
+}
+
+fn print_table(report: &DiagReport, color: bool) {
+    println!("DCGM {}  driver {}", report.dcgm_version, report.driver_version);
+    println!("{:<28} {:<8} Entities", "Test", "Result");
+    for test in &report.tests {
+        let entities: Vec<String> = test.entities.iter()
+            .filter(|(_, r)| *r != test.result)
+            .map(|(e, r)| format!("{e}: {r}"))
+            .collect();
+        println!("{:<28} {} {}", test.name, colored(test.result, color), entities.join(", "));
+        for error in &test.errors {
+            let entity = error.entity.map(|e| e.to_string()).unwrap_or_else(|| "N/A".into());
+            println!("    {entity:<10} {}", error.message);
+            if let Some(info) = &error.error {
+                println!("    {:<10} {info}: {}", "", info.remediation.unwrap_or("no documented action"));
+            }
+        }
+    }
+    println!("{:<28} {}", "Overall", colored(report.overall(), color));
+}
+
+pub fn run(dcgm: &mut DcgmLibSafe, args: &DiagArgs, json: bool) -> Result<i32, DCGMError> {
+    let options = DiagOptions {
+        level: args.level,
+        tests: args.tests.clone(),
+        timeout: args.timeout.map(Duration::from_secs),
+        fail_early: args.fail_early,
+        parameters: args.parameters.clone(),
+        ..DiagOptions::default()
+    };
+    // fail before a possibly hours-long run, not after it
+    let baseline = args.baseline.as_deref().map(DiagReport::load).transpose()?;
+    let events = args.kubernetes_events.then(|| KubeEvents::start(&KubeEventsConfig::in_cluster())).transpose()?;
+    let label = if options.tests.is_empty() {
+        format!("running {:?} diagnostics", options.level)
+    } else {
+        format!("running {}", options.tests.join(", "))
+    };
+
+    let report = {
+        let _spinner = Spinner::start(label);
+        dcgm.run_diag(&options)?
+    };
+
+    if let Some(path) = &args.save_baseline {
+        report.save(path)?;
+    }
+    // dropping the publisher waits for the events to be posted
+    if let Some(events) = events {
+        Alert::from_diag(&report).iter().for_each(|alert| events.send(alert));
+    }
+    let diff = baseline.map(|baseline| report.diff(&baseline));
+
+    if json {
+        match &diff {
+            Some(diff) => super::print_json(&serde_json::json!({ "report": report, "diff": diff }))?,
+            None => super::print_json(&report)?,
+        }
+    } else {
+        print_table(&report, std::io::stdout().is_terminal());
+        if let Some(diff) = &diff {
+            print!("\n{diff}");
+        }
+    }
+    let failed = match &diff {
+        Some(diff) => diff.has_regressions(),
+        None => !report.passed(),
+    };
+    Ok(if failed { 1 } else { 0 })
+}
+
+pub fn run_burn_in(dcgm: &mut DcgmLibSafe, args: &BurnInArgs, json: bool) -> Result<i32, DCGMError> {
+    let options = BurnInOptions {
+        level: args.level,
+        parameters: args.parameters.clone(),
+        monitor_interval: args.interval,
+        max_temperature: args.max_temperature,
+        max_power: args.max_power,
+        ..BurnInOptions::default()
+    };
+    let report = {
+        let _spinner = Spinner::start(format!("burning in for {}s", args.duration.as_secs()));
+        dcgm.burn_in_with(DCGM_GROUP_ALL_GPUS as _, args.duration, &options)?
+    };
+
+    if json {
+        super::print_json(&report)?;
+    } else {
+        println!("{report}");
+    }
+    Ok(if report.passed() { 0 } else { 1 })
+}
@@ -0,0 +1,146 @@
+use super::Cli;
+use clap::Args;
+use rust_dcgm::dcgm_bindings::catalog::{field_catalog, supported_on_gpus, FieldInfo, FieldType};
+use rust_dcgm::dcgm_bindings::daemon::FieldRef;
+use rust_dcgm::dcgm_bindings::entity::EntityGroup;
+use rust_dcgm::dcgm_bindings::DCGMError;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Args, Debug)]
+pub struct FieldsArgs {
+    /// Fields to describe, by id, tag (gpu_temp) or name (DCGM_FI_DEV_GPU_TEMP); every field when left out
+    pub fields: Vec<FieldRef>,
+    /// Only fields whose tag or short name contains this, ignoring case
+    #[arg(short = 's', long)]
+    pub search: Option<String>,
+    /// Only fields queried on this entity type (gpu, switch, gpu_i, link, cpu, ...) or global ones
+    #[arg(short = 'e', long)]
+    pub entity: Option<EntityLevel>,
+    /// Only fields of this type: int64, double, string, binary or timestamp
+    #[arg(short = 't', long = "type")]
+    pub field_type: Option<FieldType>,
+    /// Ask the hostengine which GPUs report each GPU-level field and list them as supported_gpus
+    #[arg(long)]
+    pub probe: bool,
+    /// Like --probe, but list only GPU-level fields that at least one GPU supports
+    #[arg(long)]
+    pub supported: bool,
+    /// Print a table instead of JSON; --json takes precedence
+    #[arg(long)]
+    pub table: bool,
+}
+
+/// Entity type a field is queried on, with `global` for fields that belong to no entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLevel(Option<EntityGroup>);
+
+impl FromStr for EntityLevel {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("global") {
+            return Ok(EntityLevel(None));
+        }
+        s.parse().map(|group| EntityLevel(Some(group)))
+    }
+}
+
+#[derive(Serialize)]
+struct Listed<'a> {
+    #[serde(flatten)]
+    field: &'a FieldInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supported_gpus: Option<&'a [u32]>,
+}
+
+impl FieldsArgs {
+    fn matches(&self, field: &FieldInfo) -> bool {
+        if let Some(search) = &self.search {
+            let search = search.to_ascii_lowercase();
+            if !field.tag.to_ascii_lowercase().contains(&search) && !field.short_name.to_ascii_lowercase().contains(&search) {
+                return false;
+            }
+        }
+        self.entity.is_none_or(|level| level.0 == field.entity_level)
+            && self.field_type.is_none_or(|t| field.field_type == Some(t))
+    }
+}
+
+pub fn run(cli: &Cli, args: &FieldsArgs) -> Result<i32, DCGMError> {
+    let mut catalog = field_catalog()?;
+    if !args.fields.is_empty() {
+        let ids = args.fields.iter().map(FieldRef::resolve).collect::<Result<Vec<u16>, _>>()?;
+        let mut selected: Vec<FieldInfo> = Vec::with_capacity(ids.len());
+        for id in ids {
+            match catalog.iter().find(|f| f.id == id) {
+                Some(field) => selected.push(field.clone()),
+                None => return Err(DCGMError::from(format!("unknown field id {id}"))),
+            }
+        }
+        catalog = selected;
+    }
+    catalog.retain(|f| args.matches(f));
+
+    let support = if args.probe || args.supported {
+        Some(probe(cli, &catalog)?)
+    } else {
+        None
+    };
+    let supported_gpus = |field: &FieldInfo| -> Option<&[u32]> {
+        // derived fields are supported wherever the field they are computed from is
+        let id = field.source_field.unwrap_or(field.id);
+        let support = support.as_ref().filter(|_| field.entity_level == Some(EntityGroup::Gpu))?;
+        Some(support.get(&id).map_or(&[][..], Vec::as_slice))
+    };
+    let listed: Vec<Listed> = catalog.iter()
+        .map(|field| Listed { field, supported_gpus: supported_gpus(field) })
+        .filter(|l| !args.supported || l.supported_gpus.is_some_and(|gpus| !gpus.is_empty()))
+        .collect();
+
+    if args.table && !cli.json {
+        print!("{}", table(&listed));
+    } else {
+        super::print_json(&listed)?;
+    }
+    Ok(0)
+}
+
+/// Supported GPUs of every GPU-level field in `catalog`. Only GPU fields are probed: other entity
+/// types come and go with the system and are better checked by watching them.
+fn probe(cli: &Cli, catalog: &[FieldInfo]) -> Result<BTreeMap<u16, Vec<u32>>, DCGMError> {
+    let mut ids: Vec<u16> = catalog.iter()
+        .filter(|f| f.entity_level == Some(EntityGroup::Gpu))
+        .map(|f| f.source_field.unwrap_or(f.id))
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let mut dcgm = cli.connect()?;
+    let support = supported_on_gpus(&mut dcgm, &ids);
+    let _ = dcgm.shutdown();
+    support
+}
+
+fn table(listed: &[Listed]) -> String {
+    let probed = listed.iter().any(|l| l.supported_gpus.is_some());
+    let mut out = format!("{:>6}  {:<40} {:<10} {:<6} {:<9}", "ID", "TAG", "TYPE", "UNIT", "ENTITY");
+    if probed {
+        out += " GPUS";
+    }
+    out += "\n";
+    for l in listed {
+        let f = l.field;
+        let field_type = f.field_type.map(|t| t.to_string()).unwrap_or_default();
+        out += &format!("{:>6}  {:<40} {:<10} {:<6} {:<9}", f.id, f.tag, field_type,
+                        f.dcgm_unit, f.entity_level.map_or("global".to_string(), |g| g.to_string()));
+        if let Some(gpus) = l.supported_gpus {
+            let gpus: Vec<String> = gpus.iter().map(u32::to_string).collect();
+            out += &format!(" {}", if gpus.is_empty() { "none".to_string() } else { gpus.join(",") });
+        } else if probed {
+            out += " -";
+        }
+        out += "\n";
+    }
+    out
+}
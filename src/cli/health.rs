@@ -0,0 +1,94 @@
+use clap::Args;
+use rust_dcgm::dcgm_bindings::bindings::DCGM_GROUP_ALL_GPUS;
+use rust_dcgm::dcgm_bindings::entity::Entity;
+use rust_dcgm::dcgm_bindings::health::{HealthReport, HealthResult, HealthSystems};
+use rust_dcgm::dcgm_bindings::*;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Args, Debug)]
+pub struct HealthArgs {
+    /// Keep re-checking and print only changes in health
+    #[arg(long)]
+    pub watch: bool,
+    /// Seconds between checks in watch mode
+    #[arg(long, default_value_t = 5)]
+    pub interval: u64,
+    /// Exit with status 1 as soon as any GPU reports a failure
+    #[arg(long)]
+    pub exit_on_failure: bool,
+}
+
+type HealthState = BTreeMap<(Option<Entity>, String), (HealthResult, String)>;
+
+fn state_of(report: &HealthReport) -> HealthState {
+    let mut state = HealthState::new();
+    for i in &report.incidents {
+        let entry = state.entry((i.entity, i.system.to_string())).or_insert((i.health, i.message.clone()));
+        if i.health > entry.0 {
+            *entry = (i.health, i.message.clone());
+        }
+    }
+    state
+}
+
+/// One line per change; JSON lines with `timestamp`, `entity`, `system`, `from`, `to` and `message`
+/// when `json` is set.
+fn print_transitions(previous: &HealthState, current: &HealthState, json: bool) {
+    let now = super::timestamp();
+    let report = |key: &(Option<Entity>, String), was: HealthResult, health: HealthResult, message: &str| {
+        if json {
+            let event = serde_json::json!({
+                "timestamp": now, "entity": key.0, "system": key.1, "from": was, "to": health, "message": message,
+            });
+            println!("{event}");
+            return;
+        }
+        let entity = key.0.map(|e| e.to_string()).unwrap_or_else(|| "N/A".into());
+        if message.is_empty() {
+            println!("{now}  {entity:<10} {:<16} {was} -> {health}", key.1);
+        } else {
+            println!("{now}  {entity:<10} {:<16} {was} -> {health}: {message}", key.1);
+        }
+    };
+    for (key, (health, message)) in current {
+        if previous.get(key).map(|(h, _)| h) != Some(health) {
+            let was = previous.get(key).map(|(h, _)| *h).unwrap_or(HealthResult::Pass);
+            report(key, was, *health, message);
+        }
+    }
+    for key in previous.keys().filter(|k| !current.contains_key(*k)) {
+        report(key, previous[key].0, HealthResult::Pass, "");
+    }
+}
+
+pub fn run(dcgm: &mut DcgmLibSafe, args: &HealthArgs, json: bool) -> Result<i32, DCGMError> {
+    let group = DCGM_GROUP_ALL_GPUS as _;
+    let interval = Duration::from_secs(args.interval.max(1));
+    dcgm.health_set(group, HealthSystems::all(), interval, Duration::from_secs(600))?;
+
+    if !args.watch {
+        let report = dcgm.health_check(group)?;
+        if json {
+            super::print_json(&report)?;
+        } else {
+            print!("{report}");
+        }
+        return Ok(if report.overall == HealthResult::Fail { 1 } else { 0 });
+    }
+
+    let mut previous = HealthState::new();
+    if !json {
+        println!("{}  watching health every {}s", super::timestamp(), interval.as_secs());
+    }
+    loop {
+        let report = dcgm.health_check(group)?;
+        let current = state_of(&report);
+        print_transitions(&previous, &current, json);
+        if args.exit_on_failure && report.overall == HealthResult::Fail {
+            return Ok(1);
+        }
+        previous = current;
+        std::thread::sleep(interval);
+    }
+}
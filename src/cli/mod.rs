@@ -0,0 +1,82 @@
+pub mod daemon;
+pub mod diag;
+pub mod fields;
+pub mod health;
+pub mod report;
+pub mod stats;
+
+use clap::{Parser, Subcommand};
+use rust_dcgm::dcgm_bindings::*;
+use serde::Serialize;
+
+#[derive(Parser, Debug)]
+#[command(name = "rust-dcgm", about = "Query and manage GPUs through DCGM")]
+pub struct Cli {
+    /// Standalone hostengine address
+    #[arg(long, global = true, default_value = "127.0.0.1:5555")]
+    pub host: String,
+    /// Start an embedded hostengine instead of connecting to one
+    #[arg(long, global = true)]
+    pub embedded: bool,
+    /// Print results, and errors on stderr, as JSON for scripts instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check GPU health
+    Health(health::HealthArgs),
+    /// Run DCGM diagnostics
+    Diag(diag::DiagArgs),
+    /// Run long diagnostics while monitoring health, temperature and power; one verdict per GPU
+    BurnIn(diag::BurnInArgs),
+    /// Start, stop and report per-process or job stats
+    Stats(stats::StatsArgs),
+    /// Collect fields as configured in a config file and write them to its sinks
+    Daemon(daemon::DaemonArgs),
+    /// List known fields with their id, tag, unit, type and entity level, optionally filtered and probed
+    Fields(fields::FieldsArgs),
+    /// Summarize utilization, throttling, health and diag runs of the last hours as text, HTML or JSON
+    Report(report::ReportArgs),
+}
+
+impl Cli {
+    pub fn connect(&self) -> Result<DcgmLibSafe, DCGMError> {
+        if self.embedded {
+            DcgmLibSafe::new(Mode::Embedded, &[])
+        } else {
+            let persist = matches!(&self.command, Some(Command::Stats(args)) if args.starts_watches());
+            DcgmLibSafe::new(Mode::Standalone, &[&self.host, "0", if persist { "1" } else { "0" }])
+        }
+    }
+}
+
+/// Prints `value` as pretty JSON on stdout; what every subcommand prints under `--json`.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), DCGMError> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| DCGMError::from(e.to_string()))?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Reports a failed command on stderr: `{prefix}: {error}` as text, or one JSON object with the
+/// message, error kind and DCGM return code under `--json`.
+pub fn print_error(json: bool, prefix: &str, e: &DCGMError) {
+    if json {
+        let error = serde_json::json!({ "error": e.to_string(), "kind": format!("{:?}", e.kind), "code": e.code });
+        eprintln!("{error}");
+    } else {
+        eprintln!("{prefix}: {e}");
+    }
+}
+
+/// Local time as `YYYY-MM-DD HH:MM:SS` for log-style output.
+pub fn timestamp() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as i64)
+        .unwrap_or(0);
+    format::format_time(now, false, c"%Y-%m-%d %H:%M:%S")
+}
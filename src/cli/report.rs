@@ -0,0 +1,120 @@
+use super::Cli;
+use clap::{Args, ValueEnum};
+use rust_dcgm::dcgm_bindings::bindings::DCGM_GROUP_ALL_GPUS;
+use rust_dcgm::dcgm_bindings::daemon::parse_duration;
+use rust_dcgm::dcgm_bindings::diag::DiagReport;
+use rust_dcgm::dcgm_bindings::health::HealthSystems;
+use rust_dcgm::dcgm_bindings::import::parse_json_line;
+use rust_dcgm::dcgm_bindings::report::{DiagRun, ReportBuilder};
+use rust_dcgm::dcgm_bindings::DCGMError;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Length of the window the report covers, ending now, e.g. 24h or 90m
+    #[arg(long, value_parser = parse_duration, default_value = "24h")]
+    pub last: Duration,
+    /// File written by a json_lines sink of the daemon; may be repeated
+    #[arg(short = 's', long = "samples")]
+    pub samples: Vec<PathBuf>,
+    /// Diag report saved by `diag --save-baseline` or `dcgmi diag --json`, or a directory of them; may be
+    /// repeated. Runs are dated by file modification time
+    #[arg(short = 'd', long = "diag")]
+    pub diag: Vec<PathBuf>,
+    /// Connect to the hostengine and include the current health check
+    #[arg(long)]
+    pub health: bool,
+    /// Output format; --json selects json
+    #[arg(short = 'f', long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+    /// Write the report to this file instead of stdout
+    #[arg(short = 'o', long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Html,
+    Json,
+}
+
+fn micros(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_micros() as i64).unwrap_or(0)
+}
+
+fn read_samples(path: &Path, report: &mut ReportBuilder) -> Result<(), DCGMError> {
+    let file = std::fs::File::open(path).map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", path.display())))?;
+    for (n, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", path.display())))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // the daemon may have been killed halfway through a line
+        match parse_json_line(&line) {
+            Ok(sample) => report.add_sample(&sample),
+            Err(e) => tracing::warn!("{}:{}: {e}", path.display(), n + 1),
+        }
+    }
+    Ok(())
+}
+
+/// A saved report, or `dcgmi diag --json` output when it is not one.
+fn read_diag(path: &Path) -> Result<DiagRun, DCGMError> {
+    let report = DiagReport::load(path).or_else(|e| {
+        let text = std::fs::read_to_string(path).map_err(|_| e)?;
+        DiagReport::from_dcgmi_json(&text).map_err(|e| DCGMError::from(format!("{}: {e}", path.display())))
+    })?;
+    let time = std::fs::metadata(path).and_then(|m| m.modified()).map(micros).unwrap_or(0);
+    Ok(DiagRun::new(path.display().to_string(), time, &report))
+}
+
+fn diag_files(path: &Path) -> Result<Vec<PathBuf>, DCGMError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = std::fs::read_dir(path).map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", path.display())))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub fn run(cli: &Cli, args: &ReportArgs) -> Result<i32, DCGMError> {
+    let end = micros(SystemTime::now());
+    let mut report = ReportBuilder::new(end - args.last.as_micros() as i64, end);
+    for path in &args.samples {
+        read_samples(path, &mut report)?;
+    }
+    for path in &args.diag {
+        for file in diag_files(path)? {
+            report = report.with_diag(read_diag(&file)?);
+        }
+    }
+    if args.health {
+        let mut dcgm = cli.connect()?;
+        let group = DCGM_GROUP_ALL_GPUS as _;
+        let health = dcgm.health_set(group, HealthSystems::all(), Duration::from_secs(5), Duration::from_secs(600))
+            .and_then(|()| dcgm.health_check(group));
+        let _ = dcgm.shutdown();
+        report = report.with_health(health?);
+    }
+    let report = report.build();
+
+    let format = if cli.json { ReportFormat::Json } else { args.format };
+    let rendered = match format {
+        ReportFormat::Text => report.render_text(),
+        ReportFormat::Html => report.render_html(),
+        ReportFormat::Json => serde_json::to_string_pretty(&report).map_err(|e| DCGMError::from(e.to_string()))? + "\n",
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, rendered)
+            .map_err(|e| DCGMError::from(format!("Failed to write {}: {e}", path.display())))?,
+        None => print!("{rendered}"),
+    }
+    Ok(if report.healthy() { 0 } else { 1 })
+}
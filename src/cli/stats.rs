@@ -0,0 +1,147 @@
+use clap::Args;
+use rust_dcgm::dcgm_bindings::bindings::DCGM_GROUP_ALL_GPUS;
+use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+use rust_dcgm::dcgm_bindings::*;
+use serde::Serialize;
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Report on this process
+    #[arg(long, conflicts_with = "job")]
+    pub pid: Option<u32>,
+    /// Job id to start, stop or report on
+    #[arg(long)]
+    pub job: Option<String>,
+    /// Start recording: job stats with --job, per-process stats otherwise
+    #[arg(long, conflicts_with_all = ["stop", "pid"])]
+    pub start: bool,
+    /// Stop the job and print its report
+    #[arg(long, requires = "job")]
+    pub stop: bool,
+    /// Leave the job in the hostengine after --stop instead of removing it
+    #[arg(long, requires = "stop")]
+    pub keep: bool,
+    /// Run this command between start and stop and report on it
+    #[arg(last = true, conflicts_with_all = ["start", "stop", "pid"])]
+    pub command: Vec<String>,
+}
+
+impl StatsArgs {
+    /// Whether this invocation leaves watches behind for a later one, so the connection must persist them.
+    pub fn starts_watches(&self) -> bool {
+        self.start
+    }
+}
+
+fn print_report<T: Serialize>(report: &T, text: impl FnOnce() -> String, json: bool) -> Result<(), DCGMError> {
+    if json {
+        super::print_json(report)?;
+    } else {
+        print!("{}", text());
+    }
+    Ok(())
+}
+
+fn process_summary(stats: &process::ProcessStats) -> String {
+    let s = &stats.summary;
+    let mut out = format!("GPU usage for pid {}\n", stats.pid);
+    if let Some(container) = &stats.container {
+        out += &format!("  Container:       {} ({:?})\n", container.name.as_deref().unwrap_or(container.short_id()), container.runtime);
+    }
+    out += &format!("  GPUs:            {}\n", stats.gpus.iter().map(|g| g.gpu_id.to_string()).collect::<Vec<_>>().join(","));
+    out += &format!("  Run time:        {:.1} s\n", (s.end_time - s.start_time).max(0) as f64 / 1e6);
+    out += &format!("  Energy:          {:.1} J\n", s.energy_consumed as f64 / 1000.0);
+    out += &format!("  SM util (avg):   {} %\n", s.sm_utilization.average);
+    out += &format!("  Mem util (avg):  {} %\n", s.memory_utilization.average);
+    out += &format!("  XID errors:      {}\n", s.xid_critical_errors);
+    out += &format!("  ECC SBE / DBE:   {} / {}\n", s.ecc_single_bit, s.ecc_double_bit);
+    out
+}
+
+fn show_pid(dcgm: &mut DcgmLibSafe, pid: u32, json: bool) -> Result<(), DCGMError> {
+    let stats = dcgm.pid_info(DCGM_GROUP_ALL_GPUS as _, pid)?;
+    print_report(&stats, || process_summary(&stats), json)
+}
+
+fn show_job(dcgm: &mut DcgmLibSafe, job: &str, json: bool) -> Result<(), DCGMError> {
+    let stats = dcgm.job_get_stats(job)?;
+    print_report(&stats, || stats.epilog_summary(), json)
+}
+
+/// Runs `command` to completion and returns its exit code (128 + signal if it was killed).
+fn run_command(command: &[String]) -> Result<(u32, i32), DCGMError> {
+    let mut child = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .spawn()
+        .map_err(|e| DCGMError::from(format!("Failed to run {}: {e}", command[0])))?;
+    let pid = child.id();
+    let status = child.wait().map_err(|e| DCGMError::from(e.to_string()))?;
+    let code = status.code().unwrap_or_else(|| {
+        use std::os::unix::process::ExitStatusExt;
+        128 + status.signal().unwrap_or(0)
+    });
+    Ok((pid, code))
+}
+
+pub fn run(dcgm: &mut DcgmLibSafe, args: &StatsArgs, json: bool) -> Result<i32, DCGMError> {
+    let group = DCGM_GROUP_ALL_GPUS as _;
+
+    if !args.command.is_empty() {
+        let code = match &args.job {
+            Some(job) => {
+                dcgm.watch_job_fields(group, &WatchOptions::default())?;
+                dcgm.job_start_stats(group, job)?;
+                let (_, code) = run_command(&args.command)?;
+                dcgm.job_stop_stats(job)?;
+                show_job(dcgm, job, json)?;
+                dcgm.job_remove(job)?;
+                code
+            }
+            None => {
+                dcgm.watch_pid_fields(group, &WatchOptions::default())?;
+                let (pid, code) = run_command(&args.command)?;
+                dcgm.updateAllFields()?;
+                show_pid(dcgm, pid, json)?;
+                code
+            }
+        };
+        return Ok(code);
+    }
+
+    if args.start {
+        match &args.job {
+            Some(job) => {
+                dcgm.watch_job_fields(group, &WatchOptions::default())?;
+                dcgm.job_start_stats(group, job)?;
+                if json {
+                    super::print_json(&serde_json::json!({ "started": "job", "job": job }))?;
+                } else {
+                    eprintln!("Started stats for job {job}");
+                }
+            }
+            None => {
+                dcgm.watch_pid_fields(group, &WatchOptions::default())?;
+                if json {
+                    super::print_json(&serde_json::json!({ "started": "pids" }))?;
+                } else {
+                    eprintln!("Started per-process stats");
+                }
+            }
+        }
+        return Ok(0);
+    }
+
+    match (&args.job, args.pid) {
+        (Some(job), _) if args.stop => {
+            dcgm.job_stop_stats(job)?;
+            show_job(dcgm, job, json)?;
+            if !args.keep {
+                dcgm.job_remove(job)?;
+            }
+        }
+        (Some(job), _) => show_job(dcgm, job, json)?,
+        (None, Some(pid)) => show_pid(dcgm, pid, json)?,
+        (None, None) => return Err(DCGMError::from("One of --pid, --job, --start or a command is required")),
+    }
+    Ok(0)
+}
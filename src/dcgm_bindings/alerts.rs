@@ -0,0 +1,571 @@
+use super::bindings::*;
+use super::daemon::duration;
+use super::diag::{DiagReport, DiagResult};
+use super::entity::Entity;
+use super::exporter::local_hostname;
+use super::format::rfc3339;
+use super::http::{client_tls, send, ClientTls, Outgoing, Url, SYSTEM_CA_FILE};
+use super::health::{HealthReport, HealthResult, HealthSystems};
+use super::policy::{PolicyConditions, PolicyEvent, PolicyRegistration, PolicyViolation, ViolationPayload};
+use super::{DCGMError, DcgmLibSafe};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A webhook that policy violations and health transitions are POSTed to as JSON.
+///
+/// ```toml
+/// [[sinks]]
+/// type = "webhook"
+/// url = "https://hooks.slack.com/services/..."
+/// format = "slack"
+/// dedup_window = "15m"
+/// ```
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Integration key of the PagerDuty service, for the `pagerduty` format.
+    #[serde(default)]
+    pub routing_key: Option<String>,
+    /// Extra request headers, e.g. a token the receiver checks.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Send violations of the policies set on the GPUs, e.g. with `dcgmi policy --set`.
+    #[serde(default = "default_true")]
+    pub policy: bool,
+    /// Send changes of the health watch results.
+    #[serde(default = "default_true")]
+    pub health: bool,
+    /// Further attempts after a failed delivery, with doubling backoff. Rejections (4xx other than 429)
+    /// are not retried.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_timeout", deserialize_with = "duration")]
+    pub timeout: Duration,
+    /// The same alert is sent at most once per window; a recovery ends the window.
+    #[serde(default = "default_dedup_window", deserialize_with = "duration")]
+    pub dedup_window: Duration,
+    /// CA bundle (PEM) for https URLs, the system bundle when left out. Needs the `tls` feature.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+}
+
+/// Webhook URLs, routing keys and headers are credentials; configs are printed with `{:?}` in error
+/// messages, so only the scheme and host are shown.
+impl fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let url = Url::parse(&self.url)
+            .map(|u| format!("{}://{}/...", if u.https { "https" } else { "http" }, u.authority()))
+            .unwrap_or_else(|_| "<invalid>".to_string());
+        f.debug_struct("WebhookConfig").field("url", &url).field("format", &self.format).finish_non_exhaustive()
+    }
+}
+
+/// Payload template of a webhook.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The `Alert` as JSON, with the `host` it came from.
+    #[default]
+    Generic,
+    /// A Slack incoming webhook message.
+    Slack,
+    /// A PagerDuty Events API v2 event; recoveries resolve the incident.
+    Pagerduty,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_dedup_window() -> Duration {
+    Duration::from_secs(300)
+}
+
+impl WebhookConfig {
+    /// Problems with the webhook settings, for config validation.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match Url::parse(&self.url) {
+            Ok(url) if url.https && !cfg!(feature = "tls") => problems.push("https webhooks need a build with the tls feature".to_string()),
+            Ok(_) => (),
+            Err(e) => problems.push(e),
+        }
+        if self.format == WebhookFormat::Pagerduty && self.routing_key.as_deref().is_none_or(|k| k.trim().is_empty()) {
+            problems.push("the pagerduty format needs a routing_key".to_string());
+        }
+        for (name, value) in &self.headers {
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+                problems.push(format!("'{name}' is not a valid header name"));
+            }
+            if value.contains(['\r', '\n']) {
+                problems.push(format!("header '{name}' must not contain line breaks"));
+            }
+        }
+        if !self.policy && !self.health {
+            problems.push("neither policy nor health alerts are enabled".to_string());
+        }
+        if self.timeout.is_zero() {
+            problems.push("timeout must not be zero".to_string());
+        }
+        problems
+    }
+}
+
+/// Where an alert came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSource {
+    Policy,
+    Health,
+    Diag,
+}
+
+/// Ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A policy violation, health transition or diagnostic failure, as sent to webhooks.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub source: AlertSource,
+    /// What happened, in the CamelCase of Kubernetes event reasons, e.g. `XidError`.
+    pub reason: &'static str,
+    pub severity: Severity,
+    pub entity: Option<Entity>,
+    pub summary: String,
+    /// Usec since the epoch.
+    pub timestamp: i64,
+    /// Ends the alerts with the same `dedup_key`, e.g. a health watch that passes again.
+    pub resolved: bool,
+    /// Equal for repeats of one condition, e.g. `policy/gpu0/xid/79`.
+    pub dedup_key: String,
+    pub details: serde_json::Value,
+}
+
+impl Alert {
+    pub fn from_violation(violation: &PolicyViolation) -> Self {
+        use Severity::*;
+
+        let (reason, severity, what, key) = match violation.payload {
+            ViolationPayload::Dbe { location, count, .. } =>
+                ("DoubleBitEccError", Critical, format!("{count} double bit ECC error(s) in {location:?}"), "dbe".to_string()),
+            ViolationPayload::PciReplay { count, .. } =>
+                ("PcieReplays", Warning, format!("PCIe replay count at {count}"), "pci".to_string()),
+            ViolationPayload::RetiredPages { sbe_pages, dbe_pages, .. } =>
+                ("PagesRetired", Critical, format!("{sbe_pages} single and {dbe_pages} double bit ECC page(s) retired"),
+                 "retired_pages".to_string()),
+            ViolationPayload::Thermal { temperature, .. } =>
+                ("ThermalViolation", Warning, format!("temperature {temperature} C over the policy limit"), "thermal".to_string()),
+            ViolationPayload::Power { watts, .. } =>
+                ("PowerViolation", Warning, format!("power {watts} W over the policy limit"), "power".to_string()),
+            ViolationPayload::NvLink { field_id, count, .. } =>
+                ("NvLinkErrors", Critical, format!("{count} NVLink error(s), field {field_id}"), format!("nvlink/{field_id}")),
+            ViolationPayload::Xid { xid, .. } => ("XidError", Critical, format!("XID {xid}"), format!("xid/{xid}")),
+            ViolationPayload::Unknown { condition } =>
+                ("PolicyViolation", Warning,
+                 format!("policy condition {:?} violated", PolicyConditions::from_bits_retain(condition)),
+                 format!("condition/{condition}")),
+        };
+        Alert {
+            source: AlertSource::Policy,
+            reason,
+            severity,
+            entity: Some(Entity::gpu(violation.gpu_id)),
+            summary: format!("GPU {}: {what}", violation.gpu_id),
+            timestamp: violation.payload.timestamp().unwrap_or_else(now_micros),
+            resolved: false,
+            dedup_key: format!("policy/gpu{}/{key}", violation.gpu_id),
+            details: serde_json::to_value(violation).unwrap_or_default(),
+        }
+    }
+
+    /// `system` of `entity` went from `from` to `to`; back to `Pass` resolves the alert.
+    pub fn health_transition(entity: Option<Entity>, system: HealthSystems, from: HealthResult, to: HealthResult,
+                             message: &str) -> Self {
+        let subject = entity.map_or_else(|| "Host".to_string(), |e| e.to_string());
+        let mut summary = format!("{subject} {system}: {from} -> {to}");
+        if !message.is_empty() {
+            summary = format!("{summary}: {message}");
+        }
+        Alert {
+            source: AlertSource::Health,
+            reason: match to {
+                HealthResult::Pass => "HealthRecovered",
+                HealthResult::Warn => "HealthWarning",
+                HealthResult::Fail => "HealthFailure",
+            },
+            severity: match to {
+                HealthResult::Pass => Severity::Info,
+                HealthResult::Warn => Severity::Warning,
+                HealthResult::Fail => Severity::Critical,
+            },
+            entity,
+            summary,
+            timestamp: now_micros(),
+            resolved: to == HealthResult::Pass,
+            dedup_key: format!("health/{}/{system}", entity_key(entity)),
+            details: serde_json::json!({ "system": system.to_string(), "from": from, "to": to, "message": message }),
+        }
+    }
+
+    /// One alert per entity that warned or failed a test of `report`; a test without per-entity
+    /// results gives one for the host.
+    pub fn from_diag(report: &DiagReport) -> Vec<Self> {
+        let mut alerts = Vec::new();
+        for test in report.tests.iter().filter(|t| t.result >= DiagResult::Warn) {
+            let mut entities: Vec<(Option<Entity>, DiagResult)> = test.entities.iter()
+                .filter(|(_, result)| *result >= DiagResult::Warn)
+                .map(|(entity, result)| (Some(*entity), *result))
+                .collect();
+            if entities.is_empty() {
+                entities.push((None, test.result));
+            }
+            for (entity, result) in entities {
+                let errors: Vec<&str> = test.errors.iter()
+                    .filter(|e| entity.is_none() || e.entity.is_none() || e.entity == entity)
+                    .map(|e| e.message.as_str())
+                    .collect();
+                let subject = entity.map_or_else(|| "Host".to_string(), |e| e.to_string());
+                let mut summary = format!("{subject}: diagnostic {} {}", test.name, if result == DiagResult::Fail { "failed" } else { "warned" });
+                if let Some(first) = errors.first() {
+                    summary = format!("{summary}: {first}");
+                }
+                alerts.push(Alert {
+                    source: AlertSource::Diag,
+                    reason: if result == DiagResult::Fail { "DiagFailure" } else { "DiagWarning" },
+                    severity: if result == DiagResult::Fail { Severity::Critical } else { Severity::Warning },
+                    entity,
+                    summary,
+                    timestamp: now_micros(),
+                    resolved: false,
+                    dedup_key: format!("diag/{}/{}", entity_key(entity), test.name),
+                    details: serde_json::json!({ "test": test.name, "plugin": test.plugin, "result": result, "errors": errors,
+                                                 "dcgm_version": report.dcgm_version, "driver_version": report.driver_version }),
+                });
+            }
+        }
+        alerts
+    }
+}
+
+/// `gpu0`-style part of dedup keys.
+fn entity_key(entity: Option<Entity>) -> String {
+    entity.map_or_else(|| "host".to_string(), |e| format!("{}{}", e.group, e.id).to_lowercase())
+}
+
+fn now_micros() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64
+}
+
+/// Health results per entity and system between checks, turned into one alert per change.
+#[derive(Debug, Default)]
+pub struct HealthTransitions {
+    state: BTreeMap<(Option<Entity>, u32), (HealthResult, String)>,
+}
+
+impl HealthTransitions {
+    /// Alerts for what changed since the previous report; systems no longer reported have passed again.
+    pub fn update(&mut self, report: &HealthReport) -> Vec<Alert> {
+        let mut current = BTreeMap::new();
+        for incident in &report.incidents {
+            let entry = current.entry((incident.entity, incident.system.bits()))
+                .or_insert((incident.health, incident.message.clone()));
+            if incident.health > entry.0 {
+                *entry = (incident.health, incident.message.clone());
+            }
+        }
+        let mut alerts = Vec::new();
+        for ((entity, system), (health, message)) in &current {
+            let was = self.state.get(&(*entity, *system)).map_or(HealthResult::Pass, |(h, _)| *h);
+            if was != *health {
+                alerts.push(Alert::health_transition(*entity, HealthSystems::from_bits_retain(*system), was, *health, message));
+            }
+        }
+        for ((entity, system), (was, _)) in self.state.iter().filter(|(key, _)| !current.contains_key(*key)) {
+            alerts.push(Alert::health_transition(*entity, HealthSystems::from_bits_retain(*system), *was, HealthResult::Pass, ""));
+        }
+        self.state = current;
+        alerts
+    }
+}
+
+/// The alert sources on a connection: policy violations DCGM reports for all GPUs, and health watch
+/// transitions found on every `poll`. Policy violations are only reported for policies set on the
+/// GPUs. Drop it before reconnecting; the registration does not survive the connection.
+pub struct AlertMonitor {
+    _registration: Option<PolicyRegistration>,
+    violations: Receiver<PolicyViolation>,
+    health: Option<HealthTransitions>,
+}
+
+impl AlertMonitor {
+    /// Registers for policy violations when `policy` is set, and enables every health watch, updated
+    /// every `health_interval`, when one is given.
+    pub fn start(dcgm: &mut DcgmLibSafe, policy: bool, health_interval: Option<Duration>) -> Result<Self, DCGMError> {
+        let group = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+        let (sender, violations) = mpsc::channel();
+        let registration = if policy {
+            Some(dcgm.policy_register(group, PolicyConditions::all(), move |event| {
+                if let PolicyEvent::Violation(violation) = event {
+                    let _ = sender.send(violation);
+                }
+            })?)
+        } else {
+            None
+        };
+        if let Some(interval) = health_interval {
+            dcgm.health_set(group, HealthSystems::all(), interval, Duration::from_secs(600))?;
+        }
+        Ok(Self { _registration: registration, violations, health: health_interval.map(|_| HealthTransitions::default()) })
+    }
+
+    /// Checks health, then returns the health transitions and the violations reported since the last poll.
+    pub fn poll(&mut self, dcgm: &mut DcgmLibSafe) -> Result<Vec<Alert>, DCGMError> {
+        let mut alerts = match &mut self.health {
+            Some(health) => health.update(&dcgm.health_check(DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t)?),
+            None => Vec::new(),
+        };
+        alerts.extend(self.violations.try_iter().map(|v| Alert::from_violation(&v)));
+        Ok(alerts)
+    }
+}
+
+/// The request body `format` makes of `alert`. `host` names the node the alert is about.
+pub fn payload(format: WebhookFormat, routing_key: Option<&str>, host: &str, alert: &Alert) -> serde_json::Value {
+    match format {
+        WebhookFormat::Generic => {
+            let mut body = serde_json::to_value(alert).unwrap_or_default();
+            body["host"] = host.into();
+            body
+        }
+        WebhookFormat::Slack => {
+            let icon = match (alert.resolved, alert.severity) {
+                (true, _) | (_, Severity::Info) => ":white_check_mark:",
+                (_, Severity::Warning) => ":warning:",
+                (_, Severity::Critical) => ":rotating_light:",
+            };
+            serde_json::json!({ "text": format!("{icon} *{host}* {}", alert.summary) })
+        }
+        WebhookFormat::Pagerduty => serde_json::json!({
+            "routing_key": routing_key.unwrap_or_default(),
+            "event_action": if alert.resolved { "resolve" } else { "trigger" },
+            "dedup_key": format!("{host}/{}", alert.dedup_key),
+            "payload": {
+                "summary": format!("{host}: {}", alert.summary),
+                "source": host,
+                "severity": alert.severity,
+                "timestamp": rfc3339(alert.timestamp),
+                "component": alert.entity.map(|e| e.to_string()),
+                "class": alert.source,
+                "custom_details": alert.details,
+            },
+        }),
+    }
+}
+
+/// Sends an alert once per window. A resolution is only sent for a condition that was alerted, and
+/// lets the next trigger through straight away. Only delivered alerts count: `admit` decides and
+/// `delivered` records, so a failed delivery is tried again by the next trigger.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    sent: HashMap<String, (Severity, Instant)>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self { window, sent: HashMap::new() }
+    }
+
+    pub fn admit(&self, alert: &Alert, now: Instant) -> bool {
+        // kept past the window, so a late recovery still resolves what was sent
+        if alert.resolved {
+            return self.sent.contains_key(&alert.dedup_key);
+        }
+        match self.sent.get(&alert.dedup_key) {
+            // an escalation is news even within the window
+            Some((severity, at)) => *severity < alert.severity || now.saturating_duration_since(*at) >= self.window,
+            None => true,
+        }
+    }
+
+    /// Records that `alert` reached its destination at `now`.
+    pub fn delivered(&mut self, alert: &Alert, now: Instant) {
+        if alert.resolved {
+            self.sent.remove(&alert.dedup_key);
+        } else {
+            self.sent.insert(alert.dedup_key.clone(), (alert.severity, now));
+        }
+    }
+}
+
+/// A running webhook sink. Alerts are delivered in order by a background thread, so `send` never
+/// waits on the network. Dropping the handle delivers what is queued without further retries.
+pub struct WebhookSink {
+    config: WebhookConfig,
+    queue: Option<Sender<Alert>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WebhookSink {
+    pub fn start(config: &WebhookConfig) -> Result<Self, DCGMError> {
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(DCGMError::from(format!("invalid webhook: {}", problems.join("; "))));
+        }
+        let url = Url::parse(&config.url).map_err(DCGMError::from)?;
+        let ca_file = config.ca_file.as_deref().unwrap_or(Path::new(SYSTEM_CA_FILE));
+        let tls = if url.https { Some(client_tls(ca_file)?) } else { None };
+        let (queue, alerts) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (worker_config, worker_stop) = (config.clone(), stop.clone());
+        let thread = std::thread::Builder::new()
+            .name("dcgm-webhook".into())
+            .spawn(move || deliver(&worker_config, &url, tls.as_ref(), alerts, &worker_stop))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn webhook sink: {e}")))?;
+        Ok(Self { config: config.clone(), queue: Some(queue), stop, thread: Some(thread) })
+    }
+
+    pub fn config(&self) -> &WebhookConfig {
+        &self.config
+    }
+
+    /// Queues `alert` unless the webhook does not take alerts of its source.
+    pub fn send(&self, alert: &Alert) {
+        let wanted = match alert.source {
+            AlertSource::Policy => self.config.policy,
+            AlertSource::Health => self.config.health,
+            AlertSource::Diag => true,
+        };
+        if let Some(queue) = self.queue.as_ref().filter(|_| wanted) {
+            let _ = queue.send(alert.clone());
+        }
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // closing the queue ends the delivery loop once it is drained
+        self.queue.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn deliver(config: &WebhookConfig, url: &Url, tls: Option<&ClientTls>, alerts: Receiver<Alert>, stop: &AtomicBool) {
+    let host = local_hostname().unwrap_or_else(|| "rust-dcgm".to_string());
+    let mut dedup = Deduplicator::new(config.dedup_window);
+    for alert in alerts {
+        if !dedup.admit(&alert, Instant::now()) {
+            tracing::debug!("Not sending duplicate alert '{}'", alert.summary);
+            continue;
+        }
+        let body = payload(config.format, config.routing_key.as_deref(), &host, &alert).to_string();
+        let mut backoff = Duration::from_secs(1);
+        let mut delivered = false;
+        for attempt in 0..=config.retries {
+            let request = Outgoing {
+                method: "POST",
+                url,
+                content_type: "application/json",
+                headers: &config.headers,
+                body: body.as_bytes(),
+                timeout: config.timeout,
+            };
+            let error = match send(&request, tls) {
+                Ok(status) if (200..300).contains(&status) => {
+                    delivered = true;
+                    break;
+                }
+                Ok(status) if status != 429 && status < 500 => {
+                    tracing::warn!("Webhook {} rejected alert '{}' with HTTP {status}", url.authority(), alert.summary);
+                    break;
+                }
+                Ok(status) => format!("HTTP {status}"),
+                Err(e) => e.to_string(),
+            };
+            if attempt == config.retries || stop.load(Ordering::Relaxed) {
+                tracing::warn!("Failed to deliver alert '{}' to {}: {error}", alert.summary, url.authority());
+                break;
+            }
+            tracing::debug!("Delivering alert to {} failed, retrying in {backoff:?}: {error}", url.authority());
+            let resume = Instant::now() + backoff;
+            while Instant::now() < resume && !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(resume.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)));
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        if delivered {
+            dedup.delivered(&alert, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(severity: Severity, resolved: bool) -> Alert {
+        Alert { source: AlertSource::Health, reason: "HealthFailure", severity, entity: Some(Entity::gpu(0)),
+                summary: "GPU0 failed".to_string(), timestamp: 0, resolved, dedup_key: "health/gpu0/mem".to_string(),
+                details: serde_json::Value::Null }
+    }
+
+    #[test]
+    fn undelivered_alerts_are_admitted_again() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60));
+        let (raised, now) = (alert(Severity::Warning, false), Instant::now());
+        assert!(dedup.admit(&raised, now));
+        // the first delivery failed, so the repeat is still news
+        assert!(dedup.admit(&raised, now + Duration::from_secs(1)));
+        dedup.delivered(&raised, now + Duration::from_secs(1));
+        assert!(!dedup.admit(&raised, now + Duration::from_secs(2)));
+        assert!(dedup.admit(&raised, now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn escalations_and_resolutions_follow_what_was_delivered() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let resolved = alert(Severity::Warning, true);
+        assert!(!dedup.admit(&resolved, now));
+
+        dedup.delivered(&alert(Severity::Warning, false), now);
+        assert!(dedup.admit(&alert(Severity::Critical, false), now));
+        assert!(!dedup.admit(&alert(Severity::Info, false), now));
+        assert!(dedup.admit(&resolved, now));
+        // a resolution that was not delivered leaves the alert standing
+        assert!(dedup.admit(&resolved, now));
+        dedup.delivered(&resolved, now);
+        assert!(!dedup.admit(&resolved, now));
+        assert!(dedup.admit(&alert(Severity::Warning, false), now));
+    }
+}
@@ -0,0 +1,80 @@
+use super::bindings::*;
+use super::{c_chars_to_string, DCGMError, DcgmLibSafe};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long cached device attributes are trusted by default. Identifiers never change for a GPU id,
+/// but the current power limit and memory usage do, so entries are refreshed now and then.
+pub const DEFAULT_ATTRIBUTE_TTL: Duration = Duration::from_secs(60);
+
+/// Device attributes per GPU id, shared by every `share()` of a connection.
+///
+/// Entries are boxed: `dcgmDeviceAttributes_t` is several KiB and a host can have many GPUs.
+pub(crate) struct AttributeCache {
+    ttl: Option<Duration>,
+    entries: HashMap<u32, (Instant, Box<dcgmDeviceAttributes_t>)>,
+}
+
+impl Default for AttributeCache {
+    fn default() -> Self {
+        Self { ttl: Some(DEFAULT_ATTRIBUTE_TTL), entries: HashMap::new() }
+    }
+}
+
+impl AttributeCache {
+    fn get(&self, gpu_id: u32) -> Option<dcgmDeviceAttributes_t> {
+        let (fetched, attributes) = self.entries.get(&gpu_id)?;
+        match self.ttl {
+            Some(ttl) if fetched.elapsed() >= ttl => None,
+            _ => Some(**attributes),
+        }
+    }
+
+    fn insert(&mut self, gpu_id: u32, attributes: dcgmDeviceAttributes_t) {
+        self.entries.insert(gpu_id, (Instant::now(), Box::new(attributes)));
+    }
+
+    fn invalidate(&mut self, gpu_id: Option<u32>) {
+        match gpu_id {
+            Some(gpu_id) => { self.entries.remove(&gpu_id); }
+            None => self.entries.clear(),
+        }
+    }
+}
+
+impl DcgmLibSafe {
+    /// Attributes of one GPU, from the connection's cache when the entry is younger than the TTL.
+    /// Use `getDeviceAttributes` to always query DCGM.
+    pub fn device_attributes(&mut self, gpuId: u32) -> Result<dcgmDeviceAttributes_t, DCGMError>{
+        if let Some(attributes) = self.attribute_cache().get(gpuId) {
+            return Ok(attributes);
+        }
+        let attributes = self.getDeviceAttributes(gpuId)?;
+        self.attribute_cache().insert(gpuId, attributes);
+        Ok(attributes)
+    }
+
+    /// PCI bus id of one GPU, e.g. `00000000:3B:00.0`.
+    pub fn device_bus_id(&mut self, gpuId: u32) -> Result<String, DCGMError>{
+        Ok(c_chars_to_string(&self.device_attributes(gpuId)?.identifiers.pciBusId))
+    }
+
+    /// Drops the cached attributes of one GPU, e.g. after it was reset or reconfigured.
+    pub fn invalidate_attributes(&mut self, gpuId: u32) {
+        self.attribute_cache().invalidate(Some(gpuId));
+    }
+
+    /// Drops every cached device attribute, e.g. after GPUs were added or removed.
+    pub fn invalidate(&mut self) {
+        self.attribute_cache().invalidate(None);
+    }
+
+    /// How long cached attributes are used before being fetched again; None keeps them until invalidated.
+    pub fn set_attribute_ttl(&mut self, ttl: Option<Duration>) {
+        self.attribute_cache().ttl = ttl;
+    }
+
+    fn attribute_cache(&self) -> std::sync::MutexGuard<'_, AttributeCache> {
+        self.attributes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,287 @@
+use super::bindings::*;
+use super::diag::{DiagLevel, DiagOptions, DiagParameter, DiagReport, DiagResult};
+use super::entity::{Entity, EntityGroup};
+use super::health::{HealthIncident, HealthResult, HealthSystems};
+use super::latest::LatestValuesQuery;
+use super::watch::{unique_name, WatchHandle, WatchOptions};
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct BurnInOptions {
+    /// Diagnostic level run back to back for the whole burn-in; Long or ExtraLong for acceptance.
+    pub level: DiagLevel,
+    /// Plugin parameters of every diag run.
+    pub parameters: Vec<DiagParameter>,
+    /// Health systems watched while the diagnostics run.
+    pub health: HealthSystems,
+    /// How often health, temperature and power are checked.
+    pub monitor_interval: Duration,
+    /// A GPU hotter than this (°C) at any check fails the burn-in.
+    pub max_temperature: Option<f64>,
+    /// A GPU drawing more than this (W) at any check fails the burn-in.
+    pub max_power: Option<f64>,
+}
+
+impl Default for BurnInOptions {
+    fn default() -> Self {
+        Self {
+            level: DiagLevel::Long,
+            parameters: Vec::new(),
+            health: HealthSystems::all(),
+            monitor_interval: Duration::from_secs(5),
+            max_temperature: None,
+            max_power: None,
+        }
+    }
+}
+
+/// Consolidated burn-in outcome of one GPU.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GpuBurnIn {
+    pub gpu_id: u32,
+    /// Fail when any diag test or health check failed or a limit was exceeded, Warn on warnings.
+    pub result: DiagResult,
+    /// Worst diag result over every run.
+    pub diag: DiagResult,
+    /// Worst health result seen while monitoring.
+    pub health: HealthResult,
+    pub max_temperature: Option<f64>,
+    pub max_power: Option<f64>,
+    /// Why the GPU did not pass, one line per distinct problem.
+    pub reasons: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BurnInReport {
+    pub elapsed: Duration,
+    pub diag_runs: Vec<DiagReport>,
+    /// Distinct health incidents seen while monitoring.
+    pub incidents: Vec<HealthIncident>,
+    pub gpus: Vec<GpuBurnIn>,
+}
+
+impl BurnInReport {
+    pub fn passed(&self) -> bool {
+        self.gpus.iter().all(|g| g.result < DiagResult::Warn)
+    }
+
+    pub fn failed_gpus(&self) -> Vec<u32> {
+        self.gpus.iter().filter(|g| g.result >= DiagResult::Warn).map(|g| g.gpu_id).collect()
+    }
+}
+
+impl fmt::Display for BurnInReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Burn-in: {} diag run(s) in {}s", self.diag_runs.len(), self.elapsed.as_secs())?;
+        for gpu in &self.gpus {
+            let reading = |v: Option<f64>, unit: &str| v.map(|v| format!("{v:.0}{unit}")).unwrap_or_else(|| "n/a".into());
+            writeln!(f, "GPU {:<3} {:<8} diag {:<8} health {:<8} max {} / {}", gpu.gpu_id, gpu.result.to_string(),
+                     gpu.diag.to_string(), gpu.health.to_string(), reading(gpu.max_temperature, "C"), reading(gpu.max_power, "W"))?;
+            for reason in &gpu.reasons {
+                writeln!(f, "    {reason}")?;
+            }
+        }
+        write!(f, "Overall {}", if self.passed() { "Pass" } else { "Fail" })
+    }
+}
+
+#[derive(Default)]
+struct GpuTracker {
+    health: Option<HealthResult>,
+    max_temperature: Option<f64>,
+    max_power: Option<f64>,
+}
+
+impl DcgmLibSafe {
+    /// `burn_in_with` with the default options: level 3 diagnostics, every health system.
+    pub fn burn_in(&mut self, group: dcgmGpuGrp_t, duration: Duration) -> Result<BurnInReport, DCGMError>{
+        self.burn_in_with(group, duration, &BurnInOptions::default())
+    }
+
+    /// Acceptance test of the GPUs of `group`: runs diagnostics back to back until `duration` has passed
+    /// (at least once, and a started run is always finished) while checking health, temperature and
+    /// power every `monitor_interval`, then folds everything into one result per GPU.
+    ///
+    /// Health watches for `options.health` are left enabled on `group` afterwards.
+    pub fn burn_in_with(&mut self, group: dcgmGpuGrp_t, duration: Duration, options: &BurnInOptions)
+                        -> Result<BurnInReport, DCGMError>{
+        if options.monitor_interval.is_zero() {
+            return Err(DCGMError::from("Burn-in monitor interval must not be zero"));
+        }
+        let gpus: Vec<u32> = self.getGroupEntities(group)?.into_iter()
+            .filter(|e| e.group == EntityGroup::Gpu)
+            .map(|e| e.id)
+            .collect();
+        if gpus.is_empty() {
+            return Err(DCGMError::from(format!("Group {group} has no GPUs")));
+        }
+        let diag_options = DiagOptions {
+            group,
+            level: options.level,
+            parameters: options.parameters.clone(),
+            ..DiagOptions::default()
+        };
+        // reject bad parameters now rather than after the monitors are set up
+        for parameter in &diag_options.parameters {
+            parameter.validate()?;
+        }
+
+        self.health_set(group, options.health, options.monitor_interval, duration.max(Duration::from_secs(300)))?;
+        let watch = self.watch_readings(group, options.monitor_interval)?;
+        let started = Instant::now();
+        let mut runner = self.share();
+        let diag = std::thread::Builder::new()
+            .name("dcgm-burn-in".into())
+            .spawn(move || {
+                let mut runs = Vec::new();
+                loop {
+                    runs.push(runner.run_diag(&diag_options)?);
+                    if started.elapsed() >= duration {
+                        return Ok::<_, DCGMError>(runs);
+                    }
+                }
+            });
+        let diag = match diag {
+            Ok(diag) => diag,
+            Err(e) => {
+                let _ = self.unwatch(watch);
+                return Err(DCGMError::from(format!("Failed to spawn burn-in thread: {e}")));
+            }
+        };
+
+        let entities: Vec<Entity> = gpus.iter().copied().map(Entity::gpu).collect();
+        let mut readings = LatestValuesQuery::new(&entities, &watch.fields);
+        let mut trackers: BTreeMap<u32, GpuTracker> = gpus.iter().map(|&id| (id, GpuTracker::default())).collect();
+        let mut incidents: Vec<HealthIncident> = Vec::new();
+        let mut next_check = Instant::now();
+        while !diag.is_finished() {
+            if Instant::now() < next_check {
+                std::thread::sleep(Duration::from_millis(200));
+                continue;
+            }
+            next_check = Instant::now() + options.monitor_interval;
+            self.monitor(group, &mut readings, &mut trackers, &mut incidents);
+        }
+        // one last look, so incidents raised at the very end are not missed
+        self.monitor(group, &mut readings, &mut trackers, &mut incidents);
+        if let Err(e) = self.unwatch(watch) {
+            tracing::warn!("Failed to remove the burn-in watches: {e}");
+        }
+
+        let runs = diag.join().map_err(|_| DCGMError::from("Burn-in diagnostics panicked"))??;
+        let gpus = trackers.into_iter()
+            .map(|(gpu_id, tracker)| consolidate(gpu_id, tracker, &runs, &incidents, options))
+            .collect();
+        Ok(BurnInReport { elapsed: started.elapsed(), diag_runs: runs, incidents, gpus })
+    }
+
+    fn watch_readings(&mut self, group: dcgmGpuGrp_t, interval: Duration) -> Result<WatchHandle, DCGMError>{
+        let mut fields = vec![DCGM_FI_DEV_GPU_TEMP as u16, DCGM_FI_DEV_POWER_USAGE as u16];
+        let field_group = self.fieldGroupCreate(&unique_name("burn-in"), &mut fields)?;
+        let options = WatchOptions { update_interval: interval, ..WatchOptions::default() };
+        if let Err(e) = self.watchFields(field_group, group, interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = self.fieldGroupDestroy(field_group);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group, fields, options })
+    }
+
+    /// One round of health and reading checks. Failures are logged and the burn-in goes on.
+    fn monitor(&mut self, group: dcgmGpuGrp_t, readings: &mut LatestValuesQuery, trackers: &mut BTreeMap<u32, GpuTracker>,
+               incidents: &mut Vec<HealthIncident>) {
+        match self.health_check(group) {
+            Ok(report) => {
+                for incident in report.incidents {
+                    let gpu = incident.entity.filter(|e| e.group == EntityGroup::Gpu).map(|e| e.id);
+                    if let Some(tracker) = gpu.and_then(|id| trackers.get_mut(&id)) {
+                        tracker.health = tracker.health.max(Some(incident.health));
+                    }
+                    let seen = incidents.iter().any(|i| i.entity == incident.entity && i.code == incident.code
+                                                        && i.message == incident.message);
+                    if !seen {
+                        incidents.push(incident);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Burn-in health check failed: {e}"),
+        }
+        match readings.collect(self) {
+            Ok(samples) => {
+                for sample in samples {
+                    let (Some(tracker), Some(value)) = (trackers.get_mut(&sample.entity_id), sample.value.as_f64()) else {
+                        continue;
+                    };
+                    let max = match sample.field_id as u32 {
+                        DCGM_FI_DEV_GPU_TEMP => &mut tracker.max_temperature,
+                        DCGM_FI_DEV_POWER_USAGE => &mut tracker.max_power,
+                        _ => continue,
+                    };
+                    *max = Some(max.map_or(value, |m| m.max(value)));
+                }
+            }
+            Err(e) => tracing::warn!("Burn-in temperature and power readings failed: {e}"),
+        }
+    }
+}
+
+fn consolidate(gpu_id: u32, tracker: GpuTracker, runs: &[DiagReport], incidents: &[HealthIncident],
+               options: &BurnInOptions) -> GpuBurnIn {
+    let entity = Entity::gpu(gpu_id);
+    let mut reasons = Vec::new();
+    let mut diag = DiagResult::Pass;
+    for test in runs.iter().flat_map(|r| &r.tests) {
+        // tests without per-entity results apply to the whole group
+        let result = match test.entities.iter().find(|(e, _)| *e == entity) {
+            Some(&(_, result)) => result,
+            None if test.entities.is_empty() => test.result,
+            None => continue,
+        };
+        diag = diag.max(result);
+        if result >= DiagResult::Warn {
+            let messages: Vec<&str> = test.errors.iter()
+                .filter(|e| e.entity.is_none() || e.entity == Some(entity))
+                .map(|e| e.message.as_str())
+                .collect();
+            let reason = format!("diag {}: {result}{}{}", test.name, if messages.is_empty() { "" } else { ": " }, messages.join("; "));
+            if !reasons.contains(&reason) {
+                reasons.push(reason);
+            }
+        }
+    }
+    let health = tracker.health.unwrap_or(HealthResult::Pass);
+    for incident in incidents.iter().filter(|i| i.entity == Some(entity) && i.health >= HealthResult::Warn) {
+        reasons.push(format!("health {}: {}", incident.system, incident.message));
+    }
+    let mut over_limit = false;
+    if let (Some(max), Some(limit)) = (tracker.max_temperature, options.max_temperature) {
+        if max > limit {
+            over_limit = true;
+            reasons.push(format!("temperature reached {max:.0}C, limit {limit:.0}C"));
+        }
+    }
+    if let (Some(max), Some(limit)) = (tracker.max_power, options.max_power) {
+        if max > limit {
+            over_limit = true;
+            reasons.push(format!("power reached {max:.0}W, limit {limit:.0}W"));
+        }
+    }
+    let from_health = match health {
+        HealthResult::Pass => DiagResult::Pass,
+        HealthResult::Warn => DiagResult::Warn,
+        HealthResult::Fail => DiagResult::Fail,
+    };
+    let mut result = diag.max(from_health);
+    if over_limit {
+        result = DiagResult::Fail;
+    }
+    // skipped and not-run tests alone do not make a GPU fail acceptance
+    if result < DiagResult::Warn {
+        result = DiagResult::Pass;
+    }
+    GpuBurnIn { gpu_id, result, diag, health, max_temperature: tracker.max_temperature, max_power: tracker.max_power, reasons }
+}
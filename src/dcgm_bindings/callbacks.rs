@@ -0,0 +1,18 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Runs user code invoked from a DCGM callback. A panic is caught and returned as its message, since
+/// unwinding out of an `extern "C"` trampoline would abort the process.
+pub(crate) fn catch_callback<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(panic_message)
+}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "callback panicked".to_string()
+    }
+}
@@ -0,0 +1,63 @@
+use super::bindings::*;
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+
+/// Which optional or version-gated entry points the loaded libdcgm exports. Symbols are resolved when
+/// the library is dlopened, so older installs simply report `false` here instead of failing to load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// `dcgmProfGetSupportedMetricGroups`, `dcgmProfPause` and `dcgmProfResume`.
+    pub profiling: bool,
+    /// `dcgmGetGpuInstanceHierarchy`.
+    pub mig: bool,
+    pub connect_v2: bool,
+    pub start_embedded_v2: bool,
+    pub values_since_v2: bool,
+    pub latest_values_v2: bool,
+    pub health_set_v2: bool,
+    pub policy_register_v2: bool,
+    pub action_validate_v2: bool,
+    pub nvlink_status: bool,
+    pub cpu_hierarchy: bool,
+    pub cpu_hierarchy_v2: bool,
+    pub workload_power_profiles: bool,
+    pub error_meta: bool,
+}
+
+impl Capabilities {
+    pub(crate) fn probe(dcgm: &DcgmLib) -> Self {
+        Self {
+            profiling: dcgm.dcgmProfGetSupportedMetricGroups.is_ok()
+                && dcgm.dcgmProfPause.is_ok()
+                && dcgm.dcgmProfResume.is_ok(),
+            mig: dcgm.dcgmGetGpuInstanceHierarchy.is_ok(),
+            connect_v2: dcgm.dcgmConnect_v2.is_ok(),
+            start_embedded_v2: dcgm.dcgmStartEmbedded_v2.is_ok(),
+            values_since_v2: dcgm.dcgmGetValuesSince_v2.is_ok(),
+            latest_values_v2: dcgm.dcgmGetLatestValues_v2.is_ok(),
+            health_set_v2: dcgm.dcgmHealthSet_v2.is_ok(),
+            policy_register_v2: dcgm.dcgmPolicyRegister_v2.is_ok(),
+            action_validate_v2: dcgm.dcgmActionValidate_v2.is_ok(),
+            nvlink_status: dcgm.dcgmGetNvLinkLinkStatus.is_ok(),
+            cpu_hierarchy: dcgm.dcgmGetCpuHierarchy.is_ok(),
+            cpu_hierarchy_v2: dcgm.dcgmGetCpuHierarchy_v2.is_ok(),
+            workload_power_profiles: dcgm.dcgmGetDeviceWorkloadPowerProfileInfo.is_ok(),
+            error_meta: dcgm.dcgmGetErrorMeta.is_ok(),
+        }
+    }
+
+    /// Returns a NotSupported error naming `what` when `present` is false, for early-outs before an FFI call.
+    pub fn require(present: bool, what: &str) -> Result<(), DCGMError> {
+        if present {
+            Ok(())
+        } else {
+            Err(DCGMError::not_supported(format!("{what} is not available in the loaded libdcgm")))
+        }
+    }
+}
+
+impl DcgmLibSafe {
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::probe(self.dcgm)
+    }
+}
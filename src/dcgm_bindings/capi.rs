@@ -0,0 +1,177 @@
+//! The C ABI of the `capi` feature, declared in `include/rust_dcgm.h`.
+//!
+//! Every function returns a `dcgmReturn_t` code: `DCGM_ST_OK`, the code libdcgm failed with, or the
+//! closest one for errors raised on this side. `rdcgm_last_error` has the message of the last failure
+//! on the calling thread. Strings handed out are JSON, owned by the caller and released with
+//! `rdcgm_string_free`. A panic never crosses the boundary; it is reported as `DCGM_ST_GENERIC_ERROR`.
+use super::bindings::*;
+use super::client::{Client, ConnectOptions, DcgmClient};
+use super::entity::Entity;
+use super::health::HealthSystems;
+use super::latest::decode_latest;
+use super::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
+use super::{DCGMError, DCGMErrorKind};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Duration;
+
+/// Opaque to C: `rdcgm_client`.
+pub struct RdcgmClient {
+    client: DcgmClient,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn error_code(e: &DCGMError) -> dcgmReturn_t {
+    if let Some(code) = e.code {
+        return code;
+    }
+    match e.kind {
+        DCGMErrorKind::Generic => dcgmReturn_enum_DCGM_ST_GENERIC_ERROR,
+        DCGMErrorKind::NotSupported => dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED,
+        DCGMErrorKind::PermissionDenied => dcgmReturn_enum_DCGM_ST_NO_PERMISSION,
+        DCGMErrorKind::Timeout => dcgmReturn_enum_DCGM_ST_TIMEOUT,
+        DCGMErrorKind::Disconnected => dcgmReturn_enum_DCGM_ST_CONNECTION_NOT_VALID,
+    }
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `f`, turning its error or panic into a return code and the thread's last error.
+fn call(f: impl FnOnce() -> Result<(), DCGMError>) -> dcgmReturn_t {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => dcgmReturn_enum_DCGM_ST_OK,
+        Ok(Err(e)) => {
+            set_last_error(&e.to_string());
+            error_code(&e)
+        }
+        Err(_) => {
+            set_last_error("rust-dcgm panicked");
+            dcgmReturn_enum_DCGM_ST_GENERIC_ERROR
+        }
+    }
+}
+
+fn bad_param(what: &str) -> DCGMError {
+    let mut e = DCGMError::from(format!("{what} must not be NULL"));
+    e.code = Some(dcgmReturn_enum_DCGM_ST_BADPARAM);
+    e
+}
+
+/// Hands `value` to C as a NUL-terminated JSON string.
+unsafe fn write_json<T: serde::Serialize + ?Sized>(value: &T, out: *mut *mut c_char) -> Result<(), DCGMError> {
+    let text = serde_json::to_string(value).map_err(|e| DCGMError::from(e.to_string()))?;
+    *out = CString::new(text).map_err(|e| DCGMError::from(e.to_string()))?.into_raw();
+    Ok(())
+}
+
+unsafe fn client_mut<'a>(client: *mut RdcgmClient) -> Result<&'a mut DcgmClient, DCGMError> {
+    client.as_mut().map(|c| &mut c.client).ok_or_else(|| bad_param("client"))
+}
+
+/// Connects to nv-hostengine at `address` (host[:port], or a socket path when `unix_socket` is
+/// non-zero), or starts a hostengine inside the process when `address` is NULL.
+///
+/// # Safety
+/// `address` is NULL or a NUL-terminated string; `out` points to writable storage.
+#[no_mangle]
+pub unsafe extern "C" fn rdcgm_connect(address: *const c_char, unix_socket: c_int, out: *mut *mut RdcgmClient) -> dcgmReturn_t {
+    call(|| {
+        if out.is_null() {
+            return Err(bad_param("out"));
+        }
+        let options = if address.is_null() {
+            ConnectOptions::Embedded
+        } else {
+            let address = CStr::from_ptr(address).to_str().map_err(|e| DCGMError::from(format!("address: {e}")))?;
+            ConnectOptions::Standalone { address: address.to_string(), unix_socket: unix_socket != 0, persist_after_disconnect: false }
+        };
+        let client = Client::new().connect(&options)?;
+        *out = Box::into_raw(Box::new(RdcgmClient { client }));
+        Ok(())
+    })
+}
+
+/// Removes the client's watches and groups, disconnects and frees it. NULL is ignored.
+///
+/// # Safety
+/// `client` is NULL or came from `rdcgm_connect` and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rdcgm_disconnect(client: *mut RdcgmClient) -> dcgmReturn_t {
+    if client.is_null() {
+        return dcgmReturn_enum_DCGM_ST_OK;
+    }
+    let client = Box::from_raw(client);
+    call(move || client.client.disconnect_graceful(DEFAULT_SHUTDOWN_TIMEOUT).map(drop))
+}
+
+/// Writes the live values of `fields` on every supported GPU to `json_out`, as an array of samples
+/// in the JSON form of the CLI. Nothing has to be watched first.
+///
+/// # Safety
+/// `client` came from `rdcgm_connect`; `fields` points to `count` field ids; `json_out` to writable
+/// storage.
+#[no_mangle]
+pub unsafe extern "C" fn rdcgm_snapshot(client: *mut RdcgmClient, fields: *const u16, count: usize,
+                                        json_out: *mut *mut c_char) -> dcgmReturn_t {
+    call(|| {
+        let dcgm = client_mut(client)?;
+        if (fields.is_null() && count > 0) || json_out.is_null() {
+            return Err(bad_param(if json_out.is_null() { "json_out" } else { "fields" }));
+        }
+        let mut fields = if count == 0 { Vec::new() } else { std::slice::from_raw_parts(fields, count).to_vec() };
+        let mut gpus: Vec<dcgmGroupEntityPair_t> = dcgm.getAllSupportedDevices()?.into_iter()
+            .map(|gpu| Entity::gpu(gpu).to_raw())
+            .collect();
+        let values = dcgm.entitiesGetLatestValues(&mut gpus, &mut fields, DCGM_FV_FLAG_LIVE_DATA)?;
+        let mut samples = Vec::new();
+        decode_latest(&values, &mut samples);
+        write_json(&samples, json_out)
+    })
+}
+
+/// Writes the health report of `group` to `json_out`. Non-zero `systems` (`dcgmHealthSystems_t`
+/// bits, `DCGM_HEALTH_WATCH_ALL` for all) are enabled on the group first, replacing what was
+/// watched there; zero checks what is already enabled.
+///
+/// # Safety
+/// `client` came from `rdcgm_connect`; `json_out` points to writable storage.
+#[no_mangle]
+pub unsafe extern "C" fn rdcgm_health_check(client: *mut RdcgmClient, group: dcgmGpuGrp_t, systems: u32,
+                                            json_out: *mut *mut c_char) -> dcgmReturn_t {
+    call(|| {
+        let dcgm = client_mut(client)?;
+        if json_out.is_null() {
+            return Err(bad_param("json_out"));
+        }
+        if systems != 0 {
+            dcgm.health_set(group, HealthSystems::from_bits_truncate(systems), Duration::from_secs(5),
+                            Duration::from_secs(600))?;
+        }
+        write_json(&dcgm.health_check(group)?, json_out)
+    })
+}
+
+/// Message of the last failed call on this thread; empty when there was none. Valid until the next
+/// call on the thread.
+#[no_mangle]
+pub extern "C" fn rdcgm_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Frees a string returned by this library. NULL is ignored.
+///
+/// # Safety
+/// `s` is NULL or came from this library and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rdcgm_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
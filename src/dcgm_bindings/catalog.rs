@@ -0,0 +1,242 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::import::{metric_field_id, EXPORTER_FIELDS};
+use super::latest::LatestValuesQuery;
+use super::rates::{rate_field_id, DEFAULT_COUNTER_FIELDS};
+use super::samples::decode_field_value_v2;
+use super::units::{field_semantics, field_unit, FieldSemantics, RawUnit};
+use super::watch::WatchOptions;
+use super::{c_chars_to_string, DCGMError, DcgmLib, DcgmLibSafe, DCGM_LIB};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Int64,
+    Double,
+    String,
+    Binary,
+    Timestamp,
+}
+
+impl FieldType {
+    pub fn from_raw(t: u8) -> Option<Self> {
+        match t {
+            DCGM_FT_INT64 => Some(FieldType::Int64),
+            DCGM_FT_DOUBLE => Some(FieldType::Double),
+            DCGM_FT_STRING => Some(FieldType::String),
+            DCGM_FT_BINARY => Some(FieldType::Binary),
+            DCGM_FT_TIMESTAMP => Some(FieldType::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            FieldType::Int64 => "int64",
+            FieldType::Double => "double",
+            FieldType::String => "string",
+            FieldType::Binary => "binary",
+            FieldType::Timestamp => "timestamp",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "int64" | "int" => Ok(FieldType::Int64),
+            "double" | "float" => Ok(FieldType::Double),
+            "string" | "str" => Ok(FieldType::String),
+            "binary" | "blob" => Ok(FieldType::Binary),
+            "timestamp" => Ok(FieldType::Timestamp),
+            other => Err(DCGMError::from(format!("Unknown field type {other}"))),
+        }
+    }
+}
+
+/// Metadata of one field id, from the DCGM field table plus what this crate knows about it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldInfo {
+    pub id: u16,
+    /// DCGM tag, e.g. `gpu_temp`; accepted wherever a field name is configured.
+    pub tag: String,
+    /// Column header `dcgmi dmon` uses.
+    pub short_name: String,
+    pub field_type: Option<FieldType>,
+    /// Unit as DCGM prints it, e.g. `C` or `W`; empty when DCGM has none.
+    pub dcgm_unit: String,
+    /// Unit this crate decodes the raw value in, when known; see `units::normalize`.
+    pub unit: Option<RawUnit>,
+    /// Gauge, counter, enum or string; decides how exporters type the metric.
+    pub semantics: FieldSemantics,
+    /// Entity type the field is queried on; None for global fields.
+    pub entity_level: Option<EntityGroup>,
+    /// Computed by this crate (e.g. per-second rates of counters) rather than reported by DCGM.
+    pub derived: bool,
+    /// For derived fields, the field they are computed from.
+    pub source_field: Option<u16>,
+}
+
+/// Every field id DCGM knows, ordered by id, followed by the rate fields `RateComputer` derives
+/// from `DEFAULT_COUNTER_FIELDS`. Only needs the DCGM library, not a hostengine connection.
+pub fn field_catalog() -> Result<Vec<FieldInfo>, DCGMError> {
+    let dcgm = DCGM_LIB.as_ref().map_err(Clone::clone)?;
+    unsafe { dcgm.DcgmFieldsInit() };
+    let mut catalog: Vec<FieldInfo> = (1..DCGM_FI_MAX_FIELDS as u16).filter_map(|id| lookup(dcgm, id)).collect();
+    let rates: Vec<FieldInfo> = DEFAULT_COUNTER_FIELDS.iter()
+        .filter_map(|&source| catalog.iter().find(|f| f.id == source))
+        .map(|source| FieldInfo {
+            id: rate_field_id(source.id),
+            tag: format!("{}_rate", source.tag),
+            short_name: String::new(),
+            field_type: Some(FieldType::Double),
+            dcgm_unit: String::new(),
+            unit: None,
+            semantics: FieldSemantics::Gauge,
+            entity_level: source.entity_level,
+            derived: true,
+            source_field: Some(source.id),
+        })
+        .collect();
+    catalog.extend(rates);
+    Ok(catalog)
+}
+
+/// Metadata of a single DCGM field, None for ids DCGM does not define.
+pub fn field_info(field_id: u16) -> Result<Option<FieldInfo>, DCGMError> {
+    let dcgm = DCGM_LIB.as_ref().map_err(Clone::clone)?;
+    unsafe { dcgm.DcgmFieldsInit() };
+    Ok(lookup(dcgm, field_id))
+}
+
+/// Resolves a field given as a numeric id, a DCGM tag in any case (`gpu_temp`) or a `DCGM_FI_*`
+/// constant name (`DCGM_FI_DEV_GPU_TEMP`). Ids are taken as they are; unknown names get an error
+/// naming the closest known ones.
+pub fn resolve_field(spec: &str) -> Result<u16, DCGMError> {
+    let spec = spec.trim();
+    if let Ok(id) = spec.parse::<u16>() {
+        return Ok(id);
+    }
+    let catalog = field_catalog()?;
+    if let Some(field) = catalog.iter().find(|f| f.tag.eq_ignore_ascii_case(spec)) {
+        return Ok(field.id);
+    }
+    let upper = spec.to_ascii_uppercase();
+    if let Some(id) = metric_field_id(&upper) {
+        return Ok(id);
+    }
+    // most constants are the tag behind a DEV_ or PROF_ prefix, e.g. DCGM_FI_PROF_SM_ACTIVE and sm_active
+    let bare = ["DCGM_FI_DEV_", "DCGM_FI_PROF_", "DCGM_FI_"].iter().find_map(|prefix| upper.strip_prefix(prefix));
+    if let Some(field) = bare.and_then(|bare| catalog.iter().find(|f| f.tag.eq_ignore_ascii_case(bare))) {
+        return Ok(field.id);
+    }
+
+    let constant = upper.starts_with("DCGM_");
+    let candidates: Vec<String> = if constant {
+        EXPORTER_FIELDS.iter().map(|(name, _)| name.to_string()).collect()
+    } else {
+        catalog.iter().map(|f| f.tag.clone()).collect()
+    };
+    // constants are upper case and tags lower case, so compare in the case of the candidates
+    let wanted = if constant { upper } else { spec.to_ascii_lowercase() };
+    let mut suggestions: Vec<(usize, &String)> = candidates.iter()
+        .map(|c| (edit_distance(&wanted, c), c))
+        .filter(|(distance, _)| *distance <= (wanted.len() / 4).max(2))
+        .collect();
+    suggestions.sort();
+    let suggestions: Vec<&str> = suggestions.iter().take(3).map(|(_, c)| c.as_str()).collect();
+    if suggestions.is_empty() {
+        Err(DCGMError::from(format!("unknown field '{spec}'")))
+    } else {
+        Err(DCGMError::from(format!("unknown field '{spec}', did you mean {}?", suggestions.join(" or "))))
+    }
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb)).min(row[j] + 1).min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// GPUs of the connected hostengine that report a value for each of `fields`, by field id; fields no
+/// GPU supports are left out. The fields are watched on all GPUs for a single update and unwatched
+/// again, so this costs one sampling round per `DCGM_MAX_FIELD_IDS_PER_FIELD_GROUP` fields.
+pub fn supported_on_gpus(dcgm: &mut DcgmLibSafe, fields: &[u16]) -> Result<BTreeMap<u16, Vec<u32>>, DCGMError> {
+    let gpus: Vec<Entity> = dcgm.getAllSupportedDevices()?.into_iter().map(Entity::gpu).collect();
+    let mut supported: BTreeMap<u16, Vec<u32>> = BTreeMap::new();
+    if gpus.is_empty() {
+        return Ok(supported);
+    }
+    for chunk in fields.chunks(DCGM_MAX_FIELD_IDS_PER_FIELD_GROUP as usize) {
+        let watch = dcgm.watch_all_gpus(chunk, &WatchOptions::default())?;
+        let values = LatestValuesQuery::new(&gpus, chunk).fetch(dcgm).map(|values| {
+            // not supported shows up either as the entry's status or as a blank value
+            values.iter()
+                .filter_map(|v| decode_field_value_v2(v).ok())
+                .filter(|sample| !sample.value.is_blank())
+                .map(|sample| (sample.field_id, sample.entity_id))
+                .collect::<Vec<_>>()
+        });
+        if let Err(e) = dcgm.unwatch(watch) {
+            tracing::warn!("Failed to unwatch the probed fields: {e}");
+        }
+        for (field_id, gpu) in values? {
+            supported.entry(field_id).or_default().push(gpu);
+        }
+    }
+    Ok(supported)
+}
+
+/// `field_catalog` as pretty-printed JSON.
+pub fn field_catalog_json() -> Result<String, DCGMError> {
+    serde_json::to_string_pretty(&field_catalog()?).map_err(|e| DCGMError::from(e.to_string()))
+}
+
+fn lookup(dcgm: &DcgmLib, field_id: u16) -> Option<FieldInfo> {
+    unsafe {
+        let meta = dcgm.DcgmFieldGetById(field_id);
+        if meta.is_null() {
+            return None;
+        }
+        let meta = &*meta;
+        let (short_name, dcgm_unit) = match meta.valueFormat.as_ref() {
+            Some(format) => (c_chars_to_string(&format.shortName), c_chars_to_string(&format.unit)),
+            None => (String::new(), String::new()),
+        };
+        let field_type = FieldType::from_raw(meta.fieldType as u8);
+        Some(FieldInfo {
+            id: meta.fieldId,
+            tag: c_chars_to_string(&meta.tag),
+            short_name: short_name.trim().to_string(),
+            field_type,
+            dcgm_unit: dcgm_unit.trim().to_string(),
+            unit: field_unit(meta.fieldId),
+            semantics: match field_type {
+                Some(FieldType::String | FieldType::Binary) => FieldSemantics::String,
+                _ => field_semantics(meta.fieldId),
+            },
+            entity_level: if meta.scope as u32 == DCGM_FS_GLOBAL { None } else { EntityGroup::try_from(meta.entityLevel).ok() },
+            derived: false,
+            source_field: None,
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use super::samples::{FieldValue, Sample, SampleKey};
+use std::collections::{BTreeMap, HashMap};
+
+/// Emitted when a series' value differs from the last one reported for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeEvent {
+    pub key: SampleKey,
+    /// None for the first value seen for the series.
+    pub previous: Option<FieldValue>,
+    pub current: FieldValue,
+    pub timestamp: i64,
+}
+
+/// Suppresses samples whose value has not changed since the last reported one. Numeric fields can be
+/// given an epsilon so jitter below it is not reported; everything else compares exactly.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeDetector {
+    epsilons: HashMap<u16, f64>,
+    default_epsilon: f64,
+    last: BTreeMap<SampleKey, FieldValue>,
+}
+
+impl ChangeDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Epsilon for numeric fields without a per-field override. 0 reports every change.
+    pub fn with_default_epsilon(mut self, epsilon: f64) -> Self {
+        self.default_epsilon = epsilon.abs();
+        self
+    }
+
+    pub fn set_epsilon(&mut self, field_id: u16, epsilon: f64) {
+        self.epsilons.insert(field_id, epsilon.abs());
+    }
+
+    fn changed(&self, field_id: u16, previous: &FieldValue, current: &FieldValue) -> bool {
+        match (previous.as_f64(), current.as_f64()) {
+            (Some(a), Some(b)) => {
+                let epsilon = self.epsilons.get(&field_id).copied().unwrap_or(self.default_epsilon);
+                if epsilon == 0.0 { a != b } else { (a - b).abs() >= epsilon }
+            }
+            _ => previous != current,
+        }
+    }
+
+    /// Returns an event if `sample` differs from the last reported value of its series.
+    pub fn observe(&mut self, sample: &Sample) -> Option<ChangeEvent> {
+        let key = sample.key();
+        let previous = self.last.get(&key);
+        if let Some(previous) = previous {
+            if !self.changed(sample.field_id, previous, &sample.value) {
+                return None;
+            }
+        }
+        let previous = self.last.insert(key, sample.value.clone());
+        Some(ChangeEvent { key, previous, current: sample.value.clone(), timestamp: sample.timestamp })
+    }
+
+    pub fn observe_all<'a, I: IntoIterator<Item = &'a Sample>>(&mut self, samples: I) -> Vec<ChangeEvent> {
+        samples.into_iter().filter_map(|s| self.observe(s)).collect()
+    }
+
+    /// Last reported value of a series.
+    pub fn current(&self, key: &SampleKey) -> Option<&FieldValue> {
+        self.last.get(key)
+    }
+
+    /// Forgets a series so its next value is reported as new.
+    pub fn reset(&mut self, key: &SampleKey) {
+        self.last.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
@@ -0,0 +1,171 @@
+use super::hostengine::HostengineOptions;
+use super::shutdown::ShutdownReport;
+#[cfg(feature = "simulation")]
+use super::simulation::Simulation;
+use super::{DCGMError, DcgmLibSafe, Mode};
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+/// Not connected to a hostengine yet, or no longer. Only the connect methods exist in this state.
+#[derive(Debug, Default)]
+pub struct Disconnected;
+
+/// Connected; the whole `DcgmLibSafe` API is reachable through `Deref`.
+pub struct Connected {
+    dcgm: DcgmLibSafe,
+}
+
+/// `DcgmLibSafe` with the connection state in its type, so field, group and watch calls made before
+/// connecting or after disconnecting do not compile:
+///
+/// ```no_run
+/// # use rust_dcgm::dcgm_bindings::client::Client;
+/// let mut client = Client::new().connect_embedded()?;
+/// let gpus = client.getAllSupportedDevices()?;
+/// let client = client.disconnect()?;
+/// # Ok::<(), rust_dcgm::dcgm_bindings::DCGMError>(())
+/// ```
+///
+/// ```compile_fail
+/// # use rust_dcgm::dcgm_bindings::client::Client;
+/// let mut client = Client::new();
+/// let gpus = client.getAllSupportedDevices(); // not connected
+/// ```
+pub struct Client<S> {
+    state: S,
+}
+
+/// A connected `Client`, the usual way to hold a DCGM connection.
+pub type DcgmClient = Client<Connected>;
+
+/// How `Client::connect` reaches a hostengine.
+#[derive(Clone, Debug)]
+pub enum ConnectOptions {
+    /// A hostengine inside this process. Needs root or access to /dev/nvidia*.
+    Embedded,
+    /// A running nv-hostengine at `address`: host[:port], or a socket path with `unix_socket`. With
+    /// `persist_after_disconnect` the hostengine keeps the groups and watches of the connection after
+    /// it goes away, so that `disconnect_with` can hand them to a successor.
+    Standalone { address: String, unix_socket: bool, persist_after_disconnect: bool },
+    /// An nv-hostengine this process starts, restarts when it exits and stops on shutdown.
+    StartHostengine(HostengineOptions),
+    /// A hostengine replaying a scripted scenario; needs the `simulation` feature.
+    #[cfg(feature = "simulation")]
+    Simulated(Simulation),
+}
+
+impl ConnectOptions {
+    pub fn standalone(address: &str) -> Self {
+        ConnectOptions::Standalone { address: address.to_string(), unix_socket: false, persist_after_disconnect: false }
+    }
+
+    pub fn unix_socket(path: &str) -> Self {
+        ConnectOptions::Standalone { address: path.to_string(), unix_socket: true, persist_after_disconnect: false }
+    }
+
+    /// Sets `persist_after_disconnect` of a standalone connection; the other kinds cannot outlive this
+    /// process and are returned as they are.
+    pub fn with_persist_after_disconnect(mut self, persist: bool) -> Self {
+        if let ConnectOptions::Standalone { persist_after_disconnect, .. } = &mut self {
+            *persist_after_disconnect = persist;
+        }
+        self
+    }
+}
+
+/// A hostengine on the default port of this host.
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions::standalone("127.0.0.1:5555")
+    }
+}
+
+impl Client<Disconnected> {
+    pub fn new() -> Self {
+        Self { state: Disconnected }
+    }
+
+    pub fn connect(self, options: &ConnectOptions) -> Result<Client<Connected>, DCGMError> {
+        let dcgm = match options {
+            ConnectOptions::Embedded => DcgmLibSafe::new(Mode::Embedded, &[])?,
+            ConnectOptions::Standalone { address, unix_socket, persist_after_disconnect } => {
+                let flag = |set: bool| if set { "1" } else { "0" };
+                DcgmLibSafe::new(Mode::Standalone, &[address, flag(*unix_socket), flag(*persist_after_disconnect)])?
+            }
+            ConnectOptions::StartHostengine(hostengine) => DcgmLibSafe::start_hostengine(hostengine.clone())?,
+            #[cfg(feature = "simulation")]
+            ConnectOptions::Simulated(simulation) => simulation.connect()?,
+        };
+        Ok(Client { state: Connected { dcgm } })
+    }
+
+    /// Starts a hostengine inside this process. Needs root or access to /dev/nvidia*.
+    pub fn connect_embedded(self) -> Result<Client<Connected>, DCGMError> {
+        self.connect(&ConnectOptions::Embedded)
+    }
+
+    /// Connects to a running nv-hostengine at `address`: host[:port], or a socket path with `unix_socket`.
+    pub fn connect_standalone(self, address: &str, unix_socket: bool) -> Result<Client<Connected>, DCGMError> {
+        self.connect(&ConnectOptions::Standalone { address: address.to_string(), unix_socket, persist_after_disconnect: false })
+    }
+}
+
+impl Default for Client<Disconnected> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client<Connected> {
+    /// `DcgmLibSafe::shutdown`; the client can be connected again afterwards.
+    pub fn disconnect(mut self) -> Result<Client<Disconnected>, DCGMError> {
+        self.state.dcgm.shutdown()?;
+        Ok(Client::new())
+    }
+
+    /// `DcgmLibSafe::shutdown_graceful`.
+    pub fn disconnect_graceful(mut self, timeout: Duration) -> Result<(Client<Disconnected>, ShutdownReport), DCGMError> {
+        let report = self.state.dcgm.shutdown_graceful(timeout)?;
+        Ok((Client::new(), report))
+    }
+
+    /// `DcgmLibSafe::shutdown_with`: hands the groups and watches of the connection to a successor with
+    /// `keep_watches`, removes them otherwise. A refused hand-off fails before anything is changed, so
+    /// check `can_keep_watches` first to go on using the connection in that case. `disconnect()` does
+    /// neither and leaves them to the hostengine.
+    pub fn disconnect_with(mut self, keep_watches: bool, timeout: Duration) -> Result<(Client<Disconnected>, ShutdownReport), DCGMError> {
+        let report = self.state.dcgm.shutdown_with(keep_watches, timeout)?;
+        Ok((Client::new(), report))
+    }
+
+    /// The untyped handle, for code written against `DcgmLibSafe`. Shutting it down is up to the caller.
+    pub fn into_inner(self) -> DcgmLibSafe {
+        self.state.dcgm
+    }
+}
+
+impl TryFrom<DcgmLibSafe> for Client<Connected> {
+    type Error = DCGMError;
+
+    /// Wraps an existing connection; fails when it was already shut down.
+    fn try_from(dcgm: DcgmLibSafe) -> Result<Self, DCGMError> {
+        if dcgm.is_disconnected() {
+            return Err(DCGMError::disconnected("The DCGM connection was shut down"));
+        }
+        Ok(Client { state: Connected { dcgm } })
+    }
+}
+
+impl Deref for Client<Connected> {
+    type Target = DcgmLibSafe;
+
+    fn deref(&self) -> &DcgmLibSafe {
+        &self.state.dcgm
+    }
+}
+
+impl DerefMut for Client<Connected> {
+    fn deref_mut(&mut self) -> &mut DcgmLibSafe {
+        &mut self.state.dcgm
+    }
+}
@@ -0,0 +1,281 @@
+use super::bindings::*;
+use super::entity::EntityGroup;
+use super::health::{HealthResult, HealthSystems};
+use super::watch::WatchOptions;
+use super::{c_chars_to_string, DCGMError, DcgmLibSafe, Mode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long `connect_many` waits for a target that does not set its own timeout.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One connected standalone hostengine.
+pub struct HostConnection {
+    pub address: String,
+    pub client: DcgmLibSafe,
+}
+
+/// A hostengine for `connect_many` to dial.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectTarget {
+    /// `host[:port]`, or the socket path with `unix_socket`.
+    pub address: String,
+    pub unix_socket: bool,
+    pub timeout: Duration,
+}
+
+impl ConnectTarget {
+    pub fn new(address: &str) -> Self {
+        Self { address: address.to_string(), unix_socket: false, timeout: DEFAULT_CONNECT_TIMEOUT }
+    }
+
+    pub fn unix_socket(path: &str) -> Self {
+        Self { unix_socket: true, ..Self::new(path) }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// What `connect_many` returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectStrategy {
+    /// The first target to connect, for failover between equivalent hostengines. Fails only when none can
+    /// be reached.
+    FirstSuccess,
+    /// Every target, in the order given, for fleet operations. Fails when any cannot be reached, after
+    /// disconnecting the others.
+    All,
+}
+
+/// Dials every target at once, each on its own thread and within its own timeout, and returns the
+/// connections `strategy` asks for. Connections that are not returned, including ones that only come
+/// up after the answer was decided, are disconnected.
+pub fn connect_many(targets: &[ConnectTarget], strategy: ConnectStrategy) -> Result<Vec<HostConnection>, DCGMError> {
+    if targets.is_empty() {
+        return Err(DCGMError::from("No hostengines to connect to"));
+    }
+    let (tx, rx) = mpsc::channel();
+    for (i, target) in targets.iter().enumerate() {
+        let (dialer_tx, target) = (tx.clone(), target.clone());
+        let dialer = std::thread::Builder::new()
+            .name(format!("dcgm-connect-{i}"))
+            .spawn(move || {
+                let result = DcgmLibSafe::initialized(Mode::Standalone).and_then(|mut client| {
+                    let timeout_ms = target.timeout.as_millis().clamp(1, u32::MAX as u128) as u32;
+                    client.connect_standalone_within(&target.address, target.unix_socket as u32, 0, timeout_ms)?;
+                    client.verified()
+                });
+                let _ = dialer_tx.send((i, result));
+            });
+        if let Err(e) = dialer {
+            let _ = tx.send((i, Err(DCGMError::from(format!("Failed to spawn connect thread: {e}")))));
+        }
+    }
+    drop(tx);
+
+    // libdcgm enforces each timeout; this only covers a dial that does not return at all
+    let started = Instant::now();
+    let deadline = |i: usize| started + targets[i].timeout + Duration::from_secs(1);
+    let mut results: Vec<Option<Result<DcgmLibSafe, DCGMError>>> = targets.iter().map(|_| None).collect();
+    while results.iter().any(Option::is_none) {
+        let pending = (0..targets.len()).filter(|&i| results[i].is_none());
+        let wait = pending.map(deadline).max().unwrap_or(started).saturating_duration_since(Instant::now());
+        match rx.recv_timeout(wait) {
+            Ok((i, Ok(client))) if strategy == ConnectStrategy::FirstSuccess => {
+                reap_late_connections(rx);
+                return Ok(vec![HostConnection { address: targets[i].address.clone(), client }]);
+            }
+            Ok((i, result)) => {
+                if let Err(e) = &result {
+                    tracing::warn!("Failed to connect to hostengine at {}: {e}", targets[i].address);
+                }
+                results[i] = Some(result);
+                if strategy == ConnectStrategy::All && results[i].as_ref().is_some_and(Result::is_err) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    reap_late_connections(rx);
+
+    // targets still dialing when another failed are abandoned rather than failed
+    let abandoned = strategy == ConnectStrategy::All && results.iter().any(|r| matches!(r, Some(Err(_))));
+    let mut hosts = Vec::with_capacity(targets.len());
+    let mut failures = Vec::new();
+    for (target, result) in targets.iter().zip(results) {
+        match result {
+            Some(Ok(client)) => hosts.push(HostConnection { address: target.address.clone(), client }),
+            Some(Err(e)) => failures.push(format!("{}: {e}", target.address)),
+            None if abandoned => (),
+            None => failures.push(format!("{}: no answer within {:?}", target.address, target.timeout)),
+        }
+    }
+    if failures.is_empty() && !hosts.is_empty() {
+        return Ok(hosts);
+    }
+    for host in hosts {
+        disconnect(&host.client);
+    }
+    Err(DCGMError::from(format!("Failed to connect to {}", failures.join("; "))))
+}
+
+/// Disconnects, on a thread of their own, the connections still to arrive on `rx`.
+fn reap_late_connections(rx: mpsc::Receiver<(usize, Result<DcgmLibSafe, DCGMError>)>) {
+    let reaper = std::thread::Builder::new()
+        .name("dcgm-connect-reaper".into())
+        .spawn(move || {
+            for (_, result) in rx {
+                if let Ok(client) = result {
+                    disconnect(&client);
+                }
+            }
+        });
+    if let Err(e) = reaper {
+        tracing::warn!("Failed to spawn a thread for late connections, they stay open: {e}");
+    }
+}
+
+/// Drops one connection without shutting down the library, which other connections still use.
+fn disconnect(client: &DcgmLibSafe) {
+    let _ = unsafe { client.dcgm.dcgmDisconnect(client.handle()) };
+}
+
+/// Connections to several standalone hostengines, e.g. one per node of a cluster.
+pub struct MultiHostClient {
+    hosts: Vec<HostConnection>,
+    unreachable: Vec<(String, DCGMError)>,
+}
+
+impl MultiHostClient {
+    /// Connects to every `host[:port]` in `addresses`. Hosts that cannot be reached are recorded rather than
+    /// failing the whole client; see `unreachable`.
+    pub fn connect(addresses: &[&str]) -> Self {
+        let mut hosts = Vec::new();
+        let mut unreachable = Vec::new();
+        for address in addresses {
+            match DcgmLibSafe::new(Mode::Standalone, &[address, "0"]) {
+                Ok(client) => hosts.push(HostConnection { address: address.to_string(), client }),
+                Err(e) => {
+                    tracing::warn!("Failed to connect to hostengine at {address}: {e}");
+                    unreachable.push((address.to_string(), e));
+                }
+            }
+        }
+        Self { hosts, unreachable }
+    }
+
+    pub fn hosts(&self) -> &[HostConnection] {
+        &self.hosts
+    }
+
+    pub fn unreachable(&self) -> &[(String, DCGMError)] {
+        &self.unreachable
+    }
+
+    /// Runs `f` against every connected host in turn.
+    pub fn for_each_host<T, F>(&mut self, mut f: F) -> Vec<(String, Result<T, DCGMError>)>
+    where
+        F: FnMut(&mut DcgmLibSafe) -> Result<T, DCGMError>,
+    {
+        self.hosts.iter_mut().map(|h| (h.address.clone(), f(&mut h.client))).collect()
+    }
+
+    /// Disconnects from every host, shutting the library down once at the end.
+    pub fn shutdown(mut self) -> Result<(), DCGMError> {
+        let Some(mut last) = self.hosts.pop() else { return Ok(()) };
+        for host in &self.hosts {
+            let _ = unsafe { host.client.dcgm.dcgmDisconnect(host.client.handle()) };
+        }
+        last.client.shutdown()
+    }
+
+    /// Collects a fleet-wide summary in one pass over all hosts.
+    pub fn fleet_summary(&mut self) -> FleetSummary {
+        let mut summary = FleetSummary {
+            unreachable_hosts: self.unreachable.iter().map(|(a, _)| a.clone()).collect(),
+            ..Default::default()
+        };
+        let mut utilization = Vec::new();
+        for host in &mut self.hosts {
+            match host_snapshot(&mut host.client) {
+                Ok(snapshot) => {
+                    summary.hosts += 1;
+                    summary.total_gpus += snapshot.models.len();
+                    for model in snapshot.models {
+                        *summary.models.entry(model).or_default() += 1;
+                    }
+                    utilization.extend(snapshot.utilization);
+                    summary.unhealthy_gpus.extend(snapshot.unhealthy.into_iter()
+                        .map(|(gpu_id, health)| FleetGpu { host: host.address.clone(), gpu_id, health }));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to summarize {}: {e}", host.address);
+                    summary.unreachable_hosts.push(host.address.clone());
+                }
+            }
+        }
+        if !utilization.is_empty() {
+            summary.mean_utilization = Some(utilization.iter().sum::<f64>() / utilization.len() as f64);
+            summary.max_utilization = utilization.iter().copied().reduce(f64::max);
+        }
+        summary
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FleetGpu {
+    pub host: String,
+    pub gpu_id: u32,
+    pub health: HealthResult,
+}
+
+/// Fleet-level capacity view. Utilization is GPU utilization in percent.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FleetSummary {
+    pub hosts: usize,
+    pub unreachable_hosts: Vec<String>,
+    pub total_gpus: usize,
+    /// GPU count per device name.
+    pub models: BTreeMap<String, usize>,
+    pub mean_utilization: Option<f64>,
+    pub max_utilization: Option<f64>,
+    pub unhealthy_gpus: Vec<FleetGpu>,
+}
+
+struct HostSnapshot {
+    models: Vec<String>,
+    utilization: Vec<f64>,
+    unhealthy: Vec<(u32, HealthResult)>,
+}
+
+fn host_snapshot(dcgm: &mut DcgmLibSafe) -> Result<HostSnapshot, DCGMError> {
+    let gpus = dcgm.getAllSupportedDevices()?;
+    let mut models = Vec::with_capacity(gpus.len());
+    for gpu in &gpus {
+        let attributes = dcgm.device_attributes(*gpu)?;
+        models.push(c_chars_to_string(&attributes.identifiers.deviceName));
+    }
+
+    let watch = dcgm.watch_all_gpus(&[DCGM_FI_DEV_GPU_UTIL as u16], &WatchOptions::default())?;
+    let values = dcgm.updateAllFields().and_then(|_| dcgm.watch_values(&watch));
+    let _ = dcgm.unwatch(watch);
+    let utilization = values?.iter().filter_map(|s| s.value.as_f64()).collect();
+
+    let all_gpus = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+    dcgm.health_set(all_gpus, HealthSystems::all(), Duration::from_secs(30), Duration::from_secs(600))?;
+    let report = dcgm.health_check(all_gpus)?;
+    let mut unhealthy: BTreeMap<u32, HealthResult> = BTreeMap::new();
+    for incident in report.incidents.iter().filter(|i| i.health > HealthResult::Pass) {
+        if let Some(gpu) = incident.entity.filter(|e| e.group == EntityGroup::Gpu && gpus.contains(&e.id)) {
+            let worst = unhealthy.entry(gpu.id).or_insert(incident.health);
+            *worst = (*worst).max(incident.health);
+        }
+    }
+    Ok(HostSnapshot { models, utilization, unhealthy: unhealthy.into_iter().collect() })
+}
@@ -0,0 +1,273 @@
+use super::bindings::*;
+use super::entity::EntityGroup;
+use super::init::{versioned, Versioned, Zeroable};
+use super::{c_chars_to_string, reported_count, DCGMError, DcgmLib, DcgmLibSafe, NvLinkState, NvLinkStatus};
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// Links per NvSwitch in the DCGM 3.x headers; 4.x raised it to `DCGM_NVLINK_MAX_LINKS_PER_NVSWITCH`.
+const DCGM3_NVLINK_MAX_LINKS_PER_NVSWITCH: usize = 64;
+
+static STRUCT_VERSIONS: OnceLock<StructVersions> = OnceLock::new();
+/// The `dcgmNvLinkStatus` version the hostengine accepted last; 0 before the first successful call.
+static NVLINK_STATUS_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// Version of the loaded libdcgm, parsed from `dcgmVersionInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DcgmVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl DcgmVersion {
+    /// Parses the `version` key out of the raw `key:value;key:value` build info string.
+    pub fn parse_build_info(raw: &str) -> Option<Self> {
+        let version = raw.split(';')
+            .filter_map(|kv| kv.split_once(':'))
+            .find(|(k, _)| k.trim() == "version")?
+            .1;
+        let mut parts = version.trim().split('.').map(|p| p.parse::<u32>().ok());
+        Some(Self {
+            major: parts.next()??,
+            minor: parts.next().flatten().unwrap_or(0),
+            patch: parts.next().flatten().unwrap_or(0),
+        })
+    }
+}
+
+impl fmt::Display for DcgmVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Version constants to put in request structs, picked for the libdcgm major version found at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StructVersions {
+    pub major: u32,
+    pub nvlink_status: u32,
+    pub device_attributes: u32,
+    pub connect_params: u32,
+}
+
+impl StructVersions {
+    pub fn for_major(major: u32) -> Self {
+        let nvlink_status = if major >= 4 {
+            dcgmNvLinkStatus_t::version()
+        } else {
+            dcgmNvLinkStatus_v3_dcgm3::version()
+        };
+        Self {
+            major,
+            nvlink_status,
+            device_attributes: dcgmDeviceAttributes_t::version(),
+            connect_params: dcgmConnectV2Params_t::version(),
+        }
+    }
+}
+
+/// `dcgmNvLinkNvSwitchLinkStatus_t` as laid out by DCGM 3.x.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct dcgmNvLinkNvSwitchLinkStatus_dcgm3 {
+    entityId: dcgm_field_eid_t,
+    linkState: [dcgmNvLinkLinkState_t; DCGM3_NVLINK_MAX_LINKS_PER_NVSWITCH],
+}
+
+/// `dcgmNvLinkStatus_v3` as laid out by DCGM 3.x.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct dcgmNvLinkStatus_v3_dcgm3 {
+    version: ::std::os::raw::c_uint,
+    numGpus: ::std::os::raw::c_uint,
+    gpus: [dcgmNvLinkGpuLinkStatus_v3; DCGM_MAX_NUM_DEVICES as usize],
+    numNvSwitches: ::std::os::raw::c_uint,
+    nvSwitches: [dcgmNvLinkNvSwitchLinkStatus_dcgm3; DCGM_MAX_NUM_SWITCHES as usize],
+}
+
+versioned! {
+    dcgmNvLinkStatus_v3_dcgm3 => 3,
+}
+
+pub(crate) fn library_version(dcgm: &DcgmLib) -> Result<DcgmVersion, DCGMError> {
+    let mut info = dcgmVersionInfo_t::versioned();
+    match unsafe { dcgm.dcgmVersionInfo(&raw mut info) } {
+        dcgmReturn_enum_DCGM_ST_OK => (),
+        err_code => return Err(DCGMError::from(format!("dcgmVersionInfo failed with {err_code}"))),
+    }
+    let raw = c_chars_to_string(&info.rawBuildInfoString);
+    DcgmVersion::parse_build_info(&raw)
+        .ok_or_else(|| DCGMError::from(format!("Unrecognized DCGM build info: {raw}")))
+}
+
+impl DcgmLibSafe {
+    pub fn library_version(&self) -> Result<DcgmVersion, DCGMError> {
+        library_version(self.dcgm)
+    }
+
+    /// Struct versions for the loaded library, detected once per process. Falls back to the versions
+    /// the bindings were generated for when the library does not report its version.
+    pub fn struct_versions(&self) -> StructVersions {
+        *STRUCT_VERSIONS.get_or_init(|| match library_version(self.dcgm) {
+            Ok(v) => StructVersions::for_major(v.major),
+            Err(e) => {
+                tracing::warn!("Could not detect the DCGM version, assuming 4.x: {e}");
+                StructVersions::for_major(4)
+            }
+        })
+    }
+
+    /// `getNvLinkLinkStatus` with version negotiation: asks with the version picked for the detected
+    /// libdcgm and, when DCGM answers `DCGM_ST_VER_MISMATCH` (the version could not be detected, or the
+    /// hostengine runs another DCGM than the library), once more with the other known layout. The
+    /// version that worked is used first from then on.
+    pub(crate) fn nvlink_status(&mut self) -> Result<Vec<NvLinkStatus>, DCGMError>{
+        let preferred = match NVLINK_STATUS_VERSION.load(Ordering::Relaxed) {
+            0 => self.struct_versions().nvlink_status,
+            version => version,
+        };
+        let mut version = preferred;
+        let mut result = self.nvlink_status_as(version)?;
+        if matches!(result, Err(dcgmReturn_enum_DCGM_ST_VER_MISMATCH)) {
+            version = if preferred == dcgmNvLinkStatus_t::version() {
+                dcgmNvLinkStatus_v3_dcgm3::version()
+            } else {
+                dcgmNvLinkStatus_t::version()
+            };
+            tracing::debug!("DCGM rejected dcgmNvLinkStatus version {preferred:#x}, retrying with {version:#x}");
+            result = self.nvlink_status_as(version)?;
+        }
+        match result {
+            Ok(statuses) => {
+                NVLINK_STATUS_VERSION.store(version, Ordering::Relaxed);
+                Ok(statuses)
+            }
+            Err(err_code) => Err(self.call_error(err_code, "dcgmGetNvLinkLinkStatus").arg("version", format!("{version:#x}"))),
+        }
+    }
+
+    /// One `dcgmGetNvLinkLinkStatus` call with the layout of `version`, the 3.x one or the 4.x bindings.
+    fn nvlink_status_as(&mut self, version: u32) -> Result<Result<Vec<NvLinkStatus>, dcgmReturn_t>, DCGMError>{
+        let lib = self.lib()?;
+        if version == dcgmNvLinkStatus_v3_dcgm3::version() {
+            let mut linkStatus = dcgmNvLinkStatus_v3_dcgm3::boxed_zeroed();
+            linkStatus.set_version(version);
+            let ptr = &mut *linkStatus as *mut dcgmNvLinkStatus_v3_dcgm3 as *mut dcgmNvLinkStatus_t;
+            return Ok(match unsafe { lib.dcgmGetNvLinkLinkStatus(self.handle(), ptr) } {
+                dcgmReturn_enum_DCGM_ST_OK => Ok(link_statuses(
+                    linkStatus.gpus[..reported_count(linkStatus.numGpus, linkStatus.gpus.len(), "NvLink GPUs")].iter()
+                        .map(|g| (g.entityId, &g.linkState[..])),
+                    linkStatus.nvSwitches[..reported_count(linkStatus.numNvSwitches, linkStatus.nvSwitches.len(), "NvSwitches")].iter()
+                        .map(|s| (s.entityId, &s.linkState[..])),
+                )),
+                err_code => Err(err_code),
+            });
+        }
+        let mut linkStatus = dcgmNvLinkStatus_t::boxed_zeroed();
+        linkStatus.set_version(version);
+        Ok(match unsafe { lib.dcgmGetNvLinkLinkStatus(self.handle(), &mut *linkStatus) } {
+            dcgmReturn_enum_DCGM_ST_OK => Ok(link_statuses(
+                linkStatus.gpus[..reported_count(linkStatus.numGpus, linkStatus.gpus.len(), "NvLink GPUs")].iter()
+                    .map(|g| (g.entityId, &g.linkState[..])),
+                linkStatus.nvSwitches[..reported_count(linkStatus.numNvSwitches, linkStatus.nvSwitches.len(), "NvSwitches")].iter()
+                    .map(|s| (s.entityId, &s.linkState[..])),
+            )),
+            err_code => Err(err_code),
+        })
+    }
+}
+
+/// A request struct libdcgm rejected with `DCGM_ST_VER_MISMATCH`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayoutMismatch {
+    pub name: &'static str,
+    pub version: u32,
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (version {}, {} bytes)", self.name, self.version >> 24, self.version & 0x00ff_ffff)
+    }
+}
+
+impl DcgmLibSafe {
+    /// Hands each of the large request structs the bindings use to libdcgm once, through the cheapest
+    /// call that takes it, and collects those it rejects as the wrong version. A rejected struct means
+    /// the bindings were generated from other DCGM headers than the library and hostengine in use, and
+    /// calls with it would read or write past what the other side allocated. Other errors (no GPU,
+    /// MIG or NvLink) say nothing about the layout and are ignored.
+    pub fn struct_layout_mismatches(&mut self) -> Result<Vec<LayoutMismatch>, DCGMError> {
+        let lib = self.lib()?;
+        let mut mismatches = Vec::new();
+        let mut check = |name: &'static str, version: u32, code: dcgmReturn_t| {
+            if code == dcgmReturn_enum_DCGM_ST_VER_MISMATCH {
+                mismatches.push(LayoutMismatch { name, version });
+            }
+        };
+
+        let mut group = dcgmGroupInfo_t::versioned();
+        check("dcgmGroupInfo_t", dcgmGroupInfo_t::version(),
+              unsafe { lib.dcgmGroupGetInfo(self.handle(), DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t, &raw mut group) });
+
+        if let Some(&gpu) = self.getAllSupportedDevices().unwrap_or_default().first() {
+            let version = self.struct_versions().device_attributes;
+            let mut attributes = dcgmDeviceAttributes_t::with_version(version);
+            check("dcgmDeviceAttributes_t", version,
+                  unsafe { lib.dcgmGetDeviceAttributes(self.handle(), gpu as ::std::os::raw::c_uint, &raw mut attributes) });
+        }
+
+        let mut health = dcgmHealthResponse_t::boxed_versioned();
+        check("dcgmHealthResponse_t", dcgmHealthResponse_t::version(),
+              unsafe { lib.dcgmHealthCheck(self.handle(), DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t, &mut *health) });
+
+        if lib.dcgmGetGpuInstanceHierarchy.is_ok() {
+            let mut hierarchy = dcgmMigHierarchy_v2::boxed_versioned();
+            check("dcgmMigHierarchy_v2", dcgmMigHierarchy_v2::version(),
+                  unsafe { lib.dcgmGetGpuInstanceHierarchy(self.handle(), &mut *hierarchy) });
+        }
+
+        // negotiated between the 3.x and 4.x layouts; a mismatch here means neither was accepted
+        if let Err(e) = self.nvlink_status() {
+            if let Some(code) = e.code {
+                check("dcgmNvLinkStatus_t", self.struct_versions().nvlink_status, code);
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Fails with a `DCGM_ST_VER_MISMATCH` error naming every struct of `struct_layout_mismatches`, so
+    /// that drifted bindings stop a connection instead of corrupting memory on a later call.
+    pub fn verify_struct_layouts(&mut self) -> Result<(), DCGMError> {
+        let mismatches = self.struct_layout_mismatches()?;
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let library = library_version(self.dcgm).map_or_else(|_| "of unknown version".to_string(), |v| v.to_string());
+        let structs: Vec<String> = mismatches.iter().map(LayoutMismatch::to_string).collect();
+        let mut e = DCGMError::from(format!(
+            "libdcgm {library} rejected the layout of {}; these bindings were generated from other DCGM headers. \
+             Install the DCGM release the crate was built for, or rebuild it against the installed headers",
+            structs.join(", ")));
+        e.code = Some(dcgmReturn_enum_DCGM_ST_VER_MISMATCH);
+        Err(e)
+    }
+}
+
+/// Flattens the per-GPU and per-NvSwitch link state arrays of either layout.
+fn link_statuses<'a>(gpus: impl Iterator<Item = (dcgm_field_eid_t, &'a [dcgmNvLinkLinkState_t])>,
+                     switches: impl Iterator<Item = (dcgm_field_eid_t, &'a [dcgmNvLinkLinkState_t])>) -> Vec<NvLinkStatus> {
+    let gpus = gpus.map(|(id, states)| (EntityGroup::Gpu, id, states));
+    let switches = switches.map(|(id, states)| (EntityGroup::Switch, id, states));
+    gpus.chain(switches)
+        .flat_map(|(parent_type, parent_id, states)| {
+            states.iter().enumerate().map(move |(j, state)| NvLinkStatus {
+                parent_id,
+                parent_type,
+                state: NvLinkState::from(*state),
+                index: j as u32,
+            })
+        })
+        .collect()
+}
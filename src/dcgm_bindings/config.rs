@@ -0,0 +1,342 @@
+use super::bindings::*;
+use super::entity::EntityGroup;
+use super::status::{status_errors_to_error, StatusError};
+use super::init::Versioned;
+use super::{reported_count, DCGMError, DcgmLibSafe};
+
+/// Typed view of `dcgmConfig_t`. `None` means the setting is blank (not set / ignored) or not supported.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceConfig {
+    pub gpu_id: u32,
+    pub ecc_mode: Option<bool>,
+    pub compute_mode: Option<ComputeMode>,
+    pub sync_boost: Option<bool>,
+    pub mem_clock: Option<u32>,
+    pub sm_clock: Option<u32>,
+    pub power_limit: Option<u32>,
+}
+
+/// GPU compute mode. Exclusive process allows a single compute context per GPU; prohibited allows none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComputeMode {
+    Default,
+    ExclusiveProcess,
+    Prohibited,
+}
+
+impl ComputeMode {
+    pub fn as_raw(self) -> u32 {
+        match self {
+            ComputeMode::Default => DCGM_CONFIG_COMPUTEMODE_DEFAULT,
+            ComputeMode::ExclusiveProcess => DCGM_CONFIG_COMPUTEMODE_EXCLUSIVE_PROCESS,
+            ComputeMode::Prohibited => DCGM_CONFIG_COMPUTEMODE_PROHIBITED,
+        }
+    }
+
+    pub fn from_raw(v: u32) -> Option<Self> {
+        match v {
+            DCGM_CONFIG_COMPUTEMODE_DEFAULT => Some(ComputeMode::Default),
+            DCGM_CONFIG_COMPUTEMODE_EXCLUSIVE_PROCESS => Some(ComputeMode::ExclusiveProcess),
+            DCGM_CONFIG_COMPUTEMODE_PROHIBITED => Some(ComputeMode::Prohibited),
+            _ => None,
+        }
+    }
+}
+
+fn int32_value(v: u32) -> Option<u32> {
+    if v >= DCGM_INT32_BLANK { None } else { Some(v) }
+}
+
+fn blank_or(v: Option<u32>) -> u32 {
+    v.unwrap_or(DCGM_INT32_BLANK)
+}
+
+impl DeviceConfig {
+    pub fn from_raw(raw: &dcgmConfig_t) -> Self {
+        Self {
+            gpu_id: raw.gpuId,
+            ecc_mode: int32_value(raw.eccMode).map(|v| v != 0),
+            compute_mode: int32_value(raw.computeMode).and_then(ComputeMode::from_raw),
+            sync_boost: int32_value(raw.perfState.syncBoost).map(|v| v != 0),
+            mem_clock: int32_value(raw.perfState.targetClocks.memClock),
+            sm_clock: int32_value(raw.perfState.targetClocks.smClock),
+            power_limit: int32_value(raw.powerLimit.val),
+        }
+    }
+
+    /// Builds a `dcgmConfig_t` where every `None` setting is left blank so DCGM ignores it.
+    pub fn to_raw(&self) -> dcgmConfig_t {
+        let mut raw = dcgmConfig_t::versioned();
+        raw.gpuId = self.gpu_id;
+        raw.eccMode = blank_or(self.ecc_mode.map(|v| v as u32));
+        raw.computeMode = blank_or(self.compute_mode.map(ComputeMode::as_raw));
+        raw.perfState.syncBoost = blank_or(self.sync_boost.map(|v| v as u32));
+        raw.perfState.targetClocks.set_version(dcgmClockSet_t::version());
+        raw.perfState.targetClocks.memClock = blank_or(self.mem_clock);
+        raw.perfState.targetClocks.smClock = blank_or(self.sm_clock);
+        raw.powerLimit.type_ = dcgmConfigPowerLimitType_enum_DCGM_CONFIG_POWER_CAP_INDIVIDUAL;
+        raw.powerLimit.val = blank_or(self.power_limit);
+        for profile in raw.workloadPowerProfiles.iter_mut() {
+            *profile = DCGM_INT32_BLANK;
+        }
+        raw
+    }
+}
+
+/// Whether a configuration change is already active or only takes effect after a reboot / GPU reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PendingChange {
+    Active,
+    ResetRequired,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigType {
+    Target,
+    Current,
+}
+
+impl ConfigType {
+    fn as_raw(self) -> dcgmConfigType_t {
+        match self {
+            ConfigType::Target => dcgmConfigType_enum_DCGM_CONFIG_TARGET_STATE,
+            ConfigType::Current => dcgmConfigType_enum_DCGM_CONFIG_CURRENT_STATE,
+        }
+    }
+}
+
+/// One setting whose target value differs from the value currently active on the GPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigMismatch {
+    pub setting: &'static str,
+    pub target: u32,
+    pub current: Option<u32>,
+}
+
+/// Target and current configuration of one GPU side by side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigComparison {
+    pub gpu_id: u32,
+    pub target: DeviceConfig,
+    pub current: DeviceConfig,
+    pub mismatches: Vec<ConfigMismatch>,
+}
+
+impl ConfigComparison {
+    pub fn new(target: DeviceConfig, current: DeviceConfig) -> Self {
+        let mut mismatches = Vec::new();
+        let pairs = [
+            ("ecc_mode", target.ecc_mode.map(|v| v as u32), current.ecc_mode.map(|v| v as u32)),
+            ("compute_mode", target.compute_mode.map(ComputeMode::as_raw), current.compute_mode.map(ComputeMode::as_raw)),
+            ("sync_boost", target.sync_boost.map(|v| v as u32), current.sync_boost.map(|v| v as u32)),
+            ("mem_clock", target.mem_clock, current.mem_clock),
+            ("sm_clock", target.sm_clock, current.sm_clock),
+            ("power_limit", target.power_limit, current.power_limit),
+        ];
+        // Only settings that were explicitly targeted can drift.
+        for (setting, t, c) in pairs {
+            if let Some(t) = t {
+                if Some(t) != c {
+                    mismatches.push(ConfigMismatch { setting, target: t, current: c });
+                }
+            }
+        }
+        Self { gpu_id: current.gpu_id, target, current, mismatches }
+    }
+
+    pub fn has_drift(&self) -> bool {
+        !self.mismatches.is_empty()
+    }
+}
+
+impl DcgmLibSafe {
+    fn group_gpu_ids(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<u32>, DCGMError>{
+        Ok(self.getGroupEntities(groupId)?.iter()
+            .filter(|e| e.group == EntityGroup::Gpu)
+            .map(|e| e.id)
+            .collect())
+    }
+
+    fn config_get_raw(&mut self, groupId: dcgmGpuGrp_t, configType: ConfigType, count: usize) -> Result<Vec<DeviceConfig>, dcgmReturn_t>{
+        let mut configs: Vec<dcgmConfig_t> = (0..count).map(|_| dcgmConfig_t::versioned()).collect();
+        match unsafe{self.dcgm.dcgmConfigGet(self.handle(), groupId, configType.as_raw(), count as i32, configs.as_mut_ptr(), 0)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(configs.iter().map(DeviceConfig::from_raw).collect()),
+            err_code => Err(err_code)
+        }
+    }
+
+    pub fn config_get(&mut self, groupId: dcgmGpuGrp_t, configType: ConfigType) -> Result<Vec<DeviceConfig>, DCGMError>{
+        let count = self.group_gpu_ids(groupId)?.len();
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        self.config_get_raw(groupId, configType, count)
+            .map_err(|err_code| self.call_error(err_code, "dcgmConfigGet").arg("group", groupId).arg("type", format!("{configType:?}")))
+    }
+
+    /// Fetches target and current configuration and pairs them per GPU. A group without a target
+    /// configuration compares as having no drift.
+    pub fn config_compare(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<ConfigComparison>, DCGMError>{
+        let count = self.group_gpu_ids(groupId)?.len();
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let current = self.config_get_raw(groupId, ConfigType::Current, count)
+            .map_err(|err_code| self.call_error(err_code, "dcgmConfigGet").arg("group", groupId).arg("type", "current"))?;
+        let target = match self.config_get_raw(groupId, ConfigType::Target, count) {
+            Ok(t) => t,
+            Err(dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED) => Vec::new(),
+            Err(err_code) => return Err(self.call_error(err_code, "dcgmConfigGet").arg("group", groupId).arg("type", "target")),
+        };
+        Ok(current.into_iter().map(|c| {
+            let t = target.iter().find(|t| t.gpu_id == c.gpu_id).cloned()
+                .unwrap_or(DeviceConfig { gpu_id: c.gpu_id, ..Default::default() });
+            ConfigComparison::new(t, c)
+        }).collect())
+    }
+
+    /// Re-applies the target configuration previously set on the group, e.g. after a GPU reset or
+    /// driver reload. Returns the per-GPU failures; an empty list means every GPU was enforced.
+    pub fn config_enforce(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<StatusError>, DCGMError>{
+        let mut status = self.status_create()?;
+        match unsafe{self.lib()?.dcgmConfigEnforce(self.handle(), groupId, status.raw())}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(status.drain()),
+            err_code => {
+                let errors = status.drain();
+                if errors.is_empty() {
+                    Err(self.call_error(err_code, "dcgmConfigEnforce").arg("group", groupId))
+                } else {
+                    Ok(errors)
+                }
+            }
+        }
+    }
+
+    /// Applies `config` to every GPU in the group; settings left as `None` are not touched.
+    /// Returns the per-GPU failures; an empty list means every GPU was configured.
+    pub fn config_set(&mut self, groupId: dcgmGpuGrp_t, config: &DeviceConfig) -> Result<Vec<StatusError>, DCGMError>{
+        let mut raw = config.to_raw();
+        let mut status = self.status_create()?;
+        let result = unsafe{self.lib()?.dcgmConfigSet(self.handle(), groupId, &raw mut raw, status.raw())};
+        // the current power limit is part of the cached device attributes
+        self.invalidate();
+        match result{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(status.drain()),
+            err_code => {
+                let errors = status.drain();
+                if errors.is_empty() {
+                    Err(self.call_error(err_code, "dcgmConfigSet").arg("group", groupId))
+                } else {
+                    Ok(errors)
+                }
+            }
+        }
+    }
+
+    /// Sets the power limit of every GPU in the group after checking it against each GPU's
+    /// supported min/max power limits. Nothing is applied if any GPU would reject the value.
+    pub fn set_power_limit(&mut self, groupId: dcgmGpuGrp_t, watts: u32) -> Result<Vec<StatusError>, DCGMError>{
+        let mut out_of_range = Vec::new();
+        for gpuId in self.group_gpu_ids(groupId)? {
+            let limits = self.device_attributes(gpuId)?.powerLimits;
+            if watts < limits.minPowerLimit || watts > limits.maxPowerLimit {
+                out_of_range.push(format!("gpu {gpuId} supports {}-{} W", limits.minPowerLimit, limits.maxPowerLimit));
+            }
+        }
+        if !out_of_range.is_empty() {
+            return Err(DCGMError::from(format!("power limit {watts} W is out of range: {}", out_of_range.join("; "))));
+        }
+        self.config_set(groupId, &DeviceConfig { power_limit: Some(watts), ..Default::default() })
+    }
+
+    /// Enables or disables sync boost across the GPUs of the group so their clocks stay aligned.
+    pub fn set_sync_boost(&mut self, groupId: dcgmGpuGrp_t, enabled: bool) -> Result<Vec<StatusError>, DCGMError>{
+        self.config_set(groupId, &DeviceConfig { sync_boost: Some(enabled), ..Default::default() })
+    }
+
+    /// GPUs of the group whose currently active configuration has sync boost enabled.
+    pub fn sync_boost_members(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<u32>, DCGMError>{
+        Ok(self.config_get(groupId, ConfigType::Current)?.iter()
+            .filter(|c| c.sync_boost == Some(true))
+            .map(|c| c.gpu_id)
+            .collect())
+    }
+
+    /// Supported (memory MHz, SM MHz) application clock pairs of one GPU.
+    pub fn supported_clocks(&mut self, gpuId: u32) -> Result<Vec<(u32, u32)>, DCGMError>{
+        let clockSets = self.device_attributes(gpuId)?.clockSets;
+        let count = reported_count(clockSets.count, clockSets.clockSet.len(), "clock sets");
+        Ok(clockSets.clockSet[..count].iter().map(|c| (c.memClock, c.smClock)).collect())
+    }
+
+    /// Sets application clocks on every GPU in the group after checking that the (memory, SM) pair is
+    /// one of each GPU's supported clock sets. Nothing is applied if any GPU would reject the pair.
+    pub fn set_app_clocks(&mut self, groupId: dcgmGpuGrp_t, mem_mhz: u32, sm_mhz: u32) -> Result<Vec<StatusError>, DCGMError>{
+        let mut unsupported = Vec::new();
+        for gpuId in self.group_gpu_ids(groupId)? {
+            if !self.supported_clocks(gpuId)?.contains(&(mem_mhz, sm_mhz)) {
+                unsupported.push(gpuId.to_string());
+            }
+        }
+        if !unsupported.is_empty() {
+            return Err(DCGMError::from(format!(
+                "application clocks mem {mem_mhz} MHz / sm {sm_mhz} MHz are not supported by gpu {}", unsupported.join(", "))));
+        }
+        self.config_set(groupId, &DeviceConfig { mem_clock: Some(mem_mhz), sm_clock: Some(sm_mhz), ..Default::default() })
+    }
+
+    /// Restores default application clocks on every GPU in the group.
+    pub fn reset_app_clocks(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<StatusError>, DCGMError>{
+        // DCGM treats a 0/0 clock target as a request to reset application clocks
+        self.config_set(groupId, &DeviceConfig { mem_clock: Some(0), sm_clock: Some(0), ..Default::default() })
+    }
+
+    /// Pins every GPU in the group to the (memory, SM) application clocks and reads the current
+    /// configuration back, failing unless each GPU reports exactly those clocks.
+    pub fn lock_clocks(&mut self, groupId: dcgmGpuGrp_t, mem_mhz: u32, sm_mhz: u32) -> Result<(), DCGMError>{
+        let errors = self.set_app_clocks(groupId, mem_mhz, sm_mhz)?;
+        if !errors.is_empty() {
+            return Err(status_errors_to_error("failed to lock clocks", &errors));
+        }
+        let unlocked: Vec<String> = self.config_get(groupId, ConfigType::Current)?.iter()
+            .filter(|c| c.mem_clock != Some(mem_mhz) || c.sm_clock != Some(sm_mhz))
+            .map(|c| {
+                let clock = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or_else(|| "n/a".into());
+                format!("gpu {} runs mem {} MHz / sm {} MHz", c.gpu_id, clock(c.mem_clock), clock(c.sm_clock))
+            })
+            .collect();
+        if !unlocked.is_empty() {
+            return Err(DCGMError::from(format!(
+                "clocks mem {mem_mhz} MHz / sm {sm_mhz} MHz did not apply: {}", unlocked.join("; "))));
+        }
+        Ok(())
+    }
+
+    /// Releases clocks pinned by `lock_clocks`, restoring default application clocks on every GPU in the group.
+    pub fn unlock_clocks(&mut self, groupId: dcgmGpuGrp_t) -> Result<(), DCGMError>{
+        let errors = self.reset_app_clocks(groupId)?;
+        if !errors.is_empty() {
+            return Err(status_errors_to_error("failed to unlock clocks", &errors));
+        }
+        Ok(())
+    }
+
+    /// Enables or disables ECC on every GPU in the group. ECC changes are only picked up after a
+    /// reboot or GPU reset, so the result tells, per GPU, whether the requested mode is already active.
+    pub fn set_ecc(&mut self, groupId: dcgmGpuGrp_t, enabled: bool) -> Result<Vec<(u32, PendingChange)>, DCGMError>{
+        let errors = self.config_set(groupId, &DeviceConfig { ecc_mode: Some(enabled), ..Default::default() })?;
+        if !errors.is_empty() {
+            return Err(status_errors_to_error("failed to set ECC mode", &errors));
+        }
+        Ok(self.config_get(groupId, ConfigType::Current)?.iter()
+            .map(|c| {
+                let pending = if c.ecc_mode == Some(enabled) { PendingChange::Active } else { PendingChange::ResetRequired };
+                (c.gpu_id, pending)
+            })
+            .collect())
+    }
+
+    pub fn set_compute_mode(&mut self, groupId: dcgmGpuGrp_t, mode: ComputeMode) -> Result<Vec<StatusError>, DCGMError>{
+        self.config_set(groupId, &DeviceConfig { compute_mode: Some(mode), ..Default::default() })
+    }
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ContainerRuntime {
+    Docker,
+    Containerd,
+    CriO,
+    Podman,
+    Lxc,
+}
+
+/// The container a process runs in, resolved from its cgroup path.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ContainerInfo {
+    pub runtime: ContainerRuntime,
+    pub id: String,
+    /// Human readable name when the runtime's state on disk could be read (Docker and LXC only).
+    pub name: Option<String>,
+}
+
+impl ContainerInfo {
+    /// First 12 characters of the id, as `docker ps` prints it.
+    pub fn short_id(&self) -> &str {
+        &self.id[..self.id.len().min(12)]
+    }
+}
+
+fn is_container_id(s: &str) -> bool {
+    s.len() >= 32 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Recognizes a container in one cgroup path, e.g. `/docker/<id>`, `/system.slice/docker-<id>.scope`,
+/// `/kubepods/.../cri-containerd-<id>.scope`, `crio-<id>.scope`, `libpod-<id>.scope` or `/lxc/<name>`.
+pub fn parse_cgroup_path(path: &str) -> Option<ContainerInfo> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    for (i, segment) in segments.iter().enumerate().rev() {
+        let name = segment.strip_suffix(".scope").unwrap_or(segment);
+        let prefixed = [
+            ("docker-", ContainerRuntime::Docker),
+            ("cri-containerd-", ContainerRuntime::Containerd),
+            ("crio-", ContainerRuntime::CriO),
+            ("libpod-", ContainerRuntime::Podman),
+        ];
+        for (prefix, runtime) in prefixed {
+            if let Some(id) = name.strip_prefix(prefix).filter(|id| is_container_id(id)) {
+                return Some(ContainerInfo { runtime, id: id.to_string(), name: None });
+            }
+        }
+        if i > 0 && is_container_id(name) {
+            let runtime = match segments[i - 1] {
+                "docker" => ContainerRuntime::Docker,
+                "libpod_parent" => ContainerRuntime::Podman,
+                _ => ContainerRuntime::Containerd,
+            };
+            return Some(ContainerInfo { runtime, id: name.to_string(), name: None });
+        }
+        if i > 0 && matches!(segments[i - 1], "lxc" | "lxc.payload") {
+            return Some(ContainerInfo { runtime: ContainerRuntime::Lxc, id: name.to_string(), name: Some(name.to_string()) });
+        }
+        if let Some(n) = name.strip_prefix("lxc.payload.") {
+            return Some(ContainerInfo { runtime: ContainerRuntime::Lxc, id: n.to_string(), name: Some(n.to_string()) });
+        }
+    }
+    None
+}
+
+fn docker_container_name(id: &str) -> Option<String> {
+    let config = std::fs::read_to_string(format!("/var/lib/docker/containers/{id}/config.v2.json")).ok()?;
+    // Avoids a JSON dependency; the top-level "Name" is written as "Name":"/<name>".
+    let start = config.find("\"Name\":\"")? + "\"Name\":\"".len();
+    let end = start + config[start..].find('"')?;
+    Some(config[start..end].trim_start_matches('/').to_string())
+}
+
+/// Resolves the container `pid` runs in from `/proc/<pid>/cgroup`. None for host processes or exited pids.
+pub fn container_for_pid(pid: u32) -> Option<ContainerInfo> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    // Lines are "hierarchy-id:controllers:path"; cgroup v2 has a single "0::path" line.
+    let mut info = cgroups.lines()
+        .filter_map(|l| l.splitn(3, ':').nth(2))
+        .find_map(parse_cgroup_path)?;
+    if info.runtime == ContainerRuntime::Docker {
+        info.name = docker_container_name(&info.id);
+    }
+    Some(info)
+}
@@ -0,0 +1,1156 @@
+use super::alerts::WebhookConfig;
+use super::bindings::*;
+use super::catalog::resolve_field;
+use super::entity::{Entity, EntityGroup};
+use super::hotplug::EntityEvent;
+use super::latest::LatestValuesQuery;
+use super::exporter::{ExporterConfig, GpuLabels};
+use super::histogram::HistogramConfig;
+use super::hostengine::HostengineEvent;
+use super::http::HttpConfig;
+use super::kube_events::KubeEventsConfig;
+use super::probe::HealthConfig;
+use super::sample_buffer::SampleBuffer;
+use super::samples::{Sample, Tags};
+use super::timing::{CollectionTiming, Phase};
+use super::watch::{unique_name, WatchHandle, WatchOptions};
+use super::{DCGMError, DcgmLibSafe, DcgmResultExt};
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::CString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Daemon / exporter configuration, read from TOML or YAML.
+///
+/// ```toml
+/// interval = "10s"
+///
+/// [connection]
+/// host = "127.0.0.1:5555"
+///
+/// [[groups]]
+/// name = "basic"
+/// fields = ["gpu_temp", "power_usage", 203]
+/// tags = { tenant = "teamA" }
+///
+/// [[sinks]]
+/// type = "prometheus_file"
+/// path = "/var/lib/node_exporter/textfile/dcgm.prom"
+///
+/// [labels]
+/// static = { cluster = "a100-east" }
+/// gpu = ["uuid", "device_name"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    /// Default sampling interval for groups that do not set their own.
+    #[serde(default = "default_interval", deserialize_with = "duration")]
+    pub interval: Duration,
+    pub groups: Vec<GroupConfig>,
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    #[serde(default)]
+    pub labels: LabelConfig,
+    /// Series without a new sample for this long are dropped from the output. Defaults to three times
+    /// the longest group interval.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub stale_after: Option<Duration>,
+    /// Export each value with the time DCGM sampled it.
+    #[serde(default)]
+    pub timestamps: bool,
+    /// Offset of the first collection within each group's interval: a duration, or `host` for a
+    /// stable per-node offset derived from the hostname.
+    #[serde(default, deserialize_with = "phase")]
+    pub phase: Phase,
+    /// Upper bound of a random delay added to every collection, capped to half the interval.
+    #[serde(default, deserialize_with = "duration")]
+    pub jitter: Duration,
+    /// Serve `grpc.health.v1` for Kubernetes probes, see `HealthConfig`.
+    #[serde(default)]
+    pub health: Option<HealthConfig>,
+    /// Rolling histograms of fields, exported next to their values, see `HistogramSettings`.
+    #[serde(default)]
+    pub histograms: Vec<HistogramSettings>,
+    /// Keep the recent samples of every series in memory, see `BufferConfig`.
+    #[serde(default)]
+    pub buffer: Option<BufferConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionConfig {
+    /// Standalone hostengine address, or socket path with `unix_socket`.
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default)]
+    pub unix_socket: bool,
+    /// Start an embedded hostengine instead of connecting to `host`.
+    #[serde(default)]
+    pub embedded: bool,
+    #[serde(default)]
+    pub backend: Backend,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self { host: default_host(), unix_socket: false, embedded: false, backend: Backend::default() }
+    }
+}
+
+/// Where values come from. `auto` uses DCGM and, when built with `nvml-fallback`, falls back to the
+/// reduced NVML backend if DCGM cannot be loaded or connected to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Auto,
+    Dcgm,
+    Nvml,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    pub name: String,
+    /// GPU ids to watch; all GPUs when left out.
+    #[serde(default)]
+    pub gpus: Option<Vec<u32>>,
+    pub fields: Vec<FieldRef>,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub interval: Option<Duration>,
+    #[serde(default = "default_keep_age", deserialize_with = "duration")]
+    pub keep_age: Duration,
+    /// Attached to every sample of the group and written by every sink: labels in Prometheus output,
+    /// fields in JSON lines. Lets one daemon report GPUs of several tenants, e.g. `{ tenant = "teamA" }`.
+    /// Groups sharing a GPU and field are one series, with the tags of whichever was read last.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// A field given either by numeric id or by name: its DCGM tag in any case, e.g. `gpu_temp`, or its
+/// `DCGM_FI_*` constant name, e.g. `DCGM_FI_DEV_GPU_TEMP`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FieldRef {
+    Id(u16),
+    Name(String),
+}
+
+impl FieldRef {
+    /// The field id, looking names up in the DCGM field table; see `catalog::resolve_field`.
+    pub fn resolve(&self) -> Result<u16, DCGMError> {
+        match self {
+            FieldRef::Id(id) => Ok(*id),
+            FieldRef::Name(name) => resolve_field(name),
+        }
+    }
+}
+
+impl FromStr for FieldRef {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(DCGMError::from("empty field name"));
+        }
+        Ok(s.parse().map_or_else(|_| FieldRef::Name(s.to_string()), FieldRef::Id))
+    }
+}
+
+impl fmt::Display for FieldRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldRef::Id(id) => write!(f, "{id}"),
+            FieldRef::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SinkConfig {
+    /// Prometheus text format on stdout after every collection.
+    Stdout,
+    /// Prometheus text format, atomically replaced after every collection (node_exporter textfile collector).
+    PrometheusFile { path: PathBuf },
+    /// One JSON object per sample, appended. With `rollup`, one per series and window of that length
+    /// instead, see `Rollup`, e.g. to keep 60s summaries of fields read every second.
+    JsonLines {
+        path: PathBuf,
+        #[serde(default, deserialize_with = "optional_duration")]
+        rollup: Option<Duration>,
+    },
+    /// Prometheus text format served on `/metrics`, optionally over TLS and behind auth.
+    Http(HttpConfig),
+    /// Read-only FUSE mount with one file per GPU value, `<mountpoint>/gpus/<uuid>/temp`. Needs the
+    /// fuse feature.
+    Fuse { mountpoint: PathBuf },
+    /// Policy violations and health transitions POSTed as JSON, see `WebhookConfig`.
+    Webhook(WebhookConfig),
+    /// GPU incidents as Kubernetes Events on the node, see `KubeEventsConfig`.
+    KubernetesEvents(KubeEventsConfig),
+}
+
+/// Histogram buckets and percentiles of fields over a rolling window, exported by the Prometheus
+/// sinks as `<metric>_histogram` and `<metric>_quantile`, e.g. to see how often utilization was
+/// above 90% without a TSDB.
+///
+/// ```toml
+/// [[histograms]]
+/// fields = ["gpu_utilization"]
+/// buckets = [50, 90, 100]
+/// window = "10m"
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistogramSettings {
+    pub fields: Vec<FieldRef>,
+    /// Inclusive upper bounds in the units of the fields; `+Inf` is added.
+    #[serde(default = "default_buckets")]
+    pub buckets: Vec<f64>,
+    /// Percentiles from 0 to 100.
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f64>,
+    #[serde(default = "default_histogram_window", deserialize_with = "duration")]
+    pub window: Duration,
+}
+
+impl HistogramSettings {
+    pub fn aggregator_config(&self) -> HistogramConfig {
+        HistogramConfig { buckets: self.buckets.clone(), percentiles: self.percentiles.clone(), window: self.window }
+    }
+}
+
+/// Recent samples the `Collector` keeps in memory, readable with `Collector::buffer`, e.g. to look at the
+/// last minutes of a series without reading a sink's files back.
+///
+/// ```toml
+/// [buffer]
+/// retention = "15m"
+/// max_series = 4096
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BufferConfig {
+    /// How far back each series goes at the shortest group interval.
+    #[serde(default = "default_retention", deserialize_with = "duration")]
+    pub retention: Duration,
+    /// Samples of series beyond this many are dropped.
+    #[serde(default = "default_max_series")]
+    pub max_series: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelConfig {
+    #[serde(default, rename = "static")]
+    pub static_labels: BTreeMap<String, String>,
+    /// Also add `<prefix><NAME>=value` environment variables as labels, see `static_labels_from_env`.
+    #[serde(default)]
+    pub env_prefix: Option<String>,
+    /// GPU identity labels by name (`uuid`, `pci_bus_id`, `device_name`, `minor_number`); the exporter
+    /// defaults when left out.
+    #[serde(default)]
+    pub gpu: Option<Vec<String>>,
+    /// Leave out the `Hostname` label.
+    #[serde(default)]
+    pub no_hostname: bool,
+}
+
+fn default_host() -> String {
+    "127.0.0.1:5555".to_string()
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_keep_age() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_buckets() -> Vec<f64> {
+    HistogramConfig::default().buckets
+}
+
+fn default_percentiles() -> Vec<f64> {
+    HistogramConfig::default().percentiles
+}
+
+fn default_histogram_window() -> Duration {
+    HistogramConfig::default().window
+}
+
+fn default_retention() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_max_series() -> usize {
+    4096
+}
+
+/// Parses `250ms`, `10s`, `5m`, `1h` or a bare number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let value: f64 = number.parse().map_err(|_| format!("invalid duration '{s}', expected e.g. 500ms, 10s, 5m"))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        other => return Err(format!("unknown duration unit '{other}' in '{s}', expected ms, s, m or h")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration '{s}' is out of range"))
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a duration such as \"10s\" or a number of seconds")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+        Ok(Duration::from_secs(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+        u64::try_from(v).map(Duration::from_secs).map_err(|_| E::custom("duration must not be negative"))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Duration, E> {
+        Duration::try_from_secs_f64(v).map_err(E::custom)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+        parse_duration(v).map_err(E::custom)
+    }
+}
+
+pub(crate) fn duration<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    d.deserialize_any(DurationVisitor)
+}
+
+fn phase<'de, D: Deserializer<'de>>(d: D) -> Result<Phase, D::Error> {
+    struct PhaseVisitor;
+
+    impl<'de> Visitor<'de> for PhaseVisitor {
+        type Value = Phase;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("\"host\", \"none\" or a duration such as \"2s\"")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Phase, E> {
+            DurationVisitor.visit_u64(v).map(Phase::Fixed)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Phase, E> {
+            DurationVisitor.visit_i64(v).map(Phase::Fixed)
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Phase, E> {
+            DurationVisitor.visit_f64(v).map(Phase::Fixed)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Phase, E> {
+            match v.trim() {
+                "host" => Ok(Phase::Host),
+                "none" => Ok(Phase::None),
+                other => parse_duration(other).map(Phase::Fixed).map_err(E::custom),
+            }
+        }
+    }
+    d.deserialize_any(PhaseVisitor)
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+    d.deserialize_any(DurationVisitor).map(Some)
+}
+
+impl<'de> Deserialize<'de> for FieldRef {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct FieldRefVisitor;
+
+        impl<'de> Visitor<'de> for FieldRefVisitor {
+            type Value = FieldRef;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a field id, a field tag such as \"gpu_temp\" or a name such as \"DCGM_FI_DEV_GPU_TEMP\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<FieldRef, E> {
+                u16::try_from(v).map(FieldRef::Id).map_err(|_| E::custom(format!("field id {v} is out of range")))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<FieldRef, E> {
+                u16::try_from(v).map(FieldRef::Id).map_err(|_| E::custom(format!("field id {v} is out of range")))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<FieldRef, E> {
+                v.parse().map_err(|e: DCGMError| E::custom(e))
+            }
+        }
+
+        d.deserialize_any(FieldRefVisitor)
+    }
+}
+
+/// Names a tag cannot take: the fields of a JSON sample and the labels the exporter sets itself.
+const RESERVED_TAGS: &[&str] = &["entity_group", "entity_id", "field_id", "timestamp", "value", "gpu", "UUID",
+                                  "pci_bus_id", "modelName", "device", "Hostname", "GPU_I_PROFILE", "GPU_I_ID",
+                                  "GPU_CI_ID"];
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl DaemonConfig {
+    /// Reads and validates a config file; `.toml`, `.yaml` and `.yml` are accepted.
+    pub fn load(path: &Path) -> Result<Self, DCGMError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| DCGMError::from(format!("{}: {e}", path.display())))?;
+        let parsed = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml") | Some("yml") => Self::from_yaml(&text),
+            _ => Err(DCGMError::from("unknown config format, expected a .toml, .yaml or .yml file")),
+        };
+        parsed.with_dcgm_context(|| path.display().to_string())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, DCGMError> {
+        let config: Self = toml::from_str(text).map_err(|e| DCGMError::from(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, DCGMError> {
+        let config: Self = serde_yaml::from_str(text).map_err(|e| DCGMError::from(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the schema alone cannot. Reports every problem found, one per line.
+    pub fn validate(&self) -> Result<(), DCGMError> {
+        let mut problems = Vec::new();
+        if self.groups.is_empty() {
+            problems.push("no groups configured".to_string());
+        }
+        if self.interval < Duration::from_millis(100) {
+            problems.push(format!("interval {:?} is below the 100ms minimum", self.interval));
+        }
+        let mut names = HashSet::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            let at = format!("groups[{i}] '{}'", group.name);
+            if group.name.is_empty() {
+                problems.push(format!("groups[{i}]: name is empty"));
+            } else if !names.insert(&group.name) {
+                problems.push(format!("{at}: duplicate group name"));
+            }
+            if group.fields.is_empty() {
+                problems.push(format!("{at}: no fields"));
+            }
+            // names can only be checked with libdcgm around to look them up; watching the group reports them otherwise
+            if super::DCGM_LIB.is_ok() {
+                for field in group.fields.iter().filter(|f| matches!(f, FieldRef::Name(_))) {
+                    if let Err(e) = field.resolve() {
+                        problems.push(format!("{at}: {e}"));
+                    }
+                }
+            }
+            if matches!(&group.gpus, Some(gpus) if gpus.is_empty()) {
+                problems.push(format!("{at}: empty gpus list, leave it out to watch all GPUs"));
+            }
+            let interval = group.interval.unwrap_or(self.interval);
+            if interval < Duration::from_millis(100) {
+                problems.push(format!("{at}: interval {interval:?} is below the 100ms minimum"));
+            }
+            if group.keep_age < interval {
+                problems.push(format!("{at}: keep_age {:?} is shorter than the interval {interval:?}", group.keep_age));
+            }
+            for name in group.tags.keys() {
+                if !is_label_name(name) {
+                    problems.push(format!("{at}: tag '{name}' is not a valid Prometheus label name"));
+                } else if RESERVED_TAGS.contains(&name.as_str()) {
+                    problems.push(format!("{at}: tag '{name}' is reserved"));
+                }
+            }
+        }
+        if self.connection.backend == Backend::Nvml && !cfg!(feature = "nvml-fallback") {
+            problems.push("connection.backend: nvml needs a build with the nvml-fallback feature".to_string());
+        }
+        if let Some(stale_after) = self.stale_after {
+            let longest = self.longest_interval();
+            if stale_after <= longest {
+                problems.push(format!("stale_after {stale_after:?} must be longer than the longest group interval {longest:?}"));
+            }
+        }
+        let mut paths = HashSet::new();
+        for (i, sink) in self.sinks.iter().enumerate() {
+            if let SinkConfig::PrometheusFile { path } | SinkConfig::JsonLines { path, .. } = sink {
+                if !paths.insert(path) {
+                    problems.push(format!("sinks[{i}]: {} is used by another sink", path.display()));
+                }
+            }
+            if let SinkConfig::JsonLines { rollup: Some(window), .. } = sink {
+                if *window < self.longest_interval() {
+                    problems.push(format!("sinks[{i}]: rollup {window:?} is shorter than the longest group interval {:?}",
+                                          self.longest_interval()));
+                }
+            }
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let SinkConfig::Fuse { mountpoint } = sink else { continue };
+            if !paths.insert(mountpoint) {
+                problems.push(format!("sinks[{i}]: {} is used by another sink", mountpoint.display()));
+            }
+            if !cfg!(feature = "fuse") {
+                problems.push(format!("sinks[{i}]: fuse needs a build with the fuse feature"));
+            }
+        }
+        let mut listen = HashSet::new();
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let SinkConfig::Http(http) = sink else { continue };
+            if !listen.insert(http.listen) {
+                problems.push(format!("sinks[{i}]: {} is used by another sink", http.listen));
+            }
+            if http.tls.is_some() && !cfg!(feature = "tls") {
+                problems.push(format!("sinks[{i}]: tls needs a build with the tls feature"));
+            }
+            for problem in http.auth.iter().flat_map(|a| a.problems()) {
+                problems.push(format!("sinks[{i}]: {problem}"));
+            }
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let SinkConfig::Webhook(webhook) = sink else { continue };
+            for problem in webhook.problems() {
+                problems.push(format!("sinks[{i}]: {problem}"));
+            }
+            if self.connection.backend == Backend::Nvml {
+                problems.push(format!("sinks[{i}]: webhook alerts need the DCGM backend"));
+            }
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let SinkConfig::KubernetesEvents(events) = sink else { continue };
+            if !cfg!(feature = "k8s") {
+                problems.push(format!("sinks[{i}]: kubernetes_events needs a build with the k8s feature"));
+            }
+            for problem in events.problems() {
+                problems.push(format!("sinks[{i}]: {problem}"));
+            }
+            if self.connection.backend == Backend::Nvml {
+                problems.push(format!("sinks[{i}]: kubernetes_events need the DCGM backend"));
+            }
+        }
+        if let Some(health) = &self.health {
+            if listen.contains(&health.listen) {
+                problems.push(format!("health: {} is used by a sink", health.listen));
+            }
+            if !cfg!(feature = "k8s") {
+                problems.push("health: needs a build with the k8s feature".to_string());
+            }
+        }
+        let mut aggregated = HashSet::new();
+        for (i, histogram) in self.histograms.iter().enumerate() {
+            let at = format!("histograms[{i}]");
+            if histogram.fields.is_empty() {
+                problems.push(format!("{at}: no fields"));
+            }
+            for field in &histogram.fields {
+                if !aggregated.insert(field) {
+                    problems.push(format!("{at}: field {field} is in another histogram"));
+                } else if matches!(field, FieldRef::Name(_)) && super::DCGM_LIB.is_ok() {
+                    if let Err(e) = field.resolve() {
+                        problems.push(format!("{at}: {e}"));
+                    }
+                }
+            }
+            if histogram.buckets.iter().any(|b| !b.is_finite()) {
+                problems.push(format!("{at}: buckets must be finite, +Inf is added"));
+            }
+            if histogram.percentiles.iter().any(|p| !(0.0..=100.0).contains(p)) {
+                problems.push(format!("{at}: percentiles must be between 0 and 100"));
+            }
+            if histogram.window < self.interval {
+                problems.push(format!("{at}: window {:?} is shorter than the interval {:?}", histogram.window, self.interval));
+            }
+        }
+        if let Some(buffer) = &self.buffer {
+            if buffer.retention.is_zero() {
+                problems.push("buffer: retention must not be zero".to_string());
+            }
+            if buffer.max_series == 0 {
+                problems.push("buffer: max_series must not be zero".to_string());
+            }
+        }
+        if self.sinks.iter().filter(|s| **s == SinkConfig::Stdout).count() > 1 {
+            problems.push("stdout sink configured more than once".to_string());
+        }
+        for name in self.labels.static_labels.keys() {
+            if !is_label_name(name) {
+                problems.push(format!("labels.static: '{name}' is not a valid Prometheus label name"));
+            }
+        }
+        for name in self.labels.gpu.iter().flatten() {
+            if GpuLabels::from_name(&name.to_ascii_uppercase()).is_none() {
+                problems.push(format!("labels.gpu: unknown label '{name}', expected uuid, pci_bus_id, device_name or minor_number"));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(DCGMError::from(format!("invalid config:\n  {}", problems.join("\n  "))))
+        }
+    }
+
+    /// `stale_after`, or three times the longest group interval when not set.
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after.unwrap_or_else(|| self.longest_interval() * 3)
+    }
+
+    fn longest_interval(&self) -> Duration {
+        self.groups.iter().map(|g| g.interval.unwrap_or(self.interval)).max().unwrap_or(self.interval)
+    }
+
+    fn shortest_interval(&self) -> Duration {
+        self.groups.iter().map(|g| g.interval.unwrap_or(self.interval)).min().unwrap_or(self.interval)
+    }
+
+    /// An empty buffer sized for `buffer` at the shortest group interval; None when not configured.
+    pub fn sample_buffer(&self) -> Option<SampleBuffer> {
+        self.buffer.as_ref().map(|b| SampleBuffer::with_retention(b.retention, self.shortest_interval(), b.max_series))
+    }
+
+    pub fn timing(&self) -> CollectionTiming {
+        CollectionTiming { phase: self.phase, jitter: self.jitter }
+    }
+
+    pub fn exporter_config(&self) -> ExporterConfig {
+        let mut config = ExporterConfig { timestamps: self.timestamps, ..Default::default() };
+        if self.labels.no_hostname {
+            config.hostname = None;
+        }
+        if let Some(prefix) = &self.labels.env_prefix {
+            config = config.static_labels_from_env(prefix);
+        }
+        config.static_labels.extend(self.labels.static_labels.clone());
+        if let Some(names) = &self.labels.gpu {
+            config.gpu_labels = names.iter()
+                .filter_map(|n| GpuLabels::from_name(&n.to_ascii_uppercase()))
+                .fold(GpuLabels::empty(), |acc, l| acc | l);
+        }
+        config
+    }
+}
+
+impl GroupConfig {
+    /// Field ids of this group, looking names up in the DCGM field metadata.
+    pub fn resolve_fields(&self, dcgm: &DcgmLibSafe) -> Result<Vec<u16>, DCGMError> {
+        let mut ids = Vec::with_capacity(self.fields.len());
+        let mut unknown = Vec::new();
+        for field in &self.fields {
+            // exact tags are the common case and need no walk over the field table
+            let exact = match field {
+                FieldRef::Name(name) => dcgm.field_id_by_tag(name)?,
+                FieldRef::Id(_) => None,
+            };
+            match exact.map_or_else(|| field.resolve(), Ok) {
+                Ok(id) => ids.push(id),
+                Err(e) => unknown.push(e.to_string()),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(DCGMError::from(format!("group '{}': {}", self.name, unknown.join("; "))));
+        }
+        let mut seen = HashSet::new();
+        ids.retain(|id| seen.insert(*id));
+        Ok(ids)
+    }
+}
+
+impl DcgmLibSafe {
+    /// Field id for a DCGM field tag, e.g. `gpu_temp`; None for unknown tags.
+    pub fn field_id_by_tag(&self, tag: &str) -> Result<Option<u16>, DCGMError> {
+        let lib = self.lib()?;
+        let Ok(tag) = CString::new(tag) else { return Ok(None) };
+        unsafe {
+            lib.DcgmFieldsInit();
+            let meta = lib.DcgmFieldGetByTag(tag.as_ptr());
+            if meta.is_null() {
+                return Ok(None);
+            }
+            Ok(Some((*meta).fieldId))
+        }
+    }
+}
+
+/// A configured group being watched, with the time its values are next due.
+#[derive(Debug)]
+pub struct ActiveGroup {
+    pub config: GroupConfig,
+    pub watch: WatchHandle,
+    /// GPU group created for `config.gpus`; None when the built-in all-GPUs group is used.
+    pub gpu_group: Option<dcgmGpuGrp_t>,
+    /// The same fields watched on the MIG GPU instances of the group's GPUs, in a GPU group of their
+    /// own; None when none of them has MIG enabled.
+    pub mig_watch: Option<WatchHandle>,
+    /// When the group is next read: its place on the interval grid plus this run's jitter.
+    pub next_due: Instant,
+    /// Deadline grid the group runs on; jitter moves `next_due` but never this.
+    grid: Instant,
+    query: LatestValuesQuery,
+    tags: Tags,
+}
+
+impl ActiveGroup {
+    pub fn interval(&self) -> Duration {
+        self.watch.options.update_interval
+    }
+}
+
+/// Group names touched by `Collector::reload`.
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub failed: Vec<(String, DCGMError)>,
+}
+
+impl ReloadSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "added [{}], removed [{}], changed [{}]", self.added.join(", "), self.removed.join(", "),
+               self.changed.join(", "))?;
+        for (name, e) in &self.failed {
+            write!(f, "; failed to watch '{name}': {e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Values read by `Collector::collect_due`, with the groups that failed to read and why.
+#[derive(Debug, Default)]
+pub struct Collection {
+    pub samples: Vec<Sample>,
+    pub failed: Vec<(String, DCGMError)>,
+}
+
+/// Watches every group of a `DaemonConfig` and reads their values when due.
+#[derive(Debug, Default)]
+pub struct Collector {
+    groups: Vec<ActiveGroup>,
+    timing: CollectionTiming,
+    buffer: Option<SampleBuffer>,
+}
+
+impl Collector {
+    /// Sets up the watches of every group in `config`; on failure the ones already set up are removed.
+    pub fn start(dcgm: &mut DcgmLibSafe, config: &DaemonConfig) -> Result<Self, DCGMError> {
+        let mut collector = Collector { groups: Vec::new(), timing: config.timing(), buffer: config.sample_buffer() };
+        for group in &config.groups {
+            match watch_group(dcgm, group, config.interval, &collector.timing) {
+                Ok(active) => collector.groups.push(active),
+                Err(e) => {
+                    collector.stop(dcgm);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(collector)
+    }
+
+    pub fn groups(&self) -> &[ActiveGroup] {
+        &self.groups
+    }
+
+    /// All field ids watched by any group.
+    pub fn fields(&self) -> Vec<u16> {
+        let mut fields: Vec<u16> = self.groups.iter().flat_map(|g| g.watch.fields.iter().copied()).collect();
+        fields.sort_unstable();
+        fields.dedup();
+        fields
+    }
+
+    /// Recent samples of every series returned by `collect_due`, when `buffer` is configured.
+    pub fn buffer(&self) -> Option<&SampleBuffer> {
+        self.buffer.as_ref()
+    }
+
+    /// When the next group is due; None without groups.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.groups.iter().map(|g| g.next_due).min()
+    }
+
+    /// Latest values of every group due at `now`, one batched call per group. They are also added to
+    /// the buffer, if any. A group that fails to read is reported in the result and waits for its next
+    /// turn like the others; the rest are read regardless.
+    pub fn collect_due(&mut self, dcgm: &mut DcgmLibSafe, now: Instant) -> Collection {
+        let mut collection = Collection::default();
+        for group in self.groups.iter_mut().filter(|g| g.next_due <= now) {
+            let mut samples = Vec::new();
+            match group.query.collect_into(dcgm, &mut samples) {
+                Ok(_) => {
+                    for sample in &mut samples {
+                        sample.tags = group.tags.clone();
+                    }
+                    collection.samples.append(&mut samples);
+                }
+                Err(e) => collection.failed.push((group.config.name.clone(), e)),
+            }
+            let interval = group.watch.options.update_interval;
+            while group.grid <= now {
+                group.grid += interval;
+            }
+            group.next_due = group.grid + self.timing.run_jitter(interval);
+        }
+        if let Some(buffer) = &mut self.buffer {
+            buffer.extend(collection.samples.iter().cloned());
+        }
+        collection
+    }
+
+    /// Moves the watches to match `config` without touching groups whose settings did not change.
+    /// Groups whose fields are all that changed get them swapped without a gap in their values. Other
+    /// removed and changed groups are unwatched first; groups that fail to watch are reported in the
+    /// summary and left out, the rest keep collecting. The buffer keeps its samples unless it is removed;
+    /// a resized one keeps the newest that fit.
+    pub fn reload(&mut self, dcgm: &mut DcgmLibSafe, config: &DaemonConfig) -> ReloadSummary {
+        let mut summary = ReloadSummary::default();
+        let mut kept = Vec::with_capacity(self.groups.len());
+        for mut group in self.groups.drain(..) {
+            let wanted = config.groups.iter().find(|g| g.name == group.config.name);
+            let same_interval = wanted.is_some_and(|g| g.interval.unwrap_or(config.interval) == group.interval());
+            match wanted {
+                Some(g) if *g == group.config && same_interval => kept.push(group),
+                Some(g) if same_interval && only_fields_differ(g, &group.config) && swap_fields(dcgm, &mut group, g).is_ok() => {
+                    summary.changed.push(group.config.name.clone());
+                    kept.push(group);
+                }
+                Some(_) => {
+                    summary.changed.push(group.config.name.clone());
+                    unwatch_group(dcgm, group);
+                }
+                None => {
+                    summary.removed.push(group.config.name.clone());
+                    unwatch_group(dcgm, group);
+                }
+            }
+        }
+        self.groups = kept;
+        self.timing = config.timing();
+        self.buffer = match (self.buffer.take(), config.sample_buffer()) {
+            (Some(old), Some(new)) if old.capacity_per_series() == new.capacity_per_series()
+                && old.max_series() == new.max_series() => Some(old),
+            (Some(old), Some(mut new)) => {
+                new.extend(old.range_all(i64::MIN, i64::MAX).into_values().flatten());
+                Some(new)
+            }
+            (_, new) => new,
+        };
+        for group in &config.groups {
+            if self.groups.iter().any(|g| g.config.name == group.name) {
+                continue;
+            }
+            match watch_group(dcgm, group, config.interval, &self.timing) {
+                Ok(active) => {
+                    if !summary.changed.contains(&group.name) {
+                        summary.added.push(group.name.clone());
+                    }
+                    self.groups.push(active);
+                }
+                Err(e) => summary.failed.push((group.name.clone(), e)),
+            }
+        }
+        summary
+    }
+
+    /// Follows GPUs and MIG instances that appeared or went away: groups without a GPU list read every
+    /// supported GPU, GPUs named by a group are put back into its GPU group when they return, e.g. after
+    /// a reset, and the GPU instances of the group's GPUs are watched anew. The fields are watched again
+    /// so DCGM samples the new members.
+    pub fn apply_entity_events(&mut self, dcgm: &mut DcgmLibSafe, events: &[EntityEvent]) -> Result<(), DCGMError> {
+        if !events.iter().any(|e| matches!(e.entity().group, EntityGroup::Gpu | EntityGroup::GpuInstance)) {
+            return Ok(());
+        }
+        let present = dcgm.getAllSupportedDevices()?;
+        for group in &mut self.groups {
+            let gpus: Vec<u32> = match (&group.config.gpus, group.gpu_group) {
+                (Some(gpus), Some(gpu_group)) => {
+                    for event in events.iter().filter(|e| e.entity().group == EntityGroup::Gpu && gpus.contains(&e.entity().id)) {
+                        let result = match event {
+                            EntityEvent::EntityAdded(e) => dcgm.addEntityToGroup(gpu_group, e.group, e.id),
+                            EntityEvent::EntityRemoved(e) => dcgm.removeEntityFromGroup(gpu_group, e.group, e.id),
+                        };
+                        if let Err(e) = result {
+                            tracing::warn!("Group '{}': failed to follow {event}: {e}", group.config.name);
+                        }
+                    }
+                    gpus.iter().copied().filter(|gpu| present.contains(gpu)).collect()
+                }
+                _ => present.clone(),
+            };
+            follow_mig_instances(dcgm, group, &gpus);
+            let options = &group.watch.options;
+            if let Err(e) = dcgm.watchFields(group.watch.field_group, group.watch.group, options.update_interval.as_micros() as i64,
+                                             options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+                tracing::warn!("Group '{}': failed to watch the new GPUs: {e}", group.config.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the groups at the ids their GPU and field groups got from a reconnect; see
+    /// `DcgmLibSafe::reconnect`. The watches themselves were restored by it.
+    pub fn remap(&mut self, event: &HostengineEvent) {
+        for group in &mut self.groups {
+            for watch in std::iter::once(&mut group.watch).chain(group.mig_watch.as_mut()) {
+                watch.group = event.new_group_id(watch.group);
+                watch.field_group = event.new_field_group_id(watch.field_group);
+            }
+            group.gpu_group = group.gpu_group.map(|g| event.new_group_id(g));
+        }
+    }
+
+    /// Removes every watch and the GPU groups created for them. Errors are ignored, this is best effort.
+    pub fn stop(&mut self, dcgm: &mut DcgmLibSafe) {
+        for group in self.groups.drain(..) {
+            unwatch_group(dcgm, group);
+        }
+    }
+}
+
+fn watch_group(dcgm: &mut DcgmLibSafe, config: &GroupConfig, default_interval: Duration, timing: &CollectionTiming)
+               -> Result<ActiveGroup, DCGMError> {
+    let fields = config.resolve_fields(dcgm)?;
+    let options = WatchOptions {
+        update_interval: config.interval.unwrap_or(default_interval),
+        max_keep_age: config.keep_age,
+        max_keep_samples: 0,
+    };
+    let first = Instant::now() + timing.first_offset(options.update_interval);
+    let Some(gpus) = &config.gpus else {
+        let gpus = dcgm.getAllSupportedDevices()?;
+        let watch = dcgm.watch_all_gpus(&fields, &options)?;
+        let query = LatestValuesQuery::new(&[], &watch.fields);
+        let mut active = ActiveGroup { config: config.clone(), watch, gpu_group: None, mig_watch: None, next_due: first,
+                                       grid: first, query, tags: Tags::new(config.tags.clone()) };
+        follow_mig_instances(dcgm, &mut active, &gpus);
+        return Ok(active);
+    };
+
+    let group = dcgm.createGroup(&unique_name(&config.name))?;
+    let watch = (|| {
+        for &gpu in gpus {
+            dcgm.addEntityToGroup(group, EntityGroup::Gpu, gpu)?;
+        }
+        let mut field_ids = fields.clone();
+        let field_group = dcgm.fieldGroupCreate(&unique_name(&config.name), &mut field_ids)?;
+        if let Err(e) = dcgm.watchFields(field_group, group, options.update_interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = dcgm.fieldGroupDestroy(field_group);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group, fields: field_ids, options: options.clone() })
+    })();
+    match watch {
+        Ok(watch) => {
+            let query = LatestValuesQuery::new(&[], &watch.fields);
+            let mut active = ActiveGroup { config: config.clone(), watch, gpu_group: Some(group), mig_watch: None,
+                                           next_due: first, grid: first, query, tags: Tags::new(config.tags.clone()) };
+            follow_mig_instances(dcgm, &mut active, gpus);
+            Ok(active)
+        }
+        Err(e) => {
+            let _ = dcgm.destroyGroup(group);
+            Err(e.context(format!("group '{}'", config.name)))
+        }
+    }
+}
+
+fn only_fields_differ(new: &GroupConfig, old: &GroupConfig) -> bool {
+    new.fields != old.fields && GroupConfig { fields: old.fields.clone(), ..new.clone() } == *old
+}
+
+/// Moves a group to the fields of `config` with `WatchHandle::update`.
+fn swap_fields(dcgm: &mut DcgmLibSafe, group: &mut ActiveGroup, config: &GroupConfig) -> Result<(), DCGMError> {
+    let fields = config.resolve_fields(dcgm)?;
+    if let Err(e) = group.watch.update(dcgm, &fields) {
+        tracing::warn!("Group '{}': failed to swap the fields, watching it again: {e}", config.name);
+        return Err(e);
+    }
+    if let Some(mig_watch) = &mut group.mig_watch {
+        if let Err(e) = mig_watch.update(dcgm, &fields) {
+            tracing::warn!("Group '{}': MIG instances keep the old fields: {e}", config.name);
+        }
+    }
+    group.query.set_fields(&group.watch.fields);
+    group.tags = Tags::new(config.tags.clone());
+    group.config = config.clone();
+    Ok(())
+}
+
+/// Watches the fields of `group` on the MIG GPU instances of `gpus` too, replacing an earlier watch
+/// of them, and points the query at the GPUs and those instances. GPUs without MIG are read as whole
+/// GPUs only; so is every GPU when the instances cannot be listed or watched.
+fn follow_mig_instances(dcgm: &mut DcgmLibSafe, group: &mut ActiveGroup, gpus: &[u32]) {
+    if let Some(old) = group.mig_watch.take() {
+        unwatch_mig(dcgm, old);
+    }
+    let mut entities: Vec<Entity> = gpus.iter().copied().map(Entity::gpu).collect();
+    let instances: Vec<Entity> = match dcgm.mig_hierarchy() {
+        Ok(hierarchy) => hierarchy.into_iter()
+            .filter(|m| m.entity.group == EntityGroup::GpuInstance && gpus.contains(&m.gpu_id))
+            .map(|m| m.entity)
+            .collect(),
+        Err(e) => {
+            tracing::debug!("Group '{}': no MIG instances: {e}", group.config.name);
+            Vec::new()
+        }
+    };
+    if !instances.is_empty() {
+        match watch_mig_instances(dcgm, &group.config.name, &instances, &group.watch) {
+            Ok(watch) => {
+                group.mig_watch = Some(watch);
+                entities.extend(instances);
+            }
+            Err(e) => tracing::warn!("Group '{}': failed to watch the MIG instances: {e}", group.config.name),
+        }
+    }
+    group.query.set_entities(&entities);
+}
+
+fn watch_mig_instances(dcgm: &mut DcgmLibSafe, name: &str, instances: &[Entity], like: &WatchHandle)
+                       -> Result<WatchHandle, DCGMError> {
+    let group = dcgm.createGroup(&unique_name(&format!("{name}-mig")))?;
+    let watch = (|| {
+        for instance in instances {
+            dcgm.addEntityToGroup(group, instance.group, instance.id)?;
+        }
+        let mut fields = like.fields.clone();
+        let field_group = dcgm.fieldGroupCreate(&unique_name(&format!("{name}-mig")), &mut fields)?;
+        let options = &like.options;
+        if let Err(e) = dcgm.watchFields(field_group, group, options.update_interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = dcgm.fieldGroupDestroy(field_group);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group, fields, options: options.clone() })
+    })();
+    if watch.is_err() {
+        let _ = dcgm.destroyGroup(group);
+    }
+    watch
+}
+
+fn unwatch_mig(dcgm: &mut DcgmLibSafe, watch: WatchHandle) {
+    let group = watch.group;
+    let _ = dcgm.unwatch(watch);
+    let _ = dcgm.destroyGroup(group);
+}
+
+fn unwatch_group(dcgm: &mut DcgmLibSafe, group: ActiveGroup) {
+    if let Some(mig_watch) = group.mig_watch {
+        unwatch_mig(dcgm, mig_watch);
+    }
+    let _ = dcgm.unwatch(group.watch);
+    if let Some(gpu_group) = group.gpu_group {
+        let _ = dcgm.destroyGroup(gpu_group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_take_a_unit_or_seconds() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration(" 10s "), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1 s"), Ok(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn malformed_durations_are_rejected() {
+        for s in ["", "s", "-5s", "1.2.3s", "10d", "5 minutes", "1e3s", "99999999999999999999999h"] {
+            assert!(parse_duration(s).is_err(), "{s}");
+        }
+    }
+
+    fn problems(toml: &str) -> String {
+        DaemonConfig::from_toml(toml).expect_err("invalid config").to_string()
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let err = problems(r#"
+            interval = "50ms"
+            groups = [
+                { name = "a", fields = [] },
+                { name = "a", fields = [150], gpus = [], keep_age = "10ms", tags = { "1x" = "v", gpu = "v" } },
+            ]
+        "#);
+        for problem in ["interval 50ms is below the 100ms minimum", "groups[0] 'a': no fields",
+                        "groups[1] 'a': duplicate group name", "groups[1] 'a': empty gpus list",
+                        "groups[1] 'a': keep_age 10ms is shorter", "tag '1x' is not a valid Prometheus label name",
+                        "tag 'gpu' is reserved"] {
+            assert!(err.contains(problem), "{problem} missing from {err}");
+        }
+        assert!(problems("groups = []").contains("no groups configured"));
+    }
+
+    #[test]
+    fn validation_checks_sinks_against_each_other_and_the_groups() {
+        let err = problems(r#"
+            interval = "10s"
+            stale_after = "10s"
+            groups = [{ name = "a", fields = [150] }]
+            sinks = [
+                { type = "stdout" },
+                { type = "stdout" },
+                { type = "prometheus_file", path = "/tmp/out" },
+                { type = "json_lines", path = "/tmp/out", rollup = "1s" },
+            ]
+            [buffer]
+            retention = 0
+            max_series = 0
+        "#);
+        for problem in ["stale_after 10s must be longer than the longest group interval 10s",
+                        "stdout sink configured more than once", "sinks[3]: /tmp/out is used by another sink",
+                        "sinks[3]: rollup 1s is shorter", "buffer: retention must not be zero",
+                        "buffer: max_series must not be zero"] {
+            assert!(err.contains(problem), "{problem} missing from {err}");
+        }
+    }
+
+    #[test]
+    fn valid_configs_pass() {
+        let config = DaemonConfig::from_toml(r#"
+            interval = "1s"
+            groups = [{ name = "temp", fields = [150], interval = "5s", tags = { rack = "r1" } }]
+            sinks = [{ type = "stdout" }]
+        "#).unwrap();
+        assert_eq!(config.stale_after(), Duration::from_secs(15));
+    }
+}
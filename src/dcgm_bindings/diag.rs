@@ -0,0 +1,471 @@
+use super::bindings::*;
+use super::entity::Entity;
+use super::errors::{error_info, ErrorInfo};
+use super::init::Versioned;
+use super::{c_chars_to_string, copy_str, DCGMError, DcgmLibSafe};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Diagnostic run level, `dcgmi diag -r 1..4`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum DiagLevel {
+    Short,
+    Medium,
+    Long,
+    ExtraLong,
+}
+
+impl DiagLevel {
+    fn as_validation(self) -> dcgmPolicyValidation_t {
+        match self {
+            DiagLevel::Short => dcgmPolicyValidation_enum_DCGM_POLICY_VALID_SV_SHORT,
+            DiagLevel::Medium => dcgmPolicyValidation_enum_DCGM_POLICY_VALID_SV_MED,
+            DiagLevel::Long => dcgmPolicyValidation_enum_DCGM_POLICY_VALID_SV_LONG,
+            DiagLevel::ExtraLong => dcgmPolicyValidation_enum_DCGM_POLICY_VALID_SV_XLONG,
+        }
+    }
+}
+
+impl FromStr for DiagLevel {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "short" => Ok(DiagLevel::Short),
+            "2" | "medium" | "med" => Ok(DiagLevel::Medium),
+            "3" | "long" => Ok(DiagLevel::Long),
+            "4" | "xlong" | "extralong" => Ok(DiagLevel::ExtraLong),
+            other => Err(DCGMError::from(format!("Unknown diag level {other}"))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DiagResult {
+    Pass,
+    Skip,
+    NotRun,
+    Warn,
+    Fail,
+}
+
+impl From<dcgmDiagResult_t> for DiagResult {
+    fn from(r: dcgmDiagResult_t) -> Self {
+        match r {
+            dcgmDiagResult_enum_DCGM_DIAG_RESULT_PASS => DiagResult::Pass,
+            dcgmDiagResult_enum_DCGM_DIAG_RESULT_SKIP => DiagResult::Skip,
+            dcgmDiagResult_enum_DCGM_DIAG_RESULT_WARN => DiagResult::Warn,
+            dcgmDiagResult_enum_DCGM_DIAG_RESULT_FAIL => DiagResult::Fail,
+            _ => DiagResult::NotRun,
+        }
+    }
+}
+
+impl fmt::Display for DiagResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DiagResult::Pass => "Pass",
+            DiagResult::Skip => "Skip",
+            DiagResult::NotRun => "Not Run",
+            DiagResult::Warn => "Warn",
+            DiagResult::Fail => "Fail",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiagMessage {
+    pub entity: Option<Entity>,
+    /// DCGM error id (`dcgmError_t`); None for informational messages.
+    pub code: Option<u32>,
+    /// Name and recommended action for `code`, when it is a documented error. Looked up again from
+    /// `code` when a saved report is loaded.
+    #[serde(skip_deserializing)]
+    pub error: Option<ErrorInfo>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiagTestResult {
+    pub name: String,
+    pub plugin: String,
+    pub result: DiagResult,
+    pub entities: Vec<(Entity, DiagResult)>,
+    pub errors: Vec<DiagMessage>,
+    pub info: Vec<DiagMessage>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiagReport {
+    pub tests: Vec<DiagTestResult>,
+    pub dcgm_version: String,
+    pub driver_version: String,
+}
+
+impl DiagReport {
+    /// Worst result over all tests; Pass for an empty report.
+    pub fn overall(&self) -> DiagResult {
+        self.tests.iter().map(|t| t.result).max().unwrap_or(DiagResult::Pass)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.overall() < DiagResult::Warn
+    }
+
+    /// Writes the report as JSON, to be used as the baseline of later runs.
+    pub fn save(&self, path: &Path) -> Result<(), DCGMError> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| DCGMError::from(e.to_string()))?;
+        std::fs::write(path, json).map_err(|e| DCGMError::from(format!("Failed to write {}: {e}", path.display())))
+    }
+
+    /// Reads a report written by `save`.
+    pub fn load(path: &Path) -> Result<Self, DCGMError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", path.display())))?;
+        let mut report: DiagReport = serde_json::from_str(&text)
+            .map_err(|e| DCGMError::from(format!("{}: {e}", path.display())))?;
+        for message in report.tests.iter_mut().flat_map(|t| t.errors.iter_mut()) {
+            message.error = message.code.and_then(error_info);
+        }
+        Ok(report)
+    }
+
+    /// Compares this run with `baseline` test by test and entity by entity. Tests without per-entity
+    /// results are compared on their overall result.
+    pub fn diff(&self, baseline: &DiagReport) -> DiagDiff {
+        let before = outcomes(baseline);
+        let mut diff = DiagDiff {
+            baseline_versions: (baseline.dcgm_version.clone(), baseline.driver_version.clone()),
+            current_versions: (self.dcgm_version.clone(), self.driver_version.clone()),
+            ..DiagDiff::default()
+        };
+        for test in &self.tests {
+            for (entity, after) in test_outcomes(test) {
+                let before = before.get(&(test.name.as_str(), entity)).copied();
+                let change = || DiagChange {
+                    test: test.name.clone(),
+                    entity,
+                    before,
+                    after,
+                    errors: test.errors.iter().filter(|e| entity.is_none() || e.entity == entity).cloned().collect(),
+                };
+                let was_failing = before.is_some_and(|b| b >= DiagResult::Warn);
+                if after >= DiagResult::Warn && before.is_none_or(|b| b < after) {
+                    diff.regressions.push(change());
+                } else if after >= DiagResult::Warn {
+                    diff.still_failing.push(change());
+                } else if was_failing && after == DiagResult::Pass {
+                    diff.fixed.push(change());
+                }
+            }
+        }
+        diff
+    }
+}
+
+/// Result of every (test, entity) of a report; entity None for tests without per-entity results.
+fn test_outcomes(test: &DiagTestResult) -> Vec<(Option<Entity>, DiagResult)> {
+    if test.entities.is_empty() {
+        vec![(None, test.result)]
+    } else {
+        test.entities.iter().map(|&(entity, result)| (Some(entity), result)).collect()
+    }
+}
+
+fn outcomes(report: &DiagReport) -> HashMap<(&str, Option<Entity>), DiagResult> {
+    report.tests.iter()
+        .flat_map(|t| test_outcomes(t).into_iter().map(move |(entity, result)| ((t.name.as_str(), entity), result)))
+        .collect()
+}
+
+/// One test on one entity whose result differs from, or still matches a failure in, the baseline.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DiagChange {
+    pub test: String,
+    pub entity: Option<Entity>,
+    /// None when the baseline did not run the test on this entity.
+    pub before: Option<DiagResult>,
+    pub after: DiagResult,
+    /// Errors of the new run for this entity.
+    pub errors: Vec<DiagMessage>,
+}
+
+impl fmt::Display for DiagChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.test)?;
+        if let Some(entity) = self.entity {
+            write!(f, " on {entity}")?;
+        }
+        match self.before {
+            Some(before) => write!(f, ": {before} -> {}", self.after),
+            None => write!(f, ": {} (not in baseline)", self.after),
+        }
+    }
+}
+
+/// A diag run compared with a baseline run, e.g. from before a driver or firmware update.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DiagDiff {
+    /// (DCGM, driver) versions of the baseline.
+    pub baseline_versions: (String, String),
+    pub current_versions: (String, String),
+    /// Failing or warning now and better, or not run, in the baseline.
+    pub regressions: Vec<DiagChange>,
+    /// Failing or warning in both runs, and not worse now.
+    pub still_failing: Vec<DiagChange>,
+    /// Failing or warning in the baseline and passing now.
+    pub fixed: Vec<DiagChange>,
+}
+
+impl DiagDiff {
+    pub fn has_regressions(&self) -> bool {
+        !self.regressions.is_empty()
+    }
+}
+
+impl fmt::Display for DiagDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ((old_dcgm, old_driver), (new_dcgm, new_driver)) = (&self.baseline_versions, &self.current_versions);
+        writeln!(f, "Baseline: DCGM {old_dcgm}, driver {old_driver}; now: DCGM {new_dcgm}, driver {new_driver}")?;
+        if self.regressions.is_empty() && self.fixed.is_empty() && self.still_failing.is_empty() {
+            return writeln!(f, "No differences");
+        }
+        for change in &self.regressions {
+            writeln!(f, "REGRESSION    {change}")?;
+            for error in &change.errors {
+                writeln!(f, "    {}", error.message)?;
+            }
+        }
+        for change in &self.still_failing {
+            writeln!(f, "still failing {change}")?;
+        }
+        for change in &self.fixed {
+            writeln!(f, "fixed         {change}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParameterKind {
+    Bool,
+    Number,
+    Text,
+}
+
+use ParameterKind::{Bool, Number, Text};
+
+/// Plugin parameters nvvs documents, per plugin. Every plugin also takes `is_allowed`.
+const KNOWN_PARAMETERS: &[(&str, &[(&str, ParameterKind)])] = &[
+    ("software", &[]),
+    ("context_create", &[]),
+    ("pcie", &[("test_pinned", Bool), ("test_unpinned", Bool), ("test_p2p_on", Bool), ("test_p2p_off", Bool),
+               ("test_broken_p2p", Bool), ("test_with_gemm", Bool), ("min_bandwidth", Number),
+               ("max_latency", Number), ("min_pci_gen", Number), ("min_pci_width", Number)]),
+    ("memory", &[("l1_is_allowed", Bool), ("l1cache_size_kb_per_sm", Number), ("minimum_allocation_percentage", Number)]),
+    ("memory_bandwidth", &[("minimum_bandwidth", Number)]),
+    ("diagnostic", &[("test_duration", Number), ("matrix_dim", Number), ("temperature_max", Number),
+                     ("use_doubles", Bool), ("precision", Text)]),
+    ("targeted_stress", &[("test_duration", Number), ("target_stress", Number), ("temperature_max", Number),
+                          ("use_dgemm", Bool), ("sbe_error_threshold", Number)]),
+    ("targeted_power", &[("test_duration", Number), ("target_power", Number), ("temperature_max", Number),
+                         ("use_dgemm", Bool), ("fail_on_clock_drop", Bool)]),
+    ("sm_stress", &[("test_duration", Number), ("target_stress", Number), ("temperature_max", Number),
+                    ("use_dgemm", Bool)]),
+    ("memtest", &[("test_duration", Number), ("test0", Bool), ("test1", Bool), ("test2", Bool), ("test3", Bool),
+                  ("test4", Bool), ("test5", Bool), ("test6", Bool), ("test7", Bool), ("test8", Bool),
+                  ("test9", Bool), ("test10", Bool)]),
+    ("pulse_test", &[("test_duration", Number), ("patterns", Text)]),
+    ("nvbandwidth", &[("testcases", Text)]),
+];
+
+/// One `plugin.parameter=value` setting passed to the diagnostic, e.g. `targeted_stress.test_duration=120`.
+/// Deserializes from that string form, so lists of them can sit in config files.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DiagParameter {
+    pub plugin: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl DiagParameter {
+    pub fn new(plugin: &str, name: &str, value: impl ToString) -> Self {
+        Self { plugin: plugin.to_string(), name: name.to_string(), value: value.to_string() }
+    }
+
+    /// Checks the plugin and parameter against the ones nvvs documents, and the value against the
+    /// parameter's type.
+    pub fn validate(&self) -> Result<(), DCGMError> {
+        let Some((_, known)) = KNOWN_PARAMETERS.iter().find(|(plugin, _)| *plugin == self.plugin) else {
+            let plugins: Vec<&str> = KNOWN_PARAMETERS.iter().map(|(plugin, _)| *plugin).collect();
+            return Err(DCGMError::from(format!("Unknown diag plugin '{}', expected one of {}", self.plugin, plugins.join(", "))));
+        };
+        let kind = match known.iter().find(|(name, _)| *name == self.name) {
+            Some(&(_, kind)) => kind,
+            None if self.name == "is_allowed" => Bool,
+            None => {
+                let names: Vec<&str> = std::iter::once("is_allowed").chain(known.iter().map(|(name, _)| *name)).collect();
+                return Err(DCGMError::from(format!("Unknown parameter '{}' of diag plugin {}, expected one of {}",
+                                                   self.name, self.plugin, names.join(", "))));
+            }
+        };
+        let valid = match kind {
+            Bool => matches!(self.value.to_ascii_lowercase().as_str(), "true" | "false" | "1" | "0"),
+            Number => self.value.parse::<f64>().is_ok_and(f64::is_finite),
+            Text => !self.value.is_empty(),
+        };
+        if !valid {
+            return Err(DCGMError::from(format!("{self}: expected {}", match kind {
+                Bool => "true or false",
+                Number => "a number",
+                Text => "a value",
+            })));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DiagParameter {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s.split_once('=').and_then(|(key, value)| {
+            let (plugin, name) = key.trim().split_once('.')?;
+            Some(DiagParameter::new(plugin, name, value.trim()))
+        });
+        match parsed {
+            Some(p) if !p.plugin.is_empty() && !p.name.is_empty() && !p.value.contains('\0') => Ok(p),
+            _ => Err(DCGMError::from(format!("Invalid diag parameter '{s}', expected plugin.parameter=value"))),
+        }
+    }
+}
+
+impl TryFrom<String> for DiagParameter {
+    type Error = DCGMError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DiagParameter> for String {
+    fn from(p: DiagParameter) -> Self {
+        p.to_string()
+    }
+}
+
+impl fmt::Display for DiagParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}={}", self.plugin, self.name, self.value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiagOptions {
+    pub group: dcgmGpuGrp_t,
+    pub level: DiagLevel,
+    /// Run these tests by name instead of a whole level.
+    pub tests: Vec<String>,
+    pub timeout: Option<Duration>,
+    pub fail_early: bool,
+    /// Plugin settings, e.g. a longer targeted stress run or a subset of the memtest patterns.
+    pub parameters: Vec<DiagParameter>,
+    /// Pass parameters nvvs does not document through without checking them, for newer plugins.
+    pub allow_unknown_parameters: bool,
+}
+
+impl Default for DiagOptions {
+    fn default() -> Self {
+        Self {
+            group: DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t,
+            level: DiagLevel::Short,
+            tests: Vec::new(),
+            timeout: None,
+            fail_early: false,
+            parameters: Vec::new(),
+            allow_unknown_parameters: false,
+        }
+    }
+}
+
+fn decode_response(r: &dcgmDiagResponse_v11) -> DiagReport {
+    let errors = &r.errors[..(r.numErrors as usize).min(r.errors.len())];
+    let info = &r.info[..(r.numInfo as usize).min(r.info.len())];
+    let results = &r.results[..(r.numResults as usize).min(r.results.len())];
+    let tests = r.tests[..(r.numTests as usize).min(r.tests.len())].iter().map(|t| {
+        let result_indices = &t.resultIndices[..(t.numResults as usize).min(t.resultIndices.len())];
+        let error_indices = &t.errorIndices[..(t.numErrors as usize).min(t.errorIndices.len())];
+        let info_indices = &t.infoIndices[..(t.numInfo as usize).min(t.infoIndices.len())];
+        DiagTestResult {
+            name: c_chars_to_string(&t.name),
+            plugin: c_chars_to_string(&t.pluginName),
+            result: DiagResult::from(t.result),
+            entities: result_indices.iter()
+                .filter_map(|&i| results.get(i as usize))
+                .filter_map(|e| Entity::try_from(e.entity).ok().map(|entity| (entity, DiagResult::from(e.result))))
+                .collect(),
+            errors: error_indices.iter().filter_map(|&i| errors.get(i as usize)).map(|e| DiagMessage {
+                entity: Entity::try_from(e.entity).ok(),
+                code: Some(e.code),
+                error: error_info(e.code),
+                message: c_chars_to_string(&e.msg),
+            }).collect(),
+            info: info_indices.iter().filter_map(|&i| info.get(i as usize)).map(|e| DiagMessage {
+                entity: Entity::try_from(e.entity).ok(),
+                code: None,
+                error: None,
+                message: c_chars_to_string(&e.msg),
+            }).collect(),
+        }
+    }).collect();
+    DiagReport {
+        tests,
+        dcgm_version: c_chars_to_string(&r.dcgmVersion),
+        driver_version: c_chars_to_string(&r.driverVersion),
+    }
+}
+
+impl DcgmLibSafe {
+    /// Runs DCGM diagnostics (`dcgmActionValidate_v2`). Blocks until every requested test finished.
+    pub fn run_diag(&mut self, options: &DiagOptions) -> Result<DiagReport, DCGMError>{
+        let mut request = dcgmRunDiag_v10::boxed_versioned();
+        request.groupId = options.group;
+        if options.fail_early {
+            request.flags |= DCGM_RUN_FLAGS_FAIL_EARLY;
+        }
+        if let Some(timeout) = options.timeout {
+            request.timeoutSeconds = timeout.as_secs() as u32;
+        }
+        if options.tests.is_empty() {
+            request.validate = options.level.as_validation();
+        } else {
+            if options.tests.len() > request.testNames.len() {
+                return Err(DCGMError::from(format!("At most {} tests can be requested", request.testNames.len())));
+            }
+            for (slot, name) in request.testNames.iter_mut().zip(&options.tests) {
+                copy_str(slot, name)?;
+            }
+        }
+        if options.parameters.len() > request.testParms.len() {
+            return Err(DCGMError::from(format!("At most {} diag parameters can be passed", request.testParms.len())));
+        }
+        for (slot, parameter) in request.testParms.iter_mut().zip(&options.parameters) {
+            if !options.allow_unknown_parameters {
+                parameter.validate()?;
+            }
+            copy_str(slot, &parameter.to_string())?;
+        }
+
+        let mut response = dcgmDiagResponse_v11::boxed_versioned();
+        match unsafe{self.lib()?.dcgmActionValidate_v2(self.handle(), &mut *request, &mut *response)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(decode_response(&response)),
+            err_code => Err(self.call_error(err_code, "dcgmActionValidate_v2").arg("group", options.group)
+                                    .arg("level", format!("{:?}", options.level)))
+        }
+    }
+}
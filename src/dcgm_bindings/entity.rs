@@ -0,0 +1,207 @@
+use super::bindings::*;
+use super::DCGMError;
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Typed `dcgm_field_entity_group_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EntityGroup {
+    Gpu,
+    VGpu,
+    Switch,
+    GpuInstance,
+    ComputeInstance,
+    Link,
+    Cpu,
+    CpuCore,
+    ConnectX,
+}
+
+impl EntityGroup {
+    pub const ALL: [EntityGroup; 9] = [
+        EntityGroup::Gpu,
+        EntityGroup::VGpu,
+        EntityGroup::Switch,
+        EntityGroup::GpuInstance,
+        EntityGroup::ComputeInstance,
+        EntityGroup::Link,
+        EntityGroup::Cpu,
+        EntityGroup::CpuCore,
+        EntityGroup::ConnectX,
+    ];
+
+    pub fn as_raw(self) -> dcgm_field_entity_group_t {
+        match self {
+            EntityGroup::Gpu => dcgm_field_entity_group_t_DCGM_FE_GPU,
+            EntityGroup::VGpu => dcgm_field_entity_group_t_DCGM_FE_VGPU,
+            EntityGroup::Switch => dcgm_field_entity_group_t_DCGM_FE_SWITCH,
+            EntityGroup::GpuInstance => dcgm_field_entity_group_t_DCGM_FE_GPU_I,
+            EntityGroup::ComputeInstance => dcgm_field_entity_group_t_DCGM_FE_GPU_CI,
+            EntityGroup::Link => dcgm_field_entity_group_t_DCGM_FE_LINK,
+            EntityGroup::Cpu => dcgm_field_entity_group_t_DCGM_FE_CPU,
+            EntityGroup::CpuCore => dcgm_field_entity_group_t_DCGM_FE_CPU_CORE,
+            EntityGroup::ConnectX => dcgm_field_entity_group_t_DCGM_FE_CONNECTX,
+        }
+    }
+}
+
+impl TryFrom<dcgm_field_entity_group_t> for EntityGroup {
+    type Error = DCGMError;
+
+    fn try_from(g: dcgm_field_entity_group_t) -> Result<Self, Self::Error> {
+        match g {
+            dcgm_field_entity_group_t_DCGM_FE_GPU => Ok(EntityGroup::Gpu),
+            dcgm_field_entity_group_t_DCGM_FE_VGPU => Ok(EntityGroup::VGpu),
+            dcgm_field_entity_group_t_DCGM_FE_SWITCH => Ok(EntityGroup::Switch),
+            dcgm_field_entity_group_t_DCGM_FE_GPU_I => Ok(EntityGroup::GpuInstance),
+            dcgm_field_entity_group_t_DCGM_FE_GPU_CI => Ok(EntityGroup::ComputeInstance),
+            dcgm_field_entity_group_t_DCGM_FE_LINK => Ok(EntityGroup::Link),
+            dcgm_field_entity_group_t_DCGM_FE_CPU => Ok(EntityGroup::Cpu),
+            dcgm_field_entity_group_t_DCGM_FE_CPU_CORE => Ok(EntityGroup::CpuCore),
+            dcgm_field_entity_group_t_DCGM_FE_CONNECTX => Ok(EntityGroup::ConnectX),
+            other => Err(DCGMError::from(format!("Unknown entity group {other}"))),
+        }
+    }
+}
+
+impl From<EntityGroup> for dcgm_field_entity_group_t {
+    fn from(g: EntityGroup) -> Self {
+        g.as_raw()
+    }
+}
+
+impl fmt::Display for EntityGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            EntityGroup::Gpu => "GPU",
+            EntityGroup::VGpu => "VGPU",
+            EntityGroup::Switch => "SWITCH",
+            EntityGroup::GpuInstance => "GPU_I",
+            EntityGroup::ComputeInstance => "GPU_CI",
+            EntityGroup::Link => "LINK",
+            EntityGroup::Cpu => "CPU",
+            EntityGroup::CpuCore => "CPU_CORE",
+            EntityGroup::ConnectX => "NIC",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Accepts the names `Display` prints, in any case.
+impl FromStr for EntityGroup {
+    type Err = DCGMError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EntityGroup::ALL.into_iter()
+            .find(|g| g.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| DCGMError::from(format!("Unknown entity group {s}")))
+    }
+}
+
+/// An entity group + entity id pair, the typed form of `dcgmGroupEntityPair_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Entity {
+    pub group: EntityGroup,
+    pub id: u32,
+}
+
+impl Entity {
+    pub fn new(group: EntityGroup, id: u32) -> Self {
+        Self { group, id }
+    }
+
+    pub fn gpu(id: u32) -> Self {
+        Self::new(EntityGroup::Gpu, id)
+    }
+
+    /// The `DCGM_FE_LINK` entity of one NvLink, for watching per-link fields.
+    pub fn link(link: LinkId) -> Self {
+        Self::new(EntityGroup::Link, link.to_raw())
+    }
+
+    /// The link this entity stands for, if it is a `DCGM_FE_LINK` entity with a valid id.
+    pub fn as_link(self) -> Option<LinkId> {
+        (self.group == EntityGroup::Link).then(|| LinkId::from_raw(self.id).ok()).flatten()
+    }
+
+    pub fn to_raw(self) -> dcgmGroupEntityPair_t {
+        dcgmGroupEntityPair_t { entityGroupId: self.group.as_raw(), entityId: self.id }
+    }
+}
+
+impl TryFrom<dcgmGroupEntityPair_t> for Entity {
+    type Error = DCGMError;
+
+    fn try_from(pair: dcgmGroupEntityPair_t) -> Result<Self, Self::Error> {
+        Ok(Self { group: EntityGroup::try_from(pair.entityGroupId)?, id: pair.entityId })
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.group, self.id)
+    }
+}
+
+/// One NvLink of a GPU or NvSwitch, the typed form of `dcgm_link_t`. DCGM identifies link entities
+/// by packing the parent entity type (bits 0-7), the link index (bits 8-15) and the parent id
+/// (bits 16-31) into the entity id; `to_raw` and `from_raw` convert to and from that encoding.
+///
+/// That is the layout of the DCGM 3.x headers (`uint8_t index : 8`). The 4.x headers in
+/// `src/c_headers` declare `uint32_t index : 32`, which keeps type and index at the same bits but
+/// moves the parent id past the 32-bit `raw`, so the bindgen `dcgm_link_t` only agrees with `to_raw`
+/// on the low 16 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LinkId {
+    pub parent: EntityGroup,
+    pub parent_id: u16,
+    pub index: u8,
+}
+
+impl LinkId {
+    pub fn gpu(gpu: u16, index: u8) -> Self {
+        Self { parent: EntityGroup::Gpu, parent_id: gpu, index }
+    }
+
+    pub fn switch(switch: u16, index: u8) -> Self {
+        Self { parent: EntityGroup::Switch, parent_id: switch, index }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        (self.parent.as_raw() & 0xff) | (self.index as u32) << 8 | (self.parent_id as u32) << 16
+    }
+
+    /// Fails for parents other than GPUs and NvSwitches, the only entities with links.
+    pub fn from_raw(raw: u32) -> Result<Self, DCGMError> {
+        let parent = EntityGroup::try_from(raw & 0xff)?;
+        if !matches!(parent, EntityGroup::Gpu | EntityGroup::Switch) {
+            return Err(DCGMError::from(format!("Link id {raw:#x} has parent type {parent}, expected GPU or SWITCH")));
+        }
+        Ok(Self { parent, parent_id: (raw >> 16) as u16, index: (raw >> 8) as u8 })
+    }
+}
+
+impl From<LinkId> for Entity {
+    fn from(link: LinkId) -> Self {
+        Entity::link(link)
+    }
+}
+
+impl fmt::Display for LinkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} link {}", self.parent, self.parent_id, self.index)
+    }
+}
+
+bitflags! {
+    /// Flags for `dcgmGetEntityGroupEntities` (`DCGM_GEGE_FLAG_*`).
+    ///
+    /// With no flags set DCGM lists every entity it knows about, including GPUs that are unsupported
+    /// or inactive; `ONLY_SUPPORTED` restricts the list to entities DCGM can actually manage.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct EntityListFlags: u32 {
+        const ONLY_SUPPORTED = DCGM_GEGE_FLAG_ONLY_SUPPORTED;
+    }
+}
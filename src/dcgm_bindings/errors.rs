@@ -0,0 +1,161 @@
+use super::bindings::*;
+use serde::Serialize;
+use std::ffi::CStr;
+use std::fmt;
+
+/// A documented DCGM error (`dcgmError_t`, the `DCGM_FR_*` ids of `dcgm_errors.h`) as reported by
+/// health incidents and diagnostic results, with the recommended action from the same header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ErrorInfo {
+    pub code: u32,
+    /// Symbolic id, e.g. `DCGM_FR_VOLATILE_DBE_DETECTED`, as used in NVIDIA's error documentation.
+    pub name: &'static str,
+    /// What NVIDIA recommends doing about the error; None where the header has no suggestion.
+    pub remediation: Option<&'static str>,
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.code)
+    }
+}
+
+/// The documented error with this id; None for ids newer than the bindings.
+pub fn error_info(code: u32) -> Option<ErrorInfo> {
+    let &(_, name, remediation) = ERRORS.iter().find(|(c, _, _)| *c == code)?;
+    Some(ErrorInfo { code, name, remediation: remediation.map(c_str).filter(|s| !s.is_empty() && *s != "N/A") })
+}
+
+/// Every documented error, ordered by id.
+pub fn known_errors() -> impl Iterator<Item = ErrorInfo> {
+    ERRORS.iter().filter_map(|&(code, _, _)| error_info(code))
+}
+
+fn c_str(bytes: &'static [u8]) -> &'static str {
+    CStr::from_bytes_until_nul(bytes).ok().and_then(|s| s.to_str().ok()).unwrap_or_default()
+}
+
+/// Built from `dcgm_errors.h` through the bindings, so remediation text is available without the library.
+static ERRORS: &[(dcgmError_t, &str, Option<&[u8]>)] = &[
+    (dcgmError_enum_DCGM_FR_OK, "DCGM_FR_OK", Some(DCGM_FR_OK_NEXT)),
+    (dcgmError_enum_DCGM_FR_UNKNOWN, "DCGM_FR_UNKNOWN", Some(DCGM_FR_UNKNOWN_NEXT)),
+    (dcgmError_enum_DCGM_FR_UNRECOGNIZED, "DCGM_FR_UNRECOGNIZED", Some(DCGM_FR_UNRECOGNIZED_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCI_REPLAY_RATE, "DCGM_FR_PCI_REPLAY_RATE", Some(DCGM_FR_PCI_REPLAY_RATE_NEXT)),
+    (dcgmError_enum_DCGM_FR_VOLATILE_DBE_DETECTED, "DCGM_FR_VOLATILE_DBE_DETECTED", Some(DCGM_FR_VOLATILE_DBE_DETECTED_NEXT)),
+    (dcgmError_enum_DCGM_FR_VOLATILE_SBE_DETECTED, "DCGM_FR_VOLATILE_SBE_DETECTED", Some(DCGM_FR_VOLATILE_SBE_DETECTED_NEXT)),
+    (dcgmError_enum_DCGM_FR_PENDING_PAGE_RETIREMENTS, "DCGM_FR_PENDING_PAGE_RETIREMENTS", Some(DCGM_FR_PENDING_PAGE_RETIREMENTS_NEXT)),
+    (dcgmError_enum_DCGM_FR_RETIRED_PAGES_LIMIT, "DCGM_FR_RETIRED_PAGES_LIMIT", Some(DCGM_FR_RETIRED_PAGES_LIMIT_NEXT)),
+    (dcgmError_enum_DCGM_FR_RETIRED_PAGES_DBE_LIMIT, "DCGM_FR_RETIRED_PAGES_DBE_LIMIT", Some(DCGM_FR_RETIRED_PAGES_DBE_LIMIT_NEXT)),
+    (dcgmError_enum_DCGM_FR_CORRUPT_INFOROM, "DCGM_FR_CORRUPT_INFOROM", Some(DCGM_FR_CORRUPT_INFOROM_NEXT)),
+    (dcgmError_enum_DCGM_FR_CLOCKS_EVENT_THERMAL, "DCGM_FR_CLOCKS_EVENT_THERMAL", Some(DCGM_FR_CLOCKS_EVENT_THERMAL_NEXT)),
+    (dcgmError_enum_DCGM_FR_CLOCK_THROTTLE_THERMAL, "DCGM_FR_CLOCK_THROTTLE_THERMAL", None),
+    (dcgmError_enum_DCGM_FR_POWER_UNREADABLE, "DCGM_FR_POWER_UNREADABLE", Some(DCGM_FR_POWER_UNREADABLE_NEXT)),
+    (dcgmError_enum_DCGM_FR_CLOCKS_EVENT_POWER, "DCGM_FR_CLOCKS_EVENT_POWER", Some(DCGM_FR_CLOCKS_EVENT_POWER_NEXT)),
+    (dcgmError_enum_DCGM_FR_CLOCK_THROTTLE_POWER, "DCGM_FR_CLOCK_THROTTLE_POWER", None),
+    (dcgmError_enum_DCGM_FR_NVLINK_ERROR_THRESHOLD, "DCGM_FR_NVLINK_ERROR_THRESHOLD", Some(DCGM_FR_NVLINK_ERROR_THRESHOLD_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVLINK_DOWN, "DCGM_FR_NVLINK_DOWN", Some(DCGM_FR_NVLINK_DOWN_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVSWITCH_FATAL_ERROR, "DCGM_FR_NVSWITCH_FATAL_ERROR", Some(DCGM_FR_NVSWITCH_FATAL_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVSWITCH_NON_FATAL_ERROR, "DCGM_FR_NVSWITCH_NON_FATAL_ERROR", Some(DCGM_FR_NVSWITCH_NON_FATAL_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVSWITCH_DOWN, "DCGM_FR_NVSWITCH_DOWN", Some(DCGM_FR_NVSWITCH_DOWN_NEXT)),
+    (dcgmError_enum_DCGM_FR_NO_ACCESS_TO_FILE, "DCGM_FR_NO_ACCESS_TO_FILE", Some(DCGM_FR_NO_ACCESS_TO_FILE_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVML_API, "DCGM_FR_NVML_API", Some(DCGM_FR_NVML_API_NEXT)),
+    (dcgmError_enum_DCGM_FR_DEVICE_COUNT_MISMATCH, "DCGM_FR_DEVICE_COUNT_MISMATCH", Some(DCGM_FR_DEVICE_COUNT_MISMATCH_NEXT)),
+    (dcgmError_enum_DCGM_FR_BAD_PARAMETER, "DCGM_FR_BAD_PARAMETER", Some(DCGM_FR_BAD_PARAMETER_NEXT)),
+    (dcgmError_enum_DCGM_FR_CANNOT_OPEN_LIB, "DCGM_FR_CANNOT_OPEN_LIB", Some(DCGM_FR_CANNOT_OPEN_LIB_NEXT)),
+    (dcgmError_enum_DCGM_FR_DENYLISTED_DRIVER, "DCGM_FR_DENYLISTED_DRIVER", Some(DCGM_FR_DENYLISTED_DRIVER_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVML_LIB_BAD, "DCGM_FR_NVML_LIB_BAD", Some(DCGM_FR_NVML_LIB_BAD_NEXT)),
+    (dcgmError_enum_DCGM_FR_GRAPHICS_PROCESSES, "DCGM_FR_GRAPHICS_PROCESSES", Some(DCGM_FR_GRAPHICS_PROCESSES_NEXT)),
+    (dcgmError_enum_DCGM_FR_HOSTENGINE_CONN, "DCGM_FR_HOSTENGINE_CONN", Some(DCGM_FR_HOSTENGINE_CONN_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_QUERY, "DCGM_FR_FIELD_QUERY", Some(DCGM_FR_FIELD_QUERY_NEXT)),
+    (dcgmError_enum_DCGM_FR_BAD_CUDA_ENV, "DCGM_FR_BAD_CUDA_ENV", Some(DCGM_FR_BAD_CUDA_ENV_NEXT)),
+    (dcgmError_enum_DCGM_FR_PERSISTENCE_MODE, "DCGM_FR_PERSISTENCE_MODE", Some(DCGM_FR_PERSISTENCE_MODE_NEXT)),
+    (dcgmError_enum_DCGM_FR_LOW_BANDWIDTH, "DCGM_FR_LOW_BANDWIDTH", Some(DCGM_FR_LOW_BANDWIDTH_NEXT)),
+    (dcgmError_enum_DCGM_FR_HIGH_LATENCY, "DCGM_FR_HIGH_LATENCY", Some(DCGM_FR_HIGH_LATENCY_NEXT)),
+    (dcgmError_enum_DCGM_FR_CANNOT_GET_FIELD_TAG, "DCGM_FR_CANNOT_GET_FIELD_TAG", Some(DCGM_FR_CANNOT_GET_FIELD_TAG_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_VIOLATION, "DCGM_FR_FIELD_VIOLATION", Some(DCGM_FR_FIELD_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_THRESHOLD, "DCGM_FR_FIELD_THRESHOLD", Some(DCGM_FR_FIELD_THRESHOLD_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_VIOLATION_DBL, "DCGM_FR_FIELD_VIOLATION_DBL", Some(DCGM_FR_FIELD_VIOLATION_DBL_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_THRESHOLD_DBL, "DCGM_FR_FIELD_THRESHOLD_DBL", Some(DCGM_FR_FIELD_THRESHOLD_DBL_NEXT)),
+    (dcgmError_enum_DCGM_FR_UNSUPPORTED_FIELD_TYPE, "DCGM_FR_UNSUPPORTED_FIELD_TYPE", Some(DCGM_FR_UNSUPPORTED_FIELD_TYPE_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_THRESHOLD_TS, "DCGM_FR_FIELD_THRESHOLD_TS", Some(DCGM_FR_FIELD_THRESHOLD_TS_NEXT)),
+    (dcgmError_enum_DCGM_FR_FIELD_THRESHOLD_TS_DBL, "DCGM_FR_FIELD_THRESHOLD_TS_DBL", Some(DCGM_FR_FIELD_THRESHOLD_TS_DBL_NEXT)),
+    (dcgmError_enum_DCGM_FR_THERMAL_VIOLATIONS, "DCGM_FR_THERMAL_VIOLATIONS", Some(DCGM_FR_THERMAL_VIOLATIONS_NEXT)),
+    (dcgmError_enum_DCGM_FR_THERMAL_VIOLATIONS_TS, "DCGM_FR_THERMAL_VIOLATIONS_TS", Some(DCGM_FR_THERMAL_VIOLATIONS_TS_NEXT)),
+    (dcgmError_enum_DCGM_FR_TEMP_VIOLATION, "DCGM_FR_TEMP_VIOLATION", Some(DCGM_FR_TEMP_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_CLOCKS_EVENT_VIOLATION, "DCGM_FR_CLOCKS_EVENT_VIOLATION", Some(DCGM_FR_CLOCKS_EVENT_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_THROTTLING_VIOLATION, "DCGM_FR_THROTTLING_VIOLATION", None),
+    (dcgmError_enum_DCGM_FR_INTERNAL, "DCGM_FR_INTERNAL", Some(DCGM_FR_INTERNAL_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCIE_GENERATION, "DCGM_FR_PCIE_GENERATION", Some(DCGM_FR_PCIE_GENERATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCIE_WIDTH, "DCGM_FR_PCIE_WIDTH", Some(DCGM_FR_PCIE_WIDTH_NEXT)),
+    (dcgmError_enum_DCGM_FR_ABORTED, "DCGM_FR_ABORTED", Some(DCGM_FR_ABORTED_NEXT)),
+    (dcgmError_enum_DCGM_FR_TEST_DISABLED, "DCGM_FR_TEST_DISABLED", Some(DCGM_FR_TEST_DISABLED_NEXT)),
+    (dcgmError_enum_DCGM_FR_CANNOT_GET_STAT, "DCGM_FR_CANNOT_GET_STAT", Some(DCGM_FR_CANNOT_GET_STAT_NEXT)),
+    (dcgmError_enum_DCGM_FR_STRESS_LEVEL, "DCGM_FR_STRESS_LEVEL", Some(DCGM_FR_STRESS_LEVEL_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_API, "DCGM_FR_CUDA_API", Some(DCGM_FR_CUDA_API_NEXT)),
+    (dcgmError_enum_DCGM_FR_FAULTY_MEMORY, "DCGM_FR_FAULTY_MEMORY", Some(DCGM_FR_FAULTY_MEMORY_NEXT)),
+    (dcgmError_enum_DCGM_FR_CANNOT_SET_WATCHES, "DCGM_FR_CANNOT_SET_WATCHES", Some(DCGM_FR_CANNOT_SET_WATCHES_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_UNBOUND, "DCGM_FR_CUDA_UNBOUND", Some(DCGM_FR_CUDA_UNBOUND_NEXT)),
+    (dcgmError_enum_DCGM_FR_ECC_DISABLED, "DCGM_FR_ECC_DISABLED", Some(DCGM_FR_ECC_DISABLED_NEXT)),
+    (dcgmError_enum_DCGM_FR_MEMORY_ALLOC, "DCGM_FR_MEMORY_ALLOC", Some(DCGM_FR_MEMORY_ALLOC_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_DBE, "DCGM_FR_CUDA_DBE", Some(DCGM_FR_CUDA_DBE_NEXT)),
+    (dcgmError_enum_DCGM_FR_MEMORY_MISMATCH, "DCGM_FR_MEMORY_MISMATCH", Some(DCGM_FR_MEMORY_MISMATCH_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_DEVICE, "DCGM_FR_CUDA_DEVICE", Some(DCGM_FR_CUDA_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_ECC_UNSUPPORTED, "DCGM_FR_ECC_UNSUPPORTED", Some(DCGM_FR_ECC_UNSUPPORTED_NEXT)),
+    (dcgmError_enum_DCGM_FR_ECC_PENDING, "DCGM_FR_ECC_PENDING", Some(DCGM_FR_ECC_PENDING_NEXT)),
+    (dcgmError_enum_DCGM_FR_MEMORY_BANDWIDTH, "DCGM_FR_MEMORY_BANDWIDTH", Some(DCGM_FR_MEMORY_BANDWIDTH_NEXT)),
+    (dcgmError_enum_DCGM_FR_TARGET_POWER, "DCGM_FR_TARGET_POWER", Some(DCGM_FR_TARGET_POWER_NEXT)),
+    (dcgmError_enum_DCGM_FR_API_FAIL, "DCGM_FR_API_FAIL", Some(DCGM_FR_API_FAIL_NEXT)),
+    (dcgmError_enum_DCGM_FR_API_FAIL_GPU, "DCGM_FR_API_FAIL_GPU", Some(DCGM_FR_API_FAIL_GPU_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_CONTEXT, "DCGM_FR_CUDA_CONTEXT", Some(DCGM_FR_CUDA_CONTEXT_NEXT)),
+    (dcgmError_enum_DCGM_FR_DCGM_API, "DCGM_FR_DCGM_API", Some(DCGM_FR_DCGM_API_NEXT)),
+    (dcgmError_enum_DCGM_FR_CONCURRENT_GPUS, "DCGM_FR_CONCURRENT_GPUS", Some(DCGM_FR_CONCURRENT_GPUS_NEXT)),
+    (dcgmError_enum_DCGM_FR_TOO_MANY_ERRORS, "DCGM_FR_TOO_MANY_ERRORS", Some(DCGM_FR_TOO_MANY_ERRORS_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVLINK_CRC_ERROR_THRESHOLD, "DCGM_FR_NVLINK_CRC_ERROR_THRESHOLD", Some(DCGM_FR_NVLINK_CRC_ERROR_THRESHOLD_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVLINK_ERROR_CRITICAL, "DCGM_FR_NVLINK_ERROR_CRITICAL", Some(DCGM_FR_NVLINK_ERROR_CRITICAL_NEXT)),
+    (dcgmError_enum_DCGM_FR_ENFORCED_POWER_LIMIT, "DCGM_FR_ENFORCED_POWER_LIMIT", Some(DCGM_FR_ENFORCED_POWER_LIMIT_NEXT)),
+    (dcgmError_enum_DCGM_FR_MEMORY_ALLOC_HOST, "DCGM_FR_MEMORY_ALLOC_HOST", Some(DCGM_FR_MEMORY_ALLOC_HOST_NEXT)),
+    (dcgmError_enum_DCGM_FR_GPU_OP_MODE, "DCGM_FR_GPU_OP_MODE", Some(DCGM_FR_GPU_OP_MODE_NEXT)),
+    (dcgmError_enum_DCGM_FR_NO_MEMORY_CLOCKS, "DCGM_FR_NO_MEMORY_CLOCKS", Some(DCGM_FR_NO_MEMORY_CLOCKS_NEXT)),
+    (dcgmError_enum_DCGM_FR_NO_GRAPHICS_CLOCKS, "DCGM_FR_NO_GRAPHICS_CLOCKS", Some(DCGM_FR_NO_GRAPHICS_CLOCKS_NEXT)),
+    (dcgmError_enum_DCGM_FR_HAD_TO_RESTORE_STATE, "DCGM_FR_HAD_TO_RESTORE_STATE", Some(DCGM_FR_HAD_TO_RESTORE_STATE_NEXT)),
+    (dcgmError_enum_DCGM_FR_L1TAG_UNSUPPORTED, "DCGM_FR_L1TAG_UNSUPPORTED", Some(DCGM_FR_L1TAG_UNSUPPORTED_NEXT)),
+    (dcgmError_enum_DCGM_FR_L1TAG_MISCOMPARE, "DCGM_FR_L1TAG_MISCOMPARE", Some(DCGM_FR_L1TAG_MISCOMPARE_NEXT)),
+    (dcgmError_enum_DCGM_FR_ROW_REMAP_FAILURE, "DCGM_FR_ROW_REMAP_FAILURE", Some(DCGM_FR_ROW_REMAP_FAILURE_NEXT)),
+    (dcgmError_enum_DCGM_FR_UNCONTAINED_ERROR, "DCGM_FR_UNCONTAINED_ERROR", Some(DCGM_FR_UNCONTAINED_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_EMPTY_GPU_LIST, "DCGM_FR_EMPTY_GPU_LIST", Some(DCGM_FR_EMPTY_GPU_LIST_NEXT)),
+    (dcgmError_enum_DCGM_FR_DBE_PENDING_PAGE_RETIREMENTS, "DCGM_FR_DBE_PENDING_PAGE_RETIREMENTS", Some(DCGM_FR_DBE_PENDING_PAGE_RETIREMENTS_NEXT)),
+    (dcgmError_enum_DCGM_FR_UNCORRECTABLE_ROW_REMAP, "DCGM_FR_UNCORRECTABLE_ROW_REMAP", Some(DCGM_FR_UNCORRECTABLE_ROW_REMAP_NEXT)),
+    (dcgmError_enum_DCGM_FR_PENDING_ROW_REMAP, "DCGM_FR_PENDING_ROW_REMAP", Some(DCGM_FR_PENDING_ROW_REMAP_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_MEMORY_DEVICE, "DCGM_FR_BROKEN_P2P_MEMORY_DEVICE", Some(DCGM_FR_BROKEN_P2P_MEMORY_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_WRITER_DEVICE, "DCGM_FR_BROKEN_P2P_WRITER_DEVICE", Some(DCGM_FR_BROKEN_P2P_WRITER_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVSWITCH_NVLINK_DOWN, "DCGM_FR_NVSWITCH_NVLINK_DOWN", Some(DCGM_FR_NVSWITCH_NVLINK_DOWN_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_BINARY_PERMISSIONS, "DCGM_FR_EUD_BINARY_PERMISSIONS", Some(DCGM_FR_EUD_BINARY_PERMISSIONS_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_NON_ROOT_USER, "DCGM_FR_EUD_NON_ROOT_USER", Some(DCGM_FR_EUD_NON_ROOT_USER_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_SPAWN_FAILURE, "DCGM_FR_EUD_SPAWN_FAILURE", Some(DCGM_FR_EUD_SPAWN_FAILURE_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_TIMEOUT, "DCGM_FR_EUD_TIMEOUT", Some(DCGM_FR_EUD_TIMEOUT_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_ZOMBIE, "DCGM_FR_EUD_ZOMBIE", Some(DCGM_FR_EUD_ZOMBIE_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_NON_ZERO_EXIT_CODE, "DCGM_FR_EUD_NON_ZERO_EXIT_CODE", Some(DCGM_FR_EUD_NON_ZERO_EXIT_CODE_NEXT)),
+    (dcgmError_enum_DCGM_FR_EUD_TEST_FAILED, "DCGM_FR_EUD_TEST_FAILED", Some(DCGM_FR_EUD_TEST_FAILED_NEXT)),
+    (dcgmError_enum_DCGM_FR_FILE_CREATE_PERMISSIONS, "DCGM_FR_FILE_CREATE_PERMISSIONS", Some(DCGM_FR_FILE_CREATE_PERMISSIONS_NEXT)),
+    (dcgmError_enum_DCGM_FR_PAUSE_RESUME_FAILED, "DCGM_FR_PAUSE_RESUME_FAILED", Some(DCGM_FR_PAUSE_RESUME_FAILED_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCIE_H_REPLAY_VIOLATION, "DCGM_FR_PCIE_H_REPLAY_VIOLATION", Some(DCGM_FR_PCIE_H_REPLAY_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_GPU_EXPECTED_NVLINKS_UP, "DCGM_FR_GPU_EXPECTED_NVLINKS_UP", Some(DCGM_FR_GPU_EXPECTED_NVLINKS_UP_NEXT)),
+    (dcgmError_enum_DCGM_FR_NVSWITCH_EXPECTED_NVLINKS_UP, "DCGM_FR_NVSWITCH_EXPECTED_NVLINKS_UP", Some(DCGM_FR_NVSWITCH_EXPECTED_NVLINKS_UP_NEXT)),
+    (dcgmError_enum_DCGM_FR_XID_ERROR, "DCGM_FR_XID_ERROR", Some(DCGM_FR_XID_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_SBE_VIOLATION, "DCGM_FR_SBE_VIOLATION", Some(DCGM_FR_SBE_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_DBE_VIOLATION, "DCGM_FR_DBE_VIOLATION", Some(DCGM_FR_DBE_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCIE_REPLAY_VIOLATION, "DCGM_FR_PCIE_REPLAY_VIOLATION", Some(DCGM_FR_PCIE_REPLAY_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_SBE_THRESHOLD_VIOLATION, "DCGM_FR_SBE_THRESHOLD_VIOLATION", Some(DCGM_FR_SBE_THRESHOLD_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_DBE_THRESHOLD_VIOLATION, "DCGM_FR_DBE_THRESHOLD_VIOLATION", Some(DCGM_FR_DBE_THRESHOLD_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_PCIE_REPLAY_THRESHOLD_VIOLATION, "DCGM_FR_PCIE_REPLAY_THRESHOLD_VIOLATION", Some(DCGM_FR_PCIE_REPLAY_THRESHOLD_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_CUDA_FM_NOT_INITIALIZED, "DCGM_FR_CUDA_FM_NOT_INITIALIZED", Some(DCGM_FR_CUDA_FM_NOT_INITIALIZED_NEXT)),
+    (dcgmError_enum_DCGM_FR_SXID_ERROR, "DCGM_FR_SXID_ERROR", Some(DCGM_FR_SXID_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_GFLOPS_THRESHOLD_VIOLATION, "DCGM_FR_GFLOPS_THRESHOLD_VIOLATION", Some(DCGM_FR_GFLOPS_THRESHOLD_VIOLATION_NEXT)),
+    (dcgmError_enum_DCGM_FR_NAN_VALUE, "DCGM_FR_NAN_VALUE", Some(DCGM_FR_NAN_VALUE_NEXT)),
+    (dcgmError_enum_DCGM_FR_FABRIC_MANAGER_TRAINING_ERROR, "DCGM_FR_FABRIC_MANAGER_TRAINING_ERROR", Some(DCGM_FR_FABRIC_MANAGER_TRAINING_ERROR_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_PCIE_MEMORY_DEVICE, "DCGM_FR_BROKEN_P2P_PCIE_MEMORY_DEVICE", Some(DCGM_FR_BROKEN_P2P_PCIE_MEMORY_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_PCIE_WRITER_DEVICE, "DCGM_FR_BROKEN_P2P_PCIE_WRITER_DEVICE", Some(DCGM_FR_BROKEN_P2P_PCIE_WRITER_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_NVLINK_MEMORY_DEVICE, "DCGM_FR_BROKEN_P2P_NVLINK_MEMORY_DEVICE", Some(DCGM_FR_BROKEN_P2P_NVLINK_MEMORY_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_BROKEN_P2P_NVLINK_WRITER_DEVICE, "DCGM_FR_BROKEN_P2P_NVLINK_WRITER_DEVICE", Some(DCGM_FR_BROKEN_P2P_NVLINK_WRITER_DEVICE_NEXT)),
+    (dcgmError_enum_DCGM_FR_TEST_SKIPPED, "DCGM_FR_TEST_SKIPPED", Some(DCGM_FR_TEST_SKIPPED_NEXT)),
+];
@@ -0,0 +1,463 @@
+use super::bindings::*;
+use super::c_chars_to_string;
+use super::entity::{Entity, EntityGroup};
+use super::histogram::HistogramSnapshot;
+use super::hotplug::LifecycleEvent;
+use super::latest::decode_latest;
+use super::samples::{Sample, SampleKey, Tags};
+use super::topology::sysfs_bus_id;
+use super::units::{field_semantics, FieldSemantics};
+use super::{DCGMError, DcgmLibSafe};
+use bitflags::bitflags;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::Duration;
+
+bitflags! {
+    /// GPU identity labels attached to every GPU metric.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct GpuLabels: u32 {
+        const UUID = 1;
+        const PCI_BUS_ID = 1 << 1;
+        const DEVICE_NAME = 1 << 2;
+        const MINOR_NUMBER = 1 << 3;
+    }
+}
+
+impl Default for GpuLabels {
+    /// The identity labels dcgm-exporter emits.
+    fn default() -> Self {
+        GpuLabels::UUID | GpuLabels::PCI_BUS_ID | GpuLabels::DEVICE_NAME
+    }
+}
+
+/// Static identity of a GPU, used for labels.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GpuIdentity {
+    pub gpu_id: u32,
+    pub uuid: String,
+    pub pci_bus_id: String,
+    pub device_name: String,
+    pub minor_number: Option<u32>,
+}
+
+/// Static identity of a MIG GPU or compute instance, used for labels. Its series carry the labels of
+/// its GPU plus `GPU_I_PROFILE` and `GPU_I_ID` (and `GPU_CI_ID`), like dcgm-exporter on MIG nodes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MigIdentity {
+    pub gpu_id: u32,
+    pub instance_id: u32,
+    pub compute_instance_id: Option<u32>,
+    /// Profile of the GPU instance, e.g. `1g.5gb`.
+    pub profile: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExporterConfig {
+    /// `Hostname` label value; None leaves the label out.
+    pub hostname: Option<String>,
+    /// Extra labels added to every series, e.g. cluster, rack or nodepool.
+    pub static_labels: BTreeMap<String, String>,
+    pub gpu_labels: GpuLabels,
+    /// Append each sample's DCGM timestamp (in milliseconds) to its line, so Prometheus sees when the
+    /// value was sampled rather than when it was scraped.
+    pub timestamps: bool,
+}
+
+impl Default for ExporterConfig {
+    fn default() -> Self {
+        Self { hostname: local_hostname(), static_labels: BTreeMap::new(), gpu_labels: GpuLabels::default(), timestamps: false }
+    }
+}
+
+impl ExporterConfig {
+    pub fn with_static_label(mut self, name: &str, value: &str) -> Self {
+        self.static_labels.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds a static label for every `<prefix><NAME>=value` environment variable, named after the
+    /// lowercased remainder, e.g. `DCGM_LABEL_RACK=r12` becomes `rack="r12"` with prefix `DCGM_LABEL_`.
+    pub fn static_labels_from_env(mut self, prefix: &str) -> Self {
+        for (key, value) in std::env::vars() {
+            if let Some(name) = key.strip_prefix(prefix) {
+                if !name.is_empty() {
+                    self.static_labels.insert(name.to_lowercase(), value);
+                }
+            }
+        }
+        self
+    }
+}
+
+pub(crate) fn local_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+fn read_minor_number(bus_id: &str) -> Option<u32> {
+    let info = std::fs::read_to_string(format!("/proc/driver/nvidia/gpus/{}/information", sysfs_bus_id(bus_id)?)).ok()?;
+    info.lines()
+        .find_map(|l| l.strip_prefix("Device Minor:"))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+pub(crate) fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// NVIDIA's name for a GPU instance profile: slices, then memory in GB rounded up, e.g. `1g.5gb` for
+/// 4864 MiB. Just the slices when the memory is not known.
+pub(crate) fn mig_profile_name(slices: u32, memory_mib: Option<f64>) -> String {
+    match memory_mib {
+        Some(mib) if mib > 0.0 => format!("{slices}g.{}gb", (mib / 1024.0).ceil()),
+        _ => format!("{slices}g"),
+    }
+}
+
+/// Renders samples in the Prometheus text exposition format.
+#[derive(Clone, Debug, Default)]
+pub struct Exporter {
+    config: ExporterConfig,
+    identities: BTreeMap<u32, GpuIdentity>,
+    mig: BTreeMap<Entity, MigIdentity>,
+    metric_names: HashMap<u16, String>,
+    semantics: HashMap<u16, FieldSemantics>,
+    gpu_extra_labels: BTreeMap<u32, Vec<(String, String)>>,
+}
+
+impl Exporter {
+    pub fn new(config: ExporterConfig) -> Self {
+        Self {
+            config,
+            identities: BTreeMap::new(),
+            mig: BTreeMap::new(),
+            metric_names: HashMap::new(),
+            semantics: HashMap::new(),
+            gpu_extra_labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ExporterConfig {
+        &self.config
+    }
+
+    pub fn set_identity(&mut self, identity: GpuIdentity) {
+        self.identities.insert(identity.gpu_id, identity);
+    }
+
+    /// Drops the identity and extra labels of a GPU that went away, and those of its MIG instances.
+    pub fn remove_identity(&mut self, gpu_id: u32) {
+        self.identities.remove(&gpu_id);
+        self.gpu_extra_labels.remove(&gpu_id);
+        self.mig.retain(|_, mig| mig.gpu_id != gpu_id);
+    }
+
+    pub fn set_mig_identity(&mut self, entity: Entity, identity: MigIdentity) {
+        self.mig.insert(entity, identity);
+    }
+
+    pub fn mig_identity(&self, entity: Entity) -> Option<&MigIdentity> {
+        self.mig.get(&entity)
+    }
+
+    /// Replaces the MIG instance identities with the current hierarchy. The profile names come from
+    /// the framebuffer size of each GPU instance, read live.
+    pub fn load_mig_identities(&mut self, dcgm: &mut DcgmLibSafe) -> Result<(), DCGMError> {
+        let hierarchy = dcgm.mig_hierarchy()?;
+        let mut instances: Vec<dcgmGroupEntityPair_t> = hierarchy.iter()
+            .filter(|m| m.entity.group == EntityGroup::GpuInstance)
+            .map(|m| m.entity.to_raw())
+            .collect();
+        let mut memory = Vec::new();
+        decode_latest(&dcgm.entitiesGetLatestValues(&mut instances, &mut [DCGM_FI_DEV_FB_TOTAL as u16], DCGM_FV_FLAG_LIVE_DATA)?,
+                      &mut memory);
+        let mut profiles: BTreeMap<Entity, String> = BTreeMap::new();
+        for mig in hierarchy.iter().filter(|m| m.entity.group == EntityGroup::GpuInstance) {
+            let mib = memory.iter()
+                .find(|s| s.entity_group == EntityGroup::GpuInstance && s.entity_id == mig.entity.id)
+                .and_then(|s| s.value.as_f64());
+            profiles.insert(mig.entity, mig_profile_name(mig.slices, mib));
+        }
+        self.mig = hierarchy.into_iter().map(|mig| {
+            // a compute instance is labelled with the profile of its GPU instance
+            let gpu_instance = if mig.entity.group == EntityGroup::ComputeInstance { mig.parent } else { mig.entity };
+            let identity = MigIdentity {
+                gpu_id: mig.gpu_id,
+                instance_id: mig.instance_id,
+                compute_instance_id: mig.compute_instance_id,
+                profile: profiles.get(&gpu_instance).cloned().unwrap_or_default(),
+            };
+            (mig.entity, identity)
+        }).collect();
+        Ok(())
+    }
+
+    /// Reads the identity of one GPU from DCGM.
+    pub fn load_identity(&mut self, dcgm: &mut DcgmLibSafe, gpu_id: u32) -> Result<(), DCGMError> {
+        let attributes = dcgm.device_attributes(gpu_id)?;
+        let pci_bus_id = c_chars_to_string(&attributes.identifiers.pciBusId);
+        self.set_identity(GpuIdentity {
+            gpu_id,
+            uuid: c_chars_to_string(&attributes.identifiers.uuid),
+            minor_number: read_minor_number(&pci_bus_id),
+            pci_bus_id,
+            device_name: c_chars_to_string(&attributes.identifiers.deviceName),
+        });
+        Ok(())
+    }
+
+    /// Keeps the GPU identities in line with an event from `subscribe_entity_events`.
+    pub fn apply_lifecycle_event(&mut self, dcgm: &mut DcgmLibSafe, event: &LifecycleEvent) -> Result<(), DCGMError> {
+        match *event {
+            LifecycleEvent::GpuAdded(gpu_id) => self.load_identity(dcgm, gpu_id),
+            LifecycleEvent::GpuRemoved(gpu_id) => {
+                self.remove_identity(gpu_id);
+                Ok(())
+            }
+            LifecycleEvent::MigInstanceCreated(_) | LifecycleEvent::MigInstanceDestroyed(_) => self.load_mig_identities(dcgm),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn identity(&self, gpu_id: u32) -> Option<&GpuIdentity> {
+        self.identities.get(&gpu_id)
+    }
+
+    pub fn identities(&self) -> impl Iterator<Item = &GpuIdentity> {
+        self.identities.values()
+    }
+
+    /// Replaces the per-GPU labels (e.g. pod attribution) added to that GPU's series.
+    pub fn set_gpu_labels(&mut self, gpu_id: u32, labels: Vec<(String, String)>) {
+        if labels.is_empty() {
+            self.gpu_extra_labels.remove(&gpu_id);
+        } else {
+            self.gpu_extra_labels.insert(gpu_id, labels);
+        }
+    }
+
+    pub fn set_metric_name(&mut self, field_id: u16, name: &str) {
+        self.metric_names.insert(field_id, name.to_string());
+    }
+
+    /// Metric name of a field; `DCGM_FI_<tag>` once `load_from_dcgm` ran, else `DCGM_FIELD_<id>`.
+    pub fn metric_name(&self, field_id: u16) -> String {
+        self.metric_names.get(&field_id).cloned().unwrap_or_else(|| format!("DCGM_FIELD_{field_id}"))
+    }
+
+    /// Overrides the semantics of a field, e.g. for a counter this crate does not know as one.
+    pub fn set_field_semantics(&mut self, field_id: u16, semantics: FieldSemantics) {
+        self.semantics.insert(field_id, semantics);
+    }
+
+    pub fn field_semantics(&self, field_id: u16) -> FieldSemantics {
+        self.semantics.get(&field_id).copied().unwrap_or_else(|| field_semantics(field_id))
+    }
+
+    /// Name of the exported series: `metric_name`, with `_total` appended for counters as Prometheus
+    /// and OpenMetrics name them.
+    pub fn exported_name(&self, field_id: u16) -> String {
+        let name = self.metric_name(field_id);
+        match self.field_semantics(field_id) {
+            FieldSemantics::Counter if !name.ends_with("_total") => format!("{name}_total"),
+            _ => name,
+        }
+    }
+
+    /// Fills GPU identities for every supported GPU and MIG instance, and metric names for `fields`
+    /// from DCGM.
+    pub fn load_from_dcgm(&mut self, dcgm: &mut DcgmLibSafe, fields: &[u16]) -> Result<(), DCGMError> {
+        for gpu_id in dcgm.getAllSupportedDevices()? {
+            self.load_identity(dcgm, gpu_id)?;
+        }
+        // without MIG support there are no instances to label, and GPUs keep their whole-GPU series
+        if let Err(e) = self.load_mig_identities(dcgm) {
+            tracing::debug!("No MIG instance identities: {e}");
+            self.mig.clear();
+        }
+        for &field in fields {
+            if let Some(tag) = dcgm.field_tag(field)? {
+                self.metric_names.insert(field, format!("DCGM_FI_{}", tag.to_uppercase()));
+            }
+        }
+        Ok(())
+    }
+
+    fn gpu_labels(&self, gpu_id: u32, labels: &mut Vec<(String, String)>) {
+        labels.push(("gpu".into(), gpu_id.to_string()));
+        let Some(id) = self.identities.get(&gpu_id) else { return };
+        let wanted = self.config.gpu_labels;
+        if wanted.contains(GpuLabels::UUID) {
+            labels.push(("UUID".into(), id.uuid.clone()));
+        }
+        if wanted.contains(GpuLabels::PCI_BUS_ID) {
+            labels.push(("pci_bus_id".into(), id.pci_bus_id.clone()));
+        }
+        if wanted.contains(GpuLabels::DEVICE_NAME) {
+            labels.push(("modelName".into(), id.device_name.clone()));
+        }
+        if let (true, Some(minor)) = (wanted.contains(GpuLabels::MINOR_NUMBER), id.minor_number) {
+            labels.push(("device".into(), format!("nvidia{minor}")));
+        }
+    }
+
+    fn labels(&self, key: &SampleKey, tags: &Tags) -> String {
+        let mut labels: Vec<(String, String)> = Vec::new();
+        let mig = self.mig.get(&Entity::new(key.entity_group, key.entity_id));
+        if key.entity_group == EntityGroup::Gpu {
+            self.gpu_labels(key.entity_id, &mut labels);
+            if let Some(extra) = self.gpu_extra_labels.get(&key.entity_id) {
+                labels.extend(extra.iter().cloned());
+            }
+        } else if let Some(mig) = mig {
+            // the GPU's extra labels (e.g. its pod) are left out, the GPU is shared between instances
+            self.gpu_labels(mig.gpu_id, &mut labels);
+            labels.push(("GPU_I_PROFILE".into(), mig.profile.clone()));
+            labels.push(("GPU_I_ID".into(), mig.instance_id.to_string()));
+            if let Some(ci) = mig.compute_instance_id {
+                labels.push(("GPU_CI_ID".into(), ci.to_string()));
+            }
+        } else {
+            labels.push(("entity_group".into(), key.entity_group.to_string()));
+            labels.push(("entity_id".into(), key.entity_id.to_string()));
+        }
+        if let Some(host) = &self.config.hostname {
+            labels.push(("Hostname".into(), host.clone()));
+        }
+        // a sample's tags are more specific than the static labels, so they win on a name clash
+        for (k, v) in self.config.static_labels.iter().filter(|(k, _)| tags.get(k).is_none()) {
+            labels.push((k.clone(), v.clone()));
+        }
+        for (k, v) in tags.iter() {
+            labels.push((k.to_string(), v.to_string()));
+        }
+        labels.iter()
+            .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// One metric per field, typed by its semantics: counters as `counter` named with `_total`, gauges
+    /// and enums as `gauge`. Blank and non-numeric samples and string fields are skipped.
+    pub fn render(&self, samples: &[Sample]) -> String {
+        let mut by_field: BTreeMap<u16, Vec<&Sample>> = BTreeMap::new();
+        for s in samples.iter().filter(|s| s.value.as_f64().is_some()) {
+            by_field.entry(s.field_id).or_default().push(s);
+        }
+        let mut out = String::new();
+        for (field, samples) in by_field {
+            let metric_type = match self.field_semantics(field) {
+                FieldSemantics::Counter => "counter",
+                FieldSemantics::Gauge | FieldSemantics::Enum => "gauge",
+                FieldSemantics::String => continue,
+            };
+            let name = self.exported_name(field);
+            let _ = writeln!(out, "# TYPE {name} {metric_type}");
+            for s in samples {
+                let _ = write!(out, "{name}{{{}}} {}", self.labels(&s.key(), &s.tags), s.value.as_f64().unwrap_or_default());
+                if self.config.timestamps {
+                    let _ = write!(out, " {}", s.timestamp / 1000);
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Rolling histograms as a `<metric>_histogram` histogram per field, with the configured
+    /// percentiles as `<metric>_quantile` gauges labelled `quantile="0.95"` and so on.
+    pub fn render_histograms(&self, snapshots: &[HistogramSnapshot]) -> String {
+        let mut by_field: BTreeMap<u16, Vec<&HistogramSnapshot>> = BTreeMap::new();
+        for snapshot in snapshots {
+            by_field.entry(snapshot.key.field_id).or_default().push(snapshot);
+        }
+        let mut out = String::new();
+        for (field, snapshots) in by_field {
+            let name = self.metric_name(field);
+            let _ = writeln!(out, "# TYPE {name}_histogram histogram");
+            for s in &snapshots {
+                let labels = self.labels(&s.key, &s.tags);
+                for (bound, count) in &s.buckets {
+                    let le = if bound.is_infinite() { "+Inf".to_string() } else { bound.to_string() };
+                    let _ = writeln!(out, "{name}_histogram_bucket{{{labels},le=\"{le}\"}} {count}");
+                }
+                let _ = writeln!(out, "{name}_histogram_sum{{{labels}}} {}", s.sum);
+                let _ = writeln!(out, "{name}_histogram_count{{{labels}}} {}", s.count);
+            }
+            if snapshots.iter().all(|s| s.percentiles.is_empty()) {
+                continue;
+            }
+            let _ = writeln!(out, "# TYPE {name}_quantile gauge");
+            for s in &snapshots {
+                let labels = self.labels(&s.key, &s.tags);
+                for (p, value) in &s.percentiles {
+                    let _ = writeln!(out, "{name}_quantile{{{labels},quantile=\"{}\"}} {value}", p / 100.0);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The latest sample of every series, for exporting. Series whose newest sample is older than
+/// `stale_after` are dropped, so a GPU that stopped reporting (or went away) disappears from the
+/// output instead of repeating its last value forever.
+#[derive(Clone, Debug, Default)]
+pub struct LatestSamples {
+    samples: BTreeMap<SampleKey, Sample>,
+    stale_after: Option<Duration>,
+}
+
+impl LatestSamples {
+    /// None keeps every series until it is replaced.
+    pub fn new(stale_after: Option<Duration>) -> Self {
+        Self { samples: BTreeMap::new(), stale_after }
+    }
+
+    pub fn update(&mut self, samples: impl IntoIterator<Item = Sample>) {
+        for sample in samples {
+            match self.samples.get(&sample.key()) {
+                Some(previous) if previous.timestamp > sample.timestamp => (),
+                _ => { self.samples.insert(sample.key(), sample); }
+            }
+        }
+    }
+
+    /// Drops the stale series and returns the rest. `now` is in microseconds since the epoch, like
+    /// sample timestamps.
+    pub fn current(&mut self, now: i64) -> Vec<Sample> {
+        if let Some(stale_after) = self.stale_after {
+            let oldest = now.saturating_sub(stale_after.as_micros() as i64);
+            self.samples.retain(|_, s| s.timestamp >= oldest);
+        }
+        self.samples.values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+impl DcgmLibSafe {
+    /// Short tag of a field, e.g. `gpu_temp`, from the DCGM field metadata; None for unknown fields.
+    pub fn field_tag(&self, field_id: u16) -> Result<Option<String>, DCGMError> {
+        let lib = self.lib()?;
+        unsafe {
+            lib.DcgmFieldsInit();
+            let meta = lib.DcgmFieldGetById(field_id);
+            if meta.is_null() {
+                return Ok(None);
+            }
+            Ok(Some(c_chars_to_string(&(*meta).tag)))
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use super::entity::EntityGroup;
+use super::health::HealthReport;
+use super::samples::{FieldValue, Sample};
+use super::topology::TopologyGraph;
+use super::units::Quantity;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::fmt;
+
+/// What dcgmi prints for a blank or unavailable value.
+pub const BLANK_MARKER: &str = "N/A";
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Int64(v) => write!(f, "{v}"),
+            FieldValue::Double(v) => write!(f, "{v:.3}"),
+            FieldValue::String(s) => f.write_str(s),
+            FieldValue::Blob(b) => write!(f, "<{} bytes>", b.len()),
+            FieldValue::Blank => f.write_str(BLANK_MARKER),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantity::Duration(d) => write!(f, "{} us", d.as_micros()),
+            Quantity::Ratio(r) => write!(f, "{:.1} %", r * 100.0),
+            Quantity::Count(c) => write!(f, "{c}"),
+            Quantity::Bytes(b) => write!(f, "{} MiB", (b / (1024.0 * 1024.0)).round()),
+            Quantity::Hertz(hz) => write!(f, "{} MHz", (hz / 1e6).round()),
+            q => write!(f, "{:.3} {}", q.value(), q.unit_symbol()),
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    /// `GPU 0 field 150: 45`, the way dcgmi prints a single value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} field {}: {}", self.entity_group, self.entity_id, self.field_id, self.value)
+    }
+}
+
+/// The sample value with its unit when known, e.g. `45 C` or `N/A`.
+pub fn format_value(sample: &Sample) -> String {
+    match sample.normalized() {
+        Some(Quantity::Celsius(c)) => format!("{c} C"),
+        Some(q) => q.to_string(),
+        None => sample.value.to_string(),
+    }
+}
+
+/// A usec timestamp through `strftime(fmt)`, in UTC or the local time zone. Output past 64 bytes is
+/// cut off.
+pub fn format_time(micros: i64, utc: bool, fmt: &CStr) -> String {
+    let secs = micros.div_euclid(1_000_000) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let mut buf = [0u8; 64];
+    let len = unsafe {
+        if utc {
+            libc::gmtime_r(&secs, &mut tm);
+        } else {
+            libc::localtime_r(&secs, &mut tm);
+        }
+        libc::strftime(buf.as_mut_ptr() as *mut libc::c_char, buf.len(), fmt.as_ptr(), &tm)
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// UTC `YYYY-MM-DDTHH:MM:SSZ` of a usec timestamp.
+pub fn rfc3339(micros: i64) -> String {
+    format_time(micros, true, c"%Y-%m-%dT%H:%M:%SZ")
+}
+
+/// Renders samples as a `dcgmi dmon` style table: one row per entity, one column per field.
+/// `columns` pairs each field id with the header to print for it.
+pub fn dmon_table(columns: &[(u16, &str)], samples: &[Sample]) -> String {
+    let mut rows: BTreeMap<(EntityGroup, u32), BTreeMap<u16, String>> = BTreeMap::new();
+    for s in samples {
+        rows.entry((s.entity_group, s.entity_id)).or_default().insert(s.field_id, s.value.to_string());
+    }
+    let mut out = format!("{:<12}", "#Entity");
+    for (_, header) in columns {
+        out.push_str(&format!("{header:>10}"));
+    }
+    out.push('\n');
+    for ((group, id), values) in rows {
+        out.push_str(&format!("{:<12}", format!("{group} {id}")));
+        for (field, _) in columns {
+            let v = values.get(field).map(String::as_str).unwrap_or(BLANK_MARKER);
+            out.push_str(&format!("{v:>10}"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+impl TopologyGraph {
+    /// The GPU connectivity matrix as printed by `dcgmi topo`, with `X` on the diagonal and a NUMA affinity column.
+    pub fn to_matrix(&self) -> String {
+        let gpus = self.gpus();
+        let mut out = format!("{:<8}", "");
+        for gpu in &gpus {
+            out.push_str(&format!("{:<7}", format!("GPU{gpu}")));
+        }
+        out.push_str("NUMA Affinity\n");
+        for a in &gpus {
+            out.push_str(&format!("{:<8}", format!("GPU{a}")));
+            for b in &gpus {
+                let cell = if a == b {
+                    "X".to_string()
+                } else {
+                    self.link_between(*a, *b).map(|l| l.to_string()).unwrap_or_else(|| BLANK_MARKER.to_string())
+                };
+                out.push_str(&format!("{cell:<7}"));
+            }
+            out.push_str(&self.numa_node(*a).map(|n| n.to_string()).unwrap_or_else(|| BLANK_MARKER.to_string()));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for HealthReport {
+    /// Laid out like `dcgmi health -c`: the overall result, then one line per incident.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Overall Health: {}", self.overall)?;
+        for incident in &self.incidents {
+            let entity = incident.entity.map(|e| e.to_string()).unwrap_or_else(|| BLANK_MARKER.to_string());
+            writeln!(f, "{entity:<12}{:<20}{:<10}{}", incident.system, incident.health, incident.message)?;
+            if let Some(error) = &incident.error {
+                writeln!(f, "{:<42}{error}", "")?;
+                if let Some(remediation) = error.remediation {
+                    writeln!(f, "{:<42}{remediation}", "")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
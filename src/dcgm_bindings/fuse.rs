@@ -0,0 +1,256 @@
+use super::bindings::*;
+use super::entity::EntityGroup;
+use super::exporter::Exporter;
+use super::samples::{FieldValue, Sample};
+use super::DCGMError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Files named after what scripts look for rather than the DCGM tag.
+const FILE_ALIASES: [(u16, &str); 6] = [
+    (DCGM_FI_DEV_GPU_TEMP as u16, "temp"),
+    (DCGM_FI_DEV_POWER_USAGE as u16, "power"),
+    (DCGM_FI_DEV_GPU_UTIL as u16, "util"),
+    (DCGM_FI_DEV_FB_USED as u16, "fb_used"),
+    (DCGM_FI_DEV_FB_FREE as u16, "fb_free"),
+    (DCGM_FI_DEV_SM_CLOCK as u16, "sm_clock"),
+];
+
+/// File a field is exposed as: an alias from `FILE_ALIASES`, else the lowercase DCGM tag.
+pub fn file_name(exporter: &Exporter, field_id: u16) -> String {
+    if let Some((_, alias)) = FILE_ALIASES.iter().find(|(id, _)| *id == field_id) {
+        return alias.to_string();
+    }
+    let metric = exporter.metric_name(field_id);
+    metric.strip_prefix("DCGM_FI_").unwrap_or(&metric).to_ascii_lowercase()
+}
+
+/// GPU directory (UUID, or `gpu<id>` when the identity is not known) → file name → contents.
+pub type Telemetry = BTreeMap<String, BTreeMap<String, String>>;
+
+/// The latest GPU values as a file tree, shared between the collector and the FUSE threads. Like
+/// `MetricsPage`, reads only clone a pointer to the last published tree.
+#[derive(Clone, Debug, Default)]
+pub struct TelemetryTree(Arc<RwLock<Arc<Telemetry>>>);
+
+impl TelemetryTree {
+    /// Replaces the tree with the GPU-level values of `samples`, normally `LatestSamples::current`.
+    pub fn publish(&self, exporter: &Exporter, samples: &[Sample]) {
+        let mut tree = Telemetry::new();
+        for sample in samples.iter().filter(|s| s.entity_group == EntityGroup::Gpu) {
+            if matches!(sample.value, FieldValue::Blank | FieldValue::Blob(_)) {
+                continue;
+            }
+            let gpu = exporter.identity(sample.entity_id)
+                .map_or_else(|| format!("gpu{}", sample.entity_id), |identity| identity.uuid.clone());
+            tree.entry(gpu).or_default().insert(file_name(exporter, sample.field_id), format!("{}\n", sample.value));
+        }
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(tree);
+    }
+
+    pub fn snapshot(&self) -> Arc<Telemetry> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// A read-only FUSE mount of a `TelemetryTree`: `<mountpoint>/gpus/<uuid>/temp`, `.../power`, ...
+/// Every read returns the last published value. Dropping the handle unmounts. Needs the `fuse` feature.
+pub struct FuseMount {
+    mountpoint: PathBuf,
+    #[cfg(feature = "fuse")]
+    _session: fuser::BackgroundSession,
+}
+
+impl FuseMount {
+    #[cfg(feature = "fuse")]
+    pub fn mount(mountpoint: &Path, tree: TelemetryTree) -> Result<Self, DCGMError> {
+        use fuser::MountOption;
+
+        let options = [MountOption::RO, MountOption::NoExec, MountOption::FSName("rust-dcgm".to_string())];
+        let session = fuser::spawn_mount2(fs::TelemetryFs::new(tree), mountpoint, &options)
+            .map_err(|e| DCGMError::from(format!("Failed to mount {}: {e}", mountpoint.display())))?;
+        tracing::info!("Serving GPU telemetry under {}", mountpoint.display());
+        Ok(Self { mountpoint: mountpoint.to_path_buf(), _session: session })
+    }
+
+    #[cfg(not(feature = "fuse"))]
+    pub fn mount(_mountpoint: &Path, _tree: TelemetryTree) -> Result<Self, DCGMError> {
+        Err(DCGMError::not_supported("the FUSE sink needs a build with the fuse feature"))
+    }
+
+    pub fn mountpoint(&self) -> &Path {
+        &self.mountpoint
+    }
+}
+
+#[cfg(feature = "fuse")]
+mod fs {
+    use super::TelemetryTree;
+    use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, Request};
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::time::{Duration, SystemTime};
+
+    /// Short, so a script polling a file sees new values without the kernel serving stale attributes.
+    const TTL: Duration = Duration::from_secs(1);
+    const ROOT: u64 = fuser::FUSE_ROOT_ID;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Node {
+        Root,
+        Gpus,
+        Gpu(String),
+        File(String, String),
+    }
+
+    /// Inodes are handed out the first time a path is looked up and kept for the life of the mount,
+    /// so a GPU or field that comes back gets its old inode.
+    pub(super) struct TelemetryFs {
+        tree: TelemetryTree,
+        nodes: Vec<Node>,
+        inodes: HashMap<Node, u64>,
+    }
+
+    impl TelemetryFs {
+        pub(super) fn new(tree: TelemetryTree) -> Self {
+            let mut fs = Self { tree, nodes: Vec::new(), inodes: HashMap::new() };
+            fs.inode(Node::Root);
+            fs
+        }
+
+        fn inode(&mut self, node: Node) -> u64 {
+            if let Some(&ino) = self.inodes.get(&node) {
+                return ino;
+            }
+            self.nodes.push(node.clone());
+            let ino = ROOT + self.nodes.len() as u64 - 1;
+            self.inodes.insert(node, ino);
+            ino
+        }
+
+        fn node(&self, ino: u64) -> Option<&Node> {
+            self.nodes.get(ino.checked_sub(ROOT)? as usize)
+        }
+
+        /// The node, if it is still in the published tree.
+        fn exists(&self, node: &Node) -> bool {
+            let tree = self.tree.snapshot();
+            match node {
+                Node::Root | Node::Gpus => true,
+                Node::Gpu(gpu) => tree.contains_key(gpu),
+                Node::File(gpu, file) => tree.get(gpu).is_some_and(|files| files.contains_key(file)),
+            }
+        }
+
+        fn contents(&self, node: &Node) -> Option<String> {
+            let Node::File(gpu, file) = node else { return None };
+            self.tree.snapshot().get(gpu)?.get(file).cloned()
+        }
+
+        fn attr(&self, ino: u64, node: &Node) -> FileAttr {
+            let (kind, perm, size) = match node {
+                Node::File(..) => (FileType::RegularFile, 0o444, self.contents(node).map_or(0, |c| c.len() as u64)),
+                _ => (FileType::Directory, 0o555, 0),
+            };
+            let now = SystemTime::now();
+            FileAttr {
+                ino,
+                size,
+                blocks: size.div_ceil(512),
+                atime: now,
+                mtime: now,
+                ctime: now,
+                crtime: now,
+                kind,
+                perm,
+                nlink: if kind == FileType::Directory { 2 } else { 1 },
+                uid: 0,
+                gid: 0,
+                rdev: 0,
+                blksize: 4096,
+                flags: 0,
+            }
+        }
+
+        fn children(&self, node: &Node) -> Vec<(Node, FileType, String)> {
+            let tree = self.tree.snapshot();
+            match node {
+                Node::Root => vec![(Node::Gpus, FileType::Directory, "gpus".to_string())],
+                Node::Gpus => tree.keys().map(|gpu| (Node::Gpu(gpu.clone()), FileType::Directory, gpu.clone())).collect(),
+                Node::Gpu(gpu) => tree.get(gpu).into_iter().flat_map(|files| files.keys())
+                    .map(|file| (Node::File(gpu.clone(), file.clone()), FileType::RegularFile, file.clone()))
+                    .collect(),
+                Node::File(..) => Vec::new(),
+            }
+        }
+    }
+
+    impl Filesystem for TelemetryFs {
+        fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            let (Some(parent), Some(name)) = (self.node(parent).cloned(), name.to_str()) else {
+                return reply.error(libc::ENOENT);
+            };
+            let child = match parent {
+                Node::Root if name == "gpus" => Node::Gpus,
+                Node::Gpus => Node::Gpu(name.to_string()),
+                Node::Gpu(gpu) => Node::File(gpu, name.to_string()),
+                _ => return reply.error(libc::ENOENT),
+            };
+            if !self.exists(&child) {
+                return reply.error(libc::ENOENT);
+            }
+            let ino = self.inode(child.clone());
+            reply.entry(&TTL, &self.attr(ino, &child), 0);
+        }
+
+        fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+            match self.node(ino).filter(|node| self.exists(node)) {
+                Some(node) => reply.attr(&TTL, &self.attr(ino, node)),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+            if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                return reply.error(libc::EROFS);
+            }
+            match self.node(ino) {
+                // values change size between reads, so bypass the page cache
+                Some(Node::File(..)) => reply.opened(0, fuser::consts::FOPEN_DIRECT_IO),
+                Some(_) => reply.error(libc::EISDIR),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn read(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32,
+                _lock_owner: Option<u64>, reply: ReplyData) {
+            let Some(contents) = self.node(ino).and_then(|node| self.contents(node)) else {
+                return reply.error(libc::ENOENT);
+            };
+            let start = (offset.max(0) as usize).min(contents.len());
+            let end = (start + size as usize).min(contents.len());
+            reply.data(&contents.as_bytes()[start..end]);
+        }
+
+        fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+            let Some(node) = self.node(ino).cloned() else {
+                return reply.error(libc::ENOENT);
+            };
+            let parent = match &node {
+                Node::Gpu(_) => self.inode(Node::Gpus),
+                _ => ROOT,
+            };
+            let mut entries = vec![(ino, FileType::Directory, ".".to_string()), (parent, FileType::Directory, "..".to_string())];
+            for (child, kind, name) in self.children(&node) {
+                entries.push((self.inode(child), kind, name));
+            }
+            for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset.max(0) as usize) {
+                if reply.add(ino, i as i64 + 1, kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::errors::{error_info, ErrorInfo};
+use super::init::Versioned;
+use super::{c_chars_to_string, reported_count, DCGMError, DcgmLibSafe};
+use bitflags::bitflags;
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+bitflags! {
+    /// `dcgmHealthSystems_t` bits.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct HealthSystems: u32 {
+        const PCIE = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_PCIE;
+        const NVLINK = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_NVLINK;
+        const PMU = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_PMU;
+        const MCU = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_MCU;
+        const MEM = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_MEM;
+        const SM = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_SM;
+        const INFOROM = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_INFOROM;
+        const THERMAL = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_THERMAL;
+        const POWER = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_POWER;
+        const DRIVER = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_DRIVER;
+        const NVSWITCH_NONFATAL = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_NVSWITCH_NONFATAL;
+        const NVSWITCH_FATAL = dcgmHealthSystems_enum_DCGM_HEALTH_WATCH_NVSWITCH_FATAL;
+    }
+}
+
+impl fmt::Display for HealthSystems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.iter_names().map(|(n, _)| n).collect();
+        f.write_str(&names.join("|"))
+    }
+}
+
+/// Ordered from best to worst, so `max` over incidents gives the overall result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum HealthResult {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl From<dcgmHealthWatchResults_t> for HealthResult {
+    fn from(r: dcgmHealthWatchResults_t) -> Self {
+        match r {
+            dcgmHealthWatchResult_enum_DCGM_HEALTH_RESULT_PASS => HealthResult::Pass,
+            dcgmHealthWatchResult_enum_DCGM_HEALTH_RESULT_WARN => HealthResult::Warn,
+            _ => HealthResult::Fail,
+        }
+    }
+}
+
+impl fmt::Display for HealthResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HealthResult::Pass => "Healthy",
+            HealthResult::Warn => "Warning",
+            HealthResult::Fail => "Failure",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HealthIncident {
+    #[serde(serialize_with = "serialize_systems")]
+    pub system: HealthSystems,
+    pub health: HealthResult,
+    pub entity: Option<Entity>,
+    pub message: String,
+    /// DCGM error id (`dcgmError_t`).
+    pub code: u32,
+    /// Name and recommended action for `code`, when it is a documented error.
+    pub error: Option<ErrorInfo>,
+}
+
+fn serialize_systems<S: serde::Serializer>(s: &HealthSystems, ser: S) -> Result<S::Ok, S::Error> {
+    ser.serialize_str(&s.to_string())
+}
+
+/// `dcgmHealthCheck` results for a group.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub overall: HealthResult,
+    pub incidents: Vec<HealthIncident>,
+}
+
+impl HealthReport {
+    /// GPUs with at least one incident at `min` or worse.
+    pub fn gpus_at_least(&self, min: HealthResult) -> Vec<u32> {
+        let mut gpus: Vec<u32> = self.incidents.iter()
+            .filter(|i| i.health >= min)
+            .filter_map(|i| i.entity.filter(|e| e.group == EntityGroup::Gpu).map(|e| e.id))
+            .collect();
+        gpus.sort();
+        gpus.dedup();
+        gpus
+    }
+
+    pub fn unhealthy_gpus(&self) -> Vec<u32> {
+        self.gpus_at_least(HealthResult::Warn)
+    }
+}
+
+impl DcgmLibSafe {
+    /// Enables background health watches for `systems` on `group`.
+    pub fn health_set(&mut self, group: dcgmGpuGrp_t, systems: HealthSystems, update_interval: Duration,
+                      max_keep_age: Duration) -> Result<(), DCGMError>{
+        let mut params = dcgmHealthSetParams_v2 {
+            version: dcgmHealthSetParams_v2::version(),
+            groupId: group,
+            systems: systems.bits(),
+            updateInterval: update_interval.as_micros() as i64,
+            maxKeepAge: max_keep_age.as_secs_f64(),
+        };
+        match unsafe{self.lib()?.dcgmHealthSet_v2(self.handle(), &raw mut params)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmHealthSet_v2").arg("group", group).arg("systems", format!("{systems:?}")))
+        }
+    }
+
+    pub fn health_check(&mut self, group: dcgmGpuGrp_t) -> Result<HealthReport, DCGMError>{
+        // dcgmHealthResponse_t carries 1024 incidents, keep it off the stack.
+        let mut response = dcgmHealthResponse_t::boxed_versioned();
+        match unsafe{self.lib()?.dcgmHealthCheck(self.handle(), group, &mut *response)}{
+            dcgmReturn_enum_DCGM_ST_OK => (),
+            err_code => return Err(self.call_error(err_code, "dcgmHealthCheck").arg("group", group))
+        }
+        let count = reported_count(response.incidentCount, response.incidents.len(), "health incidents");
+        let incidents = response.incidents[..count].iter().map(|i| HealthIncident {
+            system: HealthSystems::from_bits_retain(i.system),
+            health: HealthResult::from(i.health),
+            entity: Entity::try_from(i.entityInfo).ok(),
+            message: c_chars_to_string(&i.error.msg),
+            code: i.error.code,
+            error: error_info(i.error.code),
+        }).collect();
+        Ok(HealthReport { overall: HealthResult::from(response.overallHealth), incidents })
+    }
+}
@@ -0,0 +1,156 @@
+use super::samples::{Sample, SampleKey, Tags};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Settings for `HistogramAggregator`. `buckets` are inclusive upper bounds in the field's own units.
+#[derive(Clone, Debug)]
+pub struct HistogramConfig {
+    pub buckets: Vec<f64>,
+    pub percentiles: Vec<f64>,
+    pub window: Duration,
+}
+
+impl Default for HistogramConfig {
+    fn default() -> Self {
+        Self {
+            buckets: vec![10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0, 100.0],
+            percentiles: vec![50.0, 95.0, 99.0],
+            window: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Point-in-time view of one series' rolling window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot {
+    pub key: SampleKey,
+    /// Tags of the newest sample of the series.
+    pub tags: Tags,
+    /// (upper bound, cumulative count) per bucket, ending with +Inf.
+    pub buckets: Vec<(f64, u64)>,
+    /// (percentile, value) pairs in the order they were configured.
+    pub percentiles: Vec<(f64, f64)>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl HistogramSnapshot {
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        self.percentiles.iter().find(|(q, _)| *q == p).map(|(_, v)| *v)
+    }
+}
+
+/// Keeps a rolling window of numeric samples per (entity, field) and computes histograms and percentiles
+/// over it. The window is anchored to the newest sample timestamp of each series, not wall clock time.
+pub struct HistogramAggregator {
+    config: HistogramConfig,
+    series: HashMap<SampleKey, Series>,
+}
+
+#[derive(Default)]
+struct Series {
+    tags: Tags,
+    values: VecDeque<(i64, f64)>,
+}
+
+impl HistogramAggregator {
+    pub fn new(mut config: HistogramConfig) -> Self {
+        config.buckets.retain(|b| b.is_finite());
+        config.buckets.sort_by(|a, b| a.total_cmp(b));
+        config.buckets.dedup();
+        Self { config, series: HashMap::new() }
+    }
+
+    pub fn config(&self) -> &HistogramConfig {
+        &self.config
+    }
+
+    /// Adds a sample to its series. Blank and non-numeric values are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let value = match sample.value.as_f64() {
+            Some(v) if v.is_finite() => v,
+            _ => return,
+        };
+        let window_us = self.config.window.as_micros() as i64;
+        let series = self.series.entry(sample.key()).or_default();
+        let newest = series.values.iter().map(|(ts, _)| *ts).max().unwrap_or(sample.timestamp);
+        if sample.timestamp >= newest {
+            series.tags = sample.tags.clone();
+        }
+        let series = &mut series.values;
+        series.push_back((sample.timestamp, value));
+        let newest = newest.max(sample.timestamp);
+        while let Some((ts, _)) = series.front() {
+            if newest - *ts > window_us {
+                series.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn record_all<'a, I: IntoIterator<Item = &'a Sample>>(&mut self, samples: I) {
+        for sample in samples {
+            self.record(sample);
+        }
+    }
+
+    pub fn keys(&self) -> Vec<SampleKey> {
+        let mut keys: Vec<SampleKey> = self.series.keys().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn snapshot(&self, key: &SampleKey) -> Option<HistogramSnapshot> {
+        let series = self.series.get(key)?;
+        if series.values.is_empty() {
+            return None;
+        }
+        let mut values: Vec<f64> = series.values.iter().map(|(_, v)| *v).collect();
+        values.sort_by(|a, b| a.total_cmp(b));
+
+        let mut buckets = Vec::with_capacity(self.config.buckets.len() + 1);
+        for bound in &self.config.buckets {
+            let count = values.partition_point(|v| v <= bound) as u64;
+            buckets.push((*bound, count));
+        }
+        buckets.push((f64::INFINITY, values.len() as u64));
+
+        let percentiles = self.config.percentiles.iter()
+            .map(|p| (*p, nearest_rank(&values, *p)))
+            .collect();
+
+        Some(HistogramSnapshot {
+            key: *key,
+            tags: series.tags.clone(),
+            buckets,
+            percentiles,
+            count: values.len() as u64,
+            sum: values.iter().sum(),
+        })
+    }
+
+    pub fn snapshots(&self) -> Vec<HistogramSnapshot> {
+        self.keys().iter().filter_map(|k| self.snapshot(k)).collect()
+    }
+
+    /// Fraction (0.0 - 1.0) of samples in the current window strictly above `threshold`.
+    pub fn fraction_above(&self, key: &SampleKey, threshold: f64) -> Option<f64> {
+        let series = &self.series.get(key)?.values;
+        if series.is_empty() {
+            return None;
+        }
+        let above = series.iter().filter(|(_, v)| *v > threshold).count();
+        Some(above as f64 / series.len() as f64)
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+}
+
+fn nearest_rank(sorted: &[f64], p: f64) -> f64 {
+    let p = p.clamp(0.0, 100.0);
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
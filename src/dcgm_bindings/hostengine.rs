@@ -0,0 +1,415 @@
+use super::bindings::*;
+use super::shutdown::Task;
+use super::{DCGMError, DcgmLibSafe, Mode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How `Mode::StartHostengine` runs and watches its nv-hostengine child.
+#[derive(Clone, Debug)]
+pub struct HostengineOptions {
+    pub binary: PathBuf,
+    /// Must keep the hostengine in the foreground (`-n`), or it cannot be supervised.
+    pub args: Vec<String>,
+    /// Where the hostengine listens, as passed to `connectStandalone`.
+    pub address: String,
+    pub unix_socket: bool,
+    /// How long a fresh hostengine gets to accept connections.
+    pub startup_timeout: Duration,
+    /// Delay before the first restart; doubled after every failed one up to `max_backoff`. A hostengine
+    /// that stays up for `max_backoff` resets it.
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for HostengineOptions {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from("nv-hostengine"),
+            args: vec!["-n".into()],
+            address: "127.0.0.1:5555".into(),
+            unix_socket: false,
+            startup_timeout: Duration::from_secs(30),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// What the supervisor did about the hostengine child, as delivered by `hostengine_events`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum HostengineEvent {
+    Exited { pid: u32, status: String },
+    Restarted { pid: u32, attempt: u32 },
+    RestartFailed { attempt: u32, error: String },
+    /// Connected again and recreated the groups, field groups and watches of the connection, by a
+    /// supervisor restart or by `reconnect`. Ids the new hostengine handed out differently are listed as
+    /// (old, new); handles to the old ids held by the caller are stale. Health watches and policies are
+    /// not restored.
+    Reconnected {
+        groups: Vec<(dcgmGpuGrp_t, dcgmGpuGrp_t)>,
+        field_groups: Vec<(dcgmFieldGrp_t, dcgmFieldGrp_t)>,
+        /// Names of the groups and field groups created again, and how many watches were set up again.
+        restored_groups: Vec<String>,
+        restored_field_groups: Vec<String>,
+        restored_watches: usize,
+        errors: Vec<String>,
+    },
+}
+
+impl HostengineEvent {
+    /// The id `group` has after this reconnect; `group` itself when it did not change.
+    pub fn new_group_id(&self, group: dcgmGpuGrp_t) -> dcgmGpuGrp_t {
+        match self {
+            HostengineEvent::Reconnected { groups, .. } => {
+                groups.iter().find(|(old, _)| *old == group).map_or(group, |(_, new)| *new)
+            }
+            _ => group,
+        }
+    }
+
+    /// The id `field_group` has after this reconnect; `field_group` itself when it did not change.
+    pub fn new_field_group_id(&self, field_group: dcgmFieldGrp_t) -> dcgmFieldGrp_t {
+        match self {
+            HostengineEvent::Reconnected { field_groups, .. } => {
+                field_groups.iter().find(|(old, _)| *old == field_group).map_or(field_group, |(_, new)| *new)
+            }
+            _ => field_group,
+        }
+    }
+}
+
+/// `connect_standalone_within` arguments, kept to connect to the same hostengine again.
+#[derive(Clone, Debug)]
+pub(crate) struct StandaloneTarget {
+    pub(crate) address: String,
+    pub(crate) unix_socket: u32,
+    pub(crate) persist: u32,
+    pub(crate) timeout_ms: u32,
+}
+
+impl fmt::Display for HostengineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostengineEvent::Exited { pid, status } => write!(f, "nv-hostengine {pid} exited: {status}"),
+            HostengineEvent::Restarted { pid, attempt } => write!(f, "nv-hostengine restarted as {pid} (attempt {attempt})"),
+            HostengineEvent::RestartFailed { attempt, error } => write!(f, "nv-hostengine restart {attempt} failed: {error}"),
+            HostengineEvent::Reconnected { groups, field_groups, restored_groups, restored_field_groups, restored_watches, errors } => {
+                write!(f, "Reconnected to nv-hostengine, restored {} group(s), {} field group(s) and {restored_watches} watch(es)",
+                       restored_groups.len(), restored_field_groups.len())?;
+                if !groups.is_empty() || !field_groups.is_empty() {
+                    write!(f, ", {} group id(s) and {} field group id(s) changed", groups.len(), field_groups.len())?;
+                }
+                if !errors.is_empty() {
+                    write!(f, "; not restored: {}", errors.join("; "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The nv-hostengine child of a `Mode::StartHostengine` connection and the thread restarting it.
+pub(crate) struct Supervisor {
+    options: HostengineOptions,
+    child: Mutex<Option<Child>>,
+    task: Arc<Task>,
+    events: Mutex<Option<Receiver<HostengineEvent>>>,
+}
+
+impl Supervisor {
+    fn child(&self) -> MutexGuard<'_, Option<Child>> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn spawn_child(&self) -> Result<u32, DCGMError> {
+        let mut command = Command::new(&self.options.binary);
+        command.args(&self.options.args).stdin(Stdio::null()).stdout(Stdio::null());
+        // Take the hostengine down with us, even when we are killed. The signal follows the thread that
+        // forks rather than the process, so only the supervisor thread may call this.
+        unsafe {
+            command.pre_exec(|| {
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+                Ok(())
+            });
+        }
+        let child = command.spawn()
+            .map_err(|e| DCGMError::from(format!("Failed to start {}: {e}", self.options.binary.display())))?;
+        let pid = child.id();
+        *self.child() = Some(child);
+        Ok(pid)
+    }
+
+    /// The exit status of the child, once it has exited.
+    fn exited(&self) -> Option<(u32, String)> {
+        let mut child = self.child();
+        let status = child.as_mut()?.try_wait().ok()??;
+        let pid = child.take().map(|c| c.id()).unwrap_or_default();
+        Some((pid, status.to_string()))
+    }
+
+    /// Asks the child to terminate and kills it when it does not within a few seconds.
+    pub(crate) fn stop_child(&self) {
+        let Some(mut child) = self.child().take() else { return };
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        tracing::warn!("nv-hostengine {} did not exit on SIGTERM, killing it", child.id());
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    pub(crate) fn stop(&self) {
+        self.task.join();
+    }
+}
+
+impl DcgmLibSafe {
+    /// Starts nv-hostengine as a child process, connects to it, and restarts it with backoff whenever it
+    /// exits, reconnecting and recreating this connection's groups, field groups and watches each time.
+    /// `shutdown` stops the hostengine again.
+    pub fn start_hostengine(options: HostengineOptions) -> Result<Self, DCGMError> {
+        let mut dcgm = Self::initialized(Mode::StartHostengine)?;
+        dcgm.supervise_hostengine(options)?;
+        dcgm.verified()
+    }
+
+    /// `Mode::StartHostengine` with the default options; `args` may override the binary and the
+    /// address, in that order.
+    pub fn startHostengine(&mut self, args: &[&str]) -> Result<(), DCGMError>{
+        let mut options = HostengineOptions::default();
+        if let Some(binary) = args.first() {
+            options.binary = PathBuf::from(binary);
+        }
+        if let Some(address) = args.get(1) {
+            options.address = address.to_string();
+        }
+        self.supervise_hostengine(options)
+    }
+
+    /// Events of the supervised hostengine. Only the first call gets the receiver; None without a
+    /// supervised hostengine.
+    pub fn hostengine_events(&self) -> Option<Receiver<HostengineEvent>> {
+        self.hostengine.as_ref()?.events.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    fn supervise_hostengine(&mut self, options: HostengineOptions) -> Result<(), DCGMError> {
+        let (tx, events) = mpsc::channel();
+        let supervisor = Arc::new(Supervisor {
+            options,
+            child: Mutex::new(None),
+            task: Task::new("hostengine supervisor"),
+            events: Mutex::new(Some(events)),
+        });
+        // The supervisor thread starts the first child too, see `spawn_child`, and gets the connection to
+        // watch once it is up; it exits without one when connecting fails.
+        let (started_tx, started) = mpsc::channel();
+        let (client_tx, client) = mpsc::channel::<DcgmLibSafe>();
+        let (watched, stop) = (supervisor.clone(), supervisor.task.stop.clone());
+        let thread = std::thread::Builder::new()
+            .name("dcgm-hostengine".into())
+            .spawn(move || {
+                let spawned = watched.spawn_child();
+                let ok = spawned.is_ok();
+                let _ = started_tx.send(spawned);
+                if let (true, Ok(client)) = (ok, client.recv()) {
+                    supervise(client, &watched, &stop, tx);
+                }
+            })
+            .map_err(|e| DCGMError::from(format!("Failed to spawn the hostengine supervisor: {e}")))?;
+        supervisor.task.add(thread);
+        let started = started.recv().unwrap_or_else(|_| Err(DCGMError::from("The hostengine supervisor exited")));
+        if let Err(e) = started.and_then(|_| self.connect_hostengine(&supervisor)) {
+            drop(client_tx);
+            supervisor.stop_child();
+            supervisor.stop();
+            return Err(e);
+        }
+
+        self.hostengine = Some(supervisor.clone());
+        let _ = client_tx.send(self.share());
+        self.register_task(&supervisor.task);
+        Ok(())
+    }
+
+    /// Connects to the child once it accepts connections, giving up when it exits or after the startup timeout.
+    fn connect_hostengine(&mut self, supervisor: &Supervisor) -> Result<(), DCGMError> {
+        let options = &supervisor.options;
+        let deadline = Instant::now() + options.startup_timeout;
+        let args = [options.address.as_str(), if options.unix_socket { "1" } else { "0" }];
+        loop {
+            let error = match self.connectStandalone(&args) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if let Some((pid, status)) = supervisor.exited() {
+                return Err(DCGMError::from(format!("nv-hostengine {pid} exited during startup: {status}")));
+            }
+            if Instant::now() >= deadline {
+                return Err(DCGMError::timeout(format!("nv-hostengine did not accept connections within {:?}: {error}",
+                                                      options.startup_timeout)));
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    /// Connects again to the standalone hostengine this connection was made to, e.g. after it was
+    /// restarted or the connection dropped, and recreates the groups, field groups and watches created
+    /// through it. The returned `Reconnected` event lists what was restored and which ids changed; it
+    /// is logged too. Every `share()` of the connection uses the new one.
+    pub fn reconnect(&mut self) -> Result<HostengineEvent, DCGMError> {
+        let lib = self.lib()?;
+        if matches!(self.stop_mode, Mode::Embedded) {
+            return Err(DCGMError::not_supported("An embedded hostengine cannot be reconnected to"));
+        }
+        let target = self.target.lock().unwrap_or_else(|e| e.into_inner()).clone()
+            .ok_or_else(|| DCGMError::from("Not connected to a standalone hostengine"))?;
+        // the old handle belongs to the lost connection
+        unsafe { lib.dcgmDisconnect(self.handle()) };
+        self.connect_standalone_within(&target.address, target.unix_socket, target.persist, target.timeout_ms)?;
+        // the hostengine may have come back as another DCGM release
+        self.verify_struct_layouts()?;
+        self.invalidate();
+        let event = self.restore_resources();
+        tracing::warn!("{event}");
+        Ok(event)
+    }
+
+    /// Creates the recorded groups, field groups and watches on the new hostengine. What fails stays
+    /// recorded under its old id, so the next reconnect tries it again.
+    fn restore_resources(&mut self) -> HostengineEvent {
+        let (groups, field_groups, watches) = {
+            let mut resources = self.resources();
+            (std::mem::take(&mut resources.groups), std::mem::take(&mut resources.field_groups),
+             std::mem::take(&mut resources.watches))
+        };
+        let mut errors = Vec::new();
+        let (mut restored_groups, mut restored_field_groups, mut restored_watches) = (Vec::new(), Vec::new(), 0);
+        let mut group_ids = BTreeMap::new();
+        for (old, record) in groups {
+            let new = match self.createGroup(&record.name) {
+                Ok(new) => new,
+                Err(e) => {
+                    errors.push(format!("group {}: {e}", record.name));
+                    self.resources().groups.entry(old).or_insert(record);
+                    continue;
+                }
+            };
+            for entity in &record.entities {
+                if let Err(e) = self.addEntityToGroup(new, entity.group, entity.id) {
+                    errors.push(format!("{entity} in group {}: {e}", record.name));
+                }
+            }
+            group_ids.insert(old, new);
+            restored_groups.push(record.name);
+        }
+        let mut field_group_ids = BTreeMap::new();
+        for (old, mut record) in field_groups {
+            match self.fieldGroupCreate(&record.name, &mut record.fields) {
+                Ok(new) => {
+                    field_group_ids.insert(old, new);
+                    restored_field_groups.push(record.name);
+                }
+                Err(e) => {
+                    errors.push(format!("field group {}: {e}", record.name));
+                    self.resources().field_groups.entry(old).or_insert(record);
+                }
+            }
+        }
+        for ((old_group, old_field_group), watch) in watches {
+            // watches on built-in groups such as all GPUs keep their group id
+            let group = group_ids.get(&old_group).copied().or_else(|| is_builtin_group(old_group).then_some(old_group));
+            let field_group = field_group_ids.get(&old_field_group).copied();
+            let (Some(group), Some(field_group)) = (group, field_group) else {
+                errors.push(format!("watch of field group {old_field_group} on group {old_group}: its group or field group was not restored"));
+                let key = (group.unwrap_or(old_group), field_group.unwrap_or(old_field_group));
+                self.resources().watches.entry(key).or_insert(watch);
+                continue;
+            };
+            match self.watchFields(field_group, group, watch.update_freq, watch.max_keep_age, watch.max_keep_samples) {
+                Ok(()) => restored_watches += 1,
+                Err(e) => {
+                    errors.push(format!("watch of field group {field_group} on group {group}: {e}"));
+                    self.resources().watches.entry((group, field_group)).or_insert(watch);
+                }
+            }
+        }
+        HostengineEvent::Reconnected {
+            groups: group_ids.into_iter().filter(|(old, new)| old != new).collect(),
+            field_groups: field_group_ids.into_iter().filter(|(old, new)| old != new).collect(),
+            restored_groups,
+            restored_field_groups,
+            restored_watches,
+            errors,
+        }
+    }
+}
+
+/// The groups DCGM defines itself, which have the same id on every hostengine.
+fn is_builtin_group(group: dcgmGpuGrp_t) -> bool {
+    [DCGM_GROUP_ALL_GPUS, DCGM_GROUP_ALL_NVSWITCHES, DCGM_GROUP_ALL_INSTANCES, DCGM_GROUP_ALL_COMPUTE_INSTANCES,
+     DCGM_GROUP_ALL_ENTITIES].iter().any(|&builtin| builtin as dcgmGpuGrp_t == group)
+}
+
+fn supervise(mut dcgm: DcgmLibSafe, supervisor: &Supervisor, stop: &AtomicBool, tx: Sender<HostengineEvent>) {
+    let options = &supervisor.options;
+    let emit = |event: HostengineEvent| {
+        tracing::warn!("{event}");
+        let _ = tx.send(event);
+    };
+    let mut backoff = options.min_backoff;
+    let mut up_since = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(200));
+        let Some((pid, status)) = supervisor.exited() else {
+            if up_since.elapsed() >= options.max_backoff {
+                backoff = options.min_backoff;
+            }
+            continue;
+        };
+        emit(HostengineEvent::Exited { pid, status });
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let resume = Instant::now() + backoff;
+            while Instant::now() < resume {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            backoff = (backoff * 2).min(options.max_backoff);
+
+            // the old handle belongs to the dead hostengine
+            let Ok(lib) = dcgm.lib() else { return };
+            unsafe { lib.dcgmDisconnect(dcgm.handle()) };
+            let restarted = supervisor.spawn_child().and_then(|pid| dcgm.connect_hostengine(supervisor).map(|()| pid));
+            match restarted {
+                Ok(pid) => {
+                    emit(HostengineEvent::Restarted { pid, attempt });
+                    dcgm.invalidate();
+                    emit(dcgm.restore_resources());
+                    up_since = Instant::now();
+                    break;
+                }
+                Err(e) => {
+                    supervisor.stop_child();
+                    emit(HostengineEvent::RestartFailed { attempt, error: e.to_string() });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup, EntityListFlags};
+use super::shutdown::Task;
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often long-running consumers such as the daemon diff the entity lists by default.
+pub const DEFAULT_ENTITY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An entity that appeared or disappeared between two `EntityWatcher::poll` calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum EntityEvent {
+    EntityAdded(Entity),
+    EntityRemoved(Entity),
+}
+
+impl EntityEvent {
+    pub fn entity(&self) -> Entity {
+        match self {
+            EntityEvent::EntityAdded(e) | EntityEvent::EntityRemoved(e) => *e,
+        }
+    }
+}
+
+impl fmt::Display for EntityEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityEvent::EntityAdded(e) => write!(f, "{e} added"),
+            EntityEvent::EntityRemoved(e) => write!(f, "{e} removed"),
+        }
+    }
+}
+
+/// `EntityEvent` by what it means for consumers, as delivered by `subscribe_entity_events`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum LifecycleEvent {
+    GpuAdded(u32),
+    GpuRemoved(u32),
+    /// A MIG GPU instance or compute instance was created.
+    MigInstanceCreated(Entity),
+    MigInstanceDestroyed(Entity),
+    SwitchAppeared(u32),
+    SwitchRemoved(u32),
+    /// Any other entity type the subscription was asked to watch.
+    Other(EntityEvent),
+}
+
+impl From<EntityEvent> for LifecycleEvent {
+    fn from(event: EntityEvent) -> Self {
+        match event {
+            EntityEvent::EntityAdded(e) => match e.group {
+                EntityGroup::Gpu => LifecycleEvent::GpuAdded(e.id),
+                EntityGroup::GpuInstance | EntityGroup::ComputeInstance => LifecycleEvent::MigInstanceCreated(e),
+                EntityGroup::Switch => LifecycleEvent::SwitchAppeared(e.id),
+                _ => LifecycleEvent::Other(event),
+            },
+            EntityEvent::EntityRemoved(e) => match e.group {
+                EntityGroup::Gpu => LifecycleEvent::GpuRemoved(e.id),
+                EntityGroup::GpuInstance | EntityGroup::ComputeInstance => LifecycleEvent::MigInstanceDestroyed(e),
+                EntityGroup::Switch => LifecycleEvent::SwitchRemoved(e.id),
+                _ => LifecycleEvent::Other(event),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LifecycleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleEvent::GpuAdded(id) => write!(f, "GPU {id} added"),
+            LifecycleEvent::GpuRemoved(id) => write!(f, "GPU {id} removed"),
+            LifecycleEvent::MigInstanceCreated(e) => write!(f, "{e} created"),
+            LifecycleEvent::MigInstanceDestroyed(e) => write!(f, "{e} destroyed"),
+            LifecycleEvent::SwitchAppeared(id) => write!(f, "SWITCH {id} appeared"),
+            LifecycleEvent::SwitchRemoved(id) => write!(f, "SWITCH {id} removed"),
+            LifecycleEvent::Other(event) => event.fmt(f),
+        }
+    }
+}
+
+/// A GPU group whose membership follows every entity of `kinds`.
+#[derive(Clone, Debug)]
+struct ManagedGroup {
+    group: dcgmGpuGrp_t,
+    kinds: Vec<EntityGroup>,
+}
+
+/// Detects GPU hot-plug, GPU resets and MIG reconfiguration by diffing the supported GPUs and the
+/// MIG instances between polls, and keeps managed GPU groups in sync with what it finds.
+#[derive(Clone, Debug)]
+pub struct EntityWatcher {
+    kinds: Vec<EntityGroup>,
+    known: BTreeSet<Entity>,
+    managed: Vec<ManagedGroup>,
+}
+
+impl EntityWatcher {
+    /// Entity types watched by `new`: supported GPUs and MIG GPU and compute instances.
+    pub const DEFAULT_KINDS: [EntityGroup; 3] = [EntityGroup::Gpu, EntityGroup::GpuInstance, EntityGroup::ComputeInstance];
+
+    /// Takes the initial snapshot; entities present now are not reported as added.
+    pub fn new(dcgm: &mut DcgmLibSafe) -> Result<Self, DCGMError> {
+        Self::with_kinds(dcgm, &Self::DEFAULT_KINDS)
+    }
+
+    pub fn with_kinds(dcgm: &mut DcgmLibSafe, kinds: &[EntityGroup]) -> Result<Self, DCGMError> {
+        let mut watcher = Self { kinds: kinds.to_vec(), known: BTreeSet::new(), managed: Vec::new() };
+        watcher.known = watcher.list(dcgm)?;
+        Ok(watcher)
+    }
+
+    /// Entities seen by the last poll.
+    pub fn entities(&self) -> &BTreeSet<Entity> {
+        &self.known
+    }
+
+    /// Entities of one type seen by the last poll.
+    pub fn entities_of(&self, kind: EntityGroup) -> Vec<Entity> {
+        self.known.iter().filter(|e| e.group == kind).copied().collect()
+    }
+
+    /// Keeps `group` containing every entity of `kinds`: entities are added to it when they appear and
+    /// removed when they go away. Current entities are added right away.
+    pub fn manage(&mut self, dcgm: &mut DcgmLibSafe, group: dcgmGpuGrp_t, kinds: &[EntityGroup]) -> Result<(), DCGMError> {
+        let members = dcgm.getGroupEntities(group)?;
+        for entity in self.known.iter().filter(|e| kinds.contains(&e.group) && !members.contains(e)) {
+            dcgm.addEntityToGroup(group, entity.group, entity.id)?;
+        }
+        self.unmanage(group);
+        self.managed.push(ManagedGroup { group, kinds: kinds.to_vec() });
+        Ok(())
+    }
+
+    /// Stops updating `group`; its current members are left as they are.
+    pub fn unmanage(&mut self, group: dcgmGpuGrp_t) {
+        self.managed.retain(|m| m.group != group);
+    }
+
+    /// Lists the entities again and returns what changed since the last poll, removals first. Managed
+    /// groups are updated and cached attributes of changed GPUs dropped before returning.
+    pub fn poll(&mut self, dcgm: &mut DcgmLibSafe) -> Result<Vec<EntityEvent>, DCGMError> {
+        let current = self.list(dcgm)?;
+        let mut events: Vec<EntityEvent> = self.known.difference(&current).copied().map(EntityEvent::EntityRemoved).collect();
+        events.extend(current.difference(&self.known).copied().map(EntityEvent::EntityAdded));
+        self.known = current;
+        for event in &events {
+            self.apply(dcgm, *event);
+        }
+        Ok(events)
+    }
+
+    /// Entities of every watched type. A type that cannot be listed keeps the entities it had, so a
+    /// transient error (or a host without MIG support) is not reported as removals; only when no type
+    /// can be listed is the error returned.
+    fn list(&self, dcgm: &mut DcgmLibSafe) -> Result<BTreeSet<Entity>, DCGMError> {
+        let mut current = BTreeSet::new();
+        let mut last_error = None;
+        let mut listed = 0;
+        for &kind in &self.kinds {
+            match list_entities(dcgm, kind) {
+                Ok(entities) => {
+                    current.extend(entities);
+                    listed += 1;
+                }
+                Err(e) => {
+                    tracing::debug!("Could not list {kind} entities, keeping the previous ones: {e}");
+                    current.extend(self.known.iter().filter(|e| e.group == kind).copied());
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if listed == 0 => Err(e),
+            _ => Ok(current),
+        }
+    }
+
+    fn apply(&self, dcgm: &mut DcgmLibSafe, event: EntityEvent) {
+        let entity = event.entity();
+        if entity.group == EntityGroup::Gpu {
+            // a GPU id can come back after a reset as a different board
+            dcgm.invalidate_attributes(entity.id);
+        }
+        for managed in self.managed.iter().filter(|m| m.kinds.contains(&entity.group)) {
+            let result = match event {
+                EntityEvent::EntityAdded(e) => dcgm.addEntityToGroup(managed.group, e.group, e.id),
+                EntityEvent::EntityRemoved(e) => dcgm.removeEntityFromGroup(managed.group, e.group, e.id),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to update group {} for {event}: {e}", managed.group);
+            }
+        }
+    }
+}
+
+fn list_entities(dcgm: &mut DcgmLibSafe, kind: EntityGroup) -> Result<Vec<Entity>, DCGMError> {
+    let ids = dcgm.entity_group_entities(kind, EntityListFlags::ONLY_SUPPORTED)?;
+    Ok(ids.into_iter().map(|id| Entity::new(kind, id)).collect())
+}
+
+/// A running `subscribe_entity_events` poller. Events arrive on `events`; dropping the handle stops it.
+pub struct EntitySubscription {
+    pub events: Receiver<LifecycleEvent>,
+    task: Arc<Task>,
+}
+
+impl EntitySubscription {
+    /// Stops polling and waits for the poller thread to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.task.join();
+    }
+}
+
+impl Drop for EntitySubscription {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl DcgmLibSafe {
+    /// GPUs, MIG instances and NvSwitches appearing and going away, checked every
+    /// `DEFAULT_ENTITY_POLL_INTERVAL` on a thread sharing this connection.
+    pub fn subscribe_entity_events(&mut self) -> Result<EntitySubscription, DCGMError> {
+        self.subscribe_entity_events_every(DEFAULT_ENTITY_POLL_INTERVAL)
+    }
+
+    /// `subscribe_entity_events` with a custom poll interval. The entities present now are the baseline
+    /// and are not reported.
+    pub fn subscribe_entity_events_every(&mut self, interval: Duration) -> Result<EntitySubscription, DCGMError> {
+        if interval.is_zero() {
+            return Err(DCGMError::from("Entity poll interval must not be zero"));
+        }
+        let mut kinds = EntityWatcher::DEFAULT_KINDS.to_vec();
+        kinds.push(EntityGroup::Switch);
+        let watcher = EntityWatcher::with_kinds(self, &kinds)?;
+        let task = Task::new("entity subscription");
+        let (tx, events) = mpsc::channel();
+        let (client, poller_stop) = (self.share(), task.stop.clone());
+        let thread = std::thread::Builder::new()
+            .name("dcgm-entity-events".into())
+            .spawn(move || poller(client, watcher, interval, &poller_stop, tx))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn entity poller: {e}")))?;
+        task.add(thread);
+        self.register_task(&task);
+        Ok(EntitySubscription { events, task })
+    }
+}
+
+fn poller(mut dcgm: DcgmLibSafe, mut watcher: EntityWatcher, interval: Duration, stop: &AtomicBool,
+          tx: Sender<LifecycleEvent>) {
+    let tick = Duration::from_millis(50);
+    let mut next = Instant::now() + interval;
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if next > now {
+            // Sleep in short steps so stop requests are honoured promptly.
+            std::thread::sleep((next - now).min(tick));
+            continue;
+        }
+        next = now + interval;
+        match watcher.poll(&mut dcgm) {
+            Ok(events) => {
+                for event in events {
+                    if tx.send(LifecycleEvent::from(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Failed to list entities: {e}"),
+        }
+    }
+}
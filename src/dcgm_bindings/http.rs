@@ -0,0 +1,541 @@
+use super::systemd;
+use super::DCGMError;
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Connections served at the same time; further ones are closed right away.
+const MAX_CONNECTIONS: usize = 64;
+const MAX_REQUEST_HEAD: usize = 8192;
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP endpoint serving the Prometheus text on `/metrics`. Under systemd socket activation a
+/// passed socket bound to `listen` is used instead of binding a new one.
+///
+/// ```toml
+/// [[sinks]]
+/// type = "http"
+/// listen = "0.0.0.0:9400"
+/// tls = { cert = "/etc/rust-dcgm/tls.crt", key = "/etc/rust-dcgm/tls.key" }
+/// auth = { type = "bearer", token = "..." }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    pub listen: SocketAddr,
+    /// Serve HTTPS with this certificate chain and key (PEM). Needs the `tls` feature.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub auth: Option<HttpAuth>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Credentials every request has to carry in its `Authorization` header.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum HttpAuth {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+}
+
+/// Keeps secrets out of logs; configs are printed with `{:?}` in error messages.
+impl fmt::Debug for HttpAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpAuth::Basic { username, .. } => f.debug_struct("Basic").field("username", username).finish_non_exhaustive(),
+            HttpAuth::Bearer { .. } => f.debug_struct("Bearer").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl HttpAuth {
+    fn expected_header(&self) -> String {
+        match self {
+            HttpAuth::Basic { username, password } => {
+                format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}")))
+            }
+            HttpAuth::Bearer { token } => format!("Bearer {token}"),
+        }
+    }
+
+    /// Whether an `Authorization` header value matches. Compares in constant time.
+    pub fn authorizes(&self, header: Option<&str>) -> bool {
+        let expected = self.expected_header();
+        let Some(given) = header.map(str::trim) else { return false };
+        given.len() == expected.len()
+            && given.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    fn challenge(&self) -> &'static str {
+        match self {
+            HttpAuth::Basic { .. } => "Basic realm=\"rust-dcgm\"",
+            HttpAuth::Bearer { .. } => "Bearer realm=\"rust-dcgm\"",
+        }
+    }
+
+    /// Problems with the credentials themselves, for config validation.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self {
+            HttpAuth::Basic { username, password } => {
+                if username.is_empty() || password.is_empty() {
+                    problems.push("basic auth needs a username and a password".to_string());
+                }
+                if username.contains(':') {
+                    problems.push("basic auth username must not contain ':'".to_string());
+                }
+            }
+            HttpAuth::Bearer { token } if token.trim().is_empty() => problems.push("bearer token is empty".to_string()),
+            HttpAuth::Bearer { .. } => (),
+        }
+        problems
+    }
+}
+
+/// The text served on `/metrics`, shared between the collector and the server threads.
+///
+/// Scrapes only ever read the last published page: they never wait for a collection or a DCGM call,
+/// and the lock is held just long enough to clone a pointer, so concurrent scrapes and a publish
+/// do not hold each other up.
+#[derive(Clone, Debug, Default)]
+pub struct MetricsPage(Arc<RwLock<Arc<str>>>);
+
+impl MetricsPage {
+    pub fn publish(&self, text: String) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::from(text);
+    }
+
+    pub fn snapshot(&self) -> Arc<str> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// A running HTTP(S) metrics endpoint. Dropping the handle stops accepting connections.
+pub struct MetricsServer {
+    config: HttpConfig,
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(config: &HttpConfig, page: MetricsPage) -> Result<Self, DCGMError> {
+        let tls = match &config.tls {
+            Some(tls) => Some(load_tls(tls)?),
+            None => None,
+        };
+        if config.tls.is_none() && config.auth.is_some() {
+            tracing::warn!("Credentials for {} are sent in clear text without TLS", config.listen);
+        }
+        // a socket passed by systemd socket activation takes the place of our own
+        let listener = systemd::take_listener(config.listen).map_or_else(|| TcpListener::bind(config.listen), Ok)
+            .and_then(|l| l.set_nonblocking(true).map(|_| l))
+            .map_err(|e| DCGMError::from(format!("Failed to listen on {}: {e}", config.listen)))?;
+        let local_addr = listener.local_addr().map_err(|e| DCGMError::from(e.to_string()))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (auth, acceptor_stop) = (config.auth.clone(), stop.clone());
+        let thread = std::thread::Builder::new()
+            .name(format!("dcgm-http-{}", local_addr.port()))
+            .spawn(move || accept_loop(listener, tls, auth, page, &acceptor_stop))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn HTTP server: {e}")))?;
+        tracing::info!("Serving metrics on {}://{local_addr}/metrics", if config.tls.is_some() { "https" } else { "http" });
+        Ok(Self { config: config.clone(), local_addr, stop, thread: Some(thread) })
+    }
+
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    /// Bound address, useful when listening on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections; requests being served are finished by their own threads.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(feature = "tls")]
+type Tls = Arc<rustls::ServerConfig>;
+#[cfg(not(feature = "tls"))]
+type Tls = ();
+
+#[cfg(feature = "tls")]
+fn load_tls(config: &TlsConfig) -> Result<Tls, DCGMError> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", config.cert.display())))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", config.key.display())))?;
+    let server = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|b| b.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| DCGMError::from(format!("Invalid TLS certificate or key: {e}")))?;
+    Ok(Arc::new(server))
+}
+
+#[cfg(not(feature = "tls"))]
+fn load_tls(_config: &TlsConfig) -> Result<Tls, DCGMError> {
+    Err(DCGMError::not_supported("TLS needs a build with the tls feature"))
+}
+
+fn accept_loop(listener: TcpListener, tls: Option<Tls>, auth: Option<HttpAuth>, page: MetricsPage, stop: &AtomicBool) {
+    let active = Arc::new(AtomicUsize::new(0));
+    let tick = Duration::from_millis(50);
+    while !stop.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                // Poll in short steps so stop requests are honoured promptly.
+                std::thread::sleep(tick);
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to accept a metrics connection: {e}");
+                std::thread::sleep(tick);
+                continue;
+            }
+        };
+        if active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::Relaxed);
+            continue;
+        }
+        // without the tls feature Tls is () and copied into the thread
+        #[cfg(feature = "tls")]
+        let tls = tls.clone();
+        let (auth, page, active) = (auth.clone(), page.clone(), active.clone());
+        let spawned = std::thread::Builder::new().name("dcgm-http-conn".into()).spawn(move || {
+            if let Err(e) = serve_connection(stream, tls, auth.as_ref(), &page) {
+                tracing::debug!("Metrics connection failed: {e}");
+            }
+            active.fetch_sub(1, Ordering::Relaxed);
+        });
+        if let Err(e) = spawned {
+            tracing::warn!("Failed to spawn a metrics connection thread: {e}");
+        }
+    }
+}
+
+fn serve_connection(stream: TcpStream, tls: Option<Tls>, auth: Option<&HttpAuth>, page: &MetricsPage) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    match tls {
+        #[cfg(feature = "tls")]
+        Some(config) => {
+            let connection = rustls::ServerConnection::new(config).map_err(std::io::Error::other)?;
+            let mut stream = rustls::StreamOwned::new(connection, stream);
+            respond(&mut stream, auth, page)?;
+            stream.conn.send_close_notify();
+            stream.flush()
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(()) => unreachable!("TLS configs are rejected without the tls feature"),
+        None => {
+            let mut stream = stream;
+            respond(&mut stream, auth, page)
+        }
+    }
+}
+
+/// Method, path and `Authorization` header of a request; the rest of the head is ignored.
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+}
+
+fn read_request<S: Read>(stream: &mut S) -> std::io::Result<Option<Request>> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else { return Ok(None) };
+    let authorization = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .map(|(_, value)| value.trim().to_string());
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    Ok(Some(Request { method: method.to_string(), path, authorization }))
+}
+
+fn respond<S: Read + Write>(stream: &mut S, auth: Option<&HttpAuth>, page: &MetricsPage) -> std::io::Result<()> {
+    let Some(request) = read_request(stream)? else {
+        return write_response(stream, "400 Bad Request", "text/plain", &[], b"bad request\n", false);
+    };
+    let head_only = request.method == "HEAD";
+    if request.method != "GET" && !head_only {
+        return write_response(stream, "405 Method Not Allowed", "text/plain", &[("Allow", "GET, HEAD")], b"", head_only);
+    }
+    if let Some(auth) = auth {
+        if !auth.authorizes(request.authorization.as_deref()) {
+            return write_response(stream, "401 Unauthorized", "text/plain", &[("WWW-Authenticate", auth.challenge())],
+                                  b"unauthorized\n", head_only);
+        }
+    }
+    match request.path.as_str() {
+        "/metrics" => {
+            let body = page.snapshot();
+            write_response(stream, "200 OK", "text/plain; version=0.0.4; charset=utf-8", &[], body.as_bytes(), head_only)
+        }
+        "/" => write_response(stream, "200 OK", "text/plain", &[], b"rust-dcgm exporter, metrics at /metrics\n", head_only),
+        _ => write_response(stream, "404 Not Found", "text/plain", &[], b"not found\n", head_only),
+    }
+}
+
+fn write_response<S: Write>(stream: &mut S, status: &str, content_type: &str, headers: &[(&str, &str)], body: &[u8],
+                            head_only: bool) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n", body.len());
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    if !head_only {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// CA bundle for outgoing https requests when none is configured.
+pub(crate) const SYSTEM_CA_FILE: &str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// An `http://` or `https://` URL outgoing requests (webhooks, the Kubernetes API) are sent to.
+pub(crate) struct Url {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, at least `/`.
+    pub path: String,
+}
+
+impl Url {
+    pub(crate) fn parse(url: &str) -> Result<Self, String> {
+        let (https, rest) = match (url.strip_prefix("https://"), url.strip_prefix("http://")) {
+            (Some(rest), _) => (true, rest),
+            (_, Some(rest)) => (false, rest),
+            _ => return Err(format!("url '{url}' must start with http:// or https://")),
+        };
+        let (authority, path) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        if authority.is_empty() || authority.contains('@') {
+            return Err("url needs a host and must not carry credentials, use headers".to_string());
+        }
+        let (host, port) = match authority.rfind(':') {
+            // a colon inside brackets is part of an IPv6 address
+            Some(i) if !authority[i..].contains(']') => {
+                let port = authority[i + 1..].parse().map_err(|_| format!("invalid port in url '{authority}'"))?;
+                (&authority[..i], port)
+            }
+            _ => (authority, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() || host.starts_with('[') != host.ends_with(']') {
+            return Err(format!("invalid host in url '{authority}'"));
+        }
+        let path = match path {
+            "" => "/".to_string(),
+            p if p.starts_with('?') => format!("/{p}"),
+            p => p.to_string(),
+        };
+        Ok(Url { https, host: host.to_string(), port, path })
+    }
+
+    /// Host, with the port when it is not the scheme's default.
+    pub(crate) fn authority(&self) -> String {
+        let default_port = if self.https { 443 } else { 80 };
+        if self.port == default_port { self.host.clone() } else { format!("{}:{}", self.host, self.port) }
+    }
+}
+
+#[cfg(feature = "tls")]
+pub(crate) type ClientTls = Arc<rustls::ClientConfig>;
+#[cfg(not(feature = "tls"))]
+pub(crate) type ClientTls = ();
+
+/// TLS settings trusting the certificates in `ca_file` (PEM).
+#[cfg(feature = "tls")]
+pub(crate) fn client_tls(ca_file: &Path) -> Result<ClientTls, DCGMError> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+
+    let certs = CertificateDer::pem_file_iter(ca_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| DCGMError::from(format!("Failed to read {}: {e}", ca_file.display())))?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_parsable_certificates(certs);
+    let client = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| DCGMError::from(format!("Invalid TLS settings: {e}")))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(client))
+}
+
+#[cfg(not(feature = "tls"))]
+pub(crate) fn client_tls(_ca_file: &Path) -> Result<ClientTls, DCGMError> {
+    Err(DCGMError::not_supported("https needs a build with the tls feature"))
+}
+
+/// A request to send with `send`.
+pub(crate) struct Outgoing<'a> {
+    pub method: &'a str,
+    pub url: &'a Url,
+    pub content_type: &'a str,
+    pub headers: &'a BTreeMap<String, String>,
+    pub body: &'a [u8],
+    pub timeout: Duration,
+}
+
+/// Sends `request` over a new connection, with `tls` for https URLs, and returns the response status.
+/// The rest of the response is not read.
+pub(crate) fn send(request: &Outgoing, tls: Option<&ClientTls>) -> std::io::Result<u16> {
+    let url = request.url;
+    let host = url.host.trim_start_matches('[').trim_end_matches(']');
+    let addr = (host, url.port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("{host} did not resolve")))?;
+    let stream = TcpStream::connect_timeout(&addr, request.timeout)?;
+    stream.set_read_timeout(Some(request.timeout))?;
+    stream.set_write_timeout(Some(request.timeout))?;
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nUser-Agent: rust-dcgm\r\n\
+                            Connection: close\r\n", request.method, url.path, url.authority(), request.content_type, request.body.len());
+    for (name, value) in request.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    match (url.https, tls) {
+        #[cfg(feature = "tls")]
+        (true, Some(tls)) => {
+            let name = rustls::pki_types::ServerName::try_from(host.to_string()).map_err(std::io::Error::other)?;
+            let connection = rustls::ClientConnection::new(tls.clone(), name).map_err(std::io::Error::other)?;
+            exchange(&mut rustls::StreamOwned::new(connection, stream), head.as_bytes(), request.body)
+        }
+        (true, _) => Err(std::io::Error::other("https needs TLS settings")),
+        (false, _) => exchange(&mut { stream }, head.as_bytes(), request.body),
+    }
+}
+
+fn exchange<S: Read + Write>(stream: &mut S, head: &[u8], body: &[u8]) -> std::io::Result<u16> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    let mut status_line = Vec::with_capacity(64);
+    let mut byte = [0u8; 1];
+    while !status_line.ends_with(b"\r\n") && status_line.len() < 256 {
+        if stream.read(&mut byte)? == 0 {
+            break;
+        }
+        status_line.push(byte[0]);
+    }
+    String::from_utf8_lossy(&status_line).split_whitespace().nth(1).and_then(|s| s.parse().ok())
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "malformed HTTP response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &[u8]) -> Option<Request> {
+        read_request(&mut &raw[..]).unwrap()
+    }
+
+    #[test]
+    fn auth_rejects_missing_and_wrong_credentials() {
+        let bearer = HttpAuth::Bearer { token: "secret".to_string() };
+        assert!(bearer.authorizes(Some(" Bearer secret ")));
+        assert!(!bearer.authorizes(None));
+        assert!(!bearer.authorizes(Some("")));
+        assert!(!bearer.authorizes(Some("Bearer secre")));
+        assert!(!bearer.authorizes(Some("Bearer secret2")));
+        assert!(!bearer.authorizes(Some("bearer secret")));
+
+        let basic = HttpAuth::Basic { username: "prom".to_string(), password: "pw".to_string() };
+        // base64 of "prom:pw"
+        assert!(basic.authorizes(Some("Basic cHJvbTpwdw==")));
+        assert!(!basic.authorizes(Some("Basic cHJvbTpwdg==")));
+        assert!(!basic.authorizes(Some("Bearer pw")));
+    }
+
+    #[test]
+    fn requests_are_read_up_to_the_end_of_the_head() {
+        let parsed = request(b"GET /metrics?x=1 HTTP/1.1\r\nHost: a\r\nauthorization:  Bearer t \r\n\r\nbody").unwrap();
+        assert_eq!((parsed.method.as_str(), parsed.path.as_str()), ("GET", "/metrics"));
+        assert_eq!(parsed.authorization.as_deref(), Some("Bearer t"));
+        assert_eq!(request(b"GET / HTTP/1.1\r\n\r\n").unwrap().authorization, None);
+    }
+
+    #[test]
+    fn truncated_and_oversized_requests_are_rejected() {
+        assert!(request(b"").is_none());
+        assert!(request(b"GET /metrics HTTP/1.1\r\nHost: a\r\n").is_none());
+        assert!(request(b"GET\r\n\r\n").is_none());
+
+        let mut oversized = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        oversized.resize(MAX_REQUEST_HEAD + 2048, b'a');
+        oversized.extend_from_slice(b"\r\n\r\n");
+        assert!(request(&oversized).is_none());
+    }
+
+    #[test]
+    fn urls_default_ports_and_paths() {
+        let url = Url::parse("https://example.com?a=b").unwrap();
+        assert_eq!((url.https, url.host.as_str(), url.port, url.path.as_str()), (true, "example.com", 443, "/?a=b"));
+        assert_eq!(url.authority(), "example.com");
+        let url = Url::parse("http://[::1]:8080/hook").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("[::1]", 8080, "/hook"));
+        assert_eq!(url.authority(), "[::1]:8080");
+        assert_eq!(Url::parse("http://[::1]").unwrap().port, 80);
+    }
+
+    #[test]
+    fn malformed_urls_are_rejected() {
+        for url in ["example.com", "ftp://example.com", "http://", "http:///path", "http://user:pw@example.com",
+                    "http://example.com:", "http://example.com:http", "http://example.com:70000", "http://:80",
+                    "http://[::1"] {
+            assert!(Url::parse(url).is_err(), "{url}");
+        }
+    }
+}
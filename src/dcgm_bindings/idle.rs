@@ -0,0 +1,198 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::latest::LatestValuesQuery;
+use super::watch::{unique_name, WatchHandle, WatchOptions};
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct IdleOptions {
+    /// A GPU busier than this (%) at any sample is not idle.
+    pub max_utilization: f64,
+    /// A GPU with more framebuffer in use than this (MiB) at any sample is not idle. The driver keeps a
+    /// few hundred MiB for itself, so this cannot be zero.
+    pub max_memory_used: f64,
+    /// How often utilization, memory use and processes are sampled over the window.
+    pub sample_interval: Duration,
+    /// Look for processes holding the GPU's `/dev/nvidia<minor>` open. Only sees processes in the same
+    /// PID namespace, and only makes sense when the hostengine runs on this host.
+    pub check_processes: bool,
+}
+
+impl Default for IdleOptions {
+    fn default() -> Self {
+        Self {
+            max_utilization: 5.0,
+            max_memory_used: 1024.0,
+            sample_interval: Duration::from_secs(1),
+            check_processes: true,
+        }
+    }
+}
+
+/// What one GPU did over the window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IdleGpu {
+    pub gpu_id: u32,
+    /// Below every threshold at every sample, with no processes seen.
+    pub idle: bool,
+    pub max_utilization: Option<f64>,
+    /// MiB.
+    pub max_memory_used: Option<f64>,
+    /// Every process seen using the GPU.
+    pub processes: Vec<u32>,
+    /// Why the GPU is not idle, one line per check.
+    pub reasons: Vec<String>,
+}
+
+#[derive(Default)]
+struct IdleTracker {
+    minor: Option<i64>,
+    max_utilization: Option<f64>,
+    max_memory_used: Option<f64>,
+    processes: BTreeSet<u32>,
+}
+
+impl DcgmLibSafe {
+    /// `find_idle_gpus_with` with the default options: under 5% utilization and 1 GiB of memory, and no processes.
+    pub fn find_idle_gpus(&mut self, group: dcgmGpuGrp_t, window: Duration) -> Result<Vec<u32>, DCGMError>{
+        self.find_idle_gpus_with(group, window, &IdleOptions::default())
+    }
+
+    /// The GPUs of `group` that stayed idle for all of `window` and can be reclaimed.
+    pub fn find_idle_gpus_with(&mut self, group: dcgmGpuGrp_t, window: Duration, options: &IdleOptions)
+                               -> Result<Vec<u32>, DCGMError>{
+        Ok(self.gpu_idleness(group, window, options)?.into_iter().filter(|g| g.idle).map(|g| g.gpu_id).collect())
+    }
+
+    /// Samples utilization, memory use and processes of every GPU of `group` for `window` (at least once)
+    /// and reports which stayed idle. A GPU without utilization or memory readings is never idle.
+    pub fn gpu_idleness(&mut self, group: dcgmGpuGrp_t, window: Duration, options: &IdleOptions)
+                        -> Result<Vec<IdleGpu>, DCGMError>{
+        if options.sample_interval.is_zero() {
+            return Err(DCGMError::from("Idle sample interval must not be zero"));
+        }
+        let gpus: Vec<u32> = self.getGroupEntities(group)?.into_iter()
+            .filter(|e| e.group == EntityGroup::Gpu)
+            .map(|e| e.id)
+            .collect();
+        if gpus.is_empty() {
+            return Err(DCGMError::from(format!("Group {group} has no GPUs")));
+        }
+
+        let watch = self.watch_idleness(group, options.sample_interval)?;
+        let entities: Vec<Entity> = gpus.iter().copied().map(Entity::gpu).collect();
+        let mut readings = LatestValuesQuery::new(&entities, &watch.fields);
+        let mut trackers: BTreeMap<u32, IdleTracker> = gpus.iter().map(|&id| (id, IdleTracker::default())).collect();
+        let started = Instant::now();
+        let result = loop {
+            let round = Instant::now();
+            if let Err(e) = self.sample_idleness(&mut readings, &mut trackers, options) {
+                break Err(e);
+            }
+            if started.elapsed() >= window {
+                break Ok(());
+            }
+            std::thread::sleep(options.sample_interval.saturating_sub(round.elapsed()));
+        };
+        if let Err(e) = self.unwatch(watch) {
+            tracing::warn!("Failed to remove the idle detection watches: {e}");
+        }
+        result?;
+
+        Ok(trackers.into_iter().map(|(gpu_id, tracker)| judge(gpu_id, tracker, options)).collect())
+    }
+
+    fn watch_idleness(&mut self, group: dcgmGpuGrp_t, interval: Duration) -> Result<WatchHandle, DCGMError>{
+        let mut fields = vec![DCGM_FI_DEV_GPU_UTIL as u16, DCGM_FI_DEV_FB_USED as u16, DCGM_FI_DEV_MINOR_NUMBER as u16];
+        let field_group = self.fieldGroupCreate(&unique_name("idle"), &mut fields)?;
+        let options = WatchOptions { update_interval: interval, ..WatchOptions::default() };
+        if let Err(e) = self.watchFields(field_group, group, interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = self.fieldGroupDestroy(field_group);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group, fields, options })
+    }
+
+    fn sample_idleness(&mut self, readings: &mut LatestValuesQuery, trackers: &mut BTreeMap<u32, IdleTracker>,
+                       options: &IdleOptions) -> Result<(), DCGMError>{
+        for sample in readings.collect(self)? {
+            let Some(tracker) = trackers.get_mut(&sample.entity_id) else { continue };
+            let Some(value) = sample.value.as_f64() else { continue };
+            let max = match sample.field_id as u32 {
+                DCGM_FI_DEV_GPU_UTIL => &mut tracker.max_utilization,
+                DCGM_FI_DEV_FB_USED => &mut tracker.max_memory_used,
+                DCGM_FI_DEV_MINOR_NUMBER => {
+                    tracker.minor = Some(value as i64);
+                    continue;
+                }
+                _ => continue,
+            };
+            *max = Some(max.map_or(value, |m| m.max(value)));
+        }
+        if options.check_processes {
+            let users = device_users();
+            for tracker in trackers.values_mut() {
+                if let Some(pids) = tracker.minor.and_then(|minor| users.get(&minor)) {
+                    tracker.processes.extend(pids);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn judge(gpu_id: u32, tracker: IdleTracker, options: &IdleOptions) -> IdleGpu {
+    let mut reasons = Vec::new();
+    match tracker.max_utilization {
+        Some(max) if max > options.max_utilization =>
+            reasons.push(format!("utilization reached {max:.0}%, limit {:.0}%", options.max_utilization)),
+        Some(_) => (),
+        None => reasons.push("no utilization readings".to_string()),
+    }
+    match tracker.max_memory_used {
+        Some(max) if max > options.max_memory_used =>
+            reasons.push(format!("memory use reached {max:.0} MiB, limit {:.0} MiB", options.max_memory_used)),
+        Some(_) => (),
+        None => reasons.push("no memory readings".to_string()),
+    }
+    if options.check_processes {
+        if tracker.minor.is_none() {
+            reasons.push("no device minor number to look for processes".to_string());
+        } else if !tracker.processes.is_empty() {
+            let pids: Vec<String> = tracker.processes.iter().map(|p| p.to_string()).collect();
+            reasons.push(format!("in use by pid {}", pids.join(", ")));
+        }
+    }
+    IdleGpu {
+        gpu_id,
+        idle: reasons.is_empty(),
+        max_utilization: tracker.max_utilization,
+        max_memory_used: tracker.max_memory_used,
+        processes: tracker.processes.into_iter().collect(),
+        reasons,
+    }
+}
+
+/// Pids holding each `/dev/nvidia<minor>` open, by minor number. Processes that cannot be inspected are skipped.
+fn device_users() -> BTreeMap<i64, BTreeSet<u32>> {
+    let mut users: BTreeMap<i64, BTreeSet<u32>> = BTreeMap::new();
+    let Ok(procs) = std::fs::read_dir("/proc") else { return users };
+    for entry in procs.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else { continue };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else { continue };
+            let minor = target.to_str()
+                .and_then(|t| t.strip_prefix("/dev/nvidia"))
+                .and_then(|minor| minor.parse::<i64>().ok());
+            if let Some(minor) = minor {
+                users.entry(minor).or_default().insert(pid);
+            }
+        }
+    }
+    users
+}
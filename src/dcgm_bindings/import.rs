@@ -0,0 +1,312 @@
+//! Reads artifacts of the stock DCGM tooling into this crate's types: `dcgmi diag --json` output into a
+//! `DiagReport`, and dcgm-exporter `/metrics` dumps into `Sample`s, as well as the lines of the daemon's
+//! `json_lines` sink. None of them needs libdcgm, so old artifacts can be analyzed on any machine.
+
+use super::bindings::*;
+use super::diag::{DiagMessage, DiagReport, DiagResult, DiagTestResult};
+use super::entity::{Entity, EntityGroup};
+use super::errors::error_info;
+use super::samples::{FieldValue, Sample, Tags};
+use super::DCGMError;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Metric names of the dcgm-exporter default and DCP counter sets, which are the `DCGM_FI_*`
+/// constant names. Other names are looked up in the DCGM field table when libdcgm is available.
+macro_rules! exporter_fields {
+    ($($name:ident),* $(,)?) => { &[$((stringify!($name), $name as u16)),*] };
+}
+
+pub(crate) const EXPORTER_FIELDS: &[(&str, u16)] = exporter_fields![
+    DCGM_FI_DEV_SM_CLOCK, DCGM_FI_DEV_MEM_CLOCK, DCGM_FI_DEV_MEMORY_TEMP, DCGM_FI_DEV_GPU_TEMP,
+    DCGM_FI_DEV_POWER_USAGE, DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION, DCGM_FI_DEV_PCIE_REPLAY_COUNTER,
+    DCGM_FI_DEV_GPU_UTIL, DCGM_FI_DEV_MEM_COPY_UTIL, DCGM_FI_DEV_ENC_UTIL, DCGM_FI_DEV_DEC_UTIL,
+    DCGM_FI_DEV_XID_ERRORS, DCGM_FI_DEV_FB_FREE, DCGM_FI_DEV_FB_USED, DCGM_FI_DEV_FB_TOTAL,
+    DCGM_FI_DEV_NVLINK_BANDWIDTH_TOTAL, DCGM_FI_DEV_VGPU_LICENSE_STATUS, DCGM_FI_DEV_UNCORRECTABLE_REMAPPED_ROWS,
+    DCGM_FI_DEV_CORRECTABLE_REMAPPED_ROWS, DCGM_FI_DEV_ROW_REMAP_FAILURE, DCGM_FI_DEV_ROW_REMAP_PENDING,
+    DCGM_FI_DEV_CLOCK_THROTTLE_REASONS, DCGM_FI_DEV_CLOCKS_EVENT_REASONS, DCGM_FI_DEV_POWER_VIOLATION,
+    DCGM_FI_DEV_THERMAL_VIOLATION, DCGM_FI_DEV_SYNC_BOOST_VIOLATION, DCGM_FI_DEV_BOARD_LIMIT_VIOLATION,
+    DCGM_FI_DEV_LOW_UTIL_VIOLATION, DCGM_FI_DEV_RELIABILITY_VIOLATION, DCGM_FI_DEV_ECC_SBE_VOL_TOTAL,
+    DCGM_FI_DEV_ECC_DBE_VOL_TOTAL, DCGM_FI_DEV_ECC_SBE_AGG_TOTAL, DCGM_FI_DEV_ECC_DBE_AGG_TOTAL,
+    DCGM_FI_DEV_RETIRED_SBE, DCGM_FI_DEV_RETIRED_DBE, DCGM_FI_DEV_RETIRED_PENDING,
+    DCGM_FI_DEV_NVLINK_CRC_FLIT_ERROR_COUNT_TOTAL, DCGM_FI_DEV_NVLINK_CRC_DATA_ERROR_COUNT_TOTAL,
+    DCGM_FI_DEV_NVLINK_REPLAY_ERROR_COUNT_TOTAL, DCGM_FI_DEV_NVLINK_RECOVERY_ERROR_COUNT_TOTAL,
+    DCGM_FI_DEV_POWER_MGMT_LIMIT, DCGM_FI_PROF_GR_ENGINE_ACTIVE, DCGM_FI_PROF_SM_ACTIVE,
+    DCGM_FI_PROF_SM_OCCUPANCY, DCGM_FI_PROF_PIPE_TENSOR_ACTIVE, DCGM_FI_PROF_PIPE_FP64_ACTIVE,
+    DCGM_FI_PROF_PIPE_FP32_ACTIVE, DCGM_FI_PROF_PIPE_FP16_ACTIVE, DCGM_FI_PROF_DRAM_ACTIVE,
+    DCGM_FI_PROF_PCIE_TX_BYTES, DCGM_FI_PROF_PCIE_RX_BYTES, DCGM_FI_PROF_NVLINK_TX_BYTES,
+    DCGM_FI_PROF_NVLINK_RX_BYTES,
+];
+
+/// Labels that say which entity a series is for rather than being carried along as tags.
+const ENTITY_LABELS: &[&str] = &["gpu", "entity_group", "entity_id"];
+
+impl DiagReport {
+    /// Parses the output of `dcgmi diag --json`, in both the DCGM 3 layout (per-GPU `gpu_ids` and
+    /// `warnings`) and the DCGM 4 one (`entity_group`/`entity_id` and `errors`). dcgmi does not name the
+    /// plugin of a test, so its category (e.g. `Hardware`) stands in.
+    pub fn from_dcgmi_json(text: &str) -> Result<Self, DCGMError> {
+        let root: Value = serde_json::from_str(text).map_err(|e| DCGMError::from(format!("dcgmi diag output: {e}")))?;
+        let object = root.as_object().ok_or_else(|| DCGMError::from("dcgmi diag output is not a JSON object"))?;
+        let categories = object.values()
+            .find_map(|v| v.get("test_categories"))
+            .and_then(Value::as_array)
+            .ok_or_else(|| DCGMError::from("dcgmi diag output has no test_categories"))?;
+
+        let mut tests = Vec::new();
+        for category in categories {
+            let plugin = text_of(category.get("category")).unwrap_or_default();
+            for test in category.get("tests").and_then(Value::as_array).into_iter().flatten() {
+                tests.push(diag_test(test, &plugin)?);
+            }
+        }
+        let metadata = object.get("metadata");
+        let field = |names: &[&str]| names.iter()
+            .find_map(|name| text_of(object.get(*name)).or_else(|| text_of(metadata.and_then(|m| m.get(*name)))))
+            .unwrap_or_default();
+        Ok(DiagReport {
+            tests,
+            dcgm_version: field(&["version", "DCGM Version"]),
+            driver_version: field(&["Driver Version Detected", "driver_version"]),
+        })
+    }
+}
+
+fn diag_test(test: &Value, plugin: &str) -> Result<DiagTestResult, DCGMError> {
+    let name = text_of(test.get("name")).ok_or_else(|| DCGMError::from("dcgmi diag test without a name"))?;
+    let mut result = DiagTestResult {
+        name,
+        plugin: text_of(test.get("plugin")).unwrap_or_else(|| plugin.to_string()),
+        result: DiagResult::NotRun,
+        entities: Vec::new(),
+        errors: Vec::new(),
+        info: Vec::new(),
+    };
+    let mut worst = None;
+    for entry in test.get("results").and_then(Value::as_array).into_iter().flatten() {
+        let status = diag_result(entry.get("status"))?;
+        worst = worst.max(Some(status));
+        let entities = entities_of(entry);
+        result.entities.extend(entities.iter().map(|&e| (e, status)));
+        let entity = if entities.len() == 1 { Some(entities[0]) } else { None };
+        result.errors.extend(messages(entry, &["errors", "warnings"], entity));
+        result.info.extend(messages(entry, &["info"], entity));
+    }
+    result.errors.extend(messages(test, &["errors", "warnings"], None));
+    result.info.extend(messages(test, &["info"], None));
+    // DCGM 4 summarizes each test; DCGM 3 only has the per-GPU results
+    result.result = match test.get("test_summary").and_then(|s| s.get("status")) {
+        Some(status) => diag_result(Some(status))?,
+        None => worst.unwrap_or(DiagResult::NotRun),
+    };
+    Ok(result)
+}
+
+fn diag_result(status: Option<&Value>) -> Result<DiagResult, DCGMError> {
+    let status = text_of(status).unwrap_or_default();
+    match status.to_ascii_lowercase().as_str() {
+        "pass" => Ok(DiagResult::Pass),
+        "skip" | "skipped" => Ok(DiagResult::Skip),
+        "warn" | "warning" => Ok(DiagResult::Warn),
+        "fail" => Ok(DiagResult::Fail),
+        "not run" | "notrun" | "" => Ok(DiagResult::NotRun),
+        other => Err(DCGMError::from(format!("unknown dcgmi diag status '{other}'"))),
+    }
+}
+
+/// The entities of one result entry: `entity_group`/`entity_id`, or a GPU list in `gpu_ids`/`gpu_id`.
+fn entities_of(entry: &Value) -> Vec<Entity> {
+    if let Some(id) = entry.get("entity_id").and_then(number_of) {
+        let group = text_of(entry.get("entity_group")).and_then(|g| entity_group_by_name(&g)).unwrap_or(EntityGroup::Gpu);
+        return vec![Entity::new(group, id)];
+    }
+    let ids = entry.get("gpu_ids").or_else(|| entry.get("gpu_id"));
+    match ids {
+        Some(Value::String(list)) => list.split(',').filter_map(|id| id.trim().parse().ok()).map(Entity::gpu).collect(),
+        Some(Value::Array(list)) => list.iter().filter_map(number_of).map(Entity::gpu).collect(),
+        Some(id) => number_of(id).map(Entity::gpu).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Messages under any of `keys`: plain strings, or objects with the text in `msg`/`warning` and the
+/// DCGM error id in `error_id`.
+fn messages(entry: &Value, keys: &[&str], entity: Option<Entity>) -> Vec<DiagMessage> {
+    let mut out = Vec::new();
+    for value in keys.iter().filter_map(|k| entry.get(*k)) {
+        let items = match value {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for item in items {
+            let (message, code) = match item {
+                Value::String(s) => (s.clone(), None),
+                Value::Object(_) => {
+                    let message = text_of(item.get("msg")).or_else(|| text_of(item.get("warning"))).unwrap_or_default();
+                    let code = item.get("error_id").and_then(number_of);
+                    (message, code)
+                }
+                _ => continue,
+            };
+            if message.is_empty() {
+                continue;
+            }
+            out.push(DiagMessage { entity, code, error: code.and_then(error_info), message });
+        }
+    }
+    out
+}
+
+fn text_of(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn number_of(value: &Value) -> Option<u32> {
+    match value {
+        Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn entity_group_by_name(name: &str) -> Option<EntityGroup> {
+    EntityGroup::ALL.into_iter().find(|g| g.to_string().eq_ignore_ascii_case(name))
+}
+
+/// Field id of a metric name: a `DCGM_FI_*` constant name as dcgm-exporter uses, the `DCGM_FIELD_<id>`
+/// this crate's exporter falls back to, or (with libdcgm) a `DCGM_FI_<TAG>` name.
+pub fn metric_field_id(name: &str) -> Option<u16> {
+    if let Some(&(_, id)) = EXPORTER_FIELDS.iter().find(|(n, _)| *n == name) {
+        return Some(id);
+    }
+    if let Some(id) = name.strip_prefix("DCGM_FIELD_").and_then(|id| id.parse().ok()) {
+        return Some(id);
+    }
+    let tag = name.strip_prefix("DCGM_FI_")?.to_ascii_lowercase();
+    let dcgm = super::DCGM_LIB.as_ref().ok()?;
+    let tag = std::ffi::CString::new(tag).ok()?;
+    unsafe {
+        dcgm.DcgmFieldsInit();
+        let meta = dcgm.DcgmFieldGetByTag(tag.as_ptr());
+        if meta.is_null() {
+            return None;
+        }
+        Some((*meta).fieldId)
+    }
+}
+
+/// Parses a Prometheus text dump of dcgm-exporter (or of this crate's exporter) into samples. Series
+/// are matched to entities by their `gpu` or `entity_group`/`entity_id` labels and every other label
+/// becomes a tag. Samples without a timestamp on their line get `scraped_at` (usec since 1970).
+/// Comments, unknown metric names and series without an entity are skipped.
+pub fn parse_exporter_metrics(text: &str, scraped_at: i64) -> Result<Vec<Sample>, DCGMError> {
+    let mut samples = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, labels, rest) = split_series(line).map_err(|e| DCGMError::from(format!("line {}: {e}", n + 1)))?;
+        let Some(field_id) = metric_field_id(name) else {
+            tracing::debug!("Skipping unknown metric {name}");
+            continue;
+        };
+        let mut parts = rest.split_whitespace();
+        let value: f64 = parts.next()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| DCGMError::from(format!("line {}: missing or invalid value", n + 1)))?;
+        let timestamp = match parts.next() {
+            Some(ms) => ms.parse::<i64>().map_err(|_| DCGMError::from(format!("line {}: invalid timestamp", n + 1)))? * 1000,
+            None => scraped_at,
+        };
+        let entity = match (labels.get("gpu"), labels.get("entity_group"), labels.get("entity_id")) {
+            (Some(gpu), _, _) => gpu.parse().ok().map(Entity::gpu),
+            (None, Some(group), Some(id)) => entity_group_by_name(group).zip(id.parse().ok()).map(|(g, id)| Entity::new(g, id)),
+            _ => None,
+        };
+        let Some(entity) = entity else { continue };
+        let tags: BTreeMap<String, String> = labels.into_iter().filter(|(k, _)| !ENTITY_LABELS.contains(&k.as_str())).collect();
+        let value = if value.fract() == 0.0 && value.abs() < i64::MAX as f64 { FieldValue::Int64(value as i64) } else { FieldValue::Double(value) };
+        samples.push(Sample { entity_group: entity.group, entity_id: entity.id, field_id, timestamp, value, tags: Tags::new(tags) });
+    }
+    Ok(samples)
+}
+
+/// Parses one line written by the daemon's `json_lines` sink back into a sample. Numbers with a
+/// fraction or exponent become doubles, null is blank, and every other string-valued key is a tag.
+pub fn parse_json_line(line: &str) -> Result<Sample, DCGMError> {
+    let root: Value = serde_json::from_str(line).map_err(|e| DCGMError::from(format!("sample: {e}")))?;
+    let object = root.as_object().ok_or_else(|| DCGMError::from("sample is not a JSON object"))?;
+    let entity_group = object.get("entity_group")
+        .and_then(|g| EntityGroup::deserialize(g).ok())
+        .ok_or_else(|| DCGMError::from("sample without a valid entity_group"))?;
+    let entity_id = object.get("entity_id").and_then(number_of).ok_or_else(|| DCGMError::from("sample without entity_id"))?;
+    let field_id = object.get("field_id")
+        .and_then(Value::as_u64)
+        .and_then(|id| u16::try_from(id).ok())
+        .ok_or_else(|| DCGMError::from("sample without field_id"))?;
+    let timestamp = object.get("timestamp").and_then(Value::as_i64).ok_or_else(|| DCGMError::from("sample without timestamp"))?;
+    let value = match object.get("value") {
+        None | Some(Value::Null) => FieldValue::Blank,
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(v) => FieldValue::Int64(v),
+            None => FieldValue::Double(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Some(Value::String(s)) => FieldValue::String(s.clone()),
+        Some(Value::Array(bytes)) => FieldValue::Blob(bytes.iter().filter_map(Value::as_u64).map(|b| b as u8).collect()),
+        Some(other) => return Err(DCGMError::from(format!("sample value {other} is not a field value"))),
+    };
+    let tags: BTreeMap<String, String> = object.iter()
+        .filter(|(k, _)| !["entity_group", "entity_id", "field_id", "timestamp", "value"].contains(&k.as_str()))
+        .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+        .collect();
+    Ok(Sample { entity_group, entity_id, field_id, timestamp, value, tags: Tags::new(tags) })
+}
+
+/// `name{labels} rest` into its parts, unescaping label values.
+fn split_series(line: &str) -> Result<(&str, BTreeMap<String, String>, &str), String> {
+    let Some(open) = line.find('{') else {
+        let split = line.find(char::is_whitespace).ok_or("missing value")?;
+        return Ok((&line[..split], BTreeMap::new(), &line[split..]));
+    };
+    let name = &line[..open];
+    let mut labels = BTreeMap::new();
+    let mut chars = line[open + 1..].char_indices();
+    loop {
+        let mut key = String::new();
+        let end = loop {
+            match chars.next() {
+                Some((i, '}')) if key.trim().is_empty() => break Some(i),
+                Some((_, '=')) => break None,
+                Some((_, ',')) if key.trim().is_empty() => (),
+                Some((_, c)) => key.push(c),
+                None => return Err("unterminated label set".into()),
+            }
+        };
+        if let Some(i) = end {
+            return Ok((name, labels, &line[open + 1 + i + 1..]));
+        }
+        if chars.next().map(|(_, c)| c) != Some('"') {
+            return Err(format!("label {} has no quoted value", key.trim()));
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next() {
+                Some((_, '"')) => break,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => return Err("unterminated label value".into()),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err("unterminated label value".into()),
+            }
+        }
+        labels.insert(key.trim().to_string(), value);
+    }
+}
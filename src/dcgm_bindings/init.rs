@@ -0,0 +1,111 @@
+use super::bindings::*;
+
+/// A DCGM C struct for which the all-zero bit pattern is a valid value: integers, floats, char arrays,
+/// raw pointers, and structs, unions and arrays of those. Use this instead of `MaybeUninit::assume_init`
+/// for anything passed to DCGM, which may read fields it does not fill in.
+///
+/// # Safety
+/// Only implement for types whose every field accepts all-zero bytes (no references, `NonNull`, enums
+/// with no zero variant, ...).
+pub unsafe trait Zeroable: Sized {
+    fn zeroed() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+
+    /// Heap-allocates a zeroed value without building it on the stack first; for the large response structs.
+    fn boxed_zeroed() -> Box<Self> {
+        let layout = std::alloc::Layout::new::<Self>();
+        unsafe {
+            let ptr = std::alloc::alloc_zeroed(layout) as *mut Self;
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            Box::from_raw(ptr)
+        }
+    }
+}
+
+/// A zeroable DCGM struct whose leading `version` field must be stamped before DCGM sees it.
+pub trait Versioned: Zeroable {
+    /// The `N` of `MAKE_DCGM_VERSION(type, N)` for the struct the binding was generated from.
+    const VERSION: u32;
+
+    fn set_version(&mut self, version: u32);
+
+    /// `MAKE_DCGM_VERSION(Self, VERSION)`.
+    fn version() -> u32 {
+        std::mem::size_of::<Self>() as u32 | (Self::VERSION << 24)
+    }
+
+    /// Zeroed and stamped with `version()`.
+    fn versioned() -> Self {
+        Self::with_version(Self::version())
+    }
+
+    fn boxed_versioned() -> Box<Self> {
+        let mut s = Self::boxed_zeroed();
+        s.set_version(Self::version());
+        s
+    }
+
+    /// Zeroed and stamped with a version picked at runtime, e.g. from `StructVersions`.
+    fn with_version(version: u32) -> Self {
+        let mut s = Self::zeroed();
+        s.set_version(version);
+        s
+    }
+}
+
+macro_rules! zeroable {
+    ($($t:ty),* $(,)?) => {
+        $(unsafe impl Zeroable for $t {})*
+    };
+}
+
+macro_rules! versioned {
+    ($($t:ty => $v:literal),* $(,)?) => {
+        $(
+            unsafe impl Zeroable for $t {}
+            impl Versioned for $t {
+                const VERSION: u32 = $v;
+                fn set_version(&mut self, version: u32) {
+                    self.version = version;
+                }
+            }
+        )*
+    };
+}
+pub(crate) use versioned;
+
+unsafe impl Zeroable for dcgmClockSet_v1 {}
+impl Versioned for dcgmClockSet_v1 {
+    const VERSION: u32 = 1;
+    // Declared as a signed int in dcgm_structs.h.
+    fn set_version(&mut self, version: u32) {
+        self.version = version as i32;
+    }
+}
+
+zeroable! {
+    dcgmFieldValue_v1__bindgen_ty_1,
+}
+
+versioned! {
+    dcgmConfig_v2 => 2,
+    dcgmConnectV2Params_v2 => 2,
+    dcgmDeviceAttributes_v3 => 3,
+    dcgmDeviceTopology_v1 => 1,
+    dcgmDiagResponse_v11 => 11,
+    dcgmFieldGroupInfo_v1 => 1,
+    dcgmFieldValue_v1 => 1,
+    dcgmFieldValue_v2 => 2,
+    dcgmGroupInfo_v3 => 3,
+    dcgmHealthResponse_v5 => 5,
+    dcgmHealthSetParams_v2 => 2,
+    dcgmJobInfo_v3 => 3,
+    dcgmMigHierarchy_v2 => 2,
+    dcgmNvLinkStatus_v4 => 4,
+    dcgmPidInfo_v2 => 2,
+    dcgmRunDiag_v10 => 10,
+    dcgmVersionInfo_v2 => 2,
+}
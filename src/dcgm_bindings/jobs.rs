@@ -0,0 +1,178 @@
+use super::bindings::*;
+use super::process::StatSummary;
+use super::watch::WatchOptions;
+use super::init::Versioned;
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+use std::ffi::CString;
+use std::fmt::Write;
+
+/// Usage of one GPU over a job (or the sum over all GPUs for the summary). Times are usec since 1970,
+/// violation times are usec.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GpuJobStats {
+    pub gpu_id: u32,
+    /// Millijoules.
+    pub energy_consumed: i64,
+    pub power_usage: StatSummary<f64>,
+    pub pcie_rx_bandwidth: StatSummary<i64>,
+    pub pcie_tx_bandwidth: StatSummary<i64>,
+    pub pcie_replays: i64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub sm_utilization: StatSummary<i32>,
+    pub memory_utilization: StatSummary<i32>,
+    pub ecc_single_bit: u32,
+    pub ecc_double_bit: u32,
+    pub xid_critical_errors: u32,
+    pub compute_pids: Vec<u32>,
+    pub max_gpu_memory_used: i64,
+    pub power_violation_time: i64,
+    pub thermal_violation_time: i64,
+}
+
+impl From<&dcgmGpuUsageInfo_t> for GpuJobStats {
+    fn from(info: &dcgmGpuUsageInfo_t) -> Self {
+        let pids = info.numComputePids.clamp(0, info.computePidInfo.len() as i32) as usize;
+        Self {
+            gpu_id: info.gpuId,
+            energy_consumed: info.energyConsumed,
+            power_usage: info.powerUsage.into(),
+            pcie_rx_bandwidth: info.pcieRxBandwidth.into(),
+            pcie_tx_bandwidth: info.pcieTxBandwidth.into(),
+            pcie_replays: info.pcieReplays,
+            start_time: info.startTime,
+            end_time: info.endTime,
+            sm_utilization: info.smUtilization.into(),
+            memory_utilization: info.memoryUtilization.into(),
+            ecc_single_bit: info.eccSingleBit,
+            ecc_double_bit: info.eccDoubleBit,
+            xid_critical_errors: info.numXidCriticalErrors.max(0) as u32,
+            compute_pids: info.computePidInfo[..pids].iter().map(|p| p.pid).collect(),
+            max_gpu_memory_used: info.maxGpuMemoryUsed,
+            power_violation_time: info.powerViolationTime,
+            thermal_violation_time: info.thermalViolationTime,
+        }
+    }
+}
+
+/// `dcgmJobGetStats` results for one job.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JobStats {
+    pub job_id: String,
+    pub summary: GpuJobStats,
+    pub gpus: Vec<GpuJobStats>,
+}
+
+impl JobStats {
+    /// Short per-job GPU usage report for Slurm epilog scripts.
+    pub fn epilog_summary(&self) -> String {
+        let s = &self.summary;
+        let mut out = String::new();
+        let _ = writeln!(out, "GPU usage for job {}", self.job_id);
+        let _ = writeln!(out, "  GPUs:            {}", self.gpus.iter().map(|g| g.gpu_id.to_string()).collect::<Vec<_>>().join(","));
+        let _ = writeln!(out, "  Run time:        {:.1} s", (s.end_time - s.start_time).max(0) as f64 / 1e6);
+        let _ = writeln!(out, "  Energy:          {:.1} J", s.energy_consumed as f64 / 1000.0);
+        let _ = writeln!(out, "  SM util (avg):   {} %", s.sm_utilization.average);
+        let _ = writeln!(out, "  Mem util (avg):  {} %", s.memory_utilization.average);
+        let _ = writeln!(out, "  Max memory used: {} MiB", s.max_gpu_memory_used / (1024 * 1024));
+        let _ = writeln!(out, "  XID errors:      {}", s.xid_critical_errors);
+        let _ = writeln!(out, "  ECC SBE / DBE:   {} / {}", s.ecc_single_bit, s.ecc_double_bit);
+        for g in &self.gpus {
+            let _ = writeln!(out, "  GPU {}: SM {} %, mem {} %, {:.1} J, max power {:.1} W", g.gpu_id,
+                             g.sm_utilization.average, g.memory_utilization.average,
+                             g.energy_consumed as f64 / 1000.0, g.power_usage.max);
+        }
+        out
+    }
+}
+
+/// The Slurm job this process belongs to: `SLURM_JOB_ID`, else the `job_<id>` component of our cgroup.
+pub fn slurm_job_id() -> Option<String> {
+    if let Ok(id) = std::env::var("SLURM_JOB_ID") {
+        if !id.is_empty() {
+            return Some(id);
+        }
+    }
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    slurm_job_id_from_cgroup(&cgroups)
+}
+
+pub(crate) fn slurm_job_id_from_cgroup(cgroups: &str) -> Option<String> {
+    cgroups.lines()
+        .filter_map(|l| l.splitn(3, ':').nth(2))
+        .flat_map(|path| path.split('/'))
+        .find_map(|segment| segment.strip_prefix("job_").filter(|id| id.bytes().all(|b| b.is_ascii_digit())))
+        .map(str::to_string)
+}
+
+fn job_key(job_id: &str) -> Result<CString, DCGMError> {
+    if job_id.len() >= 64 {
+        return Err(DCGMError::from(format!("Job id {job_id} is longer than 63 bytes")));
+    }
+    CString::new(job_id).map_err(|_| DCGMError::from("Job id contains a NUL byte"))
+}
+
+impl DcgmLibSafe {
+    /// Starts recording the fields job stats need on `group`.
+    pub fn watch_job_fields(&mut self, group: dcgmGpuGrp_t, options: &WatchOptions) -> Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmWatchJobFields(self.handle(), group, options.update_interval.as_micros() as i64,
+                                                  options.max_keep_age.as_secs_f64(), options.max_keep_samples)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmWatchJobFields").arg("group", group))
+        }
+    }
+
+    pub fn job_start_stats(&mut self, group: dcgmGpuGrp_t, job_id: &str) -> Result<(), DCGMError>{
+        let key = job_key(job_id)?;
+        match unsafe{self.lib()?.dcgmJobStartStats(self.handle(), group, key.as_ptr() as *mut _)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmJobStartStats").arg("group", group).arg("job", job_id))
+        }
+    }
+
+    pub fn job_stop_stats(&mut self, job_id: &str) -> Result<(), DCGMError>{
+        let key = job_key(job_id)?;
+        match unsafe{self.lib()?.dcgmJobStopStats(self.handle(), key.as_ptr() as *mut _)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmJobStopStats").arg("job", job_id))
+        }
+    }
+
+    pub fn job_get_stats(&mut self, job_id: &str) -> Result<JobStats, DCGMError>{
+        let key = job_key(job_id)?;
+        let mut info = dcgmJobInfo_t::boxed_versioned();
+        match unsafe{self.lib()?.dcgmJobGetStats(self.handle(), key.as_ptr() as *mut _, &mut *info)}{
+            dcgmReturn_enum_DCGM_ST_OK => (),
+            err_code => return Err(self.call_error(err_code, "dcgmJobGetStats").arg("job", job_id))
+        }
+        let num_gpus = info.numGpus.clamp(0, info.gpus.len() as i32) as usize;
+        Ok(JobStats {
+            job_id: job_id.to_string(),
+            summary: GpuJobStats::from(&info.summary),
+            gpus: info.gpus[..num_gpus].iter().map(GpuJobStats::from).collect(),
+        })
+    }
+
+    pub fn job_remove(&mut self, job_id: &str) -> Result<(), DCGMError>{
+        let key = job_key(job_id)?;
+        match unsafe{self.lib()?.dcgmJobRemove(self.handle(), key.as_ptr() as *mut _)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmJobRemove").arg("job", job_id))
+        }
+    }
+
+    /// Starts job stats keyed by the current Slurm job id and returns that id.
+    pub fn start_slurm_job_stats(&mut self, group: dcgmGpuGrp_t) -> Result<String, DCGMError>{
+        let job_id = slurm_job_id().ok_or_else(|| DCGMError::from("Not running inside a Slurm job"))?;
+        self.job_start_stats(group, &job_id)?;
+        Ok(job_id)
+    }
+
+    /// Stops the current Slurm job's stats and returns them, for use from an epilog.
+    pub fn finish_slurm_job_stats(&mut self) -> Result<JobStats, DCGMError>{
+        let job_id = slurm_job_id().ok_or_else(|| DCGMError::from("Not running inside a Slurm job"))?;
+        self.job_stop_stats(&job_id)?;
+        self.job_get_stats(&job_id)
+    }
+}
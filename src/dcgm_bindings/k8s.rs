@@ -0,0 +1,129 @@
+use super::exporter::{Exporter, GpuIdentity};
+use super::DCGMError;
+use hyper_util::rt::TokioIo;
+use std::collections::BTreeMap;
+use tonic::transport::{Endpoint, Uri};
+
+pub const DEFAULT_POD_RESOURCES_SOCKET: &str = "/var/lib/kubelet/pod-resources/kubelet.sock";
+/// Resource names whose device IDs are GPU (or MIG) UUIDs.
+pub const GPU_RESOURCE_PREFIX: &str = "nvidia.com/";
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ListPodResourcesRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ListPodResourcesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pod_resources: Vec<PodResources>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct PodResources {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    namespace: String,
+    #[prost(message, repeated, tag = "3")]
+    containers: Vec<ContainerResources>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ContainerResources {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    devices: Vec<ContainerDevices>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ContainerDevices {
+    #[prost(string, tag = "1")]
+    resource_name: String,
+    #[prost(string, repeated, tag = "2")]
+    device_ids: Vec<String>,
+}
+
+/// The container a device is allocated to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PodAttribution {
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+    pub resource_name: String,
+}
+
+/// Lists GPU allocations known to the kubelet, keyed by device ID (the GPU or MIG UUID).
+pub fn list_gpu_allocations(socket: &str) -> Result<BTreeMap<String, PodAttribution>, DCGMError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| DCGMError::from(format!("Failed to start runtime: {e}")))?;
+    let response = runtime.block_on(list_pod_resources(socket.to_string()))?;
+    let mut allocations = BTreeMap::new();
+    for pod in response.pod_resources {
+        for container in pod.containers {
+            for devices in container.devices.iter().filter(|d| d.resource_name.starts_with(GPU_RESOURCE_PREFIX)) {
+                for id in &devices.device_ids {
+                    allocations.insert(id.clone(), PodAttribution {
+                        namespace: pod.namespace.clone(),
+                        pod: pod.name.clone(),
+                        container: container.name.clone(),
+                        resource_name: devices.resource_name.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(allocations)
+}
+
+async fn list_pod_resources(socket: String) -> Result<ListPodResourcesResponse, DCGMError> {
+    // The URI is ignored; every connection goes to the unix socket.
+    let channel = Endpoint::try_from("http://[::]:50051")
+        .map_err(|e| DCGMError::from(e.to_string()))?
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            let socket = socket.clone();
+            async move { tokio::net::UnixStream::connect(socket).await.map(TokioIo::new) }
+        }))
+        .await
+        .map_err(|e| DCGMError::from(format!("Failed to connect to the kubelet: {e}")))?;
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await.map_err(|e| DCGMError::from(format!("Kubelet not ready: {e}")))?;
+    let codec: tonic::codec::ProstCodec<ListPodResourcesRequest, ListPodResourcesResponse> = Default::default();
+    let path = tonic::codegen::http::uri::PathAndQuery::from_static("/v1.PodResourcesLister/List");
+    client.unary(tonic::Request::new(ListPodResourcesRequest {}), path, codec)
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| DCGMError::from(format!("PodResourcesLister/List failed: {e}")))
+}
+
+/// Joins kubelet allocations with DCGM GPU identities, by UUID.
+pub fn attribute_gpus<'a, I>(identities: I, allocations: &BTreeMap<String, PodAttribution>) -> BTreeMap<u32, PodAttribution>
+where
+    I: IntoIterator<Item = &'a GpuIdentity>,
+{
+    identities.into_iter()
+        .filter_map(|id| allocations.get(&id.uuid).map(|a| (id.gpu_id, a.clone())))
+        .collect()
+}
+
+impl Exporter {
+    /// Refreshes `namespace`, `pod` and `container` labels from the kubelet. GPUs no longer allocated lose them.
+    pub fn load_pod_attribution(&mut self, socket: &str) -> Result<(), DCGMError> {
+        let allocations = list_gpu_allocations(socket)?;
+        let identities: Vec<GpuIdentity> = self.identities().cloned().collect();
+        let attributed = attribute_gpus(&identities, &allocations);
+        for id in identities {
+            let labels = match attributed.get(&id.gpu_id) {
+                Some(a) => vec![
+                    ("namespace".to_string(), a.namespace.clone()),
+                    ("pod".to_string(), a.pod.clone()),
+                    ("container".to_string(), a.container.clone()),
+                ],
+                None => Vec::new(),
+            };
+            self.set_gpu_labels(id.gpu_id, labels);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use super::alerts::{Alert, Severity};
+use super::daemon::duration;
+use super::exporter::local_hostname;
+use super::format::rfc3339;
+use super::DCGMError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::mpsc::Sender;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Reasons published when the config does not list any: what needs an operator on the node.
+pub const DEFAULT_EVENT_REASONS: [&str; 6] = ["XidError", "DoubleBitEccError", "PagesRetired", "HealthFailure", "HealthRecovered", "DiagFailure"];
+/// Reasons `Alert`s carry.
+pub const ALERT_REASONS: [&str; 13] = [
+    "XidError", "DoubleBitEccError", "PagesRetired", "PcieReplays", "ThermalViolation", "PowerViolation", "NvLinkErrors",
+    "PolicyViolation", "HealthWarning", "HealthFailure", "HealthRecovered", "DiagWarning", "DiagFailure",
+];
+/// Type of the node condition kept with `condition`.
+pub const NODE_CONDITION: &str = "GpuProblem";
+const COMPONENT: &str = "rust-dcgm";
+
+/// Publishes GPU incidents as Kubernetes Events about the node, so they show in `kubectl describe node`.
+/// Runs in-cluster with the pod's service account, which needs to create `events` and, for
+/// `condition`, patch `nodes/status`. Needs the `k8s` feature.
+///
+/// ```toml
+/// [[sinks]]
+/// type = "kubernetes_events"
+/// condition = true
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KubeEventsConfig {
+    /// Node the events are about; `$NODE_NAME` (set from `spec.nodeName` with the downward API) when
+    /// left out.
+    #[serde(default)]
+    pub node: Option<String>,
+    /// Alert reasons published as events, `DEFAULT_EVENT_REASONS` when left out.
+    #[serde(default)]
+    pub reasons: Option<Vec<String>>,
+    /// Also keep a `GpuProblem` node condition, true while a health watch fails.
+    #[serde(default)]
+    pub condition: bool,
+    /// Namespace of the events; node events conventionally go to `default`.
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// The same incident is published at most once per window.
+    #[serde(default = "default_dedup_window", deserialize_with = "duration")]
+    pub dedup_window: Duration,
+}
+
+fn default_namespace() -> String {
+    "default".to_string()
+}
+
+fn default_dedup_window() -> Duration {
+    Duration::from_secs(600)
+}
+
+impl KubeEventsConfig {
+    /// In-cluster defaults: the node from `$NODE_NAME`, the default reasons and no node condition.
+    pub fn in_cluster() -> Self {
+        Self { node: None, reasons: None, condition: false, namespace: default_namespace(), dedup_window: default_dedup_window() }
+    }
+
+    pub fn node_name(&self) -> Option<String> {
+        self.node.clone().or_else(|| std::env::var("NODE_NAME").ok()).filter(|n| !n.trim().is_empty())
+    }
+
+    pub fn publishes(&self, alert: &Alert) -> bool {
+        match &self.reasons {
+            Some(reasons) => reasons.iter().any(|r| r == alert.reason),
+            None => DEFAULT_EVENT_REASONS.contains(&alert.reason),
+        }
+    }
+
+    /// Problems with the settings, for config validation.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.node_name().is_none() {
+            problems.push("no node name, set node or NODE_NAME".to_string());
+        }
+        for reason in self.reasons.iter().flatten().filter(|r| !ALERT_REASONS.contains(&r.as_str())) {
+            problems.push(format!("unknown reason '{reason}', expected one of {}", ALERT_REASONS.join(", ")));
+        }
+        if self.namespace.trim().is_empty() {
+            problems.push("namespace must not be empty".to_string());
+        }
+        problems
+    }
+}
+
+/// A `v1` Event about `node` for `alert`, as posted to `/api/v1/namespaces/<namespace>/events`.
+/// `kubectl describe node` finds node events by kind, name and a UID equal to the name.
+pub fn node_event(node: &str, namespace: &str, alert: &Alert) -> serde_json::Value {
+    let time = rfc3339(alert.timestamp);
+    serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Event",
+        "metadata": { "generateName": format!("{node}.gpu-"), "namespace": namespace },
+        "involvedObject": { "apiVersion": "v1", "kind": "Node", "name": node, "uid": node },
+        "reason": alert.reason,
+        "message": alert.summary,
+        "type": if alert.severity >= Severity::Warning && !alert.resolved { "Warning" } else { "Normal" },
+        "source": { "component": COMPONENT, "host": node },
+        "reportingComponent": COMPONENT,
+        "reportingInstance": local_hostname().unwrap_or_else(|| node.to_string()),
+        "firstTimestamp": time,
+        "lastTimestamp": time,
+        "count": 1,
+    })
+}
+
+/// Strategic merge patch of `nodes/<node>/status` setting the `GpuProblem` condition from the open
+/// health failures; conditions are merged by type, so the others are kept. `transition` sets
+/// `lastTransitionTime`, for a patch that changes the status; without it the node keeps the time of
+/// the last change.
+pub fn condition_patch(failures: &BTreeMap<String, String>, now_micros: i64, transition: bool) -> serde_json::Value {
+    let time = rfc3339(now_micros);
+    let (status, reason, message) = if failures.is_empty() {
+        ("False", "GpusHealthy", "No GPU health watch is failing".to_string())
+    } else {
+        ("True", "GpuHealthFailure", failures.values().cloned().collect::<Vec<_>>().join("; "))
+    };
+    let mut condition = serde_json::json!({
+        "type": NODE_CONDITION,
+        "status": status,
+        "reason": reason,
+        "message": message,
+        "lastHeartbeatTime": time,
+    });
+    if transition {
+        condition["lastTransitionTime"] = time.into();
+    }
+    serde_json::json!({ "status": { "conditions": [condition] } })
+}
+
+/// A running Kubernetes Events publisher. Alerts are published in order by a background thread, so
+/// `send` never waits on the API server. Needs the `k8s` feature.
+pub struct KubeEvents {
+    config: KubeEventsConfig,
+    queue: Option<Sender<Alert>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KubeEvents {
+    #[cfg(feature = "k8s")]
+    pub fn start(config: &KubeEventsConfig) -> Result<Self, DCGMError> {
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(DCGMError::from(format!("invalid kubernetes_events: {}", problems.join("; "))));
+        }
+        let api = api::ApiServer::in_cluster()?;
+        let (queue, alerts) = std::sync::mpsc::channel();
+        let worker_config = config.clone();
+        let thread = std::thread::Builder::new()
+            .name("dcgm-kube-events".into())
+            .spawn(move || api::publish(&worker_config, &api, alerts))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn Kubernetes events publisher: {e}")))?;
+        Ok(Self { config: config.clone(), queue: Some(queue), thread: Some(thread) })
+    }
+
+    #[cfg(not(feature = "k8s"))]
+    pub fn start(_config: &KubeEventsConfig) -> Result<Self, DCGMError> {
+        Err(DCGMError::not_supported("Kubernetes events need a build with the k8s feature"))
+    }
+
+    pub fn config(&self) -> &KubeEventsConfig {
+        &self.config
+    }
+
+    /// Queues `alert`; what is published is decided by the configured reasons and deduplication.
+    pub fn send(&self, alert: &Alert) {
+        if let Some(queue) = &self.queue {
+            let _ = queue.send(alert.clone());
+        }
+    }
+}
+
+impl Drop for KubeEvents {
+    /// Publishes what is queued, then stops.
+    fn drop(&mut self) {
+        self.queue.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "k8s")]
+mod api {
+    use super::super::alerts::{Alert, AlertSource, Deduplicator};
+    use super::super::http::{client_tls, send, ClientTls, Outgoing, Url};
+    use super::super::DCGMError;
+    use super::{condition_patch, node_event, KubeEventsConfig};
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    const ATTEMPTS: u32 = 3;
+
+    /// The API server of the cluster the pod runs in, reached with its service account.
+    pub(super) struct ApiServer {
+        base: String,
+        tls: ClientTls,
+    }
+
+    impl ApiServer {
+        pub(super) fn in_cluster() -> Result<Self, DCGMError> {
+            let (Ok(host), Ok(port)) = (std::env::var("KUBERNETES_SERVICE_HOST"), std::env::var("KUBERNETES_SERVICE_PORT")) else {
+                return Err(DCGMError::from("Not running in a Kubernetes pod, KUBERNETES_SERVICE_HOST is not set"));
+            };
+            let host = if host.contains(':') { format!("[{host}]") } else { host };
+            let tls = client_tls(&Path::new(SERVICE_ACCOUNT).join("ca.crt"))?;
+            Ok(Self { base: format!("https://{host}:{port}"), tls })
+        }
+
+        /// Sends `body` to `path` and returns the status. The token is read for every request, as the
+        /// kubelet rotates it.
+        fn request(&self, method: &str, path: &str, content_type: &str, body: &serde_json::Value) -> std::io::Result<u16> {
+            let token = std::fs::read_to_string(Path::new(SERVICE_ACCOUNT).join("token"))?;
+            let headers = BTreeMap::from([("Authorization".to_string(), format!("Bearer {}", token.trim()))]);
+            let url = Url::parse(&format!("{}{path}", self.base)).map_err(std::io::Error::other)?;
+            let body = body.to_string();
+            let request = Outgoing { method, url: &url, content_type, headers: &headers, body: body.as_bytes(), timeout: TIMEOUT };
+            send(&request, Some(&self.tls))
+        }
+
+        /// `request` with a few attempts for connection errors, 429 and 5xx.
+        fn request_with_retries(&self, method: &str, path: &str, content_type: &str, body: &serde_json::Value) -> Result<(), String> {
+            let mut backoff = Duration::from_secs(1);
+            for attempt in 1..=ATTEMPTS {
+                let error = match self.request(method, path, content_type, body) {
+                    Ok(status) if (200..300).contains(&status) => return Ok(()),
+                    Ok(status) if status != 429 && status < 500 => return Err(format!("HTTP {status}")),
+                    Ok(status) => format!("HTTP {status}"),
+                    Err(e) => e.to_string(),
+                };
+                if attempt == ATTEMPTS {
+                    return Err(error);
+                }
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            unreachable!("the last attempt returns")
+        }
+    }
+
+    fn now_micros() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64
+    }
+
+    pub(super) fn publish(config: &KubeEventsConfig, api: &ApiServer, alerts: Receiver<Alert>) {
+        let node = config.node_name().unwrap_or_default();
+        let events_path = format!("/api/v1/namespaces/{}/events", config.namespace);
+        let status_path = format!("/api/v1/nodes/{node}/status");
+        let mut dedup = Deduplicator::new(config.dedup_window);
+        // health failures by dedup key, for the node condition
+        let mut failures = BTreeMap::new();
+        // whether the last condition the API server accepted was failing; the status already on the
+        // node is not read back, so the first patch counts as a transition
+        let mut failing = None;
+        if config.condition {
+            set_condition(api, &status_path, &failures, &mut failing);
+        }
+        for alert in alerts {
+            if config.condition && alert.source == AlertSource::Health {
+                let changed = if alert.resolved || alert.reason != "HealthFailure" {
+                    failures.remove(&alert.dedup_key).is_some()
+                } else {
+                    failures.insert(alert.dedup_key.clone(), alert.summary.clone()).as_ref() != Some(&alert.summary)
+                };
+                if changed {
+                    set_condition(api, &status_path, &failures, &mut failing);
+                }
+            }
+            if !config.publishes(&alert) || !dedup.admit(&alert, Instant::now()) {
+                continue;
+            }
+            match api.request_with_retries("POST", &events_path, "application/json", &node_event(&node, &config.namespace, &alert)) {
+                Ok(()) => dedup.delivered(&alert, Instant::now()),
+                Err(e) => tracing::warn!("Failed to publish Kubernetes event '{}': {e}", alert.summary),
+            }
+        }
+    }
+
+    fn set_condition(api: &ApiServer, path: &str, failures: &BTreeMap<String, String>, failing: &mut Option<bool>) {
+        let now_failing = !failures.is_empty();
+        let patch = condition_patch(failures, now_micros(), *failing != Some(now_failing));
+        match api.request_with_retries("PATCH", path, "application/strategic-merge-patch+json", &patch) {
+            Ok(()) => *failing = Some(now_failing),
+            Err(e) => tracing::warn!("Failed to set the {} node condition: {e}", super::NODE_CONDITION),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::bindings::*;
+use super::entity::Entity;
+use super::init::Zeroable;
+use super::samples::{decode_field_value_v2, Sample, SampleSet};
+use super::{DCGMError, DcgmLibSafe};
+use std::fmt;
+use std::os::raw::c_uint;
+
+/// A reusable `dcgmEntitiesGetLatestValues` query over every entity × field pair in one call.
+///
+/// Each raw value is over 4 KiB (the blob union member), so the value buffer is kept between calls and
+/// only grown; steady-state collection then costs one DCGM round trip and the decoding.
+pub struct LatestValuesQuery {
+    entities: Vec<dcgmGroupEntityPair_t>,
+    fields: Vec<u16>,
+    buffer: Vec<dcgmFieldValue_v2>,
+}
+
+impl fmt::Debug for LatestValuesQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatestValuesQuery")
+            .field("entities", &self.entities.len())
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+impl LatestValuesQuery {
+    pub fn new(entities: &[Entity], fields: &[u16]) -> Self {
+        Self { entities: entities.iter().map(|e| e.to_raw()).collect(), fields: fields.to_vec(), buffer: Vec::new() }
+    }
+
+    pub fn set_entities(&mut self, entities: &[Entity]) {
+        self.entities = entities.iter().map(|e| e.to_raw()).collect();
+    }
+
+    pub fn set_fields(&mut self, fields: &[u16]) {
+        self.fields = fields.to_vec();
+    }
+
+    pub fn fields(&self) -> &[u16] {
+        &self.fields
+    }
+
+    /// Number of values one call returns.
+    pub fn len(&self) -> usize {
+        self.entities.len() * self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs the query and returns the raw values, entity-major.
+    pub fn fetch(&mut self, dcgm: &mut DcgmLibSafe) -> Result<&[dcgmFieldValue_v2], DCGMError> {
+        let n = self.len();
+        if n == 0 {
+            return Ok(&[]);
+        }
+        if self.buffer.len() < n {
+            self.buffer.resize_with(n, dcgmFieldValue_v2::zeroed);
+        }
+        match unsafe{dcgm.lib()?.dcgmEntitiesGetLatestValues(dcgm.handle(), self.entities.as_mut_ptr(), self.entities.len() as c_uint,
+                                                         self.fields.as_mut_ptr(), self.fields.len() as c_uint, 0,
+                                                         self.buffer.as_mut_ptr())}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(&self.buffer[..n]),
+            err_code => Err(dcgm.call_error(err_code, "dcgmEntitiesGetLatestValues")
+                                   .arg("entities", self.entities.len()).arg("fields", self.fields.len()))
+        }
+    }
+
+    /// Runs the query and appends every value DCGM could provide to `out`; returns how many were added.
+    pub fn collect_into(&mut self, dcgm: &mut DcgmLibSafe, out: &mut Vec<Sample>) -> Result<usize, DCGMError> {
+        let values = self.fetch(dcgm)?;
+        Ok(decode_latest(values, out))
+    }
+
+    pub fn collect(&mut self, dcgm: &mut DcgmLibSafe) -> Result<Vec<Sample>, DCGMError> {
+        let mut out = Vec::with_capacity(self.len());
+        self.collect_into(dcgm, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl DcgmLibSafe {
+    /// Latest values of every field of `field_group` on every entity of `group`, in one call. The
+    /// group's entities and the field group's fields are looked up on every call, so changes to either
+    /// are picked up; when polling the same pair in a loop, a `LatestValuesQuery` saves those lookups.
+    pub fn get_group_values(&mut self, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t) -> Result<SampleSet, DCGMError> {
+        let entities = self.getGroupEntities(group)?;
+        let fields = self.getFieldGroupFields(field_group)?;
+        let samples = LatestValuesQuery::new(&entities, &fields).collect(self)?;
+        Ok(SampleSet { entities, fields, samples })
+    }
+}
+
+/// Decodes a batch of raw values into `out`, skipping entries whose status is not OK (not watched, no
+/// data, not supported) without building an error for each. Returns how many samples were added.
+pub fn decode_latest(values: &[dcgmFieldValue_v2], out: &mut Vec<Sample>) -> usize {
+    let before = out.len();
+    out.reserve(values.len());
+    out.extend(values.iter()
+        .filter(|v| v.status == dcgmReturn_enum_DCGM_ST_OK)
+        .filter_map(|v| decode_field_value_v2(v).ok()));
+    out.len() - before
+}
@@ -0,0 +1,209 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::exporter::mig_profile_name;
+use super::init::Versioned;
+use super::samples::{decode_field_value_v2, FieldValue};
+use super::{c_chars_to_string, reported_count, DCGMError, DcgmLibSafe};
+use serde::Serialize;
+use std::mem;
+
+/// A MIG GPU or compute instance and where it sits on its GPU.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigEntity {
+    pub entity: Entity,
+    /// The GPU of a GPU instance, the GPU instance of a compute instance.
+    pub parent: Entity,
+    /// The physical GPU, for compute instances too.
+    pub gpu_id: u32,
+    pub gpu_uuid: String,
+    /// NVML GPU instance id; the `GPU_I_ID` label of dcgm-exporter.
+    pub instance_id: u32,
+    /// NVML compute instance id; None for GPU instances.
+    pub compute_instance_id: Option<u32>,
+    pub profile_id: u32,
+    /// Slices of the GPU (or of the GPU instance, for a compute instance) the profile takes.
+    pub slices: u32,
+}
+
+impl DcgmLibSafe {
+    /// Every MIG GPU and compute instance, GPU instances first. Empty when no GPU has MIG enabled.
+    pub fn mig_hierarchy(&mut self) -> Result<Vec<MigEntity>, DCGMError>{
+        // dcgmMigHierarchy_v2 carries 448 entries, keep it off the stack.
+        let mut hierarchy = dcgmMigHierarchy_v2::boxed_versioned();
+        match unsafe{self.lib()?.dcgmGetGpuInstanceHierarchy(self.handle(), &mut *hierarchy)}{
+            dcgmReturn_enum_DCGM_ST_OK => (),
+            err_code => return Err(self.call_error(err_code, "dcgmGetGpuInstanceHierarchy"))
+        }
+        let count = reported_count(hierarchy.count, hierarchy.entityList.len(), "MIG instances");
+        let mut entities: Vec<MigEntity> = hierarchy.entityList[..count].iter().filter_map(|info| {
+            let entity = Entity::try_from(info.entity).ok()?;
+            let parent = Entity::try_from(info.parent).ok()?;
+            Some(MigEntity {
+                entity,
+                parent,
+                gpu_id: parent.id,
+                gpu_uuid: c_chars_to_string(&info.info.gpuUuid),
+                instance_id: info.info.nvmlInstanceId,
+                compute_instance_id: (entity.group == EntityGroup::ComputeInstance).then_some(info.info.nvmlComputeInstanceId),
+                profile_id: info.info.nvmlMigProfileId,
+                slices: info.info.nvmlProfileSlices,
+            })
+        }).collect();
+        entities.sort_by_key(|e| e.entity);
+        // a compute instance's parent is its GPU instance, whose parent is the GPU
+        for i in 0..entities.len() {
+            if entities[i].entity.group != EntityGroup::ComputeInstance {
+                continue;
+            }
+            if let Some(gpu_id) = entities.iter().find(|e| e.entity == entities[i].parent).map(|e| e.gpu_id) {
+                entities[i].gpu_id = gpu_id;
+            }
+        }
+        Ok(entities)
+    }
+}
+
+/// A GPU instance profile a GPU offers, from `DCGM_FI_DEV_MIG_GI_INFO`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GpuInstanceProfile {
+    /// NVML profile id, what creating a GPU instance takes.
+    pub id: u32,
+    pub slices: u32,
+    /// How many instances of this profile fit on the GPU.
+    pub instance_count: u32,
+    pub multiprocessors: u32,
+    pub copy_engines: u32,
+    pub decoders: u32,
+    pub encoders: u32,
+    pub jpeg_engines: u32,
+    pub ofa_engines: u32,
+    pub memory_mib: u64,
+    pub p2p_supported: bool,
+}
+
+impl GpuInstanceProfile {
+    /// NVIDIA's name for the profile, e.g. `3g.40gb`.
+    pub fn name(&self) -> String {
+        mig_profile_name(self.slices, Some(self.memory_mib as f64))
+    }
+}
+
+impl From<&dcgmGpuInstanceProfileInfo_v1> for GpuInstanceProfile {
+    fn from(p: &dcgmGpuInstanceProfileInfo_v1) -> Self {
+        Self {
+            id: p.id,
+            slices: p.sliceCount,
+            instance_count: p.instanceCount,
+            multiprocessors: p.multiprocessorCount,
+            copy_engines: p.copyEngineCount,
+            decoders: p.decoderCount,
+            encoders: p.encoderCount,
+            jpeg_engines: p.jpegCount,
+            ofa_engines: p.ofaCount,
+            memory_mib: p.memorySizeMB,
+            p2p_supported: p.isP2pSupported != 0,
+        }
+    }
+}
+
+/// A compute instance profile a GPU instance offers, from `DCGM_FI_DEV_MIG_CI_INFO`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ComputeInstanceProfile {
+    /// NVML id of the GPU instance the profile applies to.
+    pub gpu_instance_id: u32,
+    pub id: u32,
+    /// Slices of the GPU instance the profile takes.
+    pub slices: u32,
+    pub instance_count: u32,
+    pub multiprocessors: u32,
+    pub shared_copy_engines: u32,
+    pub shared_decoders: u32,
+    pub shared_encoders: u32,
+    pub shared_jpeg_engines: u32,
+    pub shared_ofa_engines: u32,
+}
+
+impl From<&dcgmComputeInstanceProfileInfo_v1> for ComputeInstanceProfile {
+    fn from(p: &dcgmComputeInstanceProfileInfo_v1) -> Self {
+        Self {
+            gpu_instance_id: p.gpuInstanceId,
+            id: p.id,
+            slices: p.sliceCount,
+            instance_count: p.instanceCount,
+            multiprocessors: p.multiprocessorCount,
+            shared_copy_engines: p.sharedCopyEngineCount,
+            shared_decoders: p.sharedDecoderCount,
+            shared_encoders: p.sharedEncoderCount,
+            shared_jpeg_engines: p.sharedJpegCount,
+            shared_ofa_engines: p.sharedOfaCount,
+        }
+    }
+}
+
+/// The MIG profiles of one GPU. Both lists are empty for GPUs without MIG support.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MigProfiles {
+    pub gpu_id: u32,
+    pub gpu_instance: Vec<GpuInstanceProfile>,
+    pub compute_instance: Vec<ComputeInstanceProfile>,
+}
+
+impl MigProfiles {
+    /// The GPU instance profile with `slices` slices and the most memory, e.g. to pick `1g.10gb` over
+    /// `1g.5gb`.
+    pub fn gpu_instance_by_slices(&self, slices: u32) -> Option<&GpuInstanceProfile> {
+        self.gpu_instance.iter().filter(|p| p.slices == slices).max_by_key(|p| p.memory_mib)
+    }
+
+    /// The GPU instance profile named like `3g.40gb`.
+    pub fn gpu_instance_by_name(&self, name: &str) -> Option<&GpuInstanceProfile> {
+        self.gpu_instance.iter().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl DcgmLibSafe {
+    /// GPU and compute instance profiles of a GPU, read live. Compute instance profiles are per GPU
+    /// instance, so expect them only once the GPU has GPU instances.
+    pub fn mig_profiles(&mut self, gpu_id: u32) -> Result<MigProfiles, DCGMError>{
+        let mut gpu = [Entity::gpu(gpu_id).to_raw()];
+        let mut fields = [DCGM_FI_DEV_MIG_GI_INFO as u16, DCGM_FI_DEV_MIG_CI_INFO as u16];
+        let values = self.entitiesGetLatestValues(&mut gpu, &mut fields, DCGM_FV_FLAG_LIVE_DATA)?;
+        let mut profiles = MigProfiles { gpu_id, ..MigProfiles::default() };
+        for value in &values {
+            // blank or not supported without MIG
+            let Ok(sample) = decode_field_value_v2(value) else { continue };
+            let FieldValue::Blob(bytes) = sample.value else { continue };
+            match sample.field_id as u32 {
+                DCGM_FI_DEV_MIG_GI_INFO => {
+                    let entries = profile_entries::<dcgmGpuInstanceProfileInfo_v1>(
+                        &bytes, mem::offset_of!(dcgmGpuInstanceProfiles_v1, profileInfo));
+                    profiles.gpu_instance = entries.iter().map(GpuInstanceProfile::from).collect();
+                }
+                DCGM_FI_DEV_MIG_CI_INFO => {
+                    let entries = profile_entries::<dcgmComputeInstanceProfileInfo_v1>(
+                        &bytes, mem::offset_of!(dcgmComputeInstanceProfiles_v1, profileInfo));
+                    profiles.compute_instance = entries.iter().map(ComputeInstanceProfile::from).collect();
+                }
+                _ => (),
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// `mig_profiles` of every supported GPU.
+    pub fn mig_profiles_all(&mut self) -> Result<Vec<MigProfiles>, DCGMError>{
+        self.getAllSupportedDevices()?.into_iter().map(|gpu| self.mig_profiles(gpu)).collect()
+    }
+}
+
+/// The entries of a `dcgm*InstanceProfiles_v1` blob: a version and a count, then `profileCount`
+/// infos starting at `offset`. The header declares a single info; the blob may carry more after it,
+/// so the count is trusted as far as the blob is long.
+fn profile_entries<T: Copy>(bytes: &[u8], offset: usize) -> Vec<T> {
+    let Some(count) = bytes.get(4..8) else { return Vec::new() };
+    let count = u32::from_ne_bytes(count.try_into().unwrap()) as usize;
+    let available = bytes.len().saturating_sub(offset) / mem::size_of::<T>();
+    (0..count.min(available))
+        .map(|i| unsafe { std::ptr::read_unaligned(bytes[offset + i * mem::size_of::<T>()..].as_ptr() as *const T) })
+        .collect()
+}
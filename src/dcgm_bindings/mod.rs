@@ -4,7 +4,75 @@
 #![allow(unused)]
 
 pub mod bindings;
+pub mod init;
+pub mod attributes;
+pub mod samples;
+pub mod histogram;
+pub mod rollup;
+pub mod sample_buffer;
+pub mod config;
+pub mod status;
+pub mod watch;
+pub mod presets;
+pub mod entity;
+pub mod topology;
+pub mod signals;
+pub mod capabilities;
+pub mod compat;
+pub mod scheduler;
+pub mod watchdog;
+pub(crate) mod callbacks;
+pub mod policy;
+pub mod alerts;
+pub mod change;
+pub mod rates;
+pub mod units;
+pub mod format;
+pub mod exporter;
+pub mod container;
+pub mod process;
+pub mod jobs;
+pub mod health;
+pub mod cluster;
+pub mod diag;
+pub mod import;
+pub mod report;
+pub mod burnin;
+pub mod idle;
+pub mod power;
+pub mod errors;
+pub mod daemon;
+pub mod catalog;
+pub mod latest;
+pub mod timing;
+pub mod shutdown;
+pub mod session;
+pub mod client;
+pub mod hostengine;
+pub mod hotplug;
+pub mod mig;
+pub mod http;
+pub mod fuse;
+pub mod systemd;
+pub mod probe;
+#[cfg(feature = "k8s")]
+pub mod k8s;
+pub mod kube_events;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "nvml-fallback")]
+pub mod nvml;
+#[cfg(feature = "raw")]
+pub mod raw;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "simulation")]
+pub mod simulation;
 use bindings::*;
+use entity::{Entity, EntityGroup, EntityListFlags, LinkId};
+use init::{Versioned, Zeroable};
 
 use std::ffi::{CString, CStr};
 use std::os::raw::{c_uint, c_void};
@@ -12,7 +80,10 @@ use std::ptr;
 use std::fmt;
 use std::mem;
 use lazy_static::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use serde::Serialize;
 
 // Global handles and state
 static mut DCGM_LIB_HANDLE: *mut c_void = ptr::null_mut();
@@ -25,38 +96,212 @@ pub enum Mode {
     StartHostengine,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DCGMErrorKind {
+    Generic,
+    /// libdcgm is not available (stub build or `RUST_DCGM_STUB` set) or the operation is unsupported.
+    NotSupported,
+    /// The caller lacks the privileges the operation needs (e.g. embedded mode as non-root).
+    PermissionDenied,
+    /// A call did not return before its watchdog deadline; the connection is marked suspect.
+    Timeout,
+    /// The connection was shut down or lost; create a new `DcgmLibSafe` to talk to DCGM again.
+    Disconnected,
+}
+
 #[derive(Clone, Debug)]
 pub struct DCGMError {
     pub message: String,
+    pub kind: DCGMErrorKind,
+    /// The `dcgmReturn_t` libdcgm answered with, for errors it reported.
+    pub code: Option<dcgmReturn_t>,
+    /// The libdcgm call that failed, for errors it reported.
+    pub call: Option<FfiCall>,
+    /// What was being done when the error happened, innermost first; see `DcgmResultExt`.
+    pub context: Vec<String>,
+}
+
+/// A libdcgm function and the arguments that identify what it was asked to do, e.g.
+/// `dcgmWatchFields(group=2, field_group=5)`. Handles and buffers are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FfiCall {
+    pub function: &'static str,
+    pub args: Vec<(&'static str, String)>,
+}
+
+impl fmt::Display for FfiCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.function)?;
+        for (i, (name, value)) in self.args.iter().enumerate() {
+            write!(f, "{}{name}={value}", if i == 0 { "" } else { ", " })?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl DCGMError {
+    fn with_kind(message: String, kind: DCGMErrorKind) -> Self {
+        Self { message, kind, code: None, call: None, context: Vec::new() }
+    }
+
+    pub fn not_supported<T: Into<String>>(message: T) -> Self {
+        Self::with_kind(message.into(), DCGMErrorKind::NotSupported)
+    }
+
+    pub fn permission_denied<T: Into<String>>(message: T) -> Self {
+        Self::with_kind(message.into(), DCGMErrorKind::PermissionDenied)
+    }
+
+    pub fn timeout<T: Into<String>>(message: T) -> Self {
+        Self::with_kind(message.into(), DCGMErrorKind::Timeout)
+    }
+
+    pub fn disconnected<T: Into<String>>(message: T) -> Self {
+        Self::with_kind(message.into(), DCGMErrorKind::Disconnected)
+    }
+
+    /// Adds an argument to the recorded libdcgm call; does nothing for errors without one.
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Self {
+        if let Some(call) = &mut self.call {
+            call.args.push((name, value.to_string()));
+        }
+        self
+    }
+
+    /// Wraps the error in what the caller was doing; kind, code and call stay as they are.
+    pub fn context<C: Into<String>>(mut self, context: C) -> Self {
+        self.context.push(context.into());
+        self
+    }
+}
+
+/// Adds operation context to errors on their way up, keeping the DCGM code and call underneath:
+///
+/// ```no_run
+/// # use rust_dcgm::dcgm_bindings::{DcgmLibSafe, DcgmResultExt, DCGMError};
+/// # fn f(dcgm: &mut DcgmLibSafe) -> Result<(), DCGMError> {
+/// let group = dcgm.createGroup(&"exporter".to_string()).dcgm_context("while creating exporter group")?;
+/// # Ok(()) }
+/// ```
+pub trait DcgmResultExt<T> {
+    fn dcgm_context<C: Into<String>>(self, context: C) -> Result<T, DCGMError>;
+
+    /// `dcgm_context` with the context only built when there is an error.
+    fn with_dcgm_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T, DCGMError>;
+}
+
+impl<T, E: Into<DCGMError>> DcgmResultExt<T> for Result<T, E> {
+    fn dcgm_context<C: Into<String>>(self, context: C) -> Result<T, DCGMError> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_dcgm_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T, DCGMError> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+/// The kind of error a DCGM return code stands for.
+fn kind_of(code: dcgmReturn_t) -> DCGMErrorKind {
+    match code {
+        dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED => DCGMErrorKind::NotSupported,
+        dcgmReturn_enum_DCGM_ST_NO_PERMISSION | dcgmReturn_enum_DCGM_ST_REQUIRES_ROOT => DCGMErrorKind::PermissionDenied,
+        dcgmReturn_enum_DCGM_ST_TIMEOUT => DCGMErrorKind::Timeout,
+        dcgmReturn_enum_DCGM_ST_CONNECTION_NOT_VALID => DCGMErrorKind::Disconnected,
+        _ => DCGMErrorKind::Generic,
+    }
 }
 
 impl std::error::Error for DCGMError {}
 
 impl fmt::Display for DCGMError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Delegate to String’s Display
-        self.message.fmt(f)
+        for context in self.context.iter().rev() {
+            write!(f, "{context}: ")?;
+        }
+        self.message.fmt(f)?;
+        match (&self.call, self.code) {
+            (Some(call), Some(code)) => write!(f, " ({call} returned {code})"),
+            (Some(call), None) => write!(f, " ({call})"),
+            (None, _) => Ok(()),
+        }
     }
 }
 
 impl<T: Into<String>> From<T> for DCGMError {
     fn from(message: T) -> Self {
-        Self {
-            message: message.into(),
-        }
+        Self::with_kind(message.into(), DCGMErrorKind::Generic)
     }
 }
 
 unsafe impl Sync for DcgmLib {}
 
+pub(crate) fn c_chars_to_string(chars: &[std::os::raw::c_char]) -> String {
+    let bytes: Vec<u8> = chars.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The entries of a fixed-size DCGM response array to read: `count` as DCGM reported it, clamped to the
+/// array's `capacity`. A count past the capacity means DCGM had more than the struct can carry; that is
+/// logged instead of read out of bounds or dropped silently.
+pub(crate) fn reported_count(count: impl TryInto<usize>, capacity: usize, what: &str) -> usize {
+    let count = count.try_into().unwrap_or(0);
+    if count > capacity {
+        tracing::warn!("DCGM reported {count} {what}, only the first {capacity} fit in the response and are used");
+    }
+    count.min(capacity)
+}
+
+/// Copies `src` into a fixed-size C char array with a terminating NUL; fails when it does not fit.
+pub(crate) fn copy_str(dst: &mut [std::os::raw::c_char], src: &str) -> Result<(), DCGMError> {
+    if src.len() >= dst.len() {
+        return Err(DCGMError::from(format!("'{src}' is longer than {} bytes", dst.len() - 1)));
+    }
+    for (d, s) in dst.iter_mut().zip(src.bytes()) {
+        *d = s as std::os::raw::c_char;
+    }
+    dst[src.len()] = 0;
+    Ok(())
+}
+
+lazy_static! {
+    /// `errorString` results by return code. The messages are static in libdcgm, so each code crosses
+    /// FFI once and error paths in retry loops only take a read lock.
+    static ref ERROR_STRINGS: RwLock<HashMap<dcgmReturn_t, String>> = RwLock::new(HashMap::new());
+}
+
+fn error_string(dcgm: &DcgmLib, code: dcgmReturn_t) -> String {
+    if let Some(message) = ERROR_STRINGS.read().unwrap_or_else(|e| e.into_inner()).get(&code) {
+        return message.clone();
+    }
+    let ptr = unsafe { dcgm.errorString(code) };
+    let message = if ptr.is_null() {
+        format!("Unknown DCGM error {code}")
+    } else {
+        let cstr = unsafe { CStr::from_ptr(ptr) };
+        cstr.to_string_lossy().into_owned()
+    };
+    ERROR_STRINGS.write().unwrap_or_else(|e| e.into_inner()).insert(code, message.clone());
+    message
+}
+
+pub(crate) const DCGM_LIB_PATH: &str = "/usr/lib/x86_64-linux-gnu/libdcgm.so.4";
+
+fn stub_enabled() -> bool {
+    cfg!(feature = "stub") || std::env::var_os("RUST_DCGM_STUB").is_some()
+}
+
 lazy_static! {
     static ref DCGM_LIB: Result<DcgmLib, DCGMError> = {
+        if stub_enabled() {
+            return Err(DCGMError::not_supported("DCGM is not available in stub mode"));
+        }
         let dcgm = unsafe {
-            DcgmLib::new("/usr/lib/x86_64-linux-gnu/libdcgm.so.4").map_err(|e| {
+            DcgmLib::new(DCGM_LIB_PATH).map_err(|e| {
                 tracing::error!("Failed to load DCGM library: {e}");
                 DCGMError::from("Failed to load DCGM library")
             })?
         };
+        tracing::debug!("Loaded DCGM library: {:?}", capabilities::Capabilities::probe(&dcgm));
         Ok(dcgm)
     };
 }
@@ -64,64 +309,147 @@ lazy_static! {
 pub struct DcgmLibSafe {
     dcgm: &'static DcgmLib,
     stop_mode: Mode,
-    handle: dcgmHandle_t
+    /// Shared so that a reconnect (see `hostengine`) reaches every `share()` of this connection.
+    handle: Arc<AtomicUsize>,
+    /// Set when a watchdog deadline expired; shared by every `share()` of this connection.
+    suspect: Arc<AtomicBool>,
+    /// Cached device attributes; see `device_attributes`.
+    attributes: Arc<Mutex<attributes::AttributeCache>>,
+    /// Groups, watches and tasks `shutdown_graceful` cleans up.
+    resources: Arc<Mutex<shutdown::Resources>>,
+    /// Set by `shutdown`; from then on every call fails with `DCGMErrorKind::Disconnected`, on the
+    /// original and on every `share()` of it, instead of reaching DCGM with a dead handle.
+    disconnected: Arc<AtomicBool>,
+    /// The nv-hostengine child of `Mode::StartHostengine`.
+    hostengine: Option<Arc<hostengine::Supervisor>>,
+    /// Where the standalone hostengine was last connected to, for `reconnect`.
+    target: Arc<Mutex<Option<hostengine::StandaloneTarget>>>,
 }
 
 impl DcgmLibSafe {
     pub fn new(m: Mode, args: &[&str]) -> Result<Self, DCGMError> {
+        let mut dcgm = Self::initialized(m)?;
+        dcgm.connectToDcgm(m, args)?;
+        dcgm.verified()
+    }
+
+    /// Self after `verify_struct_layouts`, or that error once the connection is shut down again.
+    pub(crate) fn verified(mut self) -> Result<Self, DCGMError> {
+        if let Err(e) = self.verify_struct_layouts() {
+            let _ = self.shutdown();
+            return Err(e);
+        }
+        Ok(self)
+    }
+
+    /// Loaded and initialized, but not connected yet.
+    pub(crate) fn initialized(m: Mode) -> Result<Self, DCGMError> {
         match &*DCGM_LIB {
-            Ok(lib) => {
-                let mut dcgm = Self {dcgm: lib, stop_mode: m, handle: 0};
-                dcgm.init()?;
-                dcgm.connectToDcgm(m, args)?;
-                Ok(dcgm)
-            }
+            Ok(lib) => Self::with_lib(lib, m),
             Err(err) => Err(err.clone()),
         }
     }
 
+    /// Initialized on `lib`, which is libdcgm except for simulated connections.
+    pub(crate) fn with_lib(lib: &'static DcgmLib, m: Mode) -> Result<Self, DCGMError> {
+        let mut dcgm = Self {dcgm: lib, stop_mode: m, handle: Arc::default(), suspect: Arc::new(AtomicBool::new(false)), attributes: Arc::default(),
+                             resources: Arc::default(), disconnected: Arc::new(AtomicBool::new(false)), hostengine: None,
+                             target: Arc::default()};
+        dcgm.init()?;
+        Ok(dcgm)
+    }
+
     pub fn init(&mut self) -> Result<(), DCGMError> {
 
         match unsafe { self.dcgm.dcgmInit() } {
             dcgmReturn_enum_DCGM_ST_OK => Ok(()),
-            err_code => Err(DCGMError::from(self.get_error_msg(err_code))),
+            err_code => Err(self.call_error(err_code, "dcgmInit")),
         }
     }
 
+    /// Another `DcgmLibSafe` on the same connection, for worker threads. Only the original should be shut down.
+    pub(crate) fn share(&self) -> Self {
+        Self { dcgm: self.dcgm, stop_mode: self.stop_mode, handle: self.handle.clone(), suspect: self.suspect.clone(), attributes: self.attributes.clone(),
+               resources: self.resources.clone(), disconnected: self.disconnected.clone(),
+               hostengine: self.hostengine.clone(), target: self.target.clone() }
+    }
+
+    pub(crate) fn handle(&self) -> dcgmHandle_t {
+        self.handle.load(Ordering::Relaxed)
+    }
+
+    /// Whether `shutdown` was called on this connection or on the one it was shared from.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// The library, for calls that use `handle`; fails once the connection was shut down.
+    pub(crate) fn lib(&self) -> Result<&'static DcgmLib, DCGMError> {
+        if self.is_disconnected() {
+            return Err(DCGMError::disconnected("The DCGM connection was shut down"));
+        }
+        Ok(self.dcgm)
+    }
+
     pub fn get_error_msg(&self, code: dcgmReturn_t) -> String {
-        let ptr = unsafe { self.dcgm.errorString(code) };
-        if ptr.is_null() {
-            format!("Unknown DCGM error {code}")
-        } else {
-            let cstr = unsafe { CStr::from_ptr(ptr) };
-            cstr.to_string_lossy().into_owned()
+        error_string(self.dcgm, code)
+    }
+
+    /// The error for `code` returned by the libdcgm `function`; add the arguments with `DCGMError::arg`.
+    pub(crate) fn call_error(&self, code: dcgmReturn_t, function: &'static str) -> DCGMError {
+        DCGMError {
+            message: self.get_error_msg(code),
+            kind: kind_of(code),
+            code: Some(code),
+            call: Some(FfiCall { function, args: Vec::new() }),
+            context: Vec::new(),
         }
     }
 
     pub fn connectToDcgm(&mut self, m: Mode, args: &[&str]) -> Result<(), DCGMError>{
+        self.lib()?;
         match m{
             Mode::Embedded => return self.startEmbedded(),
             Mode::Standalone => return self.connectStandalone(args),
-            Mode::StartHostengine => Err(DCGMError::from("Not implemented")),//return self.startHostengine(),
+            Mode::StartHostengine => return self.startHostengine(args),
             _ => Err(DCGMError::from("Invalid DCGM Mode"))
         }
     }
 
     pub fn startEmbedded(&mut self) -> Result<(), DCGMError>{
-        match unsafe { self.dcgm.dcgmStartEmbedded(dcgmOperationMode_enum_DCGM_OPERATION_MODE_AUTO, &raw mut self.handle) } {
-            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
-            err_code => Err(DCGMError::from(self.get_error_msg(err_code))),
+        let mut handle: dcgmHandle_t = 0;
+        match unsafe { self.dcgm.dcgmStartEmbedded(dcgmOperationMode_enum_DCGM_OPERATION_MODE_AUTO, &raw mut handle) } {
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.handle.store(handle, Ordering::Relaxed);
+                Ok(())
+            }
+            err_code if self.is_privilege_failure(err_code) => Err(DCGMError::permission_denied(format!(
+                "Starting the embedded host engine failed ({}). Embedded mode needs root or read/write access \
+                 to /dev/nvidia*; run as root, or start nv-hostengine and use Mode::Standalone instead",
+                self.get_error_msg(err_code)))),
+            err_code => Err(self.call_error(err_code, "dcgmStartEmbedded")),
+        }
+    }
+
+    /// Errors from `dcgmStartEmbedded` that usually mean we are not privileged enough. INIT_ERROR and
+    /// NVML_ERROR are only treated that way when we are not root, since they have other causes too.
+    fn is_privilege_failure(&self, err_code: dcgmReturn_t) -> bool {
+        match err_code {
+            dcgmReturn_enum_DCGM_ST_NO_PERMISSION | dcgmReturn_enum_DCGM_ST_REQUIRES_ROOT => true,
+            dcgmReturn_enum_DCGM_ST_INIT_ERROR | dcgmReturn_enum_DCGM_ST_NVML_ERROR => unsafe { libc::geteuid() != 0 },
+            _ => false,
         }
     }
 
     pub fn stopEmbedded(&mut self) -> Result<(), DCGMError>{
-        let mut res = match unsafe{self.dcgm.dcgmStopEmbedded(self.handle)}{
+        let mut res = match unsafe{self.lib()?.dcgmStopEmbedded(self.handle())}{
             dcgmReturn_enum_DCGM_ST_OK => Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code))),
+            err_code => return Err(self.call_error(err_code, "dcgmStopEmbedded")),
         };
+        self.disconnected.store(true, Ordering::Relaxed);
         res = match unsafe{self.dcgm.dcgmShutdown()}{
             dcgmReturn_enum_DCGM_ST_OK => Ok(()),
-            err_code => Err(DCGMError::from(self.get_error_msg(err_code))),
+            err_code => Err(self.call_error(err_code, "dcgmShutdown")),
         };
         return res
     }
@@ -130,173 +458,294 @@ impl DcgmLibSafe {
         if args.len() < 2 {
             return Err(DCGMError::from("missing dcgm address and / or isUnixSocket"))
         } else{
-            let mut connect_params =  bindings::dcgmConnectV2Params_t{
-                version: make_version2(std::mem::size_of::<bindings::dcgmConnectV2Params_t>() as u32),
-                timeoutMs: 3000000,
-                persistAfterDisconnect: if args.len() == 3 {args[2].parse().unwrap()} else{0},
-                addressIsUnixSocket: args[1].parse().unwrap()
-            };
-            let addr = CString::new(args[0]).unwrap();
-            match unsafe {self.dcgm.dcgmConnect_v2(addr.as_ptr(), &raw mut connect_params, &raw mut self.handle)}{
-                dcgmReturn_enum_DCGM_ST_OK => return Ok(()),
-                err_code => return Err(DCGMError::from(self.get_error_msg(err_code))),
-            };
+            let flag = |name: &str, value: &str| value.parse::<u32>()
+                .map_err(|_| DCGMError::from(format!("{name} must be a number, got '{value}'")));
+            let persist = if args.len() == 3 {flag("persistAfterDisconnect", args[2])?} else{0};
+            self.connect_standalone_within(args[0], flag("isUnixSocket", args[1])?, persist, 3000000)
+        }
+    }
+
+    /// `dcgmConnect_v2`, giving up on the hostengine after `timeout_ms`.
+    pub(crate) fn connect_standalone_within(&mut self, address: &str, unix_socket: u32, persist: u32, timeout_ms: u32) -> Result<(), DCGMError>{
+        let mut connect_params =  bindings::dcgmConnectV2Params_t{
+            version: self.struct_versions().connect_params,
+            timeoutMs: timeout_ms,
+            persistAfterDisconnect: persist,
+            addressIsUnixSocket: unix_socket
+        };
+        let addr = CString::new(address).map_err(|_| DCGMError::from(format!("'{address}' contains a NUL byte")))?;
+        let mut handle: dcgmHandle_t = 0;
+        match unsafe {self.dcgm.dcgmConnect_v2(addr.as_ptr(), &raw mut connect_params, &raw mut handle)}{
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.handle.store(handle, Ordering::Relaxed);
+                *self.target.lock().unwrap_or_else(|e| e.into_inner()) = Some(hostengine::StandaloneTarget {
+                    address: address.to_string(), unix_socket, persist, timeout_ms,
+                });
+                Ok(())
+            }
+            err_code => Err(self.call_error(err_code, "dcgmConnect_v2").arg("address", address)),
         }
     }
 
     pub fn disconnectStandalone(&mut self) -> Result<(), DCGMError>{
-        match unsafe {self.dcgm.dcgmDisconnect(self.handle)}{
+        match unsafe {self.lib()?.dcgmDisconnect(self.handle())}{
             dcgmReturn_enum_DCGM_ST_OK => (),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => return Err(self.call_error(err_code, "dcgmDisconnect"))
         };
+        self.disconnected.store(true, Ordering::Relaxed);
 
         match unsafe {self.dcgm.dcgmShutdown()}{
             dcgmReturn_enum_DCGM_ST_OK => return Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => return Err(self.call_error(err_code, "dcgmShutdown"))
         };
     }
 
+    /// Stops the embedded hostengine or disconnects from the standalone one, stopping it too when this
+    /// connection started it. Afterwards this handle and
+    /// every `share()` of it return `DCGMErrorKind::Disconnected` errors.
+    ///
+    /// Groups and watches are left to the hostengine, which keeps them only when the connection was
+    /// made with `persistAfterDisconnect`; `shutdown_with` removes or keeps them explicitly.
     pub fn shutdown(&mut self) -> Result<(), DCGMError>{
+        self.lib()?;
+        signals::forget(&self.handle);
         match self.stop_mode{
             Mode::Embedded => return self.stopEmbedded(),
             Mode::Standalone => return self.disconnectStandalone(),
-            Mode::StartHostengine => Err(DCGMError::from("Not implemented"))
+            Mode::StartHostengine => {
+                let supervisor = self.hostengine.clone();
+                if let Some(supervisor) = &supervisor {
+                    supervisor.stop();
+                }
+                let res = self.disconnectStandalone();
+                if let Some(supervisor) = supervisor {
+                    supervisor.stop_child();
+                }
+                res
+            }
         }
     }
 
+    /// Supported GPUs. Listed through `dcgmGetEntityGroupEntities`, which reports the size it needs,
+    /// rather than `dcgmGetAllSupportedDevices` and its fixed `DCGM_MAX_NUM_DEVICES` buffer.
     pub fn getAllSupportedDevices(&mut self)-> Result<Vec<u32>, DCGMError>{
-        unsafe{
-            let mut gpu_id_list: [std::mem::MaybeUninit<u32>; DCGM_MAX_NUM_DEVICES as usize] = 
-                std::mem::MaybeUninit::uninit().assume_init();
-            let mut count: std::mem::MaybeUninit<i32> = std::mem::MaybeUninit::uninit().assume_init();
-            let res = self.dcgm.dcgmGetAllSupportedDevices(
-                self.handle, 
-                gpu_id_list.as_mut_ptr() as *mut std::os::raw::c_uint, 
-                count.as_mut_ptr());
-            if res != dcgmReturn_enum_DCGM_ST_OK{
-                return Err(DCGMError::from(self.get_error_msg(res)))
-            }
-            let initialized_slice = std::slice::from_raw_parts(gpu_id_list.as_mut_ptr() as *mut u32, count.assume_init() as usize);
-            return Ok(initialized_slice.to_vec())
-        }
+        self.entity_group_entities(EntityGroup::Gpu, EntityListFlags::ONLY_SUPPORTED)
     }
 
-    pub fn getEntityGroupEntites(&mut self, entityType: dcgm_field_entity_group_t) -> Result<Vec<u32>, DCGMError>{
-            unsafe{
-            let mut entity_id_list: [std::mem::MaybeUninit<u32>; DCGM_MAX_NUM_DEVICES as usize] = 
-                std::mem::MaybeUninit::uninit().assume_init();
-            let mut count: i32 = DCGM_MAX_NUM_DEVICES as i32;
-            let res = self.dcgm.dcgmGetEntityGroupEntities(
-                self.handle, 
-                entityType,
-                entity_id_list.as_mut_ptr() as *mut std::os::raw::c_uint, 
+    /// Lists every entity of `entityType`, including unsupported ones. See `entity_group_entities`.
+    pub fn getEntityGroupEntites(&mut self, entityType: EntityGroup) -> Result<Vec<u32>, DCGMError>{
+        self.entity_group_entities(entityType, EntityListFlags::empty())
+    }
+
+    /// Starts with room for `DCGM_MAX_NUM_DEVICES` entities and grows the buffer to whatever DCGM asks for
+    /// on `DCGM_ST_INSUFFICIENT_SIZE`, so large groups (links, CPU cores) are not truncated.
+    pub fn entity_group_entities(&mut self, entityType: EntityGroup, flags: EntityListFlags) -> Result<Vec<u32>, DCGMError>{
+        let mut capacity = DCGM_MAX_NUM_DEVICES as usize;
+        loop {
+            let mut entity_id_list = vec![0u32; capacity];
+            let mut count: i32 = capacity as i32;
+            match unsafe{self.lib()?.dcgmGetEntityGroupEntities(
+                self.handle(),
+                entityType.as_raw(),
+                entity_id_list.as_mut_ptr(),
                 &raw mut count,
-                0);
-            if res != dcgmReturn_enum_DCGM_ST_OK{
-                return Err(DCGMError::from(self.get_error_msg(res)))
+                flags.bits())}{
+                dcgmReturn_enum_DCGM_ST_OK => {
+                    entity_id_list.truncate(count.max(0) as usize);
+                    return Ok(entity_id_list)
+                }
+                dcgmReturn_enum_DCGM_ST_INSUFFICIENT_SIZE => {
+                    // DCGM reports the required size in count; double if it did not.
+                    capacity = if count as usize > capacity { count as usize } else { capacity * 2 };
+                }
+                err_code => return Err(self.call_error(err_code, "dcgmGetEntityGroupEntities").arg("entity_group", entityType))
             }
-            let initialized_slice = std::slice::from_raw_parts(entity_id_list.as_mut_ptr() as *mut u32, count as usize);
-            return Ok(initialized_slice.to_vec())
         }
     }
 
     pub fn createGroup(&mut self, group_name: &String) -> Result<dcgmGpuGrp_t, DCGMError>{
         let mut groupId: dcgmGpuGrp_t = 0;
-        match unsafe{self.dcgm.dcgmGroupCreate(
-            self.handle, 
+        match unsafe{self.lib()?.dcgmGroupCreate(
+            self.handle(), 
             dcgmGroupType_enum_DCGM_GROUP_EMPTY,
             CString::new(group_name.clone()).unwrap().as_ptr(), 
             &raw mut groupId)}{
 
-            dcgmReturn_enum_DCGM_ST_OK => return Ok(groupId),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().group_created(groupId, group_name);
+                return Ok(groupId)
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmGroupCreate").arg("name", group_name))
         };
     }
 
-    pub fn addEntityToGroup(&mut self, groupId: dcgmGpuGrp_t, entityGroupID: dcgm_field_entity_group_t, entityId: u32)->Result<(), DCGMError>{
-        match unsafe{self.dcgm.dcgmGroupAddEntity(
-            self.handle,
+    pub fn addEntityToGroup(&mut self, groupId: dcgmGpuGrp_t, entityGroupID: EntityGroup, entityId: u32)->Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmGroupAddEntity(
+            self.handle(),
             groupId,
-            entityGroupID,
+            entityGroupID.as_raw(),
             entityId
         )}{
-            dcgmReturn_enum_DCGM_ST_OK => return Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().entity_added(groupId, Entity::new(entityGroupID, entityId));
+                return Ok(())
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmGroupAddEntity").arg("group", groupId)
+                                    .arg("entity", Entity { group: entityGroupID, id: entityId }))
+        }
+    }
+
+    pub fn removeEntityFromGroup(&mut self, groupId: dcgmGpuGrp_t, entityGroupID: EntityGroup, entityId: u32)->Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmGroupRemoveEntity(
+            self.handle(),
+            groupId,
+            entityGroupID.as_raw(),
+            entityId
+        )}{
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().entity_removed(groupId, Entity::new(entityGroupID, entityId));
+                return Ok(())
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmGroupRemoveEntity").arg("group", groupId)
+                                    .arg("entity", Entity { group: entityGroupID, id: entityId }))
         }
     }
 
     pub fn destroyGroup(&mut self, groupId: dcgmGpuGrp_t)->Result<(), DCGMError>{
-        match unsafe{self.dcgm.dcgmGroupDestroy(self.handle, groupId)}{
-            dcgmReturn_enum_DCGM_ST_OK => return Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+        match unsafe{self.lib()?.dcgmGroupDestroy(self.handle(), groupId)}{
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().group_destroyed(groupId);
+                return Ok(())
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmGroupDestroy").arg("group", groupId))
+        }
+    }
+
+    pub fn getGroupEntities(&mut self, groupId: dcgmGpuGrp_t) -> Result<Vec<Entity>, DCGMError>{
+        let mut info = dcgmGroupInfo_t::versioned();
+        match unsafe{self.lib()?.dcgmGroupGetInfo(self.handle(), groupId, &raw mut info)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(info.entityList[..reported_count(info.count, info.entityList.len(), "group entities")].iter()
+                .filter_map(|pair| Entity::try_from(*pair).ok())
+                .collect()),
+            err_code => return Err(self.call_error(err_code, "dcgmGroupGetInfo").arg("group", groupId))
         }
     }
 
     pub fn fieldGroupCreate(&mut self, fieldGroupName: &str, fieldIds: &mut [u16])-> Result<dcgmFieldGrp_t, DCGMError>{
         let mut fieldHandle: dcgmFieldGrp_t = 0;
-        match unsafe{self.dcgm.dcgmFieldGroupCreate(
-            self.handle, fieldIds.len() as i32, 
+        match unsafe{self.lib()?.dcgmFieldGroupCreate(
+            self.handle(), fieldIds.len() as i32, 
             fieldIds.as_mut_ptr(), 
             CString::new(fieldGroupName.clone()).unwrap().as_ptr(), 
             &raw mut fieldHandle)}{
 
-            dcgmReturn_enum_DCGM_ST_OK => return Ok(fieldHandle),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().field_group_created(fieldHandle, fieldGroupName, fieldIds);
+                return Ok(fieldHandle)
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmFieldGroupCreate").arg("name", fieldGroupName)
+                                    .arg("fields", format!("{fieldIds:?}")))
         }
     }
 
     pub fn fieldGroupDestroy(&mut self, dcgmFieldGroupId: dcgmFieldGrp_t)->Result<(), DCGMError>{
-        match unsafe{self.dcgm.dcgmFieldGroupDestroy(self.handle, dcgmFieldGroupId)}{
-            dcgmReturn_enum_DCGM_ST_OK => return Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+        match unsafe{self.lib()?.dcgmFieldGroupDestroy(self.handle(), dcgmFieldGroupId)}{
+            dcgmReturn_enum_DCGM_ST_OK => {
+                self.resources().field_group_destroyed(dcgmFieldGroupId);
+                return Ok(())
+            }
+            err_code => return Err(self.call_error(err_code, "dcgmFieldGroupDestroy").arg("field_group", dcgmFieldGroupId))
+        }
+    }
+
+    /// Field ids of a field group: as recorded when this connection created it, else from DCGM.
+    pub fn getFieldGroupFields(&mut self, fieldGroupId: dcgmFieldGrp_t) -> Result<Vec<u16>, DCGMError>{
+        if let Some(record) = self.resources().field_groups.get(&fieldGroupId) {
+            return Ok(record.fields.clone());
+        }
+        let mut info = dcgmFieldGroupInfo_t::versioned();
+        info.fieldGroupId = fieldGroupId;
+        match unsafe{self.lib()?.dcgmFieldGroupGetInfo(self.handle(), &raw mut info)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(info.fieldIds[..reported_count(info.numFieldIds, info.fieldIds.len(), "field group fields")].to_vec()),
+            err_code => Err(self.call_error(err_code, "dcgmFieldGroupGetInfo").arg("field_group", fieldGroupId))
         }
     }
 
     pub fn watchFields(&mut self, fieldGroupId: dcgmFieldGrp_t, groupId: dcgmGpuGrp_t, updateFreq: i64, maxKeepAge: f64, maxKeepSamples: i32)->Result<(), DCGMError>{
-        match unsafe{self.dcgm.dcgmWatchFields(self.handle, groupId, fieldGroupId, updateFreq, maxKeepAge, maxKeepSamples)}{
-            dcgmReturn_enum_DCGM_ST_OK => (),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+        match unsafe{self.lib()?.dcgmWatchFields(self.handle(), groupId, fieldGroupId, updateFreq, maxKeepAge, maxKeepSamples)}{
+            dcgmReturn_enum_DCGM_ST_OK => self.resources().watched(groupId, fieldGroupId, shutdown::WatchRecord {
+                update_freq: updateFreq,
+                max_keep_age: maxKeepAge,
+                max_keep_samples: maxKeepSamples,
+            }),
+            err_code => return Err(self.call_error(err_code, "dcgmWatchFields").arg("group", groupId)
+                                    .arg("field_group", fieldGroupId).arg("update_freq_us", updateFreq))
         };
         return self.updateAllFields();
     }
 
+    /// Samples every watched field now and waits until the values are in; see `update_all_fields`.
     pub fn updateAllFields(&mut self)->Result<(), DCGMError>{
-        match unsafe{self.dcgm.dcgmUpdateAllFields(self.handle, 1)}{
+        self.update_all_fields(true)
+    }
+
+    /// `dcgmUpdateAllFields`: samples every watched field now. Without `wait_for_update` it returns as
+    /// soon as the hostengine has been asked, and values read right after may still be the previous ones.
+    pub fn update_all_fields(&mut self, wait_for_update: bool)->Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmUpdateAllFields(self.handle(), wait_for_update as i32)}{
             dcgmReturn_enum_DCGM_ST_OK => Ok(()),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => Err(self.call_error(err_code, "dcgmUpdateAllFields").arg("wait", wait_for_update))
         }
     }
 
+    /// Asks for a sample of every watched field without waiting for it, for loops that cannot afford to
+    /// block; the fresh values show up in the next reads.
+    pub fn refresh_nowait(&mut self)->Result<(), DCGMError>{
+        self.update_all_fields(false)
+    }
+
+    /// `updateAllFields` on a thread of its own, for async callers: completes once the values are in.
+    pub fn refresh(&self) -> shutdown::ThreadFuture<()> {
+        let mut dcgm = self.share();
+        shutdown::ThreadFuture::spawn("dcgm-refresh", move || dcgm.update_all_fields(true))
+    }
+
     pub fn entitiesGetLatestValues(&mut self, entities: &mut[dcgmGroupEntityPair_t], fields: &mut[u16], flags: u32) -> Result<Vec<dcgmFieldValue_v2>, DCGMError>{
-        let mut values = Vec::<dcgmFieldValue_v2>::with_capacity(fields.len()*entities.len());
-         unsafe{values.set_len(fields.len()*entities.len());}
-        match unsafe{self.dcgm.dcgmEntitiesGetLatestValues(
-            self.handle, 
-            &mut entities[0], 
+        if entities.is_empty() || fields.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut values = vec![dcgmFieldValue_v2::zeroed(); fields.len()*entities.len()];
+        match unsafe{self.lib()?.dcgmEntitiesGetLatestValues(
+            self.handle(), 
+            entities.as_mut_ptr(), 
             entities.len() as c_uint, 
-            &mut fields[0],
+            fields.as_mut_ptr(),
             fields.len() as c_uint,
             flags,
-            &mut values[0])}{
+            values.as_mut_ptr())}{
 
             dcgmReturn_enum_DCGM_ST_OK => Ok(values),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => return Err(self.call_error(err_code, "dcgmEntitiesGetLatestValues").arg("entities", entities.len())
+                                    .arg("fields", fields.len()))
         }
     }
 
-    pub fn entityGetLatestValues(&mut self, entityId: i32, entityGroup: dcgm_field_entity_group_t, fields: &mut[u16])->Result<Vec<dcgmFieldValue_v1>, DCGMError>{
-        let mut values = Vec::<dcgmFieldValue_v1>::with_capacity(fields.len());
-        unsafe{values.set_len(fields.len());}
-        match unsafe{self.dcgm.dcgmEntityGetLatestValues(
-            self.handle, 
-            entityGroup,
+    pub fn entityGetLatestValues(&mut self, entityId: i32, entityGroup: EntityGroup, fields: &mut[u16])->Result<Vec<dcgmFieldValue_v1>, DCGMError>{
+        if fields.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut values = vec![dcgmFieldValue_v1::zeroed(); fields.len()];
+        match unsafe{self.lib()?.dcgmEntityGetLatestValues(
+            self.handle(), 
+            entityGroup.as_raw(),
             entityId, 
-            &mut fields[0],
+            fields.as_mut_ptr(),
             fields.len() as c_uint,
-            &raw mut values[0])}{
+            values.as_mut_ptr())}{
 
             dcgmReturn_enum_DCGM_ST_OK => Ok(values),
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => return Err(self.call_error(err_code, "dcgmEntityGetLatestValues").arg("entity", format!("{entityGroup} {entityId}"))
+                                    .arg("fields", format!("{fields:?}")))
         }
     }
 
@@ -309,8 +758,8 @@ impl DcgmLibSafe {
             gpuBitmask |= 1 << *gpu;
         }
         let mut outputBitmask: u64 = 0;
-        match unsafe{self.dcgm.dcgmSelectGpusByTopology(
-            self.handle,
+        match unsafe{self.lib()?.dcgmSelectGpusByTopology(
+            self.handle(),
             gpuBitmask,
             numGpus,
             &raw mut outputBitmask,
@@ -327,78 +776,51 @@ impl DcgmLibSafe {
                     outputBitmask >>= 1;
                     index += 1;
                 } return Ok(indices)},
-            err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+            err_code => return Err(self.call_error(err_code, "dcgmSelectGpusByTopology").arg("gpus", format!("{gpuBitmask:#x}"))
+                                    .arg("count", numGpus))
         }
     }
 
+    /// Link states of every GPU and NvSwitch; works against DCGM 3.x and 4.x, see `compat::nvlink_status`.
     pub fn getNvLinkLinkStatus(&mut self) -> Result<Vec<NvLinkStatus>, DCGMError>{
-        unsafe{
-            let mut linkStatus: dcgmNvLinkStatus_t = std::mem::MaybeUninit::uninit().assume_init();
-            linkStatus.version = make_version4(std::mem::size_of::<dcgmNvLinkStatus_t>() as u32);
-            match self.dcgm.dcgmGetNvLinkLinkStatus(self.handle, &raw mut linkStatus){
-                dcgmReturn_enum_DCGM_ST_OK => (),
-                err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
-            }
-            let mut statuses = Vec::<NvLinkStatus>::with_capacity((linkStatus.numGpus*DCGM_NVLINK_MAX_LINKS_PER_GPU+linkStatus.numNvSwitches*DCGM_NVLINK_MAX_LINKS_PER_NVSWITCH) as usize);
-            let mut index = 0;
-            for i in 0..linkStatus.numGpus{
-                for j in 0..DCGM_NVLINK_MAX_LINKS_PER_GPU{
-                    let link = NvLinkStatus{
-                        parent_id: linkStatus.gpus[i as usize].entityId,
-                        parent_type: dcgm_field_entity_group_t_DCGM_FE_GPU,
-                        state: linkStatus.gpus[i as usize].linkState[j as usize],
-                        index: j
-                    };
-
-                    statuses.push(link);
-                    index += 1;
-                }
-            }
-
-            for i in 0..linkStatus.numNvSwitches{
-                for j in 0..DCGM_NVLINK_MAX_LINKS_PER_NVSWITCH{
-                    let link = NvLinkStatus{
-                        parent_id: linkStatus.gpus[i as usize].entityId,
-                        parent_type: dcgm_field_entity_group_t_DCGM_FE_SWITCH,
-                        state: linkStatus.gpus[i as usize].linkState[j as usize],
-                        index: j
-                    };
+        self.nvlink_status()
+    }
 
-                    statuses.push(link);
-                    index += 1;
-                }
-            }
-            return Ok(statuses);
-        }
+    /// `DCGM_FE_LINK` entities of every NvLink that is up, on GPUs and NvSwitches alike. Add them to a
+    /// group to watch per-link error and throughput fields directly.
+    pub fn nvlink_entities(&mut self) -> Result<Vec<Entity>, DCGMError>{
+        Ok(self.nvlink_status()?.iter()
+            .filter(|link| link.state == NvLinkState::Up)
+            .map(|link| Entity::link(link.link_id()))
+            .collect())
     }
 
     pub fn getDeviceAttributes(&mut self, gpuId: u32) -> Result<dcgmDeviceAttributes_t, DCGMError>{
         unsafe{
-            let mut device: dcgmDeviceAttributes_t = std::mem::MaybeUninit::uninit().assume_init();
-            device.version = make_version3(std::mem::size_of::<dcgmDeviceAttributes_t>() as u32);
-            match self.dcgm.dcgmGetDeviceAttributes(self.handle, gpuId as c_uint, &mut device){
+            let mut device = dcgmDeviceAttributes_t::with_version(self.struct_versions().device_attributes);
+            match self.lib()?.dcgmGetDeviceAttributes(self.handle(), gpuId as c_uint, &mut device){
                 dcgmReturn_enum_DCGM_ST_OK => Ok(device),
-                err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+                err_code => return Err(self.call_error(err_code, "dcgmGetDeviceAttributes").arg("gpu", gpuId))
             }
         }
     }
 
     pub fn getDeviceTopology(&mut self, gpuId: u32) -> Result<Vec<P2PLink>, DCGMError>{
         unsafe{
-            let mut topology: dcgmDeviceTopology_t = std::mem::MaybeUninit::uninit().assume_init();
-            topology.version = make_version1(std::mem::size_of::<dcgmDeviceTopology_t>() as u32);
-            match self.dcgm.dcgmGetDeviceTopology(self.handle, gpuId as c_uint, &mut topology){
+            let mut topology = dcgmDeviceTopology_t::versioned();
+            match self.lib()?.dcgmGetDeviceTopology(self.handle(), gpuId as c_uint, &mut topology){
                 dcgmReturn_enum_DCGM_ST_OK => (),
                 dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED => return Ok(Vec::<P2PLink>::new()),
-                err_code => return Err(DCGMError::from(self.get_error_msg(err_code)))
+                err_code => return Err(self.call_error(err_code, "dcgmGetDeviceTopology").arg("gpu", gpuId))
             };
-            let device = self.getDeviceAttributes(gpuId).unwrap();
-            let mut links = Vec::<P2PLink>::with_capacity(topology.numGpus as usize);
-            for i in 0..topology.numGpus{
+            let count = reported_count(topology.numGpus, topology.gpuPaths.len(), "GPU paths");
+            let mut links = Vec::<P2PLink>::with_capacity(count);
+            for path in &topology.gpuPaths[..count]{
+                let gpu = path.gpuId;
                 let link = P2PLink{
-                    gpu : topology.gpuPaths[i as usize].gpuId,
-                    bus_id: String::from("test"),//String::from_utf8(Vec::<u8>::from_iter((device.identifiers.pciBusId).iter().take_while(|&&c| c != 0).map(|&c| c as u8))).unwrap(),
-                    link: topology.gpuPaths[i as usize].path
+                    gpu,
+                    bus_id: self.device_bus_id(gpu)?,
+                    link: P2PLinkType::from(path.path)
                 };
                 links.push(link);
             }
@@ -417,16 +839,47 @@ pub fn dereference_field_value_v2(fv: &dcgmFieldValue_v2) -> Result<String, DCGM
     return Ok("a".to_string());
 }
 
+#[deprecated(note = "use the Display impl of EntityGroup")]
 pub fn field_entity_group_to_string(g: dcgm_field_entity_group_t) -> String{
-    match g{
-        dcgm_field_entity_group_t_DCGM_FE_GPU => "GPU".to_string(),
-        dcgm_field_entity_group_t_DCGM_FE_SWITCH => "SWITCH".to_string(),
-        dcgm_field_entity_group_t_DCGM_FE_CONNECTX => "NIC".to_string(),
-        dcgm_field_entity_group_t_DCGM_FE_VGPU => "VGPU".to_string(),
-        _ => "N/A".to_string()
+    match EntityGroup::try_from(g){
+        Ok(group) => group.to_string(),
+        Err(_) => "N/A".to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum NvLinkState {
+    NotSupported,
+    Disabled,
+    Down,
+    Up,
+}
+
+impl From<dcgmNvLinkLinkState_t> for NvLinkState {
+    // Values newer than this crate knows about are reported as not supported
+    fn from(link: dcgmNvLinkLinkState_t) -> Self {
+        match link{
+            dcgmNvLinkLinkState_enum_DcgmNvLinkLinkStateDisabled => NvLinkState::Disabled,
+            dcgmNvLinkLinkState_enum_DcgmNvLinkLinkStateDown => NvLinkState::Down,
+            dcgmNvLinkLinkState_enum_DcgmNvLinkLinkStateUp => NvLinkState::Up,
+            _ => NvLinkState::NotSupported
+        }
     }
 }
 
+impl fmt::Display for NvLinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            NvLinkState::NotSupported => "NOT SUPPORTED",
+            NvLinkState::Disabled => "DISABLED",
+            NvLinkState::Down => "DOWN",
+            NvLinkState::Up => "UP",
+        };
+        f.write_str(s)
+    }
+}
+
+#[deprecated(note = "use the Display impl of NvLinkState")]
 pub fn nvlink_state_to_string(link: dcgmNvLinkLinkState_t)-> String{
     match link{
         dcgmNvLinkLinkState_enum_DcgmNvLinkLinkStateNotSupported => "NOT SUPPORTED".to_string(),
@@ -439,17 +892,100 @@ pub fn nvlink_state_to_string(link: dcgmNvLinkLinkState_t)-> String{
 
 pub struct NvLinkStatus{
     pub parent_id: u32,
-    pub parent_type: dcgm_field_entity_group_t,
-    pub state: dcgmNvLinkLinkState_t,
+    pub parent_type: EntityGroup,
+    pub state: NvLinkState,
     pub index: u32,
 }
 
+impl NvLinkStatus {
+    /// The link as a `DCGM_FE_LINK` entity, for adding to groups and watching per-link fields.
+    pub fn link_id(&self) -> LinkId {
+        LinkId { parent: self.parent_type, parent_id: self.parent_id as u16, index: self.index as u8 }
+    }
+}
+
 pub struct P2PLink{
     pub gpu: u32,
     pub bus_id: String,
-    pub link: dcgmGpuLevel_enum
+    pub link: P2PLinkType
+}
+
+/// Best connection between two GPUs, ordered from worst to best so links can be compared directly.
+/// NVLink wins over any PCIe path; among PCIe paths, fewer hops through the hierarchy is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum P2PLinkType {
+    Uninitialized,
+    System,
+    Cpu,
+    HostBridge,
+    MultipleSwitches,
+    SingleSwitch,
+    Board,
+    NvLink(u8),
+}
+
+impl From<dcgmGpuLevel_enum> for P2PLinkType {
+    fn from(link: dcgmGpuLevel_enum) -> Self {
+        let nvlink = link & 0xFFFFFF00;
+        if nvlink != 0 {
+            // DCGM_TOPOLOGY_NVLINKn is bit (7 + n)
+            return P2PLinkType::NvLink((31 - nvlink.leading_zeros() - 7) as u8);
+        }
+        match link & 0xFF{
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_BOARD => P2PLinkType::Board,
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_SINGLE => P2PLinkType::SingleSwitch,
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_MULTIPLE => P2PLinkType::MultipleSwitches,
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_HOSTBRIDGE => P2PLinkType::HostBridge,
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_CPU => P2PLinkType::Cpu,
+            dcgmGpuLevel_enum_DCGM_TOPOLOGY_SYSTEM => P2PLinkType::System,
+            _ => P2PLinkType::Uninitialized
+        }
+    }
+}
+
+impl P2PLinkType {
+    pub fn is_nvlink(&self) -> bool {
+        matches!(self, P2PLinkType::NvLink(_))
+    }
+
+    /// Numeric link quality consistent with the ordering of the type; NVLink links rank above 100.
+    pub fn rank(&self) -> u32 {
+        match self {
+            P2PLinkType::Uninitialized => 0,
+            P2PLinkType::System => 1,
+            P2PLinkType::Cpu => 2,
+            P2PLinkType::HostBridge => 3,
+            P2PLinkType::MultipleSwitches => 4,
+            P2PLinkType::SingleSwitch => 5,
+            P2PLinkType::Board => 6,
+            P2PLinkType::NvLink(n) => 100 + *n as u32,
+        }
+    }
+
+    pub fn nvlink_count(&self) -> u8 {
+        match self {
+            P2PLinkType::NvLink(n) => *n,
+            _ => 0,
+        }
+    }
 }
 
+impl fmt::Display for P2PLinkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P2PLinkType::Uninitialized => f.write_str("N/A"),
+            P2PLinkType::System => f.write_str("SYS"),
+            P2PLinkType::Cpu => f.write_str("NODE"),
+            P2PLinkType::HostBridge => f.write_str("PHB"),
+            P2PLinkType::MultipleSwitches => f.write_str("PXB"),
+            P2PLinkType::SingleSwitch => f.write_str("PIX"),
+            P2PLinkType::Board => f.write_str("PSB"),
+            P2PLinkType::NvLink(n) => write!(f, "NV{n}"),
+        }
+    }
+}
+
+#[deprecated(note = "use P2PLinkType")]
 pub fn p2p_pcie_connectivity_to_string(mut link: dcgmGpuLevel_enum) -> String{
     link &= 0xFF;
     match link{
@@ -464,6 +1000,7 @@ pub fn p2p_pcie_connectivity_to_string(mut link: dcgmGpuLevel_enum) -> String{
     }
 }
 
+#[deprecated(note = "use P2PLinkType")]
 pub fn p2p_nvlink_connectivity_to_string(mut link: dcgmGpuLevel_enum) -> String{
     link &= 0xFFFFFF00;
     match link{
@@ -488,19 +1025,3 @@ pub fn p2p_nvlink_connectivity_to_string(mut link: dcgmGpuLevel_enum) -> String{
         _ => "ERR".to_string()
     }
 }
-
-fn make_version1(struct_type: u32) -> u32 {
-    struct_type | (1 << 24)
-}
-
-fn make_version2(struct_type: u32) -> u32 {
-    struct_type | (2 << 24)
-}
-
-fn make_version3(struct_type: u32) -> u32 {
-    struct_type | (3 << 24)
-}
-
-fn make_version4(struct_type: u32) -> u32 {
-	struct_type | 4<<24
-}
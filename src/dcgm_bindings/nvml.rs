@@ -0,0 +1,200 @@
+use super::bindings::*;
+use super::daemon::{DaemonConfig, FieldRef, GroupConfig};
+use super::entity::EntityGroup;
+use super::exporter::GpuIdentity;
+use super::import::metric_field_id;
+use super::samples::{FieldValue, Sample, Tags};
+use super::timing::CollectionTiming;
+use super::DCGMError;
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, Nvml};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Fields the NVML backend can read, with their DCGM tags. Values are converted to the units DCGM
+/// reports them in, so consumers cannot tell the backends apart.
+pub const NVML_FIELDS: [(u16, &str); 11] = [
+    (DCGM_FI_DEV_GPU_TEMP as u16, "gpu_temp"),
+    (DCGM_FI_DEV_POWER_USAGE as u16, "power_usage"),
+    (DCGM_FI_DEV_GPU_UTIL as u16, "gpu_utilization"),
+    (DCGM_FI_DEV_MEM_COPY_UTIL as u16, "mem_copy_utilization"),
+    (DCGM_FI_DEV_FB_TOTAL as u16, "fb_total"),
+    (DCGM_FI_DEV_FB_FREE as u16, "fb_free"),
+    (DCGM_FI_DEV_FB_USED as u16, "fb_used"),
+    (DCGM_FI_DEV_SM_CLOCK as u16, "sm_clock"),
+    (DCGM_FI_DEV_MEM_CLOCK as u16, "memory_clock"),
+    (DCGM_FI_DEV_FAN_SPEED as u16, "fan_speed"),
+    (DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION as u16, "total_energy_consumption"),
+];
+
+const MIB: u64 = 1024 * 1024;
+
+fn nvml_error(e: NvmlError) -> DCGMError {
+    match e {
+        NvmlError::NotSupported => DCGMError::not_supported("Not supported by NVML"),
+        NvmlError::NoPermission => DCGMError::permission_denied("NVML: no permission"),
+        NvmlError::Timeout => DCGMError::timeout("NVML call timed out"),
+        e => DCGMError::from(format!("NVML: {e}")),
+    }
+}
+
+pub fn nvml_field_id_by_tag(tag: &str) -> Option<u16> {
+    NVML_FIELDS.iter().find(|(_, t)| t.eq_ignore_ascii_case(tag)).map(|&(id, _)| id)
+}
+
+pub fn nvml_supports(field_id: u16) -> bool {
+    NVML_FIELDS.iter().any(|&(id, _)| id == field_id)
+}
+
+/// Reads a handful of basic per-GPU metrics straight from NVML, for hosts without libdcgm or a running
+/// hostengine. GPU ids are NVML device indices, which match DCGM's GPU ids on the same host.
+pub struct NvmlBackend {
+    nvml: Nvml,
+}
+
+impl NvmlBackend {
+    pub fn new() -> Result<Self, DCGMError> {
+        Ok(Self { nvml: Nvml::init().map_err(nvml_error)? })
+    }
+
+    pub fn gpus(&self) -> Result<Vec<u32>, DCGMError> {
+        Ok((0..self.nvml.device_count().map_err(nvml_error)?).collect())
+    }
+
+    pub fn identity(&self, gpu_id: u32) -> Result<GpuIdentity, DCGMError> {
+        let device = self.nvml.device_by_index(gpu_id).map_err(nvml_error)?;
+        Ok(GpuIdentity {
+            gpu_id,
+            uuid: device.uuid().map_err(nvml_error)?,
+            pci_bus_id: device.pci_info().map_err(nvml_error)?.bus_id,
+            device_name: device.name().map_err(nvml_error)?,
+            minor_number: device.minor_number().ok(),
+        })
+    }
+
+    /// Current values of `fields` on every GPU in `gpus`. Fields NVML does not know and values a GPU
+    /// does not support are skipped, like DCGM entries with a non-OK status.
+    pub fn latest_values(&self, gpus: &[u32], fields: &[u16]) -> Result<Vec<Sample>, DCGMError> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64;
+        let mut samples = Vec::with_capacity(gpus.len() * fields.len());
+        for &gpu_id in gpus {
+            let device = self.nvml.device_by_index(gpu_id).map_err(nvml_error)?;
+            for &field_id in fields {
+                if let Some(value) = read_field(&device, field_id) {
+                    samples.push(Sample { entity_group: EntityGroup::Gpu, entity_id: gpu_id, field_id, timestamp, value, tags: Tags::default() });
+                }
+            }
+        }
+        Ok(samples)
+    }
+}
+
+fn read_field(device: &Device, field_id: u16) -> Option<FieldValue> {
+    let int = |v: u64| FieldValue::Int64(v as i64);
+    let value = match field_id as u32 {
+        DCGM_FI_DEV_GPU_TEMP => int(device.temperature(TemperatureSensor::Gpu).ok()?.into()),
+        DCGM_FI_DEV_POWER_USAGE => FieldValue::Double(device.power_usage().ok()? as f64 / 1000.0),
+        DCGM_FI_DEV_GPU_UTIL => int(device.utilization_rates().ok()?.gpu.into()),
+        DCGM_FI_DEV_MEM_COPY_UTIL => int(device.utilization_rates().ok()?.memory.into()),
+        DCGM_FI_DEV_FB_TOTAL => int(device.memory_info().ok()?.total / MIB),
+        DCGM_FI_DEV_FB_FREE => int(device.memory_info().ok()?.free / MIB),
+        DCGM_FI_DEV_FB_USED => int(device.memory_info().ok()?.used / MIB),
+        DCGM_FI_DEV_SM_CLOCK => int(device.clock_info(Clock::SM).ok()?.into()),
+        DCGM_FI_DEV_MEM_CLOCK => int(device.clock_info(Clock::Memory).ok()?.into()),
+        DCGM_FI_DEV_FAN_SPEED => int(device.fan_speed(0).ok()?.into()),
+        DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION => int(device.total_energy_consumption().ok()?),
+        _ => return None,
+    };
+    Some(value)
+}
+
+impl GroupConfig {
+    /// Field ids of this group that the NVML backend can read. Fields it cannot are logged and left out;
+    /// a group left without fields is an error.
+    pub fn resolve_nvml_fields(&self) -> Result<Vec<u16>, DCGMError> {
+        let mut ids = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let id = match field {
+                FieldRef::Id(id) => Some(*id).filter(|id| nvml_supports(*id)),
+                // DCGM_FI_* names come from a table of their own, libdcgm may well be missing here
+                FieldRef::Name(name) => nvml_field_id_by_tag(name)
+                    .or_else(|| metric_field_id(&name.to_ascii_uppercase()).filter(|id| nvml_supports(*id))),
+            };
+            match id {
+                Some(id) if !ids.contains(&id) => ids.push(id),
+                Some(_) => (),
+                None => tracing::warn!("group '{}': field {field} is not available from NVML, skipping it", self.name),
+            }
+        }
+        if ids.is_empty() {
+            return Err(DCGMError::from(format!("group '{}': none of its fields are available from NVML", self.name)));
+        }
+        Ok(ids)
+    }
+}
+
+struct NvmlGroup {
+    gpus: Vec<u32>,
+    fields: Vec<u16>,
+    interval: Duration,
+    next_due: Instant,
+    grid: Instant,
+    tags: Tags,
+}
+
+/// The `daemon::Collector` counterpart for the NVML backend: reads each group of a `DaemonConfig`
+/// when due. NVML has no watches, so values are read at collection time.
+pub struct NvmlCollector {
+    groups: Vec<NvmlGroup>,
+    timing: CollectionTiming,
+}
+
+impl NvmlCollector {
+    pub fn start(nvml: &NvmlBackend, config: &DaemonConfig) -> Result<Self, DCGMError> {
+        let all = nvml.gpus()?;
+        let timing = config.timing();
+        let groups = config.groups.iter().map(|group| {
+            let interval = group.interval.unwrap_or(config.interval);
+            let first = Instant::now() + timing.first_offset(interval);
+            Ok(NvmlGroup {
+                gpus: group.gpus.clone().unwrap_or_else(|| all.clone()),
+                fields: group.resolve_nvml_fields()?,
+                interval,
+                next_due: first,
+                grid: first,
+                tags: Tags::new(group.tags.clone()),
+            })
+        }).collect::<Result<Vec<_>, DCGMError>>()?;
+        Ok(Self { groups, timing })
+    }
+
+    /// All field ids read by any group.
+    pub fn fields(&self) -> Vec<u16> {
+        let mut fields: Vec<u16> = self.groups.iter().flat_map(|g| g.fields.iter().copied()).collect();
+        fields.sort_unstable();
+        fields.dedup();
+        fields
+    }
+
+    /// When the next group is due; None without groups.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.groups.iter().map(|g| g.next_due).min()
+    }
+
+    /// Values of every group due at `now`.
+    pub fn collect_due(&mut self, nvml: &NvmlBackend, now: Instant) -> Result<Vec<Sample>, DCGMError> {
+        let mut samples = Vec::new();
+        for group in self.groups.iter_mut().filter(|g| g.next_due <= now) {
+            let start = samples.len();
+            samples.extend(nvml.latest_values(&group.gpus, &group.fields)?);
+            for sample in &mut samples[start..] {
+                sample.tags = group.tags.clone();
+            }
+            while group.grid <= now {
+                group.grid += group.interval;
+            }
+            group.next_due = group.grid + self.timing.run_jitter(group.interval);
+        }
+        Ok(samples)
+    }
+}
@@ -0,0 +1,216 @@
+use super::bindings::*;
+use super::callbacks::catch_callback;
+use super::{DCGMError, DCGMErrorKind, DcgmLibSafe};
+use bitflags::bitflags;
+use serde::Serialize;
+
+bitflags! {
+    /// `dcgmPolicyCondition_t` bits.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct PolicyConditions: u32 {
+        const DBE = dcgmPolicyCondition_enum_DCGM_POLICY_COND_DBE;
+        const PCI = dcgmPolicyCondition_enum_DCGM_POLICY_COND_PCI;
+        const MAX_PAGES_RETIRED = dcgmPolicyCondition_enum_DCGM_POLICY_COND_MAX_PAGES_RETIRED;
+        const THERMAL = dcgmPolicyCondition_enum_DCGM_POLICY_COND_THERMAL;
+        const POWER = dcgmPolicyCondition_enum_DCGM_POLICY_COND_POWER;
+        const NVLINK = dcgmPolicyCondition_enum_DCGM_POLICY_COND_NVLINK;
+        const XID = dcgmPolicyCondition_enum_DCGM_POLICY_COND_XID;
+    }
+}
+
+/// Where a double bit ECC error was reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum DbeLocation {
+    L1,
+    L2,
+    Device,
+    Register,
+    Texture,
+    Unknown(u32),
+}
+
+impl From<dcgmPolicyConditionDbe_t__bindgen_ty_1> for DbeLocation {
+    fn from(location: dcgmPolicyConditionDbe_t__bindgen_ty_1) -> Self {
+        match location {
+            dcgmPolicyConditionDbe_t_L1 => DbeLocation::L1,
+            dcgmPolicyConditionDbe_t_L2 => DbeLocation::L2,
+            dcgmPolicyConditionDbe_t_DEVICE => DbeLocation::Device,
+            dcgmPolicyConditionDbe_t_REGISTER => DbeLocation::Register,
+            dcgmPolicyConditionDbe_t_TEXTURE => DbeLocation::Texture,
+            other => DbeLocation::Unknown(other),
+        }
+    }
+}
+
+/// Decoded `dcgmPolicyCallbackResponse_t::val`, one variant per condition. Timestamps are usec since the epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ViolationPayload {
+    Dbe { timestamp: i64, location: DbeLocation, count: u32 },
+    PciReplay { timestamp: i64, count: u32 },
+    RetiredPages { timestamp: i64, sbe_pages: u32, dbe_pages: u32 },
+    Thermal { timestamp: i64, temperature: u32 },
+    Power { timestamp: i64, watts: u32 },
+    NvLink { timestamp: i64, field_id: u16, count: u32 },
+    Xid { timestamp: i64, xid: u32 },
+    /// A condition this crate does not know how to decode.
+    Unknown { condition: u32 },
+}
+
+impl ViolationPayload {
+    pub fn from_raw(response: &dcgmPolicyCallbackResponse_t) -> Self {
+        // Safety: DCGM fills the union member matching `condition`.
+        unsafe {
+            let val = &response.val;
+            match response.condition {
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_DBE => ViolationPayload::Dbe {
+                    timestamp: val.dbe.timestamp,
+                    location: DbeLocation::from(val.dbe.location),
+                    count: val.dbe.numerrors,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_PCI => ViolationPayload::PciReplay {
+                    timestamp: val.pci.timestamp,
+                    count: val.pci.counter,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_MAX_PAGES_RETIRED => ViolationPayload::RetiredPages {
+                    timestamp: val.mpr.timestamp,
+                    sbe_pages: val.mpr.sbepages,
+                    dbe_pages: val.mpr.dbepages,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_THERMAL => ViolationPayload::Thermal {
+                    timestamp: val.thermal.timestamp,
+                    temperature: val.thermal.thermalViolation,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_POWER => ViolationPayload::Power {
+                    timestamp: val.power.timestamp,
+                    watts: val.power.powerViolation,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_NVLINK => ViolationPayload::NvLink {
+                    timestamp: val.nvlink.timestamp,
+                    field_id: val.nvlink.fieldId,
+                    count: val.nvlink.counter,
+                },
+                dcgmPolicyCondition_enum_DCGM_POLICY_COND_XID => ViolationPayload::Xid {
+                    timestamp: val.xid.timestamp,
+                    xid: val.xid.errnum,
+                },
+                condition => ViolationPayload::Unknown { condition },
+            }
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<i64> {
+        match *self {
+            ViolationPayload::Dbe { timestamp, .. }
+            | ViolationPayload::PciReplay { timestamp, .. }
+            | ViolationPayload::RetiredPages { timestamp, .. }
+            | ViolationPayload::Thermal { timestamp, .. }
+            | ViolationPayload::Power { timestamp, .. }
+            | ViolationPayload::NvLink { timestamp, .. }
+            | ViolationPayload::Xid { timestamp, .. } => Some(timestamp),
+            ViolationPayload::Unknown { .. } => None,
+        }
+    }
+}
+
+/// A policy violation as handed to the callback by DCGM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct PolicyViolation {
+    pub gpu_id: u32,
+    #[serde(serialize_with = "serialize_conditions")]
+    pub condition: PolicyConditions,
+    pub payload: ViolationPayload,
+}
+
+fn serialize_conditions<S: serde::Serializer>(c: &PolicyConditions, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u32(c.bits())
+}
+
+/// What a policy callback receives.
+#[derive(Clone, Debug)]
+pub enum PolicyEvent {
+    Violation(PolicyViolation),
+    /// The callback panicked while handling an earlier event; the panic was caught at the FFI boundary.
+    CallbackPanicked(String),
+}
+
+type PolicyCallback = Box<dyn Fn(PolicyEvent) + Send + Sync>;
+
+/// An active `dcgmPolicyRegister_v2` registration. Unregisters on drop.
+pub struct PolicyRegistration {
+    dcgm: DcgmLibSafe,
+    group: dcgmGpuGrp_t,
+    conditions: PolicyConditions,
+    registered: bool,
+    // Passed to DCGM as userData; must stay put until unregistered.
+    callback: Box<PolicyCallback>,
+}
+
+unsafe extern "C" fn policy_trampoline(response: *mut dcgmPolicyCallbackResponse_t, userData: u64) -> std::os::raw::c_int {
+    if response.is_null() || userData == 0 {
+        return 0;
+    }
+    let callback = &*(userData as *const PolicyCallback);
+    let response = *response;
+    let event = PolicyEvent::Violation(PolicyViolation {
+        gpu_id: response.gpuId,
+        condition: PolicyConditions::from_bits_retain(response.condition),
+        payload: ViolationPayload::from_raw(&response),
+    });
+    if let Err(msg) = catch_callback(|| callback(event)) {
+        tracing::error!("Policy callback panicked: {msg}");
+        if let Err(msg) = catch_callback(|| callback(PolicyEvent::CallbackPanicked(msg))) {
+            tracing::error!("Policy callback panicked again while handling its own panic: {msg}");
+        }
+    }
+    0
+}
+
+impl PolicyRegistration {
+    pub fn conditions(&self) -> PolicyConditions {
+        self.conditions
+    }
+
+    pub fn unregister(mut self) -> Result<(), DCGMError> {
+        self.registered = false;
+        self.unregister_raw()
+    }
+
+    fn unregister_raw(&mut self) -> Result<(), DCGMError> {
+        match unsafe{self.dcgm.lib()?.dcgmPolicyUnregister(self.dcgm.handle(), self.group, self.conditions.bits())}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.dcgm.call_error(err_code, "dcgmPolicyUnregister").arg("group", self.group)
+                                    .arg("conditions", format!("{:?}", self.conditions)))
+        }
+    }
+}
+
+impl Drop for PolicyRegistration {
+    fn drop(&mut self) {
+        if self.registered {
+            match self.unregister_raw() {
+                // DCGM dropped the callback along with the connection
+                Err(e) if e.kind == DCGMErrorKind::Disconnected => (),
+                Err(e) => tracing::warn!("Failed to unregister policy callback: {e}"),
+                Ok(()) => (),
+            }
+        }
+    }
+}
+
+impl DcgmLibSafe {
+    /// Registers `callback` for violations of `conditions` on `group`. The callback runs on a DCGM thread;
+    /// panics are caught and reported back to it as `PolicyEvent::CallbackPanicked`.
+    pub fn policy_register<F>(&mut self, group: dcgmGpuGrp_t, conditions: PolicyConditions, callback: F)
+        -> Result<PolicyRegistration, DCGMError>
+    where
+        F: Fn(PolicyEvent) + Send + Sync + 'static,
+    {
+        let callback: Box<PolicyCallback> = Box::new(Box::new(callback));
+        let userData = &*callback as *const PolicyCallback as u64;
+        match unsafe{self.lib()?.dcgmPolicyRegister_v2(self.handle(), group, conditions.bits(), Some(policy_trampoline), userData)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(PolicyRegistration { dcgm: self.share(), group, conditions, registered: true, callback }),
+            err_code => Err(self.call_error(err_code, "dcgmPolicyRegister_v2").arg("group", group)
+                                    .arg("conditions", format!("{conditions:?}")))
+        }
+    }
+}
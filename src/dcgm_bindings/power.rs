@@ -0,0 +1,271 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::latest::LatestValuesQuery;
+use super::shutdown::Task;
+use super::status::status_errors_to_error;
+use super::watch::{unique_name, WatchHandle, WatchOptions};
+use super::{DCGMError, DcgmLibSafe};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Settings of a power budget controller. All power figures are watts.
+#[derive(Clone, Debug)]
+pub struct PowerBudgetOptions {
+    /// Total draw of the group's GPUs the controller keeps the node under.
+    pub budget: f64,
+    /// Limits are only raised again once the draw is this far under the budget, so they do not flap
+    /// around it. Adjustments aim at the middle of the band.
+    pub hysteresis: f64,
+    /// No GPU is limited below this; GPUs that support no lower limit keep their own minimum.
+    pub floor: Option<u32>,
+    /// How often the draw is checked and limits adjusted.
+    pub interval: Duration,
+    /// Put back the power limits the GPUs had at start when the controller stops.
+    pub restore_on_stop: bool,
+}
+
+impl PowerBudgetOptions {
+    pub fn new(budget: f64) -> Self {
+        Self { budget, hysteresis: budget * 0.05, floor: None, interval: Duration::from_secs(5), restore_on_stop: true }
+    }
+}
+
+/// What the controller did in one round.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PowerBudgetEvent {
+    /// The power limit of one GPU was changed.
+    Adjusted { gpu_id: u32, from: u32, to: u32, total_draw: f64 },
+    /// Every GPU is at its floor and the draw is still over the budget.
+    BudgetUnreachable { total_draw: f64, budget: f64 },
+}
+
+impl fmt::Display for PowerBudgetEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerBudgetEvent::Adjusted { gpu_id, from, to, total_draw } =>
+                write!(f, "gpu {gpu_id} power limit {from} W -> {to} W (group drawing {total_draw:.0} W)"),
+            PowerBudgetEvent::BudgetUnreachable { total_draw, budget } =>
+                write!(f, "group drawing {total_draw:.0} W with every GPU at its floor, budget {budget:.0} W"),
+        }
+    }
+}
+
+/// One GPU under control, with a group of its own so its limit can be set alone.
+#[derive(Clone, Debug)]
+struct ControlledGpu {
+    gpu_id: u32,
+    group: dcgmGpuGrp_t,
+    floor: u32,
+    /// The limit at start; never raised above.
+    ceiling: u32,
+    limit: u32,
+}
+
+/// A running power budget controller. Events arrive on `events`; dropping the handle stops it.
+pub struct PowerBudgetController {
+    pub events: Receiver<PowerBudgetEvent>,
+    task: Arc<Task>,
+}
+
+impl PowerBudgetController {
+    /// Stops the controller and waits for it to restore the limits.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.task.join();
+    }
+}
+
+impl Drop for PowerBudgetController {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl DcgmLibSafe {
+    /// Keeps the total power draw of the GPUs of `group` under `options.budget` by lowering their power
+    /// limits when the group draws more, and raising them back towards the limits they had at start once
+    /// it draws less than the budget minus the hysteresis. Cuts and raises are shared out in proportion
+    /// to how far each GPU is from its floor or ceiling. Runs on a thread sharing this connection.
+    pub fn start_power_budget(&mut self, group: dcgmGpuGrp_t, options: &PowerBudgetOptions) -> Result<PowerBudgetController, DCGMError>{
+        if options.interval.is_zero() {
+            return Err(DCGMError::from("Power budget interval must not be zero"));
+        }
+        if options.budget <= 0.0 || options.hysteresis < 0.0 || options.hysteresis >= options.budget {
+            return Err(DCGMError::from(format!("Invalid power budget {} W with hysteresis {} W", options.budget, options.hysteresis)));
+        }
+        let gpu_ids: Vec<u32> = self.getGroupEntities(group)?.into_iter()
+            .filter(|e| e.group == EntityGroup::Gpu)
+            .map(|e| e.id)
+            .collect();
+        if gpu_ids.is_empty() {
+            return Err(DCGMError::from(format!("Group {group} has no GPUs")));
+        }
+
+        let mut gpus = Vec::with_capacity(gpu_ids.len());
+        for gpu_id in gpu_ids {
+            match self.controlled_gpu(gpu_id, options) {
+                Ok(gpu) => gpus.push(gpu),
+                Err(e) => {
+                    self.release_gpus(&gpus, false);
+                    return Err(e.context(format!("gpu {gpu_id}")));
+                }
+            }
+        }
+        let floors: u32 = gpus.iter().map(|g| g.floor).sum();
+        if floors as f64 > options.budget {
+            tracing::warn!("Power floors of group {group} add up to {floors} W, over the {} W budget", options.budget);
+        }
+        let watch = match self.watch_power(group, options.interval) {
+            Ok(watch) => watch,
+            Err(e) => {
+                self.release_gpus(&gpus, false);
+                return Err(e);
+            }
+        };
+
+        let task = Task::new("power budget");
+        let (tx, events) = mpsc::channel();
+        let (client, controller_stop, controller_options) = (self.share(), task.stop.clone(), options.clone());
+        let thread = std::thread::Builder::new()
+            .name("dcgm-power-budget".into())
+            .spawn(move || controller(client, gpus, watch, &controller_options, &controller_stop, tx))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn power budget controller: {e}")))?;
+        task.add(thread);
+        self.register_task(&task);
+        Ok(PowerBudgetController { events, task })
+    }
+
+    fn controlled_gpu(&mut self, gpu_id: u32, options: &PowerBudgetOptions) -> Result<ControlledGpu, DCGMError>{
+        let limits = self.device_attributes(gpu_id)?.powerLimits;
+        let floor = options.floor.unwrap_or(0).max(limits.minPowerLimit).min(limits.maxPowerLimit);
+        let group = self.createGroup(&unique_name(&format!("power-budget-gpu{gpu_id}")))?;
+        if let Err(e) = self.addEntityToGroup(group, EntityGroup::Gpu, gpu_id) {
+            let _ = self.destroyGroup(group);
+            return Err(e);
+        }
+        let ceiling = limits.curPowerLimit.max(floor);
+        Ok(ControlledGpu { gpu_id, group, floor, ceiling, limit: limits.curPowerLimit })
+    }
+
+    fn watch_power(&mut self, group: dcgmGpuGrp_t, interval: Duration) -> Result<WatchHandle, DCGMError>{
+        let mut fields = vec![DCGM_FI_DEV_POWER_USAGE as u16];
+        let field_group = self.fieldGroupCreate(&unique_name("power-budget"), &mut fields)?;
+        let options = WatchOptions { update_interval: interval, ..WatchOptions::default() };
+        if let Err(e) = self.watchFields(field_group, group, interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = self.fieldGroupDestroy(field_group);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group, fields, options })
+    }
+
+    fn set_gpu_power_limit(&mut self, gpu: &ControlledGpu, watts: u32) -> Result<(), DCGMError>{
+        let errors = self.set_power_limit(gpu.group, watts)?;
+        if !errors.is_empty() {
+            return Err(status_errors_to_error(&format!("failed to set the power limit of gpu {}", gpu.gpu_id), &errors));
+        }
+        Ok(())
+    }
+
+    /// Destroys the per-GPU groups, first putting back the limits they had at start when `restore` is set.
+    fn release_gpus(&mut self, gpus: &[ControlledGpu], restore: bool) {
+        for gpu in gpus {
+            if restore && gpu.limit != gpu.ceiling {
+                if let Err(e) = self.set_gpu_power_limit(gpu, gpu.ceiling) {
+                    tracing::warn!("Failed to restore the power limit of gpu {}: {e}", gpu.gpu_id);
+                }
+            }
+            if let Err(e) = self.destroyGroup(gpu.group) {
+                tracing::warn!("Failed to destroy the power budget group of gpu {}: {e}", gpu.gpu_id);
+            }
+        }
+    }
+}
+
+fn controller(mut dcgm: DcgmLibSafe, mut gpus: Vec<ControlledGpu>, watch: WatchHandle, options: &PowerBudgetOptions,
+              stop: &AtomicBool, tx: Sender<PowerBudgetEvent>) {
+    let entities: Vec<Entity> = gpus.iter().map(|g| Entity::gpu(g.gpu_id)).collect();
+    let mut readings = LatestValuesQuery::new(&entities, &watch.fields);
+    let tick = Duration::from_millis(50);
+    let mut next = Instant::now() + options.interval;
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if next > now {
+            // Sleep in short steps so stop requests are honoured promptly.
+            std::thread::sleep((next - now).min(tick));
+            continue;
+        }
+        next = now + options.interval;
+        let draws = match readings.collect(&mut dcgm) {
+            Ok(samples) => gpus.iter()
+                .map(|g| samples.iter().find(|s| s.entity_id == g.gpu_id).and_then(|s| s.value.as_f64()))
+                .collect::<Option<Vec<f64>>>(),
+            Err(e) => {
+                tracing::warn!("Failed to read group power draw: {e}");
+                continue;
+            }
+        };
+        // limits are only moved on a complete picture of the group
+        let Some(draws) = draws else { continue };
+        for event in adjust(&mut dcgm, &mut gpus, &draws, options) {
+            // a dropped receiver only means nobody listens; the budget is still enforced
+            let _ = tx.send(event);
+        }
+    }
+    if let Err(e) = dcgm.unwatch(watch) {
+        tracing::warn!("Failed to remove the power budget watch: {e}");
+    }
+    dcgm.release_gpus(&gpus, options.restore_on_stop);
+}
+
+/// One control round: the new limit of every GPU from its draw, then the limits that changed are applied.
+fn adjust(dcgm: &mut DcgmLibSafe, gpus: &mut [ControlledGpu], draws: &[f64], options: &PowerBudgetOptions) -> Vec<PowerBudgetEvent> {
+    let total_draw: f64 = draws.iter().sum();
+    let target = options.budget - options.hysteresis / 2.0;
+    let targets: Vec<u32> = if total_draw > options.budget {
+        // a limit far above the draw would absorb the cut without lowering anything, so start from the draw
+        let starts: Vec<f64> = gpus.iter().zip(draws).map(|(g, &d)| (g.limit as f64).min(d).max(g.floor as f64)).collect();
+        let room: Vec<f64> = gpus.iter().zip(&starts).map(|(g, &s)| s - g.floor as f64).collect();
+        let total_room: f64 = room.iter().sum();
+        if total_room <= 0.0 {
+            return vec![PowerBudgetEvent::BudgetUnreachable { total_draw, budget: options.budget }];
+        }
+        let cut = (total_draw - target).min(total_room);
+        gpus.iter().zip(starts.iter().zip(&room))
+            .map(|(g, (&start, &room))| ((start - cut * room / total_room).floor() as u32).max(g.floor))
+            .collect()
+    } else if total_draw < options.budget - options.hysteresis {
+        let room: Vec<f64> = gpus.iter().map(|g| g.ceiling.saturating_sub(g.limit) as f64).collect();
+        let total_room: f64 = room.iter().sum();
+        if total_room <= 0.0 {
+            return Vec::new();
+        }
+        let raise = (target - total_draw).min(total_room);
+        gpus.iter().zip(&room)
+            .map(|(g, &room)| (g.limit + (raise * room / total_room).floor() as u32).min(g.ceiling))
+            .collect()
+    } else {
+        return Vec::new();
+    };
+
+    let mut events = Vec::new();
+    for (gpu, to) in gpus.iter_mut().zip(targets) {
+        if to == gpu.limit {
+            continue;
+        }
+        match dcgm.set_gpu_power_limit(gpu, to) {
+            Ok(()) => {
+                events.push(PowerBudgetEvent::Adjusted { gpu_id: gpu.gpu_id, from: gpu.limit, to, total_draw });
+                gpu.limit = to;
+            }
+            Err(e) => tracing::warn!("Failed to set the power limit of gpu {} to {to} W: {e}", gpu.gpu_id),
+        }
+    }
+    events
+}
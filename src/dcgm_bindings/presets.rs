@@ -0,0 +1,122 @@
+use super::bindings::*;
+use super::samples::{FieldId, Sample};
+use super::watch::{WatchHandle, WatchOptions};
+use super::{DCGMError, DcgmLibSafe};
+use std::time::Duration;
+
+/// A class of fields with the watch settings that suit it. `watch_presets` gives every preset its own
+/// field group, so profiling fields can be sampled far more often than inventory fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchPreset {
+    pub name: String,
+    pub fields: Vec<FieldId>,
+    pub options: WatchOptions,
+}
+
+impl WatchPreset {
+    pub fn new(name: &str, fields: &[FieldId], options: WatchOptions) -> Self {
+        Self { name: name.to_string(), fields: fields.to_vec(), options }
+    }
+
+    /// Identity and firmware; changes only on reboots or driver updates.
+    pub fn inventory() -> Self {
+        let fields = [DCGM_FI_DEV_NAME, DCGM_FI_DEV_BRAND, DCGM_FI_DEV_SERIAL, DCGM_FI_DEV_UUID, DCGM_FI_DEV_PCI_BUSID,
+                      DCGM_FI_DEV_MINOR_NUMBER, DCGM_FI_DEV_VBIOS_VERSION, DCGM_FI_DRIVER_VERSION, DCGM_FI_DEV_POWER_MGMT_LIMIT];
+        Self::new("inventory", &fields.map(|f| f as FieldId), WatchOptions {
+            update_interval: Duration::from_secs(300),
+            max_keep_age: Duration::from_secs(3600),
+            max_keep_samples: 2,
+        })
+    }
+
+    /// Temperatures, power, clocks, utilization and memory use.
+    pub fn telemetry() -> Self {
+        let fields = [DCGM_FI_DEV_GPU_TEMP, DCGM_FI_DEV_MEMORY_TEMP, DCGM_FI_DEV_POWER_USAGE, DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION,
+                      DCGM_FI_DEV_SM_CLOCK, DCGM_FI_DEV_MEM_CLOCK, DCGM_FI_DEV_CLOCKS_EVENT_REASONS, DCGM_FI_DEV_GPU_UTIL,
+                      DCGM_FI_DEV_MEM_COPY_UTIL, DCGM_FI_DEV_ENC_UTIL, DCGM_FI_DEV_DEC_UTIL, DCGM_FI_DEV_FB_USED, DCGM_FI_DEV_FB_FREE];
+        Self::new("telemetry", &fields.map(|f| f as FieldId), WatchOptions::default())
+    }
+
+    /// Error counters and pending retirements. Counters only go up, so a slow cadence loses nothing.
+    pub fn errors() -> Self {
+        let fields = [DCGM_FI_DEV_XID_ERRORS, DCGM_FI_DEV_ECC_SBE_VOL_TOTAL, DCGM_FI_DEV_ECC_DBE_VOL_TOTAL,
+                      DCGM_FI_DEV_PCIE_REPLAY_COUNTER, DCGM_FI_DEV_RETIRED_PENDING, DCGM_FI_DEV_ROW_REMAP_PENDING];
+        Self::new("errors", &fields.map(|f| f as FieldId), WatchOptions {
+            update_interval: Duration::from_secs(10),
+            max_keep_age: Duration::from_secs(600),
+            max_keep_samples: 0,
+        })
+    }
+
+    /// DCP profiling metrics. Averaged by DCGM over the update interval, so they need a short one to
+    /// show bursts; only a few samples are kept.
+    pub fn profiling() -> Self {
+        let fields = [DCGM_FI_PROF_GR_ENGINE_ACTIVE, DCGM_FI_PROF_SM_ACTIVE, DCGM_FI_PROF_SM_OCCUPANCY,
+                      DCGM_FI_PROF_PIPE_TENSOR_ACTIVE, DCGM_FI_PROF_DRAM_ACTIVE, DCGM_FI_PROF_PCIE_TX_BYTES,
+                      DCGM_FI_PROF_PCIE_RX_BYTES, DCGM_FI_PROF_NVLINK_TX_BYTES, DCGM_FI_PROF_NVLINK_RX_BYTES];
+        Self::new("profiling", &fields.map(|f| f as FieldId), WatchOptions {
+            update_interval: Duration::from_millis(100),
+            max_keep_age: Duration::from_secs(30),
+            max_keep_samples: 600,
+        })
+    }
+
+    /// Every built-in preset.
+    pub fn all() -> Vec<Self> {
+        vec![Self::inventory(), Self::telemetry(), Self::errors(), Self::profiling()]
+    }
+
+    /// A built-in preset by name.
+    pub fn named(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|p| p.name == name)
+    }
+
+    /// The same fields with other watch settings.
+    pub fn with_options(mut self, options: WatchOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// A preset being watched, as returned by `watch_presets`.
+#[derive(Clone, Debug)]
+pub struct PresetWatch {
+    pub preset: String,
+    pub watch: WatchHandle,
+}
+
+impl DcgmLibSafe {
+    /// Watches every preset on all GPUs, each in its own field group with its own settings. Either all
+    /// presets are watched or, on error, none.
+    pub fn watch_presets(&mut self, presets: &[WatchPreset]) -> Result<Vec<PresetWatch>, DCGMError>{
+        let mut watches = Vec::with_capacity(presets.len());
+        for preset in presets {
+            match self.watch_all_gpus(&preset.fields, &preset.options) {
+                Ok(watch) => watches.push(PresetWatch { preset: preset.name.clone(), watch }),
+                Err(e) => {
+                    self.unwatch_presets(watches);
+                    return Err(e.context(format!("preset {}", preset.name)));
+                }
+            }
+        }
+        Ok(watches)
+    }
+
+    /// Latest values of every preset, in one list.
+    pub fn preset_values(&mut self, watches: &[PresetWatch]) -> Result<Vec<Sample>, DCGMError>{
+        let mut samples = Vec::new();
+        for watch in watches {
+            samples.extend(self.watch_values(&watch.watch)?);
+        }
+        Ok(samples)
+    }
+
+    /// Unwatches every preset; failures are logged, so the rest are still cleaned up.
+    pub fn unwatch_presets(&mut self, watches: Vec<PresetWatch>) {
+        for watch in watches {
+            if let Err(e) = self.unwatch(watch.watch) {
+                tracing::warn!("Failed to unwatch preset {}: {e}", watch.preset);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The standard `grpc.health.v1.Health` service, for Kubernetes gRPC liveness and readiness probes.
+/// Needs the `k8s` feature.
+///
+/// ```toml
+/// [health]
+/// listen = "0.0.0.0:9401"
+/// ```
+///
+/// Probes name the check in their `service` field:
+/// - `""` or `readiness`: connected to DCGM and a collection succeeded within the daemon's `stale_after`.
+/// - `liveness`: a collection succeeded (or the daemon started) within `stale_after`, so a collection
+///   loop that is stuck gets the pod restarted while a slow start does not.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    pub listen: SocketAddr,
+}
+
+/// Status of a health check, as in `grpc.health.v1.HealthCheckResponse.ServingStatus`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServingStatus {
+    Serving,
+    NotServing,
+}
+
+/// What the collection loop reports for the probes to look at. Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct ProbeState(Arc<Mutex<Probe>>);
+
+#[derive(Debug)]
+struct Probe {
+    started: Instant,
+    connected: bool,
+    last_collection: Option<Instant>,
+    stale_after: Duration,
+}
+
+impl ProbeState {
+    pub fn new(stale_after: Duration) -> Self {
+        Self(Arc::new(Mutex::new(Probe { started: Instant::now(), connected: false, last_collection: None, stale_after })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Probe> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.lock().connected = connected;
+    }
+
+    /// A collection succeeded just now.
+    pub fn collected(&self) {
+        self.lock().last_collection = Some(Instant::now());
+    }
+
+    /// Follows a config reload.
+    pub fn set_stale_after(&self, stale_after: Duration) {
+        self.lock().stale_after = stale_after;
+    }
+
+    /// Status of the check named `service`, `None` for a check that does not exist.
+    pub fn check(&self, service: &str) -> Option<ServingStatus> {
+        let probe = self.lock();
+        let fresh = |since: Option<Instant>| since.is_some_and(|t| t.elapsed() <= probe.stale_after);
+        let serving = match service {
+            "" | "readiness" => probe.connected && fresh(probe.last_collection),
+            "liveness" => fresh(probe.last_collection.or(Some(probe.started))),
+            _ => return None,
+        };
+        Some(if serving { ServingStatus::Serving } else { ServingStatus::NotServing })
+    }
+}
+
+#[cfg(feature = "k8s")]
+pub use server::GrpcHealthServer;
+
+#[cfg(feature = "k8s")]
+mod server {
+    use super::{ProbeState, ServingStatus};
+    use crate::dcgm_bindings::DCGMError;
+    use std::convert::Infallible;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+    use tonic::body::BoxBody;
+    use tonic::codegen::{http, Body, BoxFuture, StdError};
+    use tonic::server::{Grpc, NamedService};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::{Request, Response, Status};
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HealthCheckRequest {
+        #[prost(string, tag = "1")]
+        service: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HealthCheckResponse {
+        #[prost(enumeration = "WireStatus", tag = "1")]
+        status: i32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    enum WireStatus {
+        Unknown = 0,
+        Serving = 1,
+        NotServing = 2,
+        ServiceUnknown = 3,
+    }
+
+    /// `grpc.health.v1.Health` over `ProbeState`. Only `Check` is served; `Watch` is not used by probes
+    /// and answers `UNIMPLEMENTED`.
+    #[derive(Clone)]
+    struct HealthService(ProbeState);
+
+    impl NamedService for HealthService {
+        const NAME: &'static str = "grpc.health.v1.Health";
+    }
+
+    impl<B> tower::Service<http::Request<B>> for HealthService
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            if request.uri().path() != "/grpc.health.v1.Health/Check" {
+                return Box::pin(async { Ok(Status::unimplemented("").into_http()) });
+            }
+            let state = self.0.clone();
+            let check = tower::service_fn(move |request: Request<HealthCheckRequest>| {
+                let status = state.check(&request.get_ref().service);
+                async move {
+                    match status {
+                        Some(ServingStatus::Serving) => Ok(Response::new(HealthCheckResponse { status: WireStatus::Serving as i32 })),
+                        Some(ServingStatus::NotServing) => Ok(Response::new(HealthCheckResponse { status: WireStatus::NotServing as i32 })),
+                        None => Err(Status::not_found("unknown service")),
+                    }
+                }
+            });
+            Box::pin(async move { Ok(Grpc::new(tonic::codec::ProstCodec::default()).unary(check, request).await) })
+        }
+    }
+
+    /// A running gRPC health endpoint. Dropping the handle stops it.
+    pub struct GrpcHealthServer {
+        local_addr: SocketAddr,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl GrpcHealthServer {
+        pub fn start(listen: SocketAddr, state: ProbeState) -> Result<Self, DCGMError> {
+            let listener = TcpListener::bind(listen)
+                .and_then(|l| l.set_nonblocking(true).map(|_| l))
+                .map_err(|e| DCGMError::from(format!("Failed to listen on {listen}: {e}")))?;
+            let local_addr = listener.local_addr().map_err(|e| DCGMError::from(e.to_string()))?;
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| DCGMError::from(format!("Failed to start runtime: {e}")))?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let server_stop = stop.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("dcgm-grpc-health-{}", local_addr.port()))
+                .spawn(move || runtime.block_on(serve(listener, state, &server_stop)))
+                .map_err(|e| DCGMError::from(format!("Failed to spawn gRPC health server: {e}")))?;
+            tracing::info!("Serving gRPC health checks on {local_addr}");
+            Ok(Self { local_addr, stop, thread: Some(thread) })
+        }
+
+        /// Bound address, useful when listening on port 0.
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
+        pub fn stop(mut self) {
+            self.shutdown();
+        }
+
+        fn shutdown(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    impl Drop for GrpcHealthServer {
+        fn drop(&mut self) {
+            self.shutdown();
+        }
+    }
+
+    async fn serve(listener: TcpListener, state: ProbeState, stop: &AtomicBool) {
+        let incoming = match tokio::net::TcpListener::from_std(listener)
+            .map_err(|e| e.into())
+            .and_then(|l| TcpIncoming::from_listener(l, true, None)) {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                tracing::error!("gRPC health server failed: {e}");
+                return;
+            }
+        };
+        let stopped = async {
+            // checked in short steps like the other background threads
+            while !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        // Checks are answered right away, so there is nothing to drain: open connections are dropped
+        // with the runtime instead of holding up the stop until the prober closes them.
+        let server = tonic::transport::Server::builder()
+            .add_service(HealthService(state))
+            .serve_with_incoming(incoming);
+        tokio::select! {
+            result = server => if let Err(e) = result {
+                tracing::error!("gRPC health server failed: {e}");
+            },
+            _ = stopped => (),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::bindings::*;
+use super::container::{container_for_pid, ContainerInfo};
+use super::watch::WatchOptions;
+use super::init::Versioned;
+use super::{DCGMError, DcgmLibSafe};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StatSummary<T> {
+    pub min: T,
+    pub max: T,
+    pub average: T,
+}
+
+impl From<dcgmStatSummaryInt64_t> for StatSummary<i64> {
+    fn from(s: dcgmStatSummaryInt64_t) -> Self {
+        Self { min: s.minValue, max: s.maxValue, average: s.average }
+    }
+}
+
+impl From<dcgmStatSummaryInt32_t> for StatSummary<i32> {
+    fn from(s: dcgmStatSummaryInt32_t) -> Self {
+        Self { min: s.minValue, max: s.maxValue, average: s.average }
+    }
+}
+
+impl From<dcgmStatSummaryFp64_t> for StatSummary<f64> {
+    fn from(s: dcgmStatSummaryFp64_t) -> Self {
+        Self { min: s.minValue, max: s.maxValue, average: s.average }
+    }
+}
+
+/// Usage of one process on one GPU (or summed over all GPUs for the summary). Times are usec since 1970.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GpuProcessStats {
+    pub gpu_id: u32,
+    /// Millijoules.
+    pub energy_consumed: i64,
+    pub pcie_rx_bandwidth: StatSummary<i64>,
+    pub pcie_tx_bandwidth: StatSummary<i64>,
+    pub pcie_replays: i64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub sm_utilization: StatSummary<i32>,
+    pub memory_utilization: StatSummary<i32>,
+    pub ecc_single_bit: u32,
+    pub ecc_double_bit: u32,
+    pub xid_critical_errors: u32,
+    pub other_compute_pids: Vec<u32>,
+}
+
+impl From<&dcgmPidSingleInfo_t> for GpuProcessStats {
+    fn from(info: &dcgmPidSingleInfo_t) -> Self {
+        let others = info.numOtherComputePids.clamp(0, info.otherComputePids.len() as i32) as usize;
+        Self {
+            gpu_id: info.gpuId,
+            energy_consumed: info.energyConsumed,
+            pcie_rx_bandwidth: info.pcieRxBandwidth.into(),
+            pcie_tx_bandwidth: info.pcieTxBandwidth.into(),
+            pcie_replays: info.pcieReplays,
+            start_time: info.startTime,
+            end_time: info.endTime,
+            sm_utilization: info.smUtilization.into(),
+            memory_utilization: info.memoryUtilization.into(),
+            ecc_single_bit: info.eccSingleBit,
+            ecc_double_bit: info.eccDoubleBit,
+            xid_critical_errors: info.numXidCriticalErrors.max(0) as u32,
+            other_compute_pids: info.otherComputePids[..others].to_vec(),
+        }
+    }
+}
+
+/// `dcgmGetPidInfo` results for one process.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProcessStats {
+    pub pid: u32,
+    pub summary: GpuProcessStats,
+    pub gpus: Vec<GpuProcessStats>,
+    /// The container the process ran in, if it is still running and inside one.
+    pub container: Option<ContainerInfo>,
+}
+
+impl DcgmLibSafe {
+    /// Starts recording per-process stats on `group`. Must be called before the processes of interest start.
+    pub fn watch_pid_fields(&mut self, group: dcgmGpuGrp_t, options: &WatchOptions) -> Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmWatchPidFields(self.handle(), group, options.update_interval.as_micros() as i64,
+                                                  options.max_keep_age.as_secs_f64(), options.max_keep_samples)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmWatchPidFields").arg("group", group))
+        }
+    }
+
+    pub fn pid_info(&mut self, group: dcgmGpuGrp_t, pid: u32) -> Result<ProcessStats, DCGMError>{
+        let mut info = dcgmPidInfo_t::boxed_versioned();
+        info.pid = pid;
+        match unsafe{self.lib()?.dcgmGetPidInfo(self.handle(), group, &mut *info)}{
+            dcgmReturn_enum_DCGM_ST_OK => (),
+            err_code => return Err(self.call_error(err_code, "dcgmGetPidInfo").arg("group", group).arg("pid", pid))
+        }
+        let num_gpus = info.numGpus.clamp(0, info.gpus.len() as i32) as usize;
+        Ok(ProcessStats {
+            pid,
+            summary: GpuProcessStats::from(&info.summary),
+            gpus: info.gpus[..num_gpus].iter().map(GpuProcessStats::from).collect(),
+            container: container_for_pid(pid),
+        })
+    }
+}
@@ -0,0 +1,203 @@
+//! The `rust_dcgm` Python module (the `python` feature), built with maturin:
+//!
+//! ```python
+//! import rust_dcgm
+//! with rust_dcgm.Client("127.0.0.1:5555") as dcgm:
+//!     watch = dcgm.watch(["gpu_temp", "power_usage"], interval_ms=1000)
+//!     for sample in dcgm.values(watch):
+//!         print(sample["entity_id"], sample["field_id"], sample["value"])
+//!     print(dcgm.health()["overall"])
+//! ```
+//!
+//! Results are the JSON forms the CLI prints, as plain dicts and lists. DCGM errors are raised as
+//! `rust_dcgm.DcgmError`. Calls into DCGM release the GIL.
+// #[pymethods] converts every PyResult it returns into PyResult
+#![allow(clippy::useless_conversion)]
+use super::bindings::*;
+use super::catalog::resolve_field;
+use super::client::{Client as RustClient, ConnectOptions, DcgmClient};
+use super::diag::{DiagLevel, DiagOptions};
+use super::health::HealthSystems;
+use super::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
+use super::watch::{WatchHandle, WatchOptions};
+use super::{c_chars_to_string, DCGMError};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::time::Duration;
+
+pyo3::create_exception!(rust_dcgm, DcgmError, pyo3::exceptions::PyException);
+
+impl From<DCGMError> for PyErr {
+    fn from(e: DCGMError) -> Self {
+        DcgmError::new_err(e.to_string())
+    }
+}
+
+fn to_python<T: Serialize + ?Sized>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let text = serde_json::to_string(value).map_err(|e| DcgmError::new_err(e.to_string()))?;
+    Ok(py.import_bound("json")?.call_method1("loads", (text,))?.unbind())
+}
+
+/// A field by id, tag (`gpu_temp`) or name (`DCGM_FI_DEV_GPU_TEMP`).
+#[derive(FromPyObject)]
+enum FieldArg {
+    Id(u16),
+    Name(String),
+}
+
+impl FieldArg {
+    fn resolve(&self) -> Result<u16, DCGMError> {
+        match self {
+            FieldArg::Id(id) => Ok(*id),
+            FieldArg::Name(name) => resolve_field(name),
+        }
+    }
+}
+
+/// Fields watched on every GPU; pass it to `Client.values` and `Client.unwatch`.
+#[pyclass(module = "rust_dcgm")]
+pub struct Watch {
+    handle: Option<WatchHandle>,
+}
+
+#[pymethods]
+impl Watch {
+    #[getter]
+    fn fields(&self) -> Vec<u16> {
+        self.handle.as_ref().map(|h| h.fields.clone()).unwrap_or_default()
+    }
+}
+
+impl Watch {
+    fn handle(&self) -> Result<&WatchHandle, DCGMError> {
+        self.handle.as_ref().ok_or_else(|| DCGMError::from("The watch was removed"))
+    }
+}
+
+/// A connection to DCGM. Closed by `close()` or at the end of a `with` block.
+#[pyclass(name = "Client", module = "rust_dcgm")]
+pub struct Client {
+    client: Option<DcgmClient>,
+}
+
+impl Client {
+    fn dcgm(&mut self) -> Result<&mut DcgmClient, DCGMError> {
+        self.client.as_mut().ok_or_else(|| DCGMError::disconnected("The client is closed"))
+    }
+}
+
+#[pymethods]
+impl Client {
+    /// Connects to nv-hostengine at `address` (host[:port], or a socket path with `unix_socket`), or
+    /// starts a hostengine inside this process with `embedded`.
+    #[new]
+    #[pyo3(signature = (address = "127.0.0.1:5555", unix_socket = false, embedded = false))]
+    fn new(py: Python<'_>, address: &str, unix_socket: bool, embedded: bool) -> PyResult<Self> {
+        let options = if embedded {
+            ConnectOptions::Embedded
+        } else {
+            ConnectOptions::Standalone { address: address.to_string(), unix_socket, persist_after_disconnect: false }
+        };
+        let client = py.allow_threads(|| RustClient::new().connect(&options))?;
+        Ok(Self { client: Some(client) })
+    }
+
+    /// Supported GPUs as dicts of `gpu_id`, `uuid`, `name` and `pci_bus_id`.
+    fn devices(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let dcgm = self.dcgm()?;
+        let devices = py.allow_threads(|| {
+            let mut devices = Vec::new();
+            for gpu_id in dcgm.getAllSupportedDevices()? {
+                let attributes = dcgm.device_attributes(gpu_id)?;
+                devices.push(serde_json::json!({
+                    "gpu_id": gpu_id,
+                    "uuid": c_chars_to_string(&attributes.identifiers.uuid),
+                    "name": c_chars_to_string(&attributes.identifiers.deviceName),
+                    "pci_bus_id": c_chars_to_string(&attributes.identifiers.pciBusId),
+                }));
+            }
+            Ok::<_, DCGMError>(devices)
+        })?;
+        to_python(py, &devices)
+    }
+
+    /// Starts watching `fields` (ids, tags or names) on every GPU.
+    #[pyo3(signature = (fields, interval_ms = 1000, max_keep_age_s = 300.0))]
+    fn watch(&mut self, py: Python<'_>, fields: Vec<FieldArg>, interval_ms: u64, max_keep_age_s: f64) -> PyResult<Watch> {
+        let fields = fields.iter().map(FieldArg::resolve).collect::<Result<Vec<u16>, _>>()?;
+        let options = WatchOptions {
+            update_interval: Duration::from_millis(interval_ms.max(1)),
+            max_keep_age: Duration::from_secs_f64(max_keep_age_s.max(0.0)),
+            ..WatchOptions::default()
+        };
+        let dcgm = self.dcgm()?;
+        let handle = py.allow_threads(|| dcgm.watch_all_gpus(&fields, &options))?;
+        Ok(Watch { handle: Some(handle) })
+    }
+
+    /// Latest value of every watched field on every GPU, as sample dicts.
+    fn values(&mut self, py: Python<'_>, watch: &Watch) -> PyResult<PyObject> {
+        let (dcgm, handle) = (self.dcgm()?, watch.handle()?);
+        let samples = py.allow_threads(|| dcgm.watch_values(handle))?;
+        to_python(py, &samples)
+    }
+
+    fn unwatch(&mut self, py: Python<'_>, watch: &mut Watch) -> PyResult<()> {
+        if let Some(handle) = watch.handle.take() {
+            let dcgm = self.dcgm()?;
+            py.allow_threads(|| dcgm.unwatch(handle))?;
+        }
+        Ok(())
+    }
+
+    /// Enables every health system on all GPUs and returns the health report.
+    fn health(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let dcgm = self.dcgm()?;
+        let group = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+        let report = py.allow_threads(|| {
+            dcgm.health_set(group, HealthSystems::all(), Duration::from_secs(5), Duration::from_secs(600))?;
+            dcgm.health_check(group)
+        })?;
+        to_python(py, &report)
+    }
+
+    /// Runs diagnostics on all GPUs: a level (`short`, `medium`, `long`, `xlong`) or named tests.
+    #[pyo3(signature = (level = "short", tests = Vec::new(), timeout_s = None))]
+    fn diag(&mut self, py: Python<'_>, level: &str, tests: Vec<String>, timeout_s: Option<u64>) -> PyResult<PyObject> {
+        let options = DiagOptions {
+            level: level.parse::<DiagLevel>()?,
+            tests,
+            timeout: timeout_s.map(Duration::from_secs),
+            ..DiagOptions::default()
+        };
+        let dcgm = self.dcgm()?;
+        let report = py.allow_threads(|| dcgm.run_diag(&options))?;
+        to_python(py, &report)
+    }
+
+    /// Removes what this client watched and disconnects. Further calls raise `DcgmError`.
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        if let Some(client) = self.client.take() {
+            py.allow_threads(|| client.disconnect_graceful(DEFAULT_SHUTDOWN_TIMEOUT))?;
+        }
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, py: Python<'_>, _args: &Bound<'_, pyo3::types::PyTuple>) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+}
+
+#[pymodule]
+fn rust_dcgm(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_class::<Watch>()?;
+    m.add("DcgmError", m.py().get_type_bound::<DcgmError>())?;
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use super::bindings::*;
+use super::samples::{FieldValue, Sample, SampleKey};
+use std::collections::{BTreeMap, HashSet};
+
+/// Derived rate fields are published as `RATE_FIELD_BASE + source field id`, well above any real DCGM field.
+pub const RATE_FIELD_BASE: u16 = 0x8000;
+
+/// Fields DCGM reports as monotonically increasing counters.
+pub const DEFAULT_COUNTER_FIELDS: &[u16] = &[
+    DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION as u16,
+    DCGM_FI_DEV_PCIE_REPLAY_COUNTER as u16,
+    DCGM_FI_DEV_TOTAL_APP_CLOCKS_VIOLATION as u16,
+    DCGM_FI_DEV_TOTAL_BASE_CLOCKS_VIOLATION as u16,
+    DCGM_FI_DEV_ECC_SBE_VOL_TOTAL as u16,
+    DCGM_FI_DEV_ECC_DBE_VOL_TOTAL as u16,
+    DCGM_FI_DEV_ECC_SBE_AGG_TOTAL as u16,
+    DCGM_FI_DEV_ECC_DBE_AGG_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_CRC_FLIT_ERROR_COUNT_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_CRC_DATA_ERROR_COUNT_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_REPLAY_ERROR_COUNT_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_RECOVERY_ERROR_COUNT_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_BANDWIDTH_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_TX_BANDWIDTH_TOTAL as u16,
+    DCGM_FI_DEV_NVLINK_RX_BANDWIDTH_TOTAL as u16,
+];
+
+pub fn rate_field_id(field_id: u16) -> u16 {
+    RATE_FIELD_BASE | field_id
+}
+
+pub fn is_rate_field(field_id: u16) -> bool {
+    field_id & RATE_FIELD_BASE != 0
+}
+
+/// The counter a derived rate field was computed from.
+pub fn rate_source_field(field_id: u16) -> Option<u16> {
+    is_rate_field(field_id).then_some(field_id & !RATE_FIELD_BASE)
+}
+
+/// Turns cumulative counter samples into per-second rates. A counter that goes backwards is treated as a
+/// reset (driver reload, GPU reset) and its new value is taken as the increase since the reset.
+#[derive(Clone, Debug)]
+pub struct RateComputer {
+    counters: HashSet<u16>,
+    last: BTreeMap<SampleKey, (i64, f64)>,
+    resets: u64,
+}
+
+impl Default for RateComputer {
+    fn default() -> Self {
+        Self::new(DEFAULT_COUNTER_FIELDS.iter().copied())
+    }
+}
+
+impl RateComputer {
+    pub fn new<I: IntoIterator<Item = u16>>(counters: I) -> Self {
+        Self { counters: counters.into_iter().collect(), last: BTreeMap::new(), resets: 0 }
+    }
+
+    pub fn add_counter(&mut self, field_id: u16) {
+        self.counters.insert(field_id);
+    }
+
+    pub fn is_counter(&self, field_id: u16) -> bool {
+        self.counters.contains(&field_id)
+    }
+
+    /// Number of counter resets seen so far.
+    pub fn resets(&self) -> u64 {
+        self.resets
+    }
+
+    /// Returns the derived rate sample for `sample`, or None if it is not a counter, is blank, or is the
+    /// first (or an out-of-order) sample of its series.
+    pub fn observe(&mut self, sample: &Sample) -> Option<Sample> {
+        if !self.is_counter(sample.field_id) {
+            return None;
+        }
+        let value = sample.value.as_f64()?;
+        let key = sample.key();
+        let previous = self.last.get(&key).copied();
+        if matches!(previous, Some((ts, _)) if sample.timestamp <= ts) {
+            return None;
+        }
+        self.last.insert(key, (sample.timestamp, value));
+        let (prev_ts, prev_value) = previous?;
+        let delta = if value < prev_value {
+            self.resets += 1;
+            value
+        } else {
+            value - prev_value
+        };
+        let seconds = sample.timestamp.saturating_sub(prev_ts) as f64 / 1_000_000.0;
+        Some(Sample {
+            entity_group: sample.entity_group,
+            entity_id: sample.entity_id,
+            field_id: rate_field_id(sample.field_id),
+            timestamp: sample.timestamp,
+            value: FieldValue::Double(delta / seconds),
+            tags: sample.tags.clone(),
+        })
+    }
+
+    /// Appends the derived rate samples for `samples` to the same stream.
+    pub fn augment(&mut self, samples: &mut Vec<Sample>) {
+        let derived: Vec<Sample> = samples.iter().filter_map(|s| self.observe(s)).collect();
+        samples.extend(derived);
+    }
+
+    pub fn remove_series(&mut self, key: &SampleKey) {
+        self.last.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
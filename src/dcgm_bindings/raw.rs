@@ -0,0 +1,67 @@
+//! Direct access to libdcgm for functions this crate does not wrap yet: the generated bindings, the
+//! loaded library, the connection handle, and the helpers the wrapped calls use to stamp struct
+//! versions, marshal strings and turn return codes into `DCGMError`s.
+//!
+//! ```no_run
+//! use rust_dcgm::dcgm_bindings::raw::{self, Versioned};
+//! use rust_dcgm::dcgm_bindings::{DcgmLibSafe, Mode};
+//!
+//! let dcgm = DcgmLibSafe::new(Mode::Standalone, &["127.0.0.1", "0"])?;
+//! let mut info = raw::dcgmVersionInfo_t::versioned();
+//! let code = unsafe { dcgm.raw_lib()?.dcgmHostengineVersionInfo(dcgm.raw_handle()?, &raw mut info) };
+//! dcgm.raw_check(code, "dcgmHostengineVersionInfo")?;
+//! println!("{}", raw::from_c_chars(&info.rawBuildInfoString));
+//! # Ok::<(), rust_dcgm::dcgm_bindings::DCGMError>(())
+//! ```
+//!
+//! Nothing here keeps track of what the raw calls create: groups, field groups and watches made this way
+//! are neither cleaned up by `shutdown_graceful` nor recreated after a supervised hostengine restarts.
+
+pub use super::bindings::*;
+pub use super::init::{Versioned, Zeroable};
+use super::{c_chars_to_string, copy_str, DCGMError, DcgmLibSafe};
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+/// `MAKE_DCGM_VERSION(T, version)`, for structs without a `Versioned` impl.
+pub fn make_version<T>(version: u32) -> u32 {
+    std::mem::size_of::<T>() as u32 | (version << 24)
+}
+
+/// A C string for `const char *` arguments; fails on interior NULs.
+pub fn to_c_string(s: &str) -> Result<CString, DCGMError> {
+    CString::new(s).map_err(|_| DCGMError::from(format!("'{s}' contains a NUL byte")))
+}
+
+/// A fixed-size C char array from a DCGM struct, up to its NUL.
+pub fn from_c_chars(chars: &[c_char]) -> String {
+    c_chars_to_string(chars)
+}
+
+/// Copies `s` into a fixed-size C char array of a request struct; fails when it does not fit.
+pub fn copy_to_c_chars(dst: &mut [c_char], s: &str) -> Result<(), DCGMError> {
+    copy_str(dst, s)
+}
+
+impl DcgmLibSafe {
+    /// The connection handle. A supervised hostengine gets a new one when it restarts, so fetch it for
+    /// every call instead of keeping it. Fails once the connection was shut down.
+    pub fn raw_handle(&self) -> Result<dcgmHandle_t, DCGMError> {
+        self.lib()?;
+        Ok(self.handle())
+    }
+
+    /// The loaded libdcgm. Fails once the connection was shut down.
+    pub fn raw_lib(&self) -> Result<&'static DcgmLib, DCGMError> {
+        self.lib()
+    }
+
+    /// Ok for `DCGM_ST_OK`, otherwise the error for `code` with `function` recorded, as the wrapped
+    /// calls report it. Add arguments with `DCGMError::arg`.
+    pub fn raw_check(&self, code: dcgmReturn_t, function: &'static str) -> Result<(), DCGMError> {
+        match code {
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, function)),
+        }
+    }
+}
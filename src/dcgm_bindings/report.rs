@@ -0,0 +1,472 @@
+//! Summaries of a time window for daily fleet reports: per-entity averages of the utilization and other
+//! gauge fields, throttling and error counter increases, XIDs, health incidents and the outcome of
+//! diag runs. Built from samples of any source (the daemon's `json_lines` sink, exporter dumps,
+//! `values_since`), so no hostengine is needed to summarize a past day.
+
+use super::bindings::*;
+use super::diag::{DiagReport, DiagResult};
+use super::entity::Entity;
+use super::exporter::local_hostname;
+use super::format::rfc3339;
+use super::health::{HealthReport, HealthResult};
+use super::import::EXPORTER_FIELDS;
+use super::rates::rate_source_field;
+use super::samples::Sample;
+use super::units::{field_semantics, FieldSemantics};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Cumulative throttling counters, in usec, and the name each is reported under. DCGM's per-reason
+/// counters are gauges to the exporter; over a window they are summed like the counters they are.
+pub const VIOLATION_FIELDS: &[(u16, &str)] = &[
+    (DCGM_FI_DEV_POWER_VIOLATION as u16, "power"),
+    (DCGM_FI_DEV_THERMAL_VIOLATION as u16, "thermal"),
+    (DCGM_FI_DEV_SYNC_BOOST_VIOLATION as u16, "sync_boost"),
+    (DCGM_FI_DEV_BOARD_LIMIT_VIOLATION as u16, "board_limit"),
+    (DCGM_FI_DEV_LOW_UTIL_VIOLATION as u16, "low_util"),
+    (DCGM_FI_DEV_RELIABILITY_VIOLATION as u16, "reliability"),
+    (DCGM_FI_DEV_TOTAL_APP_CLOCKS_VIOLATION as u16, "app_clocks"),
+    (DCGM_FI_DEV_TOTAL_BASE_CLOCKS_VIOLATION as u16, "base_clocks"),
+];
+
+const GPU_UTIL: u16 = DCGM_FI_DEV_GPU_UTIL as u16;
+const MEM_COPY_UTIL: u16 = DCGM_FI_DEV_MEM_COPY_UTIL as u16;
+const SM_ACTIVE: u16 = DCGM_FI_PROF_SM_ACTIVE as u16;
+const POWER_USAGE: u16 = DCGM_FI_DEV_POWER_USAGE as u16;
+const GPU_TEMP: u16 = DCGM_FI_DEV_GPU_TEMP as u16;
+const XID_ERRORS: u16 = DCGM_FI_DEV_XID_ERRORS as u16;
+const ECC_DBE: u16 = DCGM_FI_DEV_ECC_DBE_VOL_TOTAL as u16;
+
+fn semantics_of(field_id: u16) -> FieldSemantics {
+    if VIOLATION_FIELDS.iter().any(|&(id, _)| id == field_id) {
+        FieldSemantics::Counter
+    } else {
+        field_semantics(field_id)
+    }
+}
+
+/// `DCGM_FI_*` name of the field as dcgm-exporter has it, `<name>_rate` for derived rates, else
+/// `DCGM_FIELD_<id>`.
+fn field_name(field_id: u16) -> String {
+    if let Some(source) = rate_source_field(field_id) {
+        return format!("{}_rate", field_name(source));
+    }
+    EXPORTER_FIELDS.iter()
+        .find(|&&(_, id)| id == field_id)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("DCGM_FIELD_{field_id}"))
+}
+
+/// What a field did over the window, by its semantics.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FieldStats {
+    Gauge { min: f64, max: f64, mean: f64 },
+    /// Sum of the increases between samples; a counter going backwards is taken as a reset, as
+    /// `RateComputer` does.
+    Counter { increase: f64 },
+    /// Distinct codes seen, ascending.
+    Enum { values: Vec<i64> },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldSummary {
+    pub field_id: u16,
+    pub name: String,
+    pub samples: u64,
+    #[serde(flatten)]
+    pub stats: FieldStats,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EntitySummary {
+    pub entity: Entity,
+    /// Ordered by field id.
+    pub fields: Vec<FieldSummary>,
+    /// Seconds spent throttled over the window per `VIOLATION_FIELDS` reason; zero ones are left out.
+    pub violations: BTreeMap<&'static str, f64>,
+    /// Distinct XIDs reported over the window.
+    pub xids: Vec<i64>,
+}
+
+impl EntitySummary {
+    pub fn field(&self, field_id: u16) -> Option<&FieldSummary> {
+        self.fields.iter().find(|f| f.field_id == field_id)
+    }
+
+    /// Mean of a gauge field over the window.
+    pub fn mean(&self, field_id: u16) -> Option<f64> {
+        match self.field(field_id)?.stats {
+            FieldStats::Gauge { mean, .. } => Some(mean),
+            _ => None,
+        }
+    }
+
+    pub fn max(&self, field_id: u16) -> Option<f64> {
+        match self.field(field_id)?.stats {
+            FieldStats::Gauge { max, .. } => Some(max),
+            _ => None,
+        }
+    }
+
+    /// Increase of a counter field over the window.
+    pub fn increase(&self, field_id: u16) -> Option<f64> {
+        match self.field(field_id)?.stats {
+            FieldStats::Counter { increase } => Some(increase),
+            _ => None,
+        }
+    }
+
+    pub fn throttled_seconds(&self) -> f64 {
+        self.violations.values().sum()
+    }
+}
+
+/// A failing test of a diag run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DiagFailure {
+    pub test: String,
+    pub result: DiagResult,
+    /// Entities the test warned or failed on; empty when it has no per-entity results.
+    pub entities: Vec<Entity>,
+    pub messages: Vec<String>,
+}
+
+/// One past diag run, as kept for the report's history.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DiagRun {
+    /// Where the report was read from, e.g. its file name.
+    pub source: String,
+    /// usec since 1970; reports carry no time of their own, so this is usually the file's mtime.
+    pub time: i64,
+    pub overall: DiagResult,
+    pub dcgm_version: String,
+    pub failures: Vec<DiagFailure>,
+}
+
+impl DiagRun {
+    pub fn new(source: impl Into<String>, time: i64, report: &DiagReport) -> Self {
+        let failures = report.tests.iter()
+            .filter(|t| t.result >= DiagResult::Warn)
+            .map(|t| DiagFailure {
+                test: t.name.clone(),
+                result: t.result,
+                entities: t.entities.iter().filter(|(_, r)| *r >= DiagResult::Warn).map(|&(e, _)| e).collect(),
+                messages: t.errors.iter().map(|e| e.message.clone()).collect(),
+            })
+            .collect();
+        Self { source: source.into(), time, overall: report.overall(), dcgm_version: report.dcgm_version.clone(), failures }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    samples: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    last: Option<(i64, f64)>,
+    increase: f64,
+    values: BTreeSet<i64>,
+}
+
+impl Series {
+    fn add(&mut self, semantics: FieldSemantics, timestamp: i64, value: f64) {
+        match semantics {
+            FieldSemantics::Counter => {
+                // out of order readings cannot be told from resets, so they are left out
+                if self.last.is_some_and(|(t, _)| timestamp <= t) {
+                    return;
+                }
+                if let Some((_, last)) = self.last {
+                    self.increase += if value >= last { value - last } else { value };
+                }
+                self.last = Some((timestamp, value));
+            }
+            FieldSemantics::Enum => {
+                self.values.insert(value as i64);
+            }
+            FieldSemantics::Gauge | FieldSemantics::String => {
+                if self.samples == 0 {
+                    (self.min, self.max) = (value, value);
+                }
+                self.sum += value;
+                self.min = self.min.min(value);
+                self.max = self.max.max(value);
+            }
+        }
+        self.samples += 1;
+    }
+
+    fn summary(&self, field_id: u16, semantics: FieldSemantics) -> FieldSummary {
+        let stats = match semantics {
+            FieldSemantics::Counter => FieldStats::Counter { increase: self.increase },
+            FieldSemantics::Enum => FieldStats::Enum { values: self.values.iter().copied().collect() },
+            FieldSemantics::Gauge | FieldSemantics::String => {
+                FieldStats::Gauge { min: self.min, max: self.max, mean: self.sum / self.samples as f64 }
+            }
+        };
+        FieldSummary { field_id, name: field_name(field_id), samples: self.samples, stats }
+    }
+}
+
+/// Accumulates samples, health results and diag runs of one window into a `SummaryReport`. Samples
+/// are folded in as they come, so a day of them never has to be held in memory.
+#[derive(Debug)]
+pub struct ReportBuilder {
+    host: Option<String>,
+    start: i64,
+    end: i64,
+    samples: u64,
+    series: BTreeMap<(Entity, u16), Series>,
+    health: Option<HealthReport>,
+    diag: Vec<DiagRun>,
+}
+
+impl ReportBuilder {
+    /// A report over `start..end`, in usec since 1970.
+    pub fn new(start: i64, end: i64) -> Self {
+        Self { host: None, start, end, samples: 0, series: BTreeMap::new(), health: None, diag: Vec::new() }
+    }
+
+    /// Host named in the report; the local hostname by default.
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    pub fn with_health(mut self, health: HealthReport) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Adds a diag run to the history; runs outside the window are left out.
+    pub fn with_diag(mut self, run: DiagRun) -> Self {
+        if (self.start..=self.end).contains(&run.time) {
+            self.diag.push(run);
+        }
+        self
+    }
+
+    /// Folds a sample in. Samples outside the window and non-numeric or blank values are skipped.
+    pub fn add_sample(&mut self, sample: &Sample) {
+        let Some(value) = sample.value.as_f64() else { return };
+        if !(self.start..=self.end).contains(&sample.timestamp) {
+            return;
+        }
+        let entity = Entity::new(sample.entity_group, sample.entity_id);
+        self.series.entry((entity, sample.field_id)).or_default().add(semantics_of(sample.field_id), sample.timestamp, value);
+        self.samples += 1;
+    }
+
+    pub fn build(mut self) -> SummaryReport {
+        let mut entities: Vec<EntitySummary> = Vec::new();
+        for (&(entity, field_id), series) in &self.series {
+            if entities.last().is_none_or(|e| e.entity != entity) {
+                entities.push(EntitySummary { entity, fields: Vec::new(), violations: BTreeMap::new(), xids: Vec::new() });
+            }
+            let summary = entities.last_mut().unwrap();
+            let field = series.summary(field_id, semantics_of(field_id));
+            if let Some(&(_, reason)) = VIOLATION_FIELDS.iter().find(|&&(id, _)| id == field_id) {
+                if series.increase > 0.0 {
+                    summary.violations.insert(reason, series.increase / 1e6);
+                }
+            }
+            if field_id == XID_ERRORS {
+                summary.xids = series.values.iter().copied().filter(|&xid| xid != 0).collect();
+            }
+            summary.fields.push(field);
+        }
+        self.diag.sort_by_key(|run| run.time);
+        SummaryReport {
+            host: self.host.or_else(local_hostname).unwrap_or_default(),
+            start: self.start,
+            end: self.end,
+            samples: self.samples,
+            entities,
+            health: self.health,
+            diag: self.diag,
+        }
+    }
+}
+
+/// Utilization, violations, incidents and diag history of one host over one window. Serializes as
+/// the JSON report; `render_text` and `render_html` lay out the same content for people.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SummaryReport {
+    pub host: String,
+    /// Window, in usec since 1970.
+    pub start: i64,
+    pub end: i64,
+    /// Samples that went into the summaries.
+    pub samples: u64,
+    pub entities: Vec<EntitySummary>,
+    /// None when health was not checked.
+    pub health: Option<HealthReport>,
+    /// Oldest first.
+    pub diag: Vec<DiagRun>,
+}
+
+/// Columns of the overview table, one row per entity.
+const OVERVIEW: &[&str] = &["Entity", "GPU util", "Mem util", "SM active", "Power", "Temp max", "Throttled", "DBE", "XIDs"];
+
+fn or_dash(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "-".to_string())
+}
+
+impl EntitySummary {
+    fn overview_row(&self) -> Vec<String> {
+        let xids: Vec<String> = self.xids.iter().map(i64::to_string).collect();
+        vec![
+            self.entity.to_string(),
+            or_dash(self.mean(GPU_UTIL).map(|u| format!("{u:.1} %"))),
+            or_dash(self.mean(MEM_COPY_UTIL).map(|u| format!("{u:.1} %"))),
+            or_dash(self.mean(SM_ACTIVE).map(|r| format!("{:.1} %", r * 100.0))),
+            or_dash(self.mean(POWER_USAGE).map(|w| format!("{w:.0} W"))),
+            or_dash(self.max(GPU_TEMP).map(|c| format!("{c:.0} C"))),
+            format!("{:.0} s", self.throttled_seconds()),
+            or_dash(self.increase(ECC_DBE).map(|n| format!("{n:.0}"))),
+            if xids.is_empty() { "-".to_string() } else { xids.join(",") },
+        ]
+    }
+}
+
+fn diag_failure_line(failure: &DiagFailure) -> String {
+    let entities: Vec<String> = failure.entities.iter().map(Entity::to_string).collect();
+    let mut line = format!("{} {}", failure.test, failure.result);
+    if !entities.is_empty() {
+        line += &format!(" on {}", entities.join(", "));
+    }
+    if !failure.messages.is_empty() {
+        line += &format!(": {}", failure.messages.join("; "));
+    }
+    line
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl SummaryReport {
+    /// Whether the health check passed and the latest diag run neither warned nor failed; true when
+    /// neither is known.
+    pub fn healthy(&self) -> bool {
+        self.health.as_ref().is_none_or(|h| h.overall == HealthResult::Pass)
+            && self.diag.last().is_none_or(|run| run.overall < DiagResult::Warn)
+    }
+
+    fn title(&self) -> String {
+        format!("GPU report for {}, {} to {}", self.host, rfc3339(self.start), rfc3339(self.end))
+    }
+
+    pub fn render_text(&self) -> String {
+        let mut out = format!("{}\n{} samples\n\n", self.title(), self.samples);
+        let rows: Vec<Vec<String>> = self.entities.iter().map(EntitySummary::overview_row).collect();
+        let widths: Vec<usize> = (0..OVERVIEW.len())
+            .map(|i| rows.iter().map(|r| r[i].len()).chain([OVERVIEW[i].len()]).max().unwrap_or(0))
+            .collect();
+        for row in std::iter::once(OVERVIEW.iter().map(|h| h.to_string()).collect()).chain(rows) {
+            let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, w)| format!("{cell:<w$}")).collect();
+            let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+        }
+
+        let throttled: Vec<&EntitySummary> = self.entities.iter().filter(|e| !e.violations.is_empty()).collect();
+        if !throttled.is_empty() {
+            out += "\nThrottling\n";
+            for entity in throttled {
+                let reasons: Vec<String> = entity.violations.iter().map(|(reason, s)| format!("{reason} {s:.1} s")).collect();
+                let _ = writeln!(out, "  {:<12}{}", entity.entity.to_string(), reasons.join(", "));
+            }
+        }
+
+        match &self.health {
+            Some(health) => {
+                out += "\n";
+                out += &health.to_string();
+            }
+            None => out += "\nHealth: not checked\n",
+        }
+
+        out += "\nDiagnostics\n";
+        if self.diag.is_empty() {
+            out += "  no runs in the window\n";
+        }
+        for run in &self.diag {
+            let _ = writeln!(out, "  {}  {:<8}{}", rfc3339(run.time), run.overall.to_string(), run.source);
+            for failure in &run.failures {
+                let _ = writeln!(out, "      {}", diag_failure_line(failure));
+            }
+        }
+        out
+    }
+
+    /// A standalone page, to be mailed or published as it is.
+    pub fn render_html(&self) -> String {
+        let title = escape_html(&self.title());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n\
+             <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 8px;text-align:left}}.bad{{color:#b00}}</style>\n\
+             </head><body>\n<h1>{title}</h1>\n<p>{} samples</p>\n",
+            self.samples,
+        );
+        out += "<h2>Utilization</h2>\n<table>\n<tr>";
+        for header in OVERVIEW {
+            let _ = write!(out, "<th>{header}</th>");
+        }
+        out += "</tr>\n";
+        for entity in &self.entities {
+            out += "<tr>";
+            for cell in entity.overview_row() {
+                let _ = write!(out, "<td>{}</td>", escape_html(&cell));
+            }
+            out += "</tr>\n";
+        }
+        out += "</table>\n";
+
+        let throttled: Vec<&EntitySummary> = self.entities.iter().filter(|e| !e.violations.is_empty()).collect();
+        if !throttled.is_empty() {
+            out += "<h2>Throttling</h2>\n<ul>\n";
+            for entity in throttled {
+                let reasons: Vec<String> = entity.violations.iter().map(|(reason, s)| format!("{reason} {s:.1} s")).collect();
+                let _ = writeln!(out, "<li>{}: {}</li>", escape_html(&entity.entity.to_string()), reasons.join(", "));
+            }
+            out += "</ul>\n";
+        }
+
+        out += "<h2>Health</h2>\n";
+        match &self.health {
+            None => out += "<p>Not checked</p>\n",
+            Some(health) => {
+                let class = if health.overall == HealthResult::Pass { "" } else { " class=\"bad\"" };
+                let _ = writeln!(out, "<p{class}>{}</p>", health.overall);
+                if !health.incidents.is_empty() {
+                    out += "<table>\n<tr><th>Entity</th><th>System</th><th>Health</th><th>Message</th></tr>\n";
+                    for incident in &health.incidents {
+                        let entity = incident.entity.map(|e| e.to_string()).unwrap_or_else(|| "-".to_string());
+                        let _ = writeln!(out, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                                         escape_html(&entity), incident.system, incident.health, escape_html(&incident.message));
+                    }
+                    out += "</table>\n";
+                }
+            }
+        }
+
+        out += "<h2>Diagnostics</h2>\n";
+        if self.diag.is_empty() {
+            out += "<p>No runs in the window</p>\n";
+        } else {
+            out += "<table>\n<tr><th>Time</th><th>Result</th><th>Report</th><th>Failures</th></tr>\n";
+            for run in &self.diag {
+                let class = if run.overall >= DiagResult::Warn { " class=\"bad\"" } else { "" };
+                let failures: Vec<String> = run.failures.iter().map(|f| escape_html(&diag_failure_line(f))).collect();
+                let _ = writeln!(out, "<tr><td>{}</td><td{class}>{}</td><td>{}</td><td>{}</td></tr>",
+                                 rfc3339(run.time), run.overall, escape_html(&run.source), failures.join("<br>"));
+            }
+            out += "</table>\n";
+        }
+        out += "</body></html>\n";
+        out
+    }
+}
@@ -0,0 +1,157 @@
+use super::samples::{Sample, SampleKey, Tags};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Summary of one (entity, field) series over one rollup window. Timestamps are usec since 1970.
+/// Serializes like a `Sample` of the mean at the start of the window, with the other statistics next
+/// to it, so readers of samples can read rollups too.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Rollup {
+    #[serde(flatten)]
+    pub key: SampleKey,
+    #[serde(rename = "timestamp")]
+    pub window_start: i64,
+    pub window_end: i64,
+    pub min: f64,
+    pub max: f64,
+    #[serde(rename = "value")]
+    pub mean: f64,
+    pub last: f64,
+    pub last_timestamp: i64,
+    pub count: u64,
+    /// Tags of the last sample of the window.
+    #[serde(flatten)]
+    pub tags: Tags,
+}
+
+#[derive(Clone, Debug)]
+struct Accumulator {
+    window_start: i64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    last: f64,
+    last_timestamp: i64,
+    count: u64,
+    tags: Tags,
+}
+
+impl Accumulator {
+    fn new(window_start: i64, sample: &Sample, v: f64) -> Self {
+        Self { window_start, min: v, max: v, sum: v, last: v, last_timestamp: sample.timestamp, count: 1,
+               tags: sample.tags.clone() }
+    }
+
+    fn add(&mut self, sample: &Sample, v: f64) {
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.sum += v;
+        if sample.timestamp >= self.last_timestamp {
+            self.last = v;
+            self.last_timestamp = sample.timestamp;
+            self.tags = sample.tags.clone();
+        }
+        self.count += 1;
+    }
+
+    fn finish(&self, key: SampleKey, window_us: i64) -> Rollup {
+        Rollup {
+            key,
+            window_start: self.window_start,
+            window_end: self.window_start + window_us,
+            min: self.min,
+            max: self.max,
+            mean: self.sum / self.count as f64,
+            last: self.last,
+            last_timestamp: self.last_timestamp,
+            count: self.count,
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+/// Reduces high-frequency samples to one min/max/mean/last row per (entity, field) per window.
+/// Windows are aligned to multiples of the window length since the epoch, so rollups from different
+/// hosts line up. A window is emitted once a sample for a later window arrives or `flush` is called.
+pub struct RollupEngine {
+    window_us: i64,
+    open: BTreeMap<SampleKey, Accumulator>,
+    completed: Vec<Rollup>,
+}
+
+impl RollupEngine {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window_us: (window.as_micros() as i64).max(1),
+            open: BTreeMap::new(),
+            completed: Vec::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_micros(self.window_us as u64)
+    }
+
+    fn window_start(&self, ts: i64) -> i64 {
+        ts - ts.rem_euclid(self.window_us)
+    }
+
+    /// Adds a sample. Blank and non-numeric values are ignored, as are samples older than the
+    /// series' currently open window.
+    pub fn record(&mut self, sample: &Sample) {
+        let value = match sample.value.as_f64() {
+            Some(v) if v.is_finite() => v,
+            _ => return,
+        };
+        let start = self.window_start(sample.timestamp);
+        let key = sample.key();
+        match self.open.get_mut(&key) {
+            Some(acc) if acc.window_start == start => acc.add(sample, value),
+            Some(acc) if acc.window_start > start => (),
+            Some(acc) => {
+                self.completed.push(acc.finish(key, self.window_us));
+                *acc = Accumulator::new(start, sample, value);
+            }
+            None => {
+                self.open.insert(key, Accumulator::new(start, sample, value));
+            }
+        }
+    }
+
+    pub fn record_all<'a, I: IntoIterator<Item = &'a Sample>>(&mut self, samples: I) {
+        for sample in samples {
+            self.record(sample);
+        }
+    }
+
+    /// Returns every window that has been closed by newer samples.
+    pub fn drain_completed(&mut self) -> Vec<Rollup> {
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Closes every open window that ends at or before `now` (usec since 1970) and returns all completed rollups.
+    pub fn flush(&mut self, now: i64) -> Vec<Rollup> {
+        let window_us = self.window_us;
+        let expired: Vec<SampleKey> = self.open.iter()
+            .filter(|(_, acc)| acc.window_start + window_us <= now)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in expired {
+            if let Some(acc) = self.open.remove(&key) {
+                self.completed.push(acc.finish(key, window_us));
+            }
+        }
+        self.drain_completed()
+    }
+
+    /// Closes every open window regardless of time, e.g. on shutdown.
+    pub fn flush_all(&mut self) -> Vec<Rollup> {
+        let window_us = self.window_us;
+        let open = std::mem::take(&mut self.open);
+        for (key, acc) in open {
+            self.completed.push(acc.finish(key, window_us));
+        }
+        self.drain_completed()
+    }
+}
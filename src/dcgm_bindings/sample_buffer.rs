@@ -0,0 +1,113 @@
+use super::samples::{Sample, SampleKey};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bounded in-memory store holding the most recent samples of every (entity, field) series.
+/// Each series is a ring buffer of at most `capacity_per_series` samples; once `max_series` distinct
+/// series exist, samples for new series are dropped.
+#[derive(Debug)]
+pub struct SampleBuffer {
+    capacity_per_series: usize,
+    max_series: usize,
+    series: HashMap<SampleKey, VecDeque<Sample>>,
+    dropped: u64,
+}
+
+impl SampleBuffer {
+    pub fn new(capacity_per_series: usize, max_series: usize) -> Self {
+        Self {
+            capacity_per_series: capacity_per_series.max(1),
+            max_series,
+            series: HashMap::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Sizes each ring so that `retention` worth of samples at `interval` fit.
+    pub fn with_retention(retention: Duration, interval: Duration, max_series: usize) -> Self {
+        let per_series = retention.as_micros() / interval.as_micros().max(1);
+        Self::new(per_series as usize + 1, max_series)
+    }
+
+    pub fn capacity_per_series(&self) -> usize {
+        self.capacity_per_series
+    }
+
+    pub fn max_series(&self) -> usize {
+        self.max_series
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        let key = sample.key();
+        if !self.series.contains_key(&key) && self.series.len() >= self.max_series {
+            self.dropped += 1;
+            return;
+        }
+        let ring = self.series.entry(key).or_insert_with(|| VecDeque::with_capacity(self.capacity_per_series));
+        if ring.len() == self.capacity_per_series {
+            ring.pop_front();
+        }
+        // Samples normally arrive in order; keep the ring sorted if one doesn't.
+        let pos = ring.partition_point(|s| s.timestamp <= sample.timestamp);
+        ring.insert(pos, sample);
+    }
+
+    pub fn extend<I: IntoIterator<Item = Sample>>(&mut self, samples: I) {
+        for sample in samples {
+            self.push(sample);
+        }
+    }
+
+    /// Samples of one series with `start <= timestamp < end` (usec since 1970), oldest first.
+    pub fn range(&self, key: &SampleKey, start: i64, end: i64) -> Vec<Sample> {
+        match self.series.get(key) {
+            Some(ring) => ring.iter().filter(|s| s.timestamp >= start && s.timestamp < end).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Samples of every series with `start <= timestamp < end`, grouped by series.
+    pub fn range_all(&self, start: i64, end: i64) -> HashMap<SampleKey, Vec<Sample>> {
+        self.series.keys()
+            .map(|k| (*k, self.range(k, start, end)))
+            .filter(|(_, v)| !v.is_empty())
+            .collect()
+    }
+
+    /// Samples of one series from the last `window` of wall clock time.
+    pub fn last(&self, key: &SampleKey, window: Duration) -> Vec<Sample> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as i64;
+        self.range(key, now - window.as_micros() as i64, i64::MAX)
+    }
+
+    pub fn latest(&self, key: &SampleKey) -> Option<&Sample> {
+        self.series.get(key).and_then(|ring| ring.back())
+    }
+
+    pub fn keys(&self) -> Vec<SampleKey> {
+        let mut keys: Vec<SampleKey> = self.series.keys().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn len(&self) -> usize {
+        self.series.values().map(|r| r.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of samples rejected because `max_series` was reached.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn remove_series(&mut self, key: &SampleKey) {
+        self.series.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+}
@@ -0,0 +1,224 @@
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::DCGMError;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::sync::Arc;
+
+/// A DCGM field id, e.g. `DCGM_FI_DEV_GPU_TEMP as FieldId`.
+pub type FieldId = u16;
+
+/// A decoded DCGM field value. Blank sentinels (DCGM_*_BLANK and friends) are mapped to `Blank`.
+/// Serializes as the bare value, with `Blank` as null.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Int64(i64),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Blank,
+}
+
+impl FieldValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FieldValue::Int64(v) => Some(*v as f64),
+            FieldValue::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        matches!(self, FieldValue::Blank)
+    }
+}
+
+/// Key/value tags attached where samples are collected, e.g. `tenant=teamA`, and carried to every sink.
+/// Shared between the samples of a collection, so tagging them costs a reference count each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tags(Arc<BTreeMap<String, String>>);
+
+impl Tags {
+    pub fn new(tags: BTreeMap<String, String>) -> Self {
+        Self(Arc::new(tags))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl From<BTreeMap<String, String>> for Tags {
+    fn from(tags: BTreeMap<String, String>) -> Self {
+        Self::new(tags)
+    }
+}
+
+impl Serialize for Tags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+/// One field value for one entity at one point in time. `timestamp` is in usec since 1970.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Sample {
+    pub entity_group: EntityGroup,
+    pub entity_id: u32,
+    pub field_id: u16,
+    pub timestamp: i64,
+    pub value: FieldValue,
+    /// Serialized as fields of the sample itself.
+    #[serde(flatten)]
+    pub tags: Tags,
+}
+
+impl Sample {
+    pub fn key(&self) -> SampleKey {
+        SampleKey {
+            entity_group: self.entity_group,
+            entity_id: self.entity_id,
+            field_id: self.field_id,
+        }
+    }
+}
+
+/// Latest values of a set of entities × fields, as `get_group_values` returns them. Entity-major;
+/// pairs DCGM had no value for are left out, see `missing`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SampleSet {
+    pub entities: Vec<Entity>,
+    pub fields: Vec<FieldId>,
+    pub samples: Vec<Sample>,
+}
+
+impl SampleSet {
+    pub fn get(&self, entity: Entity, field_id: FieldId) -> Option<&Sample> {
+        self.samples.iter().find(|s| s.entity_group == entity.group && s.entity_id == entity.id && s.field_id == field_id)
+    }
+
+    pub fn for_entity(&self, entity: Entity) -> impl Iterator<Item = &Sample> {
+        self.samples.iter().filter(move |s| s.entity_group == entity.group && s.entity_id == entity.id)
+    }
+
+    /// Entity × field pairs without a value: not supported, not watched or no data yet.
+    pub fn missing(&self) -> Vec<(Entity, FieldId)> {
+        self.entities.iter()
+            .flat_map(|&entity| self.fields.iter().map(move |&field_id| (entity, field_id)))
+            .filter(|&(entity, field_id)| self.get(entity, field_id).is_none())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Sample> {
+        self.samples.iter()
+    }
+}
+
+impl IntoIterator for SampleSet {
+    type Item = Sample;
+    type IntoIter = std::vec::IntoIter<Sample>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.samples.into_iter()
+    }
+}
+
+/// Identifies a single (entity, field) series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct SampleKey {
+    pub entity_group: EntityGroup,
+    pub entity_id: u32,
+    pub field_id: u16,
+}
+
+fn int64_is_blank(v: i64) -> bool {
+    v >= DCGM_INT64_BLANK as i64
+}
+
+fn fp64_is_blank(v: f64) -> bool {
+    v >= DCGM_FP64_BLANK
+}
+
+fn str_is_blank(s: &str) -> bool {
+    s.starts_with("<<<") && s.ends_with(">>>")
+}
+
+fn decode_value(field_type: u16, value: &dcgmFieldValue_v1__bindgen_ty_1) -> Result<FieldValue, DCGMError> {
+    unsafe {
+        match field_type as u8 {
+            DCGM_FT_INT64 | DCGM_FT_TIMESTAMP => {
+                let v = value.i64_;
+                if int64_is_blank(v) { Ok(FieldValue::Blank) } else { Ok(FieldValue::Int64(v)) }
+            }
+            DCGM_FT_DOUBLE => {
+                let v = value.dbl;
+                if fp64_is_blank(v) { Ok(FieldValue::Blank) } else { Ok(FieldValue::Double(v)) }
+            }
+            DCGM_FT_STRING => {
+                let s = CStr::from_bytes_until_nul(&*(&value.str_ as *const _ as *const [u8; 256]))
+                    .map(|c| c.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if str_is_blank(&s) { Ok(FieldValue::Blank) } else { Ok(FieldValue::String(s)) }
+            }
+            DCGM_FT_BINARY => {
+                let blob = &*(&value.blob as *const _ as *const [u8; 4096]);
+                Ok(FieldValue::Blob(blob.to_vec()))
+            }
+            other => Err(DCGMError::from(format!("Unknown field type {}", other as char))),
+        }
+    }
+}
+
+fn check_status(status: i32) -> Result<(), DCGMError> {
+    match status {
+        dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+        dcgmReturn_enum_DCGM_ST_NOT_WATCHED => Err(DCGMError::from("Field Value is not being watched")),
+        dcgmReturn_enum_DCGM_ST_NO_DATA => Err(DCGMError::from("No data is available for this field")),
+        dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED => Err(DCGMError::from("Field is not supported on this entity")),
+        _ => Err(DCGMError::from("Unknown or Unimplemented Return Status")),
+    }
+}
+
+pub fn decode_field_value_v1(entity_group: EntityGroup, entity_id: u32, fv: &dcgmFieldValue_v1) -> Result<Sample, DCGMError> {
+    check_status(fv.status)?;
+    Ok(Sample {
+        entity_group,
+        entity_id,
+        field_id: fv.fieldId,
+        timestamp: fv.ts,
+        value: decode_value(fv.fieldType, &fv.value)?,
+        tags: Tags::default(),
+    })
+}
+
+pub fn decode_field_value_v2(fv: &dcgmFieldValue_v2) -> Result<Sample, DCGMError> {
+    check_status(fv.status)?;
+    // v1 and v2 share the same value union layout
+    let value = unsafe { &*(&fv.value as *const dcgmFieldValue_v2__bindgen_ty_1 as *const dcgmFieldValue_v1__bindgen_ty_1) };
+    Ok(Sample {
+        entity_group: EntityGroup::try_from(fv.entityGroupId)?,
+        entity_id: fv.entityId,
+        field_id: fv.fieldId,
+        timestamp: fv.ts,
+        value: decode_value(fv.fieldType, value)?,
+        tags: Tags::default(),
+    })
+}
@@ -0,0 +1,253 @@
+use super::entity::Entity;
+use super::latest::LatestValuesQuery;
+use super::samples::Sample;
+use super::shutdown::Task;
+use super::timing::CollectionTiming;
+use super::{DCGMError, DcgmLibSafe};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One set of entities and fields read on a fixed cadence.
+#[derive(Clone, Debug)]
+pub struct CollectionJob {
+    pub name: String,
+    pub entities: Vec<Entity>,
+    pub fields: Vec<u16>,
+    pub interval: Duration,
+}
+
+/// Output of one run of a job.
+#[derive(Debug)]
+pub struct CollectionResult {
+    pub job: String,
+    /// When the run was due; runs are scheduled on fixed deadlines so this never drifts.
+    pub scheduled: Instant,
+    pub collected_at: Instant,
+    /// Ticks skipped since the previous run because the scheduler fell behind.
+    pub missed: u64,
+    pub samples: Result<Vec<Sample>, DCGMError>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SchedulerOptions {
+    pub workers: usize,
+    /// Jobs falling due within this window of each other are dispatched together after one `updateAllFields`.
+    pub coalesce_window: Duration,
+    /// Call `dcgmUpdateAllFields` before each dispatch; needed when the watches are not auto-updated.
+    pub force_update: bool,
+    /// Phase offset and jitter of every job.
+    pub timing: CollectionTiming,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            coalesce_window: Duration::from_millis(10),
+            force_update: true,
+            timing: CollectionTiming::default(),
+        }
+    }
+}
+
+/// Multiplexes many `CollectionJob`s with different intervals over a small worker pool.
+pub struct CollectionScheduler {
+    jobs: Vec<CollectionJob>,
+    options: SchedulerOptions,
+}
+
+/// A running scheduler. Results arrive on `results`; dropping the handle stops it.
+pub struct SchedulerHandle {
+    pub results: Receiver<CollectionResult>,
+    task: Arc<Task>,
+}
+
+struct Dispatch {
+    job: usize,
+    scheduled: Instant,
+    missed: u64,
+}
+
+impl CollectionScheduler {
+    pub fn new(options: SchedulerOptions) -> Self {
+        Self { jobs: Vec::new(), options }
+    }
+
+    pub fn add_job(&mut self, job: CollectionJob) -> Result<(), DCGMError> {
+        if job.interval.is_zero() {
+            return Err(DCGMError::from(format!("Job {} has a zero interval", job.name)));
+        }
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    pub fn jobs(&self) -> &[CollectionJob] {
+        &self.jobs
+    }
+
+    pub fn start(self, dcgm: &DcgmLibSafe) -> Result<SchedulerHandle, DCGMError> {
+        let task = Task::new("collection scheduler");
+        let (result_tx, results) = mpsc::channel();
+        let (work_tx, work_rx) = mpsc::channel::<Dispatch>();
+        let work_rx = Arc::new(Mutex::new(work_rx));
+        let jobs = Arc::new(self.jobs);
+
+        for i in 0..self.options.workers.max(1) {
+            let (client, jobs, work_rx, result_tx) = (dcgm.share(), jobs.clone(), work_rx.clone(), result_tx.clone());
+            let thread = std::thread::Builder::new()
+                .name(format!("dcgm-collect-{i}"))
+                .spawn(move || worker(client, &jobs, &work_rx, &result_tx))
+                .map_err(|e| DCGMError::from(format!("Failed to spawn collection worker: {e}")))?;
+            task.add(thread);
+        }
+
+        let (client, options, dispatcher_stop) = (dcgm.share(), self.options, task.stop.clone());
+        let thread = std::thread::Builder::new()
+            .name("dcgm-collect-dispatch".into())
+            .spawn(move || dispatcher(client, &jobs, &options, &dispatcher_stop, work_tx))
+            .map_err(|e| DCGMError::from(format!("Failed to spawn collection dispatcher: {e}")))?;
+        task.add(thread);
+        dcgm.register_task(&task);
+
+        Ok(SchedulerHandle { results, task })
+    }
+}
+
+impl SchedulerHandle {
+    /// Stops dispatching and waits for in-flight collections to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.task.join();
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn dispatcher(mut dcgm: DcgmLibSafe, jobs: &[CollectionJob], options: &SchedulerOptions, stop: &AtomicBool,
+              work_tx: Sender<Dispatch>) {
+    let start = Instant::now();
+    // Deadline grid of each job; the queue holds the jittered time each job actually fires.
+    let mut grid: Vec<Instant> = jobs.iter().map(|j| start + options.timing.first_offset(j.interval)).collect();
+    let mut queue: BinaryHeap<Reverse<(Instant, usize)>> = grid.iter().enumerate()
+        .map(|(i, &first)| Reverse((first, i)))
+        .collect();
+    let tick = Duration::from_millis(50);
+
+    while !stop.load(Ordering::Relaxed) {
+        let Some(&Reverse((next, _))) = queue.peek() else { return };
+        let now = Instant::now();
+        if next > now {
+            // Sleep in short steps so stop requests are honoured promptly.
+            std::thread::sleep((next - now).min(tick));
+            continue;
+        }
+
+        let mut batch = Vec::new();
+        let horizon = now + options.coalesce_window;
+        while let Some(&Reverse((fire, job))) = queue.peek() {
+            if fire > horizon {
+                break;
+            }
+            queue.pop();
+            let interval = jobs[job].interval;
+            let due = grid[job];
+            let (following, missed) = next_deadline(due, interval, now);
+            grid[job] = following;
+            queue.push(Reverse((following + options.timing.run_jitter(interval), job)));
+            batch.push(Dispatch { job, scheduled: due, missed });
+        }
+
+        if options.force_update {
+            if let Err(e) = dcgm.updateAllFields() {
+                tracing::warn!("updateAllFields failed before collection: {e}");
+            }
+        }
+        for dispatch in batch {
+            if work_tx.send(dispatch).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The deadline following `due` on its grid of `interval`, and the ticks skipped to reach it. Runs
+/// stay on the original grid; when the scheduler fell behind, only the latest tick already past `now`
+/// is kept instead of bursting through every one of them.
+fn next_deadline(due: Instant, interval: Duration, now: Instant) -> (Instant, u64) {
+    let mut following = due + interval;
+    let mut missed = 0;
+    while following + interval <= now {
+        following += interval;
+        missed += 1;
+    }
+    (following, missed)
+}
+
+fn worker(mut dcgm: DcgmLibSafe, jobs: &[CollectionJob], work_rx: &Mutex<Receiver<Dispatch>>,
+          result_tx: &Sender<CollectionResult>) {
+    // One query (and value buffer) per job, kept for the life of the worker.
+    let mut queries: Vec<LatestValuesQuery> = jobs.iter().map(|j| LatestValuesQuery::new(&j.entities, &j.fields)).collect();
+    loop {
+        let dispatch = match work_rx.lock() {
+            Ok(rx) => match rx.recv() {
+                Ok(d) => d,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+        let job = &jobs[dispatch.job];
+        let samples = queries[dispatch.job].collect(&mut dcgm);
+        let result = CollectionResult {
+            job: job.name.clone(),
+            scheduled: dispatch.scheduled,
+            collected_at: Instant::now(),
+            missed: dispatch.missed,
+            samples,
+        };
+        if result_tx.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn runs_on_time_move_one_tick() {
+        let start = Instant::now();
+        assert_eq!(next_deadline(start, INTERVAL, start), (start + INTERVAL, 0));
+        assert_eq!(next_deadline(start, INTERVAL, start + Duration::from_secs(5)), (start + INTERVAL, 0));
+        // the next tick is due right away but nothing was skipped
+        assert_eq!(next_deadline(start, INTERVAL, start + INTERVAL), (start + INTERVAL, 0));
+    }
+
+    #[test]
+    fn late_runs_skip_to_the_latest_tick_on_the_grid() {
+        let start = Instant::now();
+        assert_eq!(next_deadline(start, INTERVAL, start + Duration::from_secs(35)), (start + Duration::from_secs(30), 2));
+        assert_eq!(next_deadline(start, INTERVAL, start + Duration::from_secs(40)), (start + Duration::from_secs(40), 3));
+
+        let mut due = start;
+        for now in [12, 19, 47, 48, 90].map(Duration::from_secs) {
+            let (following, _) = next_deadline(due, INTERVAL, start + now);
+            assert_eq!((following - start).as_nanos() % INTERVAL.as_nanos(), 0, "{following:?} left the grid");
+            assert!(following > due && following <= start + now + INTERVAL);
+            due = following;
+        }
+    }
+}
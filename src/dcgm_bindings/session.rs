@@ -0,0 +1,162 @@
+use super::bindings::*;
+use super::entity::Entity;
+use super::policy::{PolicyConditions, PolicyEvent, PolicyRegistration};
+use super::watch::{unique_name, WatchHandle, WatchOptions};
+use super::{DCGMError, DCGMErrorKind, DcgmLibSafe};
+
+/// Groups, field groups, watches and policy registrations made for one task, removed from the
+/// hostengine again when the session is closed or dropped. Short-lived tools on a shared hostengine
+/// use it so that an early return or a panic does not leave their state behind:
+///
+/// ```no_run
+/// # use rust_dcgm::dcgm_bindings::{DcgmLibSafe, DCGMError};
+/// # use rust_dcgm::dcgm_bindings::entity::Entity;
+/// # use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+/// # fn f(dcgm: &mut DcgmLibSafe) -> Result<(), DCGMError> {
+/// let mut session = dcgm.session();
+/// let group = session.create_group("burn", &[Entity::gpu(0)])?;
+/// let fields = session.create_field_group("burn-fields", &[150, 155])?;
+/// session.watch(group, fields, &WatchOptions::default())?;
+/// // ... read values through session.connection() ...
+/// session.close()?; // or let it drop
+/// # Ok(()) }
+/// ```
+///
+/// Only what is created through the session's own methods is owned by it; calls made through
+/// `connection()` are not tracked.
+pub struct Session<'a> {
+    dcgm: &'a mut DcgmLibSafe,
+    groups: Vec<dcgmGpuGrp_t>,
+    field_groups: Vec<dcgmFieldGrp_t>,
+    watches: Vec<(dcgmGpuGrp_t, dcgmFieldGrp_t)>,
+    policies: Vec<PolicyRegistration>,
+    closed: bool,
+}
+
+impl DcgmLibSafe {
+    /// A `Session` on this connection.
+    pub fn session(&mut self) -> Session<'_> {
+        Session { dcgm: self, groups: Vec::new(), field_groups: Vec::new(), watches: Vec::new(), policies: Vec::new(),
+                  closed: false }
+    }
+}
+
+impl Session<'_> {
+    /// The connection, for queries. Groups and watches created through it are not owned by the session.
+    pub fn connection(&mut self) -> &mut DcgmLibSafe {
+        self.dcgm
+    }
+
+    /// Creates a GPU group holding `entities`; destroyed with the session.
+    pub fn create_group(&mut self, name: &str, entities: &[Entity]) -> Result<dcgmGpuGrp_t, DCGMError> {
+        let group = self.dcgm.createGroup(&name.to_string())?;
+        self.groups.push(group);
+        for entity in entities {
+            self.dcgm.addEntityToGroup(group, entity.group, entity.id)?;
+        }
+        Ok(group)
+    }
+
+    /// Creates as many groups as `entities` need at `DCGM_GROUP_MAX_ENTITIES_V2` entities per group,
+    /// named `name-0`, `name-1`, ...; for populations a single group cannot hold, such as every link or
+    /// CPU core of a dense node. All are destroyed with the session.
+    pub fn create_groups(&mut self, name: &str, entities: &[Entity]) -> Result<Vec<dcgmGpuGrp_t>, DCGMError> {
+        entities.chunks(DCGM_GROUP_MAX_ENTITIES_V2 as usize).enumerate()
+            .map(|(i, chunk)| self.create_group(&format!("{name}-{i}"), chunk))
+            .collect()
+    }
+
+    /// Creates a field group of `fields`; destroyed with the session.
+    pub fn create_field_group(&mut self, name: &str, fields: &[u16]) -> Result<dcgmFieldGrp_t, DCGMError> {
+        let mut fields = fields.to_vec();
+        let field_group = self.dcgm.fieldGroupCreate(name, &mut fields)?;
+        self.field_groups.push(field_group);
+        Ok(field_group)
+    }
+
+    /// Watches `field_group` on `group`; unwatched with the session.
+    pub fn watch(&mut self, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t, options: &WatchOptions) -> Result<(), DCGMError> {
+        self.dcgm.watchFields(field_group, group, options.update_interval.as_micros() as i64,
+                              options.max_keep_age.as_secs_f64(), options.max_keep_samples)?;
+        if !self.watches.contains(&(group, field_group)) {
+            self.watches.push((group, field_group));
+        }
+        Ok(())
+    }
+
+    /// `DcgmLibSafe::watch_all_gpus`, with the field group and the watch owned by the session. Read the
+    /// values with `connection().watch_values(..)`; do not `unwatch` the handle yourself.
+    pub fn watch_all_gpus(&mut self, fields: &[u16], options: &WatchOptions) -> Result<WatchHandle, DCGMError> {
+        let field_group = self.create_field_group(&unique_name("session"), fields)?;
+        let group = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+        self.watch(group, field_group, options)?;
+        Ok(WatchHandle { group, field_group, fields: fields.to_vec(), options: options.clone() })
+    }
+
+    /// `DcgmLibSafe::policy_register`, unregistered with the session.
+    pub fn register_policy<F>(&mut self, group: dcgmGpuGrp_t, conditions: PolicyConditions, callback: F) -> Result<(), DCGMError>
+    where
+        F: Fn(PolicyEvent) + Send + Sync + 'static,
+    {
+        let registration = self.dcgm.policy_register(group, conditions, callback)?;
+        self.policies.push(registration);
+        Ok(())
+    }
+
+    /// Tears the session down now: policies are unregistered, then watches, field groups and groups are
+    /// removed, newest first. Every step is attempted; the errors are returned together.
+    pub fn close(mut self) -> Result<(), DCGMError> {
+        let errors = self.teardown();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DCGMError::from(format!("Session teardown failed: {}", errors.join("; "))))
+        }
+    }
+
+    fn teardown(&mut self) -> Vec<String> {
+        self.closed = true;
+        let mut errors = Vec::new();
+        for policy in self.policies.drain(..).rev() {
+            match policy.unregister() {
+                Err(e) if e.kind != DCGMErrorKind::Disconnected => errors.push(format!("unregister policy: {e}")),
+                _ => (),
+            }
+        }
+        // the hostengine dropped the rest along with the connection
+        if self.dcgm.is_disconnected() {
+            return errors;
+        }
+        for (group, field_group) in self.watches.drain(..).rev() {
+            let Ok(lib) = self.dcgm.lib() else { break };
+            match unsafe{lib.dcgmUnwatchFields(self.dcgm.handle(), group, field_group)}{
+                dcgmReturn_enum_DCGM_ST_OK => self.dcgm.resources().unwatched(group, field_group),
+                err_code => errors.push(format!("unwatch field group {field_group} on group {group}: {}",
+                                                self.dcgm.get_error_msg(err_code))),
+            }
+        }
+        for field_group in self.field_groups.drain(..).rev() {
+            if let Err(e) = self.dcgm.fieldGroupDestroy(field_group) {
+                errors.push(format!("destroy field group {field_group}: {e}"));
+            }
+        }
+        for group in self.groups.drain(..).rev() {
+            if let Err(e) = self.dcgm.destroyGroup(group) {
+                errors.push(format!("destroy group {group}: {e}"));
+            }
+        }
+        errors
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let errors = self.teardown();
+        if !errors.is_empty() {
+            tracing::warn!("Session teardown failed: {}", errors.join("; "));
+        }
+    }
+}
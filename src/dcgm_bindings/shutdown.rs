@@ -0,0 +1,504 @@
+use super::bindings::*;
+use super::entity::Entity;
+use super::{DCGMError, DcgmLibSafe, Mode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Budget of a graceful shutdown when the caller has no better number, e.g. the daemon on exit.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Flush = Box<dyn FnOnce() -> Result<(), DCGMError> + Send>;
+
+/// Background threads of one consumer of a connection (a scheduler, an entity subscription), stopped
+/// by `shutdown_graceful` before the connection goes away.
+pub(crate) struct Task {
+    name: &'static str,
+    pub(crate) stop: Arc<AtomicBool>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Task {
+    pub(crate) fn new(name: &'static str) -> Arc<Self> {
+        Arc::new(Self { name, stop: Arc::new(AtomicBool::new(false)), threads: Mutex::new(Vec::new()) })
+    }
+
+    pub(crate) fn add(&self, thread: JoinHandle<()>) {
+        self.threads().push(thread);
+    }
+
+    /// Asks the threads to stop and waits for all of them.
+    pub(crate) fn join(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let threads: Vec<_> = self.threads().drain(..).collect();
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+
+    /// `join` that gives up at `deadline`; false when some thread was still running then.
+    fn join_until(&self, deadline: Instant) -> bool {
+        self.stop.store(true, Ordering::Relaxed);
+        loop {
+            if self.threads().iter().all(|t| t.is_finished()) {
+                self.join();
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn threads(&self) -> MutexGuard<'_, Vec<JoinHandle<()>>> {
+        self.threads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A GPU group created through a connection, with what it takes to create it again.
+#[derive(Clone, Debug)]
+pub(crate) struct GroupRecord {
+    pub(crate) name: String,
+    pub(crate) entities: Vec<Entity>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct FieldGroupRecord {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<u16>,
+}
+
+/// `watchFields` arguments.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WatchRecord {
+    pub(crate) update_freq: i64,
+    pub(crate) max_keep_age: f64,
+    pub(crate) max_keep_samples: i32,
+}
+
+/// What a connection created, shared by every `share()` of it: `shutdown_graceful` cleans it up and a
+/// hostengine restart (see `hostengine`) creates it again.
+#[derive(Default)]
+pub(crate) struct Resources {
+    pub(crate) groups: BTreeMap<dcgmGpuGrp_t, GroupRecord>,
+    pub(crate) field_groups: BTreeMap<dcgmFieldGrp_t, FieldGroupRecord>,
+    /// Keyed by (GPU group, field group) as passed to `watchFields`.
+    pub(crate) watches: BTreeMap<(dcgmGpuGrp_t, dcgmFieldGrp_t), WatchRecord>,
+    tasks: Vec<Weak<Task>>,
+    flushes: Vec<(String, Flush)>,
+}
+
+impl Resources {
+    pub(crate) fn group_created(&mut self, group: dcgmGpuGrp_t, name: &str) {
+        self.groups.insert(group, GroupRecord { name: name.to_string(), entities: Vec::new() });
+    }
+
+    pub(crate) fn entity_added(&mut self, group: dcgmGpuGrp_t, entity: Entity) {
+        if let Some(record) = self.groups.get_mut(&group) {
+            if !record.entities.contains(&entity) {
+                record.entities.push(entity);
+            }
+        }
+    }
+
+    pub(crate) fn entity_removed(&mut self, group: dcgmGpuGrp_t, entity: Entity) {
+        if let Some(record) = self.groups.get_mut(&group) {
+            record.entities.retain(|e| *e != entity);
+        }
+    }
+
+    pub(crate) fn group_destroyed(&mut self, group: dcgmGpuGrp_t) {
+        self.groups.remove(&group);
+        self.watches.retain(|&(g, _), _| g != group);
+    }
+
+    pub(crate) fn field_group_created(&mut self, field_group: dcgmFieldGrp_t, name: &str, fields: &[u16]) {
+        self.field_groups.insert(field_group, FieldGroupRecord { name: name.to_string(), fields: fields.to_vec() });
+    }
+
+    pub(crate) fn field_group_destroyed(&mut self, field_group: dcgmFieldGrp_t) {
+        self.field_groups.remove(&field_group);
+        self.watches.retain(|&(_, f), _| f != field_group);
+    }
+
+    pub(crate) fn watched(&mut self, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t, watch: WatchRecord) {
+        self.watches.insert((group, field_group), watch);
+    }
+
+    pub(crate) fn unwatched(&mut self, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t) {
+        self.watches.remove(&(group, field_group));
+    }
+}
+
+/// A group a connection created, as listed in a `RemoteState`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteGroup {
+    pub id: dcgmGpuGrp_t,
+    pub name: String,
+    pub entities: Vec<Entity>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteFieldGroup {
+    pub id: dcgmFieldGrp_t,
+    pub name: String,
+    pub fields: Vec<u16>,
+}
+
+/// `watchFields` of `field_group` on `group`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteWatch {
+    pub group: dcgmGpuGrp_t,
+    pub field_group: dcgmFieldGrp_t,
+    /// usec.
+    pub update_freq: i64,
+    /// Seconds.
+    pub max_keep_age: f64,
+    pub max_keep_samples: i32,
+}
+
+/// The server-side state a connection created and tracks: its groups, field groups and watches. On
+/// disconnect the hostengine drops them, unless the connection was made with
+/// `persistAfterDisconnect`; then they stay until someone removes them. Health watches and policy
+/// registrations are not tracked and follow the same rule. Serializable, so that a process can pass
+/// what it keeps to its successor, which takes it over with `adopt`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RemoteState {
+    /// Whether the hostengine keeps this state when the connection goes away.
+    pub persist_after_disconnect: bool,
+    pub groups: Vec<RemoteGroup>,
+    pub field_groups: Vec<RemoteFieldGroup>,
+    pub watches: Vec<RemoteWatch>,
+}
+
+impl RemoteState {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.field_groups.is_empty() && self.watches.is_empty()
+    }
+}
+
+/// What `shutdown_graceful` did. Nothing in here stopped the disconnect.
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    pub tasks_stopped: usize,
+    /// Tasks whose threads were still running at the deadline; they are left detached.
+    pub tasks_abandoned: Vec<String>,
+    pub flushed: usize,
+    pub unwatched: usize,
+    pub groups_destroyed: usize,
+    /// The deadline passed and the remaining steps were skipped; the hostengine drops what is left of
+    /// this connection's watches and groups when it disconnects.
+    pub timed_out: bool,
+    /// Steps that failed, in order.
+    pub errors: Vec<String>,
+    /// What was left on the hostengine for a successor by `shutdown_with(true, ..)`.
+    pub kept: Option<RemoteState>,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.tasks_abandoned.is_empty() && !self.timed_out && self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} task(s) stopped, {} flushed, {} watch(es) removed, {} group(s) destroyed",
+               self.tasks_stopped, self.flushed, self.unwatched, self.groups_destroyed)?;
+        if !self.tasks_abandoned.is_empty() {
+            write!(f, "; abandoned: {}", self.tasks_abandoned.join(", "))?;
+        }
+        if let Some(kept) = &self.kept {
+            write!(f, "; kept {} group(s), {} field group(s), {} watch(es)", kept.groups.len(), kept.field_groups.len(), kept.watches.len())?;
+        }
+        if self.timed_out {
+            write!(f, "; timed out")?;
+        }
+        if !self.errors.is_empty() {
+            write!(f, "; errors: {}", self.errors.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+impl DcgmLibSafe {
+    /// Runs `flush` during `shutdown_graceful`, after the background tasks stopped and before any watch
+    /// is removed, e.g. to push the last samples out of a sink. Flushes run in registration order.
+    pub fn on_shutdown<F>(&self, name: &str, flush: F)
+        where F: FnOnce() -> Result<(), DCGMError> + Send + 'static {
+        self.resources().flushes.push((name.to_string(), Box::new(flush)));
+    }
+
+    /// Tears the connection down in order: stops the schedulers and entity subscriptions started on it,
+    /// runs the `on_shutdown` flushes, removes the watches and the groups it created, then disconnects.
+    ///
+    /// Every step up to the disconnect is best effort and bounded by `timeout`: once it has passed the
+    /// remaining steps are skipped and recorded in the report. Only a failed disconnect is an error.
+    /// Like `shutdown`, call this on the original connection, not on a `share()` of it.
+    pub fn shutdown_graceful(&mut self, timeout: Duration) -> Result<ShutdownReport, DCGMError> {
+        self.shutdown_with(false, timeout)
+    }
+
+    /// `shutdown_graceful` that decides explicitly what happens to the groups, field groups and
+    /// watches of the connection. Without `keep_watches` they are removed, whatever the connection was
+    /// made with. With it they are left on the hostengine and listed in `ShutdownReport::kept`, for a
+    /// successor to `adopt`; background tasks are stopped and flushes run either way.
+    ///
+    /// Keeping fails up front, with nothing changed, unless `can_keep_watches` allows it.
+    pub fn shutdown_with(&mut self, keep_watches: bool, timeout: Duration) -> Result<ShutdownReport, DCGMError> {
+        self.lib()?;
+        if keep_watches {
+            self.can_keep_watches()?;
+        }
+        let deadline = Instant::now() + timeout;
+        let mut report = ShutdownReport::default();
+        let (tasks, flushes) = {
+            let mut resources = self.resources();
+            (std::mem::take(&mut resources.tasks), std::mem::take(&mut resources.flushes))
+        };
+
+        for task in tasks.iter().filter_map(Weak::upgrade) {
+            if task.join_until(deadline) {
+                report.tasks_stopped += 1;
+            } else {
+                tracing::warn!("{} did not stop in time, leaving it behind", task.name);
+                report.tasks_abandoned.push(task.name.to_string());
+            }
+        }
+
+        run_flushes(flushes, deadline, &mut report);
+
+        if keep_watches {
+            report.kept = Some(self.remote_state());
+            self.shutdown()?;
+            return Ok(report);
+        }
+
+        let (watches, groups, field_groups) = {
+            let mut resources = self.resources();
+            let watches = std::mem::take(&mut resources.watches);
+            (watches, std::mem::take(&mut resources.groups), std::mem::take(&mut resources.field_groups))
+        };
+        for (group, field_group) in watches.into_keys() {
+            if past(deadline, &mut report) {
+                break;
+            }
+            match unsafe{self.lib()?.dcgmUnwatchFields(self.handle(), group, field_group)}{
+                dcgmReturn_enum_DCGM_ST_OK => report.unwatched += 1,
+                err_code => report.errors.push(format!("unwatch group {group}: {}", self.get_error_msg(err_code))),
+            }
+        }
+        for field_group in field_groups.into_keys() {
+            if past(deadline, &mut report) {
+                break;
+            }
+            if let Err(e) = self.fieldGroupDestroy(field_group) {
+                report.errors.push(format!("destroy field group {field_group}: {e}"));
+            }
+        }
+        for group in groups.into_keys() {
+            if past(deadline, &mut report) {
+                break;
+            }
+            match self.destroyGroup(group) {
+                Ok(()) => report.groups_destroyed += 1,
+                Err(e) => report.errors.push(format!("destroy group {group}: {e}")),
+            }
+        }
+
+        self.shutdown()?;
+        Ok(report)
+    }
+
+    /// Whether the hostengine keeps this connection's state after it disconnects: only a standalone
+    /// hostengine this process did not start, connected to with `persistAfterDisconnect`.
+    pub fn persists_after_disconnect(&self) -> bool {
+        matches!(self.stop_mode, Mode::Standalone)
+            && self.target.lock().unwrap_or_else(|e| e.into_inner()).as_ref().is_some_and(|t| t.persist != 0)
+    }
+
+    /// Ok when `shutdown_with(true, ..)` can leave the state to a successor; otherwise the reason the
+    /// hostengine would drop it anyway.
+    pub fn can_keep_watches(&self) -> Result<(), DCGMError> {
+        match self.stop_mode {
+            Mode::Embedded => Err(DCGMError::not_supported("An embedded hostengine stops with this process; its watches cannot be handed off")),
+            Mode::StartHostengine => Err(DCGMError::not_supported("The hostengine this process started is stopped on disconnect; its watches cannot be handed off")),
+            Mode::Standalone if !self.persists_after_disconnect() => Err(DCGMError::not_supported(
+                "The connection was made without persistAfterDisconnect, so the hostengine drops its watches on disconnect")),
+            Mode::Standalone => Ok(()),
+        }
+    }
+
+    /// The groups, field groups and watches this connection created and has not removed, i.e. what
+    /// `shutdown_with` removes or keeps.
+    pub fn remote_state(&self) -> RemoteState {
+        let resources = self.resources();
+        RemoteState {
+            persist_after_disconnect: self.persists_after_disconnect(),
+            groups: resources.groups.iter()
+                .map(|(&id, g)| RemoteGroup { id, name: g.name.clone(), entities: g.entities.clone() })
+                .collect(),
+            field_groups: resources.field_groups.iter()
+                .map(|(&id, f)| RemoteFieldGroup { id, name: f.name.clone(), fields: f.fields.clone() })
+                .collect(),
+            watches: resources.watches.iter()
+                .map(|(&(group, field_group), w)| RemoteWatch {
+                    group, field_group, update_freq: w.update_freq, max_keep_age: w.max_keep_age, max_keep_samples: w.max_keep_samples,
+                })
+                .collect(),
+        }
+    }
+
+    /// Takes over state a predecessor kept with `shutdown_with(true, ..)`: from now on this connection
+    /// removes or keeps it on shutdown as if it had created it. The groups and field groups, including
+    /// those the watches name, are checked to still exist; whether the watches are still active is not.
+    /// The successor should connect with `persistAfterDisconnect` as well, since the hostengine only
+    /// lets it remove watches that persisted that way.
+    pub fn adopt(&mut self, state: &RemoteState) -> Result<(), DCGMError> {
+        let groups: BTreeSet<_> = state.groups.iter().map(|g| g.id).chain(state.watches.iter().map(|w| w.group)).collect();
+        for group in groups {
+            self.getGroupEntities(group).map_err(|e| DCGMError::from(format!("group {group} is gone: {e}")))?;
+        }
+        let field_groups: BTreeSet<_> = state.field_groups.iter().map(|g| g.id)
+            .chain(state.watches.iter().map(|w| w.field_group)).collect();
+        for field_group in field_groups {
+            self.getFieldGroupFields(field_group).map_err(|e| DCGMError::from(format!("field group {field_group} is gone: {e}")))?;
+        }
+        let mut resources = self.resources();
+        for group in &state.groups {
+            resources.groups.insert(group.id, GroupRecord { name: group.name.clone(), entities: group.entities.clone() });
+        }
+        for field_group in &state.field_groups {
+            resources.field_groups.insert(field_group.id, FieldGroupRecord { name: field_group.name.clone(), fields: field_group.fields.clone() });
+        }
+        for watch in &state.watches {
+            resources.watches.insert((watch.group, watch.field_group), WatchRecord {
+                update_freq: watch.update_freq, max_keep_age: watch.max_keep_age, max_keep_samples: watch.max_keep_samples,
+            });
+        }
+        Ok(())
+    }
+
+    /// `shutdown_graceful` on a thread of its own, for async callers. The future does not depend on a
+    /// particular runtime.
+    pub fn shutdown_graceful_async(mut self, timeout: Duration) -> ShutdownFuture {
+        ThreadFuture::spawn("dcgm-shutdown", move || self.shutdown_graceful(timeout))
+    }
+
+    /// Makes `task` part of this connection's graceful shutdown. Tasks that were dropped are forgotten.
+    pub(crate) fn register_task(&self, task: &Arc<Task>) {
+        let mut resources = self.resources();
+        resources.tasks.retain(|t| t.strong_count() > 0);
+        resources.tasks.push(Arc::downgrade(task));
+    }
+
+    pub(crate) fn resources(&self) -> MutexGuard<'_, Resources> {
+        self.resources.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Runs the flushes on a thread so a stuck sink cannot hold the shutdown past `deadline`.
+fn run_flushes(flushes: Vec<(String, Flush)>, deadline: Instant, report: &mut ShutdownReport) {
+    if flushes.is_empty() {
+        return;
+    }
+    let total = flushes.len();
+    let (tx, rx) = mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("dcgm-shutdown-flush".into())
+        .spawn(move || {
+            for (name, flush) in flushes {
+                if tx.send((name, flush())).is_err() {
+                    return;
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        report.errors.push(format!("Failed to spawn flush thread: {e}"));
+        return;
+    }
+    for _ in 0..total {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((_, Ok(()))) => report.flushed += 1,
+            Ok((name, Err(e))) => report.errors.push(format!("flush {name}: {e}")),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                report.timed_out = true;
+                return;
+            }
+            // a flush panicked; the ones after it never ran
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                report.errors.push("a flush panicked".to_string());
+                return;
+            }
+        }
+    }
+}
+
+fn past(deadline: Instant, report: &mut ShutdownReport) -> bool {
+    if Instant::now() >= deadline {
+        report.timed_out = true;
+    }
+    report.timed_out
+}
+
+struct FutureState<T> {
+    result: Option<Result<T, DCGMError>>,
+    waker: Option<Waker>,
+}
+
+/// Completes with the result of a blocking call run on a thread of its own, for async callers. Does
+/// not depend on a particular runtime.
+pub struct ThreadFuture<T> {
+    state: Arc<Mutex<FutureState<T>>>,
+}
+
+/// Completes when the `shutdown_graceful_async` thread is done.
+pub type ShutdownFuture = ThreadFuture<ShutdownReport>;
+
+impl<T: Send + 'static> ThreadFuture<T> {
+    /// Runs `f` on a new thread named `name`; failing to spawn it completes the future with that error.
+    pub(crate) fn spawn<F>(name: &str, f: F) -> Self
+        where F: FnOnce() -> Result<T, DCGMError> + Send + 'static {
+        let state = Arc::new(Mutex::new(FutureState { result: None, waker: None }));
+        let shared = state.clone();
+        let spawned = std::thread::Builder::new()
+            .name(name.into())
+            .spawn(move || {
+                let result = f();
+                let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+        if let Err(e) = spawned {
+            state.lock().unwrap_or_else(|e| e.into_inner()).result =
+                Some(Err(DCGMError::from(format!("Failed to spawn {name} thread: {e}"))));
+        }
+        ThreadFuture { state }
+    }
+}
+
+impl<T> Future for ThreadFuture<T> {
+    type Output = Result<T, DCGMError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
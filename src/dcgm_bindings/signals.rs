@@ -0,0 +1,125 @@
+use super::bindings::*;
+use super::{DCGMError, DcgmLibSafe, Mode, DCGM_LIB};
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Connection torn down when SIGINT/SIGTERM arrives, if any. The handle is read when the signal
+/// arrives, so a reconnect after registration is followed.
+static REGISTERED: Mutex<Option<(Arc<AtomicUsize>, Mode)>> = Mutex::new(None);
+/// Write end of the self-pipe, -1 until `install_signal_cleanup` has run.
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+/// Set by SIGHUP once `install_reload_handler` has run, cleared by `take_reload_request`.
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(sig: c_int) {
+    // Only async-signal-safe work here; the cleanup thread does the rest.
+    let fd = PIPE_WRITE.load(Ordering::Relaxed);
+    let byte = sig as u8;
+    unsafe { libc::write(fd, &byte as *const u8 as *const c_void, 1) };
+}
+
+extern "C" fn on_reload(_sig: c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+fn cleanup_thread(read_fd: c_int) {
+    let mut byte: u8 = 0;
+    loop {
+        let n = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut c_void, 1) };
+        if n == 1 {
+            break;
+        }
+        if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        return;
+    }
+    let sig = byte as c_int;
+    let registered = REGISTERED.lock().map(|mut r| r.take()).unwrap_or(None);
+    if let (Some((handle, mode)), Ok(dcgm)) = (registered, &*DCGM_LIB) {
+        let handle: dcgmHandle_t = handle.load(Ordering::Relaxed);
+        tracing::info!("Signal {sig} received, shutting down DCGM");
+        unsafe {
+            match mode {
+                Mode::Embedded => { dcgm.dcgmStopEmbedded(handle); }
+                // the hostengine child gets SIGTERM through PR_SET_PDEATHSIG
+                Mode::Standalone | Mode::StartHostengine => { dcgm.dcgmDisconnect(handle); }
+            }
+            dcgm.dcgmShutdown();
+        }
+    }
+    // Let the default disposition terminate the process with the original signal.
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
+    }
+}
+
+fn install_handlers() -> Result<(), DCGMError> {
+    let mut fds: [c_int; 2] = [-1, -1];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(DCGMError::from(format!("Failed to create signal pipe: {}", std::io::Error::last_os_error())));
+    }
+    PIPE_WRITE.store(fds[1], Ordering::Relaxed);
+    let read_fd = fds[0];
+    std::thread::Builder::new()
+        .name("dcgm-signal-cleanup".into())
+        .spawn(move || cleanup_thread(read_fd))
+        .map_err(|e| DCGMError::from(format!("Failed to spawn signal cleanup thread: {e}")))?;
+
+    for sig in [libc::SIGINT, libc::SIGTERM] {
+        set_handler(sig, on_signal)?;
+    }
+    Ok(())
+}
+
+fn set_handler(sig: c_int, handler: extern "C" fn(c_int)) -> Result<(), DCGMError> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(sig, &action, std::ptr::null_mut()) != 0 {
+            return Err(DCGMError::from(format!("Failed to install handler for signal {sig}: {}",
+                                               std::io::Error::last_os_error())));
+        }
+    }
+    Ok(())
+}
+
+/// Makes SIGHUP request a configuration reload instead of terminating the process. Poll with
+/// `take_reload_request`.
+pub fn install_reload_handler() -> Result<(), DCGMError> {
+    set_handler(libc::SIGHUP, on_reload)
+}
+
+/// Whether SIGHUP arrived since the last call.
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Drops the registration for the connection sharing `handle` so a later signal does not shut it
+/// down twice.
+pub(crate) fn forget(handle: &Arc<AtomicUsize>) {
+    if let Ok(mut registered) = REGISTERED.lock() {
+        if matches!(&*registered, Some((h, _)) if Arc::ptr_eq(h, handle)) {
+            *registered = None;
+        }
+    }
+}
+
+impl DcgmLibSafe {
+    /// Installs SIGINT/SIGTERM handlers that stop the embedded hostengine or disconnect from the
+    /// standalone one before the process exits, so DCGM does not keep our watches around.
+    /// Only the most recently registered connection is cleaned up.
+    pub fn install_signal_cleanup(&self) -> Result<(), DCGMError>{
+        let mut registered = REGISTERED.lock()
+            .map_err(|_| DCGMError::from("Signal cleanup registry is poisoned"))?;
+        if PIPE_WRITE.load(Ordering::Relaxed) < 0 {
+            install_handlers()?;
+        }
+        *registered = Some((self.handle.clone(), self.stop_mode));
+        Ok(())
+    }
+}
@@ -0,0 +1,880 @@
+//! A scripted DCGM for integration tests without GPUs (the `simulation` feature). A `Scenario` declares
+//! the GPUs and timelines of field values, health incidents and connection outages; a `Simulation`
+//! replays it behind the regular client API, on a clock that only moves when the test moves it:
+//!
+//! ```no_run
+//! # use rust_dcgm::dcgm_bindings::DCGMError;
+//! # use rust_dcgm::dcgm_bindings::bindings::*;
+//! # use rust_dcgm::dcgm_bindings::client::{Client, ConnectOptions};
+//! # use rust_dcgm::dcgm_bindings::entity::Entity;
+//! # use rust_dcgm::dcgm_bindings::samples::FieldValue;
+//! # use rust_dcgm::dcgm_bindings::simulation::{Scenario, Simulation};
+//! # use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+//! # use std::time::Duration;
+//! # fn f() -> Result<(), DCGMError> {
+//! let temp = DCGM_FI_DEV_GPU_TEMP as u16;
+//! let simulation = Simulation::new(Scenario::new()
+//!     .with_gpus(1)
+//!     .with_value(Duration::ZERO, Entity::gpu(0), temp, FieldValue::Int64(40))
+//!     .with_value(Duration::from_secs(30), Entity::gpu(0), temp, FieldValue::Int64(95))
+//!     .with_outage(Duration::from_secs(60), Duration::from_secs(10)));
+//! let mut dcgm = Client::new().connect(&ConnectOptions::Simulated(simulation.clone()))?;
+//! let watch = dcgm.watch_all_gpus(&[temp], &WatchOptions::default())?;
+//! simulation.advance(Duration::from_secs(30));
+//! assert_eq!(dcgm.watch_values(&watch)?[0].value, FieldValue::Int64(95));
+//! # Ok(()) }
+//! ```
+//!
+//! Modelled are GPU enumeration and attributes, groups, field groups, watches, latest values,
+//! `values_since`, health watches and health checks. NvLink status and P2P topology are not supported
+//! and the MIG hierarchy is empty. Other DCGM calls are not modelled and fail with
+//! `DCGM_ST_NOT_SUPPORTED`.
+use super::bindings::*;
+use super::entity::{Entity, EntityGroup};
+use super::health::{HealthResult, HealthSystems};
+use super::init::Versioned;
+use super::samples::FieldValue;
+use super::testing::{field_type_of, field_value_v1, field_value_v2};
+use super::{copy_str, DCGMError, DcgmLib, DcgmLibSafe, Mode};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{c_char, c_int, c_uint, c_ushort, c_void, CStr};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Where sample timestamps of a scenario start unless set (2023-11-14 22:13:20 UTC), in usec since
+/// the epoch, so that runs are reproducible.
+pub const DEFAULT_EPOCH_US: i64 = 1_700_000_000_000_000;
+
+const BUILD_INFO: &str = "version:4.0.0;arch:x86_64;buildtype:Simulation";
+
+lazy_static::lazy_static! {
+    static ref LIB: Result<DcgmLib, DCGMError> = simulated_lib();
+    /// Simulations by the handles of their connections.
+    static ref SIMULATIONS: Mutex<HashMap<dcgmHandle_t, Simulation>> = Mutex::new(HashMap::new());
+}
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+/// Identity of a simulated GPU, as `dcgmGetDeviceAttributes` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedGpu {
+    pub name: String,
+    pub uuid: String,
+    pub pci_bus_id: String,
+    pub serial: String,
+}
+
+impl SimulatedGpu {
+    /// The identity `Scenario::with_gpus` gives GPU `id`.
+    pub fn numbered(id: u32) -> Self {
+        Self {
+            name: "NVIDIA Simulated GPU".to_string(),
+            uuid: format!("GPU-00000000-0000-0000-0000-{id:012x}"),
+            pci_bus_id: format!("00000000:{:02X}:00.0", id + 1),
+            serial: format!("{id:013}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct HealthEvent {
+    at: Duration,
+    entity: Entity,
+    systems: HealthSystems,
+    result: HealthResult,
+    message: String,
+}
+
+/// What a simulated hostengine reports over time. Times are offsets from the start of the scenario.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    gpus: Vec<SimulatedGpu>,
+    values: BTreeMap<(Entity, u16), Vec<(Duration, FieldValue)>>,
+    health: Vec<HealthEvent>,
+    outages: Vec<(Duration, Duration)>,
+    epoch_us: i64,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scenario {
+    /// No GPUs, values, incidents or outages.
+    pub fn new() -> Self {
+        Self { gpus: Vec::new(), values: BTreeMap::new(), health: Vec::new(), outages: Vec::new(),
+               epoch_us: DEFAULT_EPOCH_US }
+    }
+
+    /// Adds `count` GPUs with the identities of `SimulatedGpu::numbered`.
+    pub fn with_gpus(mut self, count: u32) -> Self {
+        for _ in 0..count {
+            let id = self.gpus.len() as u32;
+            self.gpus.push(SimulatedGpu::numbered(id));
+        }
+        self
+    }
+
+    /// Adds one GPU; its id is the number of GPUs before it.
+    pub fn with_gpu(mut self, gpu: SimulatedGpu) -> Self {
+        self.gpus.push(gpu);
+        self
+    }
+
+    /// `field` of `entity` reads `value` from `at` on, until its next scripted value.
+    pub fn with_value(mut self, at: Duration, entity: Entity, field: u16, value: FieldValue) -> Self {
+        let timeline = self.values.entry((entity, field)).or_default();
+        let i = timeline.partition_point(|(t, _)| *t <= at);
+        timeline.insert(i, (at, value));
+        self
+    }
+
+    /// `values` one `interval` apart, the first at `start`.
+    pub fn with_series(mut self, entity: Entity, field: u16, start: Duration, interval: Duration,
+                       values: impl IntoIterator<Item = FieldValue>) -> Self {
+        for (i, value) in values.into_iter().enumerate() {
+            self = self.with_value(start + interval * i as u32, entity, field, value);
+        }
+        self
+    }
+
+    /// From `at` on, health checks watching any of `systems` report `result` for `entity` with
+    /// `message`, until a later event for the same system; `HealthResult::Pass` clears the incident.
+    pub fn with_health(mut self, at: Duration, entity: Entity, systems: HealthSystems, result: HealthResult,
+                       message: &str) -> Self {
+        self.health.push(HealthEvent { at, entity, systems, result, message: message.to_string() });
+        self.health.sort_by_key(|e| e.at);
+        self
+    }
+
+    /// Every call fails with `DCGM_ST_CONNECTION_NOT_VALID` from `at` for `duration`, as while the
+    /// hostengine is unreachable. Groups and watches are still there afterwards.
+    pub fn with_outage(mut self, at: Duration, duration: Duration) -> Self {
+        self.outages.push((at, duration));
+        self
+    }
+
+    /// Sample timestamps are `epoch_us` plus the scripted offset.
+    pub fn with_epoch(mut self, epoch_us: i64) -> Self {
+        self.epoch_us = epoch_us;
+        self
+    }
+
+    /// Every time something scripted changes, in order.
+    pub fn change_times(&self) -> Vec<Duration> {
+        let mut times: Vec<Duration> = self.values.values().flat_map(|t| t.iter().map(|(at, _)| *at))
+            .chain(self.health.iter().map(|e| e.at))
+            .chain(self.outages.iter().flat_map(|(at, duration)| [*at, *at + *duration]))
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+        times
+    }
+
+    fn value_at(&self, entity: Entity, field: u16, now: Duration) -> Option<&(Duration, FieldValue)> {
+        let timeline = self.values.get(&(entity, field))?;
+        timeline[..timeline.partition_point(|(t, _)| *t <= now)].last()
+    }
+
+    /// The type of the first non-blank value of the timeline; blank-only fields read as int64.
+    fn field_type(&self, entity: Entity, field: u16) -> u8 {
+        self.values.get(&(entity, field))
+            .and_then(|timeline| timeline.iter().find_map(|(_, v)| field_type_of(v)))
+            .unwrap_or(DCGM_FT_INT64)
+    }
+}
+
+/// Groups, field groups and watches made on a simulated hostengine.
+#[derive(Debug, Default)]
+struct Host {
+    last_id: usize,
+    groups: HashMap<dcgmGpuGrp_t, (String, Vec<Entity>)>,
+    field_groups: HashMap<dcgmFieldGrp_t, (String, Vec<u16>)>,
+    watches: HashSet<(dcgmGpuGrp_t, dcgmFieldGrp_t)>,
+    health: HashMap<dcgmGpuGrp_t, HealthSystems>,
+}
+
+impl Host {
+    fn next_id(&mut self) -> usize {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    scenario: Scenario,
+    now_us: AtomicI64,
+    host: Mutex<Host>,
+}
+
+/// A hostengine replaying a `Scenario`. Clones share the clock and the hostengine state.
+#[derive(Clone, Debug)]
+pub struct Simulation(Arc<Shared>);
+
+impl Simulation {
+    /// Starts the scenario; the clock stands at zero.
+    pub fn new(scenario: Scenario) -> Self {
+        Self(Arc::new(Shared { scenario, now_us: AtomicI64::new(0), host: Mutex::default() }))
+    }
+
+    pub fn scenario(&self) -> &Scenario {
+        &self.0.scenario
+    }
+
+    /// Time since the scenario started.
+    pub fn now(&self) -> Duration {
+        Duration::from_micros(self.0.now_us.load(Ordering::SeqCst) as u64)
+    }
+
+    /// `now` as a sample timestamp in usec since the epoch, e.g. for `LatestSamples::current`.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp_of(self.now())
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.now_us.fetch_add(by.as_micros() as i64, Ordering::SeqCst);
+    }
+
+    /// Moves the clock to `at`; it never goes back.
+    pub fn advance_to(&self, at: Duration) {
+        self.0.now_us.fetch_max(at.as_micros() as i64, Ordering::SeqCst);
+    }
+
+    /// Moves the clock to the next scripted change and returns its time; None after the last one.
+    pub fn step(&self) -> Option<Duration> {
+        let now = self.now();
+        let next = self.0.scenario.change_times().into_iter().find(|t| *t > now)?;
+        self.advance_to(next);
+        Some(next)
+    }
+
+    /// A connection to the simulated hostengine. Connections to the same simulation see the same
+    /// groups and watches; shut them down like any other.
+    pub fn connect(&self) -> Result<DcgmLibSafe, DCGMError> {
+        let lib = LIB.as_ref().map_err(Clone::clone)?;
+        let dcgm = DcgmLibSafe::with_lib(lib, Mode::Embedded)?;
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        registry().insert(handle, self.clone());
+        dcgm.handle.store(handle, Ordering::Relaxed);
+        Ok(dcgm)
+    }
+
+    fn timestamp_of(&self, at: Duration) -> i64 {
+        self.0.scenario.epoch_us + at.as_micros() as i64
+    }
+
+    fn reachable(&self) -> bool {
+        let now = self.now();
+        !self.0.scenario.outages.iter().any(|(at, duration)| now >= *at && now < *at + *duration)
+    }
+
+    fn host(&self) -> MutexGuard<'_, Host> {
+        self.0.host.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn gpus(&self) -> Vec<Entity> {
+        (0..self.0.scenario.gpus.len() as u32).map(Entity::gpu).collect()
+    }
+
+    fn group_entities(&self, host: &Host, group: dcgmGpuGrp_t) -> Option<Vec<Entity>> {
+        if group == DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t {
+            return Some(self.gpus());
+        }
+        host.groups.get(&group).map(|(_, entities)| entities.clone())
+    }
+
+    fn watched(&self, host: &Host, entity: Entity, field: u16) -> bool {
+        host.watches.iter().any(|(group, field_group)| {
+            host.field_groups.get(field_group).is_some_and(|(_, fields)| fields.contains(&field))
+                && self.group_entities(host, *group).is_some_and(|entities| entities.contains(&entity))
+        })
+    }
+
+    /// The current value of `field` on `pair` with its type and timestamp, or the status DCGM puts
+    /// in place of a value.
+    fn lookup(&self, host: &Host, pair: dcgmGroupEntityPair_t, field: u16, live: bool)
+              -> Result<(Entity, u8, i64, &FieldValue), dcgmReturn_t> {
+        let entity = Entity::try_from(pair).map_err(|_| dcgmReturn_enum_DCGM_ST_BADPARAM)?;
+        if !live && !self.watched(host, entity, field) {
+            return Err(dcgmReturn_enum_DCGM_ST_NOT_WATCHED);
+        }
+        let (at, value) = self.0.scenario.value_at(entity, field, self.now()).ok_or(dcgmReturn_enum_DCGM_ST_NO_DATA)?;
+        Ok((entity, self.0.scenario.field_type(entity, field), self.timestamp_of(*at), value))
+    }
+
+    /// Open incidents of `entities` in `systems`, in entity and system order.
+    fn incidents(&self, entities: &[Entity], systems: HealthSystems) -> BTreeMap<(Entity, u32), (HealthResult, &str)> {
+        let now = self.now();
+        let mut open = BTreeMap::new();
+        for event in self.0.scenario.health.iter().filter(|e| e.at <= now && entities.contains(&e.entity)) {
+            for system in (event.systems & systems).iter() {
+                if event.result == HealthResult::Pass {
+                    open.remove(&(event.entity, system.bits()));
+                } else {
+                    open.insert((event.entity, system.bits()), (event.result, event.message.as_str()));
+                }
+            }
+        }
+        open
+    }
+}
+
+fn registry() -> MutexGuard<'static, HashMap<dcgmHandle_t, Simulation>> {
+    SIMULATIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `f` on the simulation behind `handle`, or fails as DCGM does on a lost connection.
+fn on(handle: dcgmHandle_t, f: impl FnOnce(&Simulation) -> dcgmReturn_t) -> dcgmReturn_t {
+    let simulation = registry().get(&handle).cloned();
+    match simulation {
+        Some(simulation) if simulation.reachable() => f(&simulation),
+        _ => dcgmReturn_enum_DCGM_ST_CONNECTION_NOT_VALID,
+    }
+}
+
+/// Function pointer types that can point at a stub failing with `DCGM_ST_NOT_SUPPORTED`.
+trait NotSupported {
+    fn stub() -> Self;
+}
+
+macro_rules! impl_not_supported {
+    ($($arg:ident),*) => {
+        impl<$($arg),*> NotSupported for unsafe extern "C" fn($($arg),*) -> dcgmReturn_t {
+            fn stub() -> Self {
+                unsafe extern "C" fn not_supported<$($arg),*>($(_: $arg),*) -> dcgmReturn_t {
+                    dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED
+                }
+                not_supported::<$($arg),*>
+            }
+        }
+    };
+}
+impl_not_supported!();
+impl_not_supported!(A);
+impl_not_supported!(A, B);
+impl_not_supported!(A, B, C);
+impl_not_supported!(A, B, C, D);
+impl_not_supported!(A, B, C, D, E);
+impl_not_supported!(A, B, C, D, E, F);
+impl_not_supported!(A, B, C, D, E, F, G);
+
+/// Points each of the `calls` of `lib` at a stub failing with `DCGM_ST_NOT_SUPPORTED`.
+macro_rules! not_supported {
+    ($lib:ident, $($call:ident),+) => {
+        $( $lib.$call = Ok(NotSupported::stub()); )+
+    };
+}
+
+/// The function table of libdcgm with the modelled calls pointing here and every other call failing
+/// with `DCGM_ST_NOT_SUPPORTED`, so that no call reaches a libdcgm loaded into this process.
+fn simulated_lib() -> Result<DcgmLib, DCGMError> {
+    let mut lib = unsafe { DcgmLib::from_library(libloading::os::unix::Library::this()) }
+        .map_err(|e| DCGMError::from(format!("Failed to set up the simulated DCGM: {e}")))?;
+    lib.errorString = Ok(error_string);
+    lib.DcgmFieldsInit = Ok(fields_init);
+    lib.DcgmFieldGetById = Ok(field_get_by_id);
+    lib.dcgmInit = Ok(success);
+    lib.dcgmShutdown = Ok(success);
+    lib.dcgmStopEmbedded = Ok(close);
+    lib.dcgmDisconnect = Ok(close);
+    lib.dcgmVersionInfo = Ok(version_info);
+    lib.dcgmHostengineVersionInfo = Ok(hostengine_version_info);
+    lib.dcgmGetEntityGroupEntities = Ok(get_entity_group_entities);
+    lib.dcgmGetDeviceAttributes = Ok(get_device_attributes);
+    lib.dcgmGroupCreate = Ok(group_create);
+    lib.dcgmGroupDestroy = Ok(group_destroy);
+    lib.dcgmGroupAddEntity = Ok(group_add_entity);
+    lib.dcgmGroupRemoveEntity = Ok(group_remove_entity);
+    lib.dcgmGroupGetInfo = Ok(group_get_info);
+    lib.dcgmFieldGroupCreate = Ok(field_group_create);
+    lib.dcgmFieldGroupDestroy = Ok(field_group_destroy);
+    lib.dcgmFieldGroupGetInfo = Ok(field_group_get_info);
+    lib.dcgmWatchFields = Ok(watch_fields);
+    lib.dcgmUnwatchFields = Ok(unwatch_fields);
+    lib.dcgmUpdateAllFields = Ok(update_all_fields);
+    lib.dcgmEntitiesGetLatestValues = Ok(entities_get_latest_values);
+    lib.dcgmEntityGetLatestValues = Ok(entity_get_latest_values);
+    lib.dcgmGetValuesSince_v2 = Ok(get_values_since);
+    lib.dcgmHealthSet_v2 = Ok(health_set);
+    lib.dcgmHealthCheck = Ok(health_check);
+    lib.dcgmGetNvLinkLinkStatus = Ok(get_nvlink_link_status);
+    lib.dcgmGetDeviceTopology = Ok(get_device_topology);
+    lib.dcgmGetGpuInstanceHierarchy = Ok(get_gpu_instance_hierarchy);
+    lib.DcgmFieldGetByTag = Ok(field_get_by_tag);
+    lib.DcgmFieldsTerm = Ok(fields_init);
+    lib.DcgmFieldsGetEntityGroupString = Ok(entity_group_string);
+    lib.dcgmErrorGetPriorityByCode = Ok(error_priority);
+    lib.dcgmErrorGetCategoryByCode = Ok(error_category);
+    lib.dcgmErrorGetFormatMsgByCode = Ok(error_format_msg);
+    lib.dcgmGetErrorMeta = Ok(error_meta);
+    not_supported!(lib,
+        dcgmStartEmbedded, dcgmStartEmbedded_v2, dcgmConnect, dcgmConnect_v2,
+        dcgmHostengineSetLoggingSeverity, dcgmHostengineIsHealthy, dcgmModuleIdToName,
+        dcgmGetAllDevices, dcgmGetAllSupportedDevices, dcgmGetCpuHierarchy,
+        dcgmGetCpuHierarchy_v2, dcgmGroupAddDevice, dcgmGroupRemoveDevice,
+        dcgmGroupGetAllIds, dcgmFieldGroupGetAll, dcgmStatusCreate,
+        dcgmStatusDestroy, dcgmStatusGetCount, dcgmStatusPopError,
+        dcgmStatusClear, dcgmConfigSet, dcgmConfigGet, dcgmConfigEnforce,
+        dcgmGetValuesSince, dcgmGetLatestValues, dcgmGetLatestValues_v2,
+        dcgmGetLatestValuesForFields, dcgmGetFieldSummary, dcgmWatchPidFields,
+        dcgmGetPidInfo, dcgmWatchJobFields, dcgmJobStartStats,
+        dcgmJobStopStats, dcgmJobGetStats, dcgmJobRemove, dcgmJobRemoveAll,
+        dcgmHealthSet, dcgmHealthGet, dcgmPolicySet, dcgmPolicyGet,
+        dcgmPolicyRegister_v2, dcgmPolicyUnregister, dcgmActionValidate,
+        dcgmActionValidate_v2, dcgmRunDiagnostic, dcgmPolicyTrigger,
+        dcgmGetDeviceWorkloadPowerProfileInfo, dcgmGetGroupTopology,
+        dcgmIntrospectGetHostengineMemoryUsage, dcgmIntrospectGetHostengineCpuUtilization,
+        dcgmSelectGpusByTopology, dcgmModuleDenylist, dcgmModuleGetStatuses,
+        dcgmProfGetSupportedMetricGroups, dcgmProfPause, dcgmProfResume,
+        dcgmAddFakeInstances);
+    Ok(lib)
+}
+
+/// `s`, cut to fit a C char array of `capacity` with its NUL.
+fn fitted(s: &str, capacity: usize) -> &str {
+    let mut end = s.len().min(capacity.saturating_sub(1));
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn health_result(result: HealthResult) -> dcgmHealthWatchResults_t {
+    match result {
+        HealthResult::Pass => dcgmHealthWatchResult_enum_DCGM_HEALTH_RESULT_PASS,
+        HealthResult::Warn => dcgmHealthWatchResult_enum_DCGM_HEALTH_RESULT_WARN,
+        HealthResult::Fail => dcgmHealthWatchResult_enum_DCGM_HEALTH_RESULT_FAIL,
+    }
+}
+
+/// A value slot holding only `status`, as DCGM fills it for values it does not have.
+fn status_v2(pair: dcgmGroupEntityPair_t, field: u16, status: dcgmReturn_t) -> dcgmFieldValue_v2 {
+    let mut fv = dcgmFieldValue_v2::versioned();
+    fv.entityGroupId = pair.entityGroupId;
+    fv.entityId = pair.entityId;
+    fv.fieldId = field;
+    fv.fieldType = DCGM_FT_INT64 as u16;
+    fv.status = status;
+    fv.value.i64_ = DCGM_INT64_BLANK as i64;
+    fv
+}
+
+unsafe extern "C" fn error_string(result: dcgmReturn_t) -> *const c_char {
+    let message: &'static CStr = match result {
+        dcgmReturn_enum_DCGM_ST_OK => c"Success",
+        dcgmReturn_enum_DCGM_ST_BADPARAM => c"Bad parameter passed to function",
+        dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED => c"Setting not configured",
+        dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED => c"This request is not supported",
+        dcgmReturn_enum_DCGM_ST_VER_MISMATCH => c"The requested version was not supported",
+        dcgmReturn_enum_DCGM_ST_NO_DATA => c"No data is available",
+        dcgmReturn_enum_DCGM_ST_NOT_WATCHED => c"Field not watched",
+        dcgmReturn_enum_DCGM_ST_INSUFFICIENT_SIZE => c"An input argument is not large enough",
+        dcgmReturn_enum_DCGM_ST_CONNECTION_NOT_VALID => c"The connection to the host engine is not valid any longer",
+        _ => c"Simulated DCGM error",
+    };
+    message.as_ptr()
+}
+
+unsafe extern "C" fn fields_init() -> c_int {
+    0
+}
+
+/// Field metadata is not simulated.
+unsafe extern "C" fn field_get_by_id(_field_id: c_ushort) -> dcgm_field_meta_p {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn field_get_by_tag(_tag: *const c_char) -> dcgm_field_meta_p {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn entity_group_string(_group: dcgm_field_entity_group_t) -> *const c_char {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn error_priority(_code: c_uint) -> dcgmErrorSeverity_t {
+    dcgmErrorSeverity_enum_DCGM_ERROR_NONE
+}
+
+unsafe extern "C" fn error_category(_code: c_uint) -> dcgmErrorCategory_t {
+    dcgmErrorCategory_enum_DCGM_FR_EC_NONE
+}
+
+unsafe extern "C" fn error_format_msg(_code: c_uint) -> *const c_char {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn error_meta(_error: dcgmError_t) -> *const dcgm_error_meta_t {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn success() -> dcgmReturn_t {
+    dcgmReturn_enum_DCGM_ST_OK
+}
+
+/// Stopping or disconnecting forgets the connection; it works during an outage too.
+unsafe extern "C" fn close(handle: dcgmHandle_t) -> dcgmReturn_t {
+    registry().remove(&handle);
+    dcgmReturn_enum_DCGM_ST_OK
+}
+
+unsafe extern "C" fn version_info(info: *mut dcgmVersionInfo_t) -> dcgmReturn_t {
+    let Some(info) = info.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+    if info.version != dcgmVersionInfo_t::version() {
+        return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+    }
+    let _ = copy_str(&mut info.rawBuildInfoString, BUILD_INFO);
+    dcgmReturn_enum_DCGM_ST_OK
+}
+
+unsafe extern "C" fn hostengine_version_info(handle: dcgmHandle_t, info: *mut dcgmVersionInfo_t) -> dcgmReturn_t {
+    on(handle, |_| version_info(info))
+}
+
+unsafe extern "C" fn get_entity_group_entities(handle: dcgmHandle_t, entity_group: dcgm_field_entity_group_t,
+                                               entities: *mut dcgm_field_eid_t, count: *mut c_int,
+                                               _flags: c_uint) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(count) = count.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        let ids: Vec<u32> = match EntityGroup::try_from(entity_group) {
+            Ok(EntityGroup::Gpu) => simulation.gpus().iter().map(|gpu| gpu.id).collect(),
+            _ => Vec::new(),
+        };
+        let capacity = (*count).max(0) as usize;
+        *count = ids.len() as c_int;
+        if ids.len() > capacity {
+            return dcgmReturn_enum_DCGM_ST_INSUFFICIENT_SIZE;
+        }
+        if !ids.is_empty() {
+            if entities.is_null() {
+                return dcgmReturn_enum_DCGM_ST_BADPARAM;
+            }
+            std::slice::from_raw_parts_mut(entities, ids.len()).copy_from_slice(&ids);
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn get_device_attributes(handle: dcgmHandle_t, gpu_id: c_uint,
+                                           attributes: *mut dcgmDeviceAttributes_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(attributes) = attributes.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if attributes.version != dcgmDeviceAttributes_t::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        let Some(gpu) = simulation.0.scenario.gpus.get(gpu_id as usize) else {
+            return dcgmReturn_enum_DCGM_ST_BADPARAM;
+        };
+        let identifiers = &mut attributes.identifiers;
+        let _ = copy_str(&mut identifiers.brandName, "NVIDIA");
+        let len = identifiers.deviceName.len();
+        let _ = copy_str(&mut identifiers.deviceName, fitted(&gpu.name, len));
+        let len = identifiers.uuid.len();
+        let _ = copy_str(&mut identifiers.uuid, fitted(&gpu.uuid, len));
+        let len = identifiers.pciBusId.len();
+        let _ = copy_str(&mut identifiers.pciBusId, fitted(&gpu.pci_bus_id, len));
+        let len = identifiers.serial.len();
+        let _ = copy_str(&mut identifiers.serial, fitted(&gpu.serial, len));
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn group_create(handle: dcgmHandle_t, group_type: dcgmGroupType_t, name: *const c_char,
+                                  group: *mut dcgmGpuGrp_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(group) = group.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        let name = if name.is_null() { String::new() } else { CStr::from_ptr(name).to_string_lossy().into_owned() };
+        let entities = if group_type == dcgmGroupType_enum_DCGM_GROUP_DEFAULT { simulation.gpus() } else { Vec::new() };
+        let mut host = simulation.host();
+        let id = host.next_id();
+        host.groups.insert(id, (name, entities));
+        *group = id;
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn group_destroy(handle: dcgmHandle_t, group: dcgmGpuGrp_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let mut host = simulation.host();
+        if host.groups.remove(&group).is_none() {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        }
+        host.watches.retain(|(g, _)| *g != group);
+        host.health.remove(&group);
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn group_add_entity(handle: dcgmHandle_t, group: dcgmGpuGrp_t, entity_group: dcgm_field_entity_group_t,
+                                      entity_id: dcgm_field_eid_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let pair = dcgmGroupEntityPair_t { entityGroupId: entity_group, entityId: entity_id };
+        let Ok(entity) = Entity::try_from(pair) else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        let mut host = simulation.host();
+        let Some((_, entities)) = host.groups.get_mut(&group) else { return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED };
+        if !entities.contains(&entity) {
+            entities.push(entity);
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn group_remove_entity(handle: dcgmHandle_t, group: dcgmGpuGrp_t, entity_group: dcgm_field_entity_group_t,
+                                         entity_id: dcgm_field_eid_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let mut host = simulation.host();
+        let Some((_, entities)) = host.groups.get_mut(&group) else { return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED };
+        entities.retain(|e| e.to_raw().entityGroupId != entity_group || e.id != entity_id);
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn group_get_info(handle: dcgmHandle_t, group: dcgmGpuGrp_t, info: *mut dcgmGroupInfo_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(info) = info.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if info.version != dcgmGroupInfo_t::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        let host = simulation.host();
+        let Some(entities) = simulation.group_entities(&host, group) else {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        };
+        let name = host.groups.get(&group).map_or("DCGM_ALL_SUPPORTED_GPUS", |(name, _)| name.as_str());
+        let len = info.groupName.len();
+        let _ = copy_str(&mut info.groupName, fitted(name, len));
+        let count = entities.len().min(info.entityList.len());
+        for (slot, entity) in info.entityList.iter_mut().zip(&entities) {
+            *slot = entity.to_raw();
+        }
+        info.count = count as c_uint;
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn field_group_create(handle: dcgmHandle_t, count: c_int, field_ids: *mut c_ushort,
+                                        name: *const c_char, field_group: *mut dcgmFieldGrp_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(field_group) = field_group.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if count <= 0 || field_ids.is_null() {
+            return dcgmReturn_enum_DCGM_ST_BADPARAM;
+        }
+        let fields = std::slice::from_raw_parts(field_ids, count as usize).to_vec();
+        let name = if name.is_null() { String::new() } else { CStr::from_ptr(name).to_string_lossy().into_owned() };
+        let mut host = simulation.host();
+        let id = host.next_id();
+        host.field_groups.insert(id, (name, fields));
+        *field_group = id;
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn field_group_destroy(handle: dcgmHandle_t, field_group: dcgmFieldGrp_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let mut host = simulation.host();
+        if host.field_groups.remove(&field_group).is_none() {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        }
+        host.watches.retain(|(_, fg)| *fg != field_group);
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn field_group_get_info(handle: dcgmHandle_t, info: *mut dcgmFieldGroupInfo_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(info) = info.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if info.version != dcgmFieldGroupInfo_t::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        let host = simulation.host();
+        let Some((name, fields)) = host.field_groups.get(&info.fieldGroupId) else {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        };
+        let len = info.fieldGroupName.len();
+        let _ = copy_str(&mut info.fieldGroupName, fitted(name, len));
+        let count = fields.len().min(info.fieldIds.len());
+        info.fieldIds[..count].copy_from_slice(&fields[..count]);
+        info.numFieldIds = count as c_uint;
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn watch_fields(handle: dcgmHandle_t, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t,
+                                  _update_freq: i64, _max_keep_age: f64, _max_keep_samples: c_int) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let mut host = simulation.host();
+        if simulation.group_entities(&host, group).is_none() || !host.field_groups.contains_key(&field_group) {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        }
+        host.watches.insert((group, field_group));
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn unwatch_fields(handle: dcgmHandle_t, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        simulation.host().watches.remove(&(group, field_group));
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+/// Values follow the clock, so there is nothing to sample.
+unsafe extern "C" fn update_all_fields(handle: dcgmHandle_t, _wait_for_update: c_int) -> dcgmReturn_t {
+    on(handle, |_| dcgmReturn_enum_DCGM_ST_OK)
+}
+
+unsafe extern "C" fn entities_get_latest_values(handle: dcgmHandle_t, entities: *mut dcgmGroupEntityPair_t,
+                                                entity_count: c_uint, fields: *mut c_ushort, field_count: c_uint,
+                                                flags: c_uint, values: *mut dcgmFieldValue_v2) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        if entities.is_null() || fields.is_null() || values.is_null() {
+            return dcgmReturn_enum_DCGM_ST_BADPARAM;
+        }
+        let entities = std::slice::from_raw_parts(entities, entity_count as usize);
+        let fields = std::slice::from_raw_parts(fields, field_count as usize);
+        let values = std::slice::from_raw_parts_mut(values, entities.len() * fields.len());
+        let live = flags & DCGM_FV_FLAG_LIVE_DATA != 0;
+        let host = simulation.host();
+        let slots = entities.iter().flat_map(|pair| fields.iter().map(move |field| (*pair, *field)));
+        for (slot, (pair, field)) in values.iter_mut().zip(slots) {
+            *slot = match simulation.lookup(&host, pair, field, live) {
+                Ok((entity, field_type, ts, value)) => field_value_v2(entity, field, field_type, ts, value),
+                Err(status) => status_v2(pair, field, status),
+            };
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn entity_get_latest_values(handle: dcgmHandle_t, entity_group: dcgm_field_entity_group_t,
+                                              entity_id: c_int, fields: *mut c_ushort, count: c_uint,
+                                              values: *mut dcgmFieldValue_v1) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        if fields.is_null() || values.is_null() {
+            return dcgmReturn_enum_DCGM_ST_BADPARAM;
+        }
+        let pair = dcgmGroupEntityPair_t { entityGroupId: entity_group, entityId: entity_id as dcgm_field_eid_t };
+        let fields = std::slice::from_raw_parts(fields, count as usize);
+        let values = std::slice::from_raw_parts_mut(values, fields.len());
+        let host = simulation.host();
+        for (slot, &field) in values.iter_mut().zip(fields) {
+            *slot = match simulation.lookup(&host, pair, field, false) {
+                Ok((_, field_type, ts, value)) => field_value_v1(field, field_type, ts, value),
+                Err(status) => {
+                    let mut fv = field_value_v1(field, DCGM_FT_INT64, 0, &FieldValue::Blank);
+                    fv.status = status;
+                    fv
+                }
+            };
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+/// Every scripted value of the watch from `since` up to now, one callback per entity.
+unsafe extern "C" fn get_values_since(handle: dcgmHandle_t, group: dcgmGpuGrp_t, field_group: dcgmFieldGrp_t,
+                                      since: i64, next_since: *mut i64, callback: dcgmFieldValueEntityEnumeration_f,
+                                      user_data: *mut c_void) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let (entities, fields) = {
+            let host = simulation.host();
+            let fields = host.field_groups.get(&field_group).map(|(_, fields)| fields.clone());
+            let (Some(entities), Some(fields)) = (simulation.group_entities(&host, group), fields) else {
+                return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+            };
+            if !host.watches.contains(&(group, field_group)) {
+                return dcgmReturn_enum_DCGM_ST_NOT_WATCHED;
+            }
+            (entities, fields)
+        };
+        let now = simulation.timestamp();
+        let scenario = simulation.scenario();
+        for entity in entities {
+            let mut values = Vec::new();
+            for &field in &fields {
+                let Some(timeline) = scenario.values.get(&(entity, field)) else { continue };
+                let field_type = scenario.field_type(entity, field);
+                for (at, value) in timeline {
+                    let ts = simulation.timestamp_of(*at);
+                    if ts >= since && ts <= now {
+                        values.push(field_value_v1(field, field_type, ts, value));
+                    }
+                }
+            }
+            let Some(callback) = callback.filter(|_| !values.is_empty()) else { continue };
+            let raw = entity.to_raw();
+            if callback(raw.entityGroupId, raw.entityId, values.as_mut_ptr(), values.len() as c_int, user_data) != 0 {
+                break;
+            }
+        }
+        if let Some(next_since) = next_since.as_mut() {
+            *next_since = now + 1;
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn health_set(handle: dcgmHandle_t, params: *mut dcgmHealthSetParams_v2) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(params) = params.as_ref() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if params.version != dcgmHealthSetParams_v2::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        let mut host = simulation.host();
+        if simulation.group_entities(&host, params.groupId).is_none() {
+            return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+        }
+        host.health.insert(params.groupId, HealthSystems::from_bits_retain(params.systems));
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn health_check(handle: dcgmHandle_t, group: dcgmGpuGrp_t, response: *mut dcgmHealthResponse_t) -> dcgmReturn_t {
+    on(handle, |simulation| {
+        let Some(response) = response.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if response.version != dcgmHealthResponse_t::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        let (entities, systems) = {
+            let host = simulation.host();
+            let Some(entities) = simulation.group_entities(&host, group) else {
+                return dcgmReturn_enum_DCGM_ST_NOT_CONFIGURED;
+            };
+            (entities, host.health.get(&group).copied().unwrap_or(HealthSystems::empty()))
+        };
+        let incidents = simulation.incidents(&entities, systems);
+        let overall = incidents.values().map(|(result, _)| *result).max().unwrap_or(HealthResult::Pass);
+        response.overallHealth = health_result(overall);
+        response.incidentCount = incidents.len().min(response.incidents.len()) as c_uint;
+        for (incident, ((entity, system), (result, message))) in response.incidents.iter_mut().zip(incidents) {
+            incident.system = system;
+            incident.health = health_result(result);
+            incident.entityInfo = entity.to_raw();
+            let len = incident.error.msg.len();
+            let _ = copy_str(&mut incident.error.msg, fitted(message, len));
+        }
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
+
+unsafe extern "C" fn get_nvlink_link_status(handle: dcgmHandle_t, _status: *mut dcgmNvLinkStatus_v4) -> dcgmReturn_t {
+    on(handle, |_| dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED)
+}
+
+unsafe extern "C" fn get_device_topology(handle: dcgmHandle_t, _gpu_id: c_uint,
+                                         _topology: *mut dcgmDeviceTopology_t) -> dcgmReturn_t {
+    on(handle, |_| dcgmReturn_enum_DCGM_ST_NOT_SUPPORTED)
+}
+
+unsafe extern "C" fn get_gpu_instance_hierarchy(handle: dcgmHandle_t, hierarchy: *mut dcgmMigHierarchy_v2) -> dcgmReturn_t {
+    on(handle, |_| {
+        let Some(hierarchy) = hierarchy.as_mut() else { return dcgmReturn_enum_DCGM_ST_BADPARAM };
+        if hierarchy.version != dcgmMigHierarchy_v2::version() {
+            return dcgmReturn_enum_DCGM_ST_VER_MISMATCH;
+        }
+        hierarchy.count = 0;
+        dcgmReturn_enum_DCGM_ST_OK
+    })
+}
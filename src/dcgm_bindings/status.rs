@@ -0,0 +1,80 @@
+use super::bindings::*;
+use super::{error_string, DCGMError, DcgmLibSafe};
+
+/// One per-GPU failure popped from a DCGM status handle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusError {
+    pub gpu_id: u32,
+    pub field_id: i16,
+    pub status: dcgmReturn_t,
+    pub message: String,
+}
+
+/// Owns a `dcgmStatus_t` for multi-GPU operations (config, policy) and destroys it on drop.
+pub struct StatusHandle {
+    dcgm: &'static DcgmLib,
+    handle: dcgmStatus_t,
+}
+
+impl StatusHandle {
+    pub fn raw(&self) -> dcgmStatus_t {
+        self.handle
+    }
+
+    pub fn count(&self) -> Result<u32, DCGMError> {
+        let mut count: u32 = 0;
+        match unsafe { self.dcgm.dcgmStatusGetCount(self.handle, &raw mut count) } {
+            dcgmReturn_enum_DCGM_ST_OK => Ok(count),
+            err_code => Err(DCGMError::from(error_string(self.dcgm, err_code))),
+        }
+    }
+
+    /// Pops every error recorded in the handle, leaving it empty.
+    pub fn drain(&mut self) -> Vec<StatusError> {
+        let mut errors = Vec::new();
+        loop {
+            let mut info = dcgmErrorInfo_t { gpuId: 0, fieldId: 0, status: 0 };
+            match unsafe { self.dcgm.dcgmStatusPopError(self.handle, &raw mut info) } {
+                dcgmReturn_enum_DCGM_ST_OK => errors.push(StatusError {
+                    gpu_id: info.gpuId,
+                    field_id: info.fieldId,
+                    status: info.status,
+                    message: error_string(self.dcgm, info.status),
+                }),
+                _ => break,
+            }
+        }
+        errors
+    }
+
+    pub fn clear(&mut self) -> Result<(), DCGMError> {
+        match unsafe { self.dcgm.dcgmStatusClear(self.handle) } {
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(DCGMError::from(error_string(self.dcgm, err_code))),
+        }
+    }
+}
+
+impl Drop for StatusHandle {
+    fn drop(&mut self) {
+        unsafe { self.dcgm.dcgmStatusDestroy(self.handle) };
+    }
+}
+
+/// Folds per-GPU failures into a single error.
+pub fn status_errors_to_error(context: &str, errors: &[StatusError]) -> DCGMError {
+    let details: Vec<String> = errors.iter()
+        .map(|e| format!("gpu {}: {}", e.gpu_id, e.message))
+        .collect();
+    DCGMError::from(format!("{context}: {}", details.join("; ")))
+}
+
+impl DcgmLibSafe {
+    pub fn status_create(&self) -> Result<StatusHandle, DCGMError>{
+        let mut handle: dcgmStatus_t = 0;
+        match unsafe{self.dcgm.dcgmStatusCreate(&raw mut handle)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(StatusHandle { dcgm: self.dcgm, handle }),
+            err_code => Err(self.call_error(err_code, "dcgmStatusCreate"))
+        }
+    }
+}
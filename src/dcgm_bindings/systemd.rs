@@ -0,0 +1,136 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{self, UnixDatagram};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The first descriptor systemd passes with socket activation (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+lazy_static::lazy_static! {
+    /// Descriptors passed by socket activation that no server has taken yet.
+    static ref LISTEN_FDS: Mutex<Vec<OwnedFd>> = Mutex::new(listen_fds_from_env());
+}
+
+/// Takes over the descriptors in `LISTEN_FDS` when they were meant for this process.
+fn listen_fds_from_env() -> Vec<OwnedFd> {
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse::<RawFd>().ok()).unwrap_or(0);
+    if !for_us || count <= 0 {
+        return Vec::new();
+    }
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // children such as a supervised nv-hostengine must not inherit the sockets
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            unsafe { OwnedFd::from_raw_fd(fd) }
+        })
+        .collect()
+}
+
+/// Takes the listening TCP socket systemd passed for `addr`, if any. A socket bound to the unspecified
+/// address also matches a configured unspecified address of the other IP version on the same port.
+pub fn take_listener(addr: SocketAddr) -> Option<TcpListener> {
+    let mut fds = LISTEN_FDS.lock().unwrap_or_else(|e| e.into_inner());
+    let position = fds.iter().position(|fd| {
+        let Ok(listener) = fd.try_clone().map(TcpListener::from) else { return false };
+        match listener.local_addr() {
+            Ok(local) => local == addr
+                || (local.port() == addr.port() && local.ip().is_unspecified() && addr.ip().is_unspecified()),
+            Err(_) => false,
+        }
+    })?;
+    Some(TcpListener::from(fds.remove(position)))
+}
+
+/// Sends `state` (newline separated `KEY=value` assignments) to the service manager. Ok(false) when not
+/// running under systemd with a notify socket.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return Ok(false) };
+    let bytes = path.as_encoded_bytes();
+    let addr = match bytes.strip_prefix(b"@") {
+        Some(name) => net::SocketAddr::from_abstract_name(name)?,
+        None => net::SocketAddr::from_pathname(&path)?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(true)
+}
+
+/// `sd_notify` for a long-running service: readiness, reload and stop notifications, a status line,
+/// and keep-alive pings when the unit sets `WatchdogSec=`. Does nothing outside systemd.
+///
+/// ```ini
+/// [Service]
+/// Type=notify-reload
+/// ExecStart=/usr/bin/rust-dcgm daemon -c /etc/rust-dcgm/daemon.toml
+/// WatchdogSec=30
+/// ```
+#[derive(Debug)]
+pub struct Notifier {
+    watchdog: Option<Duration>,
+    last_ping: Instant,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        Self { watchdog: watchdog_interval(), last_ping: Instant::now() }
+    }
+
+    /// The longest the caller may go between `keep_alive` calls.
+    pub fn keep_alive_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|w| w / 2)
+    }
+
+    pub fn ready(&mut self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={status}"));
+        self.last_ping = Instant::now();
+    }
+
+    pub fn reloading(&self) {
+        self.send(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={status}"));
+    }
+
+    /// Pings the watchdog when half its interval has passed since the last ping.
+    pub fn keep_alive(&mut self) {
+        let Some(interval) = self.keep_alive_interval() else { return };
+        if self.last_ping.elapsed() >= interval {
+            self.send("WATCHDOG=1");
+            self.last_ping = Instant::now();
+        }
+    }
+
+    fn send(&self, state: &str) {
+        if let Err(e) = notify(state) {
+            tracing::warn!("Failed to notify systemd: {e}");
+        }
+    }
+}
+
+/// `WATCHDOG_USEC`, when it is meant for this process.
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// CLOCK_MONOTONIC in microseconds, which `RELOADING=1` has to carry.
+fn monotonic_usec() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
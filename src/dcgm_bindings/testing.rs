@@ -0,0 +1,116 @@
+use super::bindings::*;
+use super::entity::Entity;
+use super::samples::FieldValue;
+use super::init::{Versioned, Zeroable};
+use super::{c_chars_to_string, DCGMError, DcgmLibSafe, DCGM_LIB_PATH};
+use std::os::raw::c_char;
+
+type InjectFn = unsafe extern "C" fn(dcgmHandle_t, dcgm_field_entity_group_t, dcgm_field_eid_t, *mut dcgmFieldValue_v1) -> dcgmReturn_t;
+
+lazy_static::lazy_static! {
+    /// `dcgmEntityInjectFieldValue` lives in dcgm_test_apis.h, which the generated bindings leave out.
+    static ref INJECT: Result<(libloading::Library, InjectFn), DCGMError> = unsafe {
+        let lib = libloading::Library::new(DCGM_LIB_PATH)
+            .map_err(|e| DCGMError::from(format!("Failed to load DCGM library: {e}")))?;
+        let inject = *lib.get::<InjectFn>(b"dcgmEntityInjectFieldValue\0")
+            .map_err(|_| DCGMError::not_supported("dcgmEntityInjectFieldValue is not exported by this DCGM"))?;
+        Ok((lib, inject))
+    };
+}
+
+/// How strings are read out of the fixed-size char arrays of DCGM structs (attributes, build info,
+/// error messages); for fuzzing with arbitrary bytes.
+pub fn attribute_string(chars: &[c_char]) -> String {
+    c_chars_to_string(chars)
+}
+
+/// `dcgmDeviceAttributes_t` PCI bus id to its sysfs form; for fuzzing.
+pub fn sysfs_bus_id(dcgm_bus_id: &str) -> Option<String> {
+    super::topology::sysfs_bus_id(dcgm_bus_id)
+}
+
+/// DCGM field type matching a decoded value; `Blank` has none of its own.
+pub fn field_type_of(value: &FieldValue) -> Option<u8> {
+    match value {
+        FieldValue::Int64(_) => Some(DCGM_FT_INT64),
+        FieldValue::Double(_) => Some(DCGM_FT_DOUBLE),
+        FieldValue::String(_) => Some(DCGM_FT_STRING),
+        FieldValue::Blob(_) => Some(DCGM_FT_BINARY),
+        FieldValue::Blank => None,
+    }
+}
+
+/// The raw value union holding `value`, or the blank sentinel of `field_type` for `Blank`. Strings longer
+/// than 255 bytes and blobs longer than 4096 bytes are truncated.
+pub fn encode_value(field_type: u8, value: &FieldValue) -> dcgmFieldValue_v1__bindgen_ty_1 {
+    let mut raw = dcgmFieldValue_v1__bindgen_ty_1::zeroed();
+    match (value, field_type) {
+        (FieldValue::Int64(v), _) => raw.i64_ = *v,
+        (FieldValue::Double(v), _) => raw.dbl = *v,
+        (FieldValue::String(s), _) => unsafe {
+            for (d, b) in raw.str_.iter_mut().zip(s.bytes().take(255)) {
+                *d = b as c_char;
+            }
+        },
+        (FieldValue::Blob(bytes), _) => unsafe {
+            for (d, b) in raw.blob.iter_mut().zip(bytes) {
+                *d = *b as c_char;
+            }
+        },
+        (FieldValue::Blank, DCGM_FT_DOUBLE) => raw.dbl = DCGM_FP64_BLANK,
+        (FieldValue::Blank, DCGM_FT_STRING) => unsafe {
+            for (d, b) in raw.str_.iter_mut().zip(DCGM_STR_BLANK.iter()) {
+                *d = *b as c_char;
+            }
+        },
+        (FieldValue::Blank, _) => raw.i64_ = DCGM_INT64_BLANK as i64,
+    }
+    raw
+}
+
+/// A `dcgmFieldValue_v1` as DCGM would return it (and as `dcgmEntityInjectFieldValue` takes it).
+pub fn field_value_v1(field_id: u16, field_type: u8, timestamp: i64, value: &FieldValue) -> dcgmFieldValue_v1 {
+    dcgmFieldValue_v1 {
+        version: dcgmFieldValue_v1::version(),
+        fieldId: field_id,
+        fieldType: field_type as u16,
+        status: dcgmReturn_enum_DCGM_ST_OK,
+        ts: timestamp,
+        value: encode_value(field_type, value),
+    }
+}
+
+/// A `dcgmFieldValue_v2` for `entity`, as `dcgmEntitiesGetLatestValues` returns it.
+pub fn field_value_v2(entity: Entity, field_id: u16, field_type: u8, timestamp: i64, value: &FieldValue) -> dcgmFieldValue_v2 {
+    let raw = entity.to_raw();
+    let mut fv = dcgmFieldValue_v2::versioned();
+    fv.entityGroupId = raw.entityGroupId;
+    fv.entityId = raw.entityId;
+    fv.fieldId = field_id;
+    fv.fieldType = field_type as u16;
+    fv.status = dcgmReturn_enum_DCGM_ST_OK;
+    fv.ts = timestamp;
+    // v1 and v2 share the same value union layout
+    let value = encode_value(field_type, value);
+    unsafe {
+        std::ptr::copy_nonoverlapping(&value as *const _ as *const u8, &mut fv.value as *mut _ as *mut u8,
+                                      std::mem::size_of::<dcgmFieldValue_v1__bindgen_ty_1>());
+    }
+    fv
+}
+
+impl DcgmLibSafe {
+    /// Injects a value for `field_id` on `entity`, as if the driver had reported it at `timestamp`.
+    /// Needs a hostengine that allows injection (e.g. an embedded one).
+    pub fn inject_field_value(&mut self, entity: Entity, field_id: u16, field_type: u8, timestamp: i64,
+                              value: &FieldValue) -> Result<(), DCGMError>{
+        let (_, inject) = INJECT.as_ref().map_err(Clone::clone)?;
+        let raw = entity.to_raw();
+        let mut fv = field_value_v1(field_id, field_type, timestamp, value);
+        match unsafe{inject(self.handle(), raw.entityGroupId, raw.entityId, &mut fv)}{
+            dcgmReturn_enum_DCGM_ST_OK => Ok(()),
+            err_code => Err(self.call_error(err_code, "dcgmEntityInjectFieldValue").arg("entity", entity)
+                                    .arg("field", field_id))
+        }
+    }
+}
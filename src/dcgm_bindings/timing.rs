@@ -0,0 +1,68 @@
+use super::exporter::local_hostname;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Where in its interval a collection runs for the first time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Phase {
+    /// Right away.
+    #[default]
+    None,
+    /// This far into the interval (modulo the interval).
+    Fixed(Duration),
+    /// A fraction of the interval derived from the hostname, so every node lands on a different but
+    /// stable offset across restarts.
+    Host,
+}
+
+/// Phase offset and per-run jitter of periodic collections, so many nodes polling their hostengines
+/// or pushing to the same sink do not all fire on the same tick.
+///
+/// Runs stay on a fixed grid of `interval` after the phase offset; jitter delays a single run without
+/// moving the grid, so it never accumulates into drift.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollectionTiming {
+    pub phase: Phase,
+    /// Upper bound of the random delay added to each run. Capped to half the interval.
+    pub jitter: Duration,
+}
+
+impl CollectionTiming {
+    /// Delay of the first run of a collection with this interval.
+    pub fn first_offset(&self, interval: Duration) -> Duration {
+        if interval.is_zero() {
+            return Duration::ZERO;
+        }
+        match self.phase {
+            Phase::None => Duration::ZERO,
+            Phase::Fixed(offset) => Duration::from_nanos((offset.as_nanos() % interval.as_nanos()) as u64),
+            Phase::Host => interval.mul_f64(host_fraction()),
+        }
+    }
+
+    /// Random delay for one run, below `jitter` and at most half of `interval`.
+    pub fn run_jitter(&self, interval: Duration) -> Duration {
+        let max = self.jitter.min(interval / 2);
+        if max.is_zero() {
+            return Duration::ZERO;
+        }
+        max.mul_f64(random_fraction())
+    }
+}
+
+/// Stable value in [0, 1) from the hostname (FNV-1a), 0 when it cannot be read.
+fn host_fraction() -> f64 {
+    let Some(host) = local_hostname() else { return 0.0 };
+    let hash = host.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Value in [0, 1) from std's per-process random hash keys; plenty for spreading load.
+fn random_fraction() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,498 @@
+use super::entity::EntityGroup;
+use super::{c_chars_to_string, DCGMError, DcgmLibSafe, NvLinkState, P2PLinkType};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum TopologyNode {
+    Gpu(u32),
+    Switch(u32),
+    /// A CPU socket, identified by its NUMA node.
+    Cpu(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TopologyLink {
+    /// Direct GPU to GPU connection as classified by DCGM.
+    P2P(P2PLinkType),
+    /// GPU attached to an NVSwitch with `links` NVLinks up.
+    NvSwitch { links: u32 },
+    /// GPU attached to the CPU socket of its NUMA node.
+    Affinity,
+}
+
+impl TopologyLink {
+    /// Number of NVLinks carried by the link, 1 for PCIe style links.
+    pub fn width(&self) -> u32 {
+        match self {
+            TopologyLink::P2P(P2PLinkType::NvLink(n)) => *n as u32,
+            TopologyLink::NvSwitch { links } => *links,
+            _ => 1,
+        }
+    }
+
+    // Comparable quality of a single hop, higher is better
+    fn rank(&self) -> u32 {
+        match self {
+            TopologyLink::P2P(t) => t.rank(),
+            TopologyLink::NvSwitch { links } => 100 + links,
+            TopologyLink::Affinity => P2PLinkType::Cpu.rank(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct TopologyEdge {
+    pub a: TopologyNode,
+    pub b: TopologyNode,
+    pub link: TopologyLink,
+}
+
+impl TopologyEdge {
+    pub fn other(&self, node: TopologyNode) -> Option<TopologyNode> {
+        if self.a == node {
+            Some(self.b)
+        } else if self.b == node {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+}
+
+/// GPUs, NVSwitches and CPU sockets of a system as an undirected graph.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TopologyGraph {
+    nodes: BTreeSet<TopologyNode>,
+    edges: Vec<TopologyEdge>,
+    bus_ids: BTreeMap<u32, String>,
+}
+
+impl TopologyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, node: TopologyNode) {
+        self.nodes.insert(node);
+    }
+
+    /// Adds an edge, replacing any existing edge between the same two nodes.
+    pub fn add_edge(&mut self, a: TopologyNode, b: TopologyNode, link: TopologyLink) {
+        self.nodes.insert(a);
+        self.nodes.insert(b);
+        self.edges.retain(|e| !(e.other(a) == Some(b) && e.other(b) == Some(a)));
+        self.edges.push(TopologyEdge { a, b, link });
+    }
+
+    pub fn set_bus_id(&mut self, gpu: u32, bus_id: String) {
+        self.bus_ids.insert(gpu, bus_id);
+    }
+
+    pub fn bus_id(&self, gpu: u32) -> Option<&str> {
+        self.bus_ids.get(&gpu).map(|s| s.as_str())
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &TopologyNode> {
+        self.nodes.iter()
+    }
+
+    pub fn edges(&self) -> &[TopologyEdge] {
+        &self.edges
+    }
+
+    pub fn gpus(&self) -> Vec<u32> {
+        self.nodes.iter().filter_map(|n| match n { TopologyNode::Gpu(id) => Some(*id), _ => None }).collect()
+    }
+
+    pub fn neighbors(&self, node: TopologyNode) -> Vec<(TopologyNode, TopologyLink)> {
+        self.edges.iter().filter_map(|e| e.other(node).map(|o| (o, e.link))).collect()
+    }
+
+    /// Direct link between two GPUs, if DCGM reported one.
+    pub fn link_between(&self, a: u32, b: u32) -> Option<P2PLinkType> {
+        self.neighbors(TopologyNode::Gpu(a)).into_iter().find_map(|(n, l)| match (n, l) {
+            (TopologyNode::Gpu(id), TopologyLink::P2P(t)) if id == b => Some(t),
+            _ => None,
+        })
+    }
+
+    /// GPUs directly connected to `gpu` over NVLink, with the NVLink count of each connection.
+    pub fn peers_with_nvlink(&self, gpu: u32) -> Vec<(u32, u8)> {
+        let mut peers: Vec<(u32, u8)> = self.neighbors(TopologyNode::Gpu(gpu)).into_iter()
+            .filter_map(|(n, l)| match (n, l) {
+                (TopologyNode::Gpu(id), TopologyLink::P2P(P2PLinkType::NvLink(c))) => Some((id, c)),
+                _ => None,
+            })
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    pub fn numa_node(&self, gpu: u32) -> Option<u32> {
+        self.neighbors(TopologyNode::Gpu(gpu)).into_iter().find_map(|(n, l)| match (n, l) {
+            (TopologyNode::Cpu(numa), TopologyLink::Affinity) => Some(numa),
+            _ => None,
+        })
+    }
+
+    /// Path from `from` to `to` whose weakest hop is as good as possible, preferring fewer hops on ties.
+    pub fn best_path(&self, from: TopologyNode, to: TopologyNode) -> Option<Vec<TopologyNode>> {
+        if !self.nodes.contains(&from) || !self.nodes.contains(&to) {
+            return None;
+        }
+        // (bottleneck rank, hops) per node; a widest-path variant of Dijkstra
+        let mut best: HashMap<TopologyNode, (u32, usize)> = HashMap::new();
+        let mut prev: HashMap<TopologyNode, TopologyNode> = HashMap::new();
+        let mut done: BTreeSet<TopologyNode> = BTreeSet::new();
+        best.insert(from, (u32::MAX, 0));
+        loop {
+            let current = best.iter()
+                .filter(|(n, _)| !done.contains(n))
+                .max_by(|(na, (ra, ha)), (nb, (rb, hb))| ra.cmp(rb).then(hb.cmp(ha)).then(nb.cmp(na)))
+                .map(|(n, v)| (*n, *v));
+            let (node, (rank, hops)) = current?;
+            if node == to {
+                break;
+            }
+            done.insert(node);
+            for (next, link) in self.neighbors(node) {
+                if done.contains(&next) {
+                    continue;
+                }
+                let candidate = (rank.min(link.rank()), hops + 1);
+                let better = match best.get(&next) {
+                    Some((r, h)) => candidate.0 > *r || (candidate.0 == *r && candidate.1 < *h),
+                    None => true,
+                };
+                if better {
+                    best.insert(next, candidate);
+                    prev.insert(next, node);
+                }
+            }
+        }
+        let mut path = vec![to];
+        let mut node = to;
+        while node != from {
+            node = prev[&node];
+            path.push(node);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+impl fmt::Display for TopologyNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyNode::Gpu(id) => write!(f, "GPU{id}"),
+            TopologyNode::Switch(id) => write!(f, "NVSwitch{id}"),
+            TopologyNode::Cpu(numa) => write!(f, "CPU{numa}"),
+        }
+    }
+}
+
+impl fmt::Display for TopologyLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyLink::P2P(t) => t.fmt(f),
+            TopologyLink::NvSwitch { links } => write!(f, "NV{links}"),
+            TopologyLink::Affinity => f.write_str("NUMA"),
+        }
+    }
+}
+
+impl TopologyGraph {
+    /// Renders the graph in Graphviz DOT format, e.g. for `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph topology {\n");
+        for node in &self.nodes {
+            let (shape, label) = match node {
+                TopologyNode::Gpu(id) => match self.bus_id(*id) {
+                    Some(bus) => ("box", format!("GPU{id}\\n{bus}")),
+                    None => ("box", format!("GPU{id}")),
+                },
+                TopologyNode::Switch(id) => ("diamond", format!("NVSwitch{id}")),
+                TopologyNode::Cpu(numa) => ("ellipse", format!("CPU (NUMA {numa})")),
+            };
+            dot.push_str(&format!("    \"{node}\" [shape={shape}, label=\"{label}\"];\n"));
+        }
+        for edge in &self.edges {
+            let style = match edge.link {
+                TopologyLink::P2P(P2PLinkType::NvLink(_)) | TopologyLink::NvSwitch { .. } => "bold",
+                TopologyLink::Affinity => "dotted",
+                _ => "solid",
+            };
+            dot.push_str(&format!("    \"{}\" -- \"{}\" [label=\"{}\", style={style}];\n", edge.a, edge.b, edge.link));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Interconnect quality of a GPU set. Ordered so that a greater score is a better set: first by the
+/// weakest pair in the set, then by the sum over all pairs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GpuSetScore {
+    pub weakest_pair: u32,
+    pub total: u32,
+}
+
+// Above this many combinations subsets are grown greedily instead of enumerated
+const MAX_EXHAUSTIVE_SUBSETS: usize = 100_000;
+
+fn binomial(n: usize, k: usize) -> usize {
+    let k = k.min(n - k);
+    (0..k).fold(1usize, |acc, i| acc.saturating_mul(n - i) / (i + 1))
+}
+
+fn for_each_subset(items: &[u32], k: usize, start: usize, current: &mut Vec<u32>, f: &mut dyn FnMut(&[u32])) {
+    if current.len() == k {
+        f(current);
+        return;
+    }
+    for i in start..items.len() {
+        if items.len() - i < k - current.len() {
+            break;
+        }
+        current.push(items[i]);
+        for_each_subset(items, k, i + 1, current, f);
+        current.pop();
+    }
+}
+
+impl TopologyGraph {
+    /// Score of a single GPU pair: direct NVLink, then a shared NVSwitch, then PCIe distance, with a
+    /// small bonus for pairs on the same NUMA node.
+    pub fn pair_score(&self, a: u32, b: u32) -> u32 {
+        if let Some(P2PLinkType::NvLink(n)) = self.link_between(a, b) {
+            return 100 + 10 * n as u32;
+        }
+        let switch_links = |gpu: u32| -> BTreeMap<u32, u32> {
+            self.neighbors(TopologyNode::Gpu(gpu)).into_iter().filter_map(|(n, l)| match (n, l) {
+                (TopologyNode::Switch(id), TopologyLink::NvSwitch { links }) => Some((id, links)),
+                _ => None,
+            }).collect()
+        };
+        let (sa, sb) = (switch_links(a), switch_links(b));
+        if let Some(links) = sa.iter().filter_map(|(id, la)| sb.get(id).map(|lb| (*la).min(*lb))).max() {
+            if links > 0 {
+                return 100 + 10 * links;
+            }
+        }
+        let pcie = self.link_between(a, b).map(|t| t.rank()).unwrap_or(0) * 10;
+        let same_numa = match (self.numa_node(a), self.numa_node(b)) {
+            (Some(x), Some(y)) if x == y => 5,
+            _ => 0,
+        };
+        pcie + same_numa
+    }
+
+    pub fn score_gpu_set(&self, gpus: &[u32]) -> GpuSetScore {
+        let mut weakest = u32::MAX;
+        let mut total = 0;
+        for (i, a) in gpus.iter().enumerate() {
+            for b in &gpus[i + 1..] {
+                let score = self.pair_score(*a, *b);
+                weakest = weakest.min(score);
+                total += score;
+            }
+        }
+        GpuSetScore { weakest_pair: if weakest == u32::MAX { 0 } else { weakest }, total }
+    }
+
+    /// Subsets of `size` GPUs drawn from `candidates`, best interconnect first. Large searches fall
+    /// back to growing sets greedily from every starting GPU.
+    pub fn rank_gpu_subsets(&self, candidates: &[u32], size: usize) -> Vec<(Vec<u32>, GpuSetScore)> {
+        let mut candidates = candidates.to_vec();
+        candidates.sort();
+        candidates.dedup();
+        if size == 0 || size > candidates.len() {
+            return Vec::new();
+        }
+        let mut ranked = Vec::new();
+        if binomial(candidates.len(), size) <= MAX_EXHAUSTIVE_SUBSETS {
+            for_each_subset(&candidates, size, 0, &mut Vec::with_capacity(size), &mut |subset| {
+                ranked.push((subset.to_vec(), self.score_gpu_set(subset)));
+            });
+        } else {
+            for start in &candidates {
+                let mut set = vec![*start];
+                while set.len() < size {
+                    let next = candidates.iter()
+                        .filter(|c| !set.contains(c))
+                        .max_by_key(|c| {
+                            let mut with = set.clone();
+                            with.push(**c);
+                            (self.score_gpu_set(&with), std::cmp::Reverse(**c))
+                        })
+                        .copied();
+                    match next {
+                        Some(n) => set.push(n),
+                        None => break,
+                    }
+                }
+                set.sort();
+                if !ranked.iter().any(|(s, _)| *s == set) {
+                    let score = self.score_gpu_set(&set);
+                    ranked.push((set, score));
+                }
+            }
+        }
+        ranked.sort_by(|(sa, a), (sb, b)| b.cmp(a).then(sa.cmp(sb)));
+        ranked
+    }
+
+    /// Pure-Rust counterpart of `selectGpusByTopology`: the best-connected `size` GPUs of `candidates`.
+    pub fn select_gpus(&self, candidates: &[u32], size: usize) -> Option<Vec<u32>> {
+        self.rank_gpu_subsets(candidates, size).into_iter().next().map(|(set, _)| set)
+    }
+}
+
+pub(crate) fn sysfs_bus_id(dcgm_bus_id: &str) -> Option<String> {
+    // DCGM reports "00000000:3B:00.0", sysfs uses "0000:3b:00.0"
+    let (domain, rest) = dcgm_bus_id.split_once(':')?;
+    let domain = u32::from_str_radix(domain, 16).ok()?;
+    Some(format!("{domain:04x}:{}", rest.to_lowercase()))
+}
+
+fn read_numa_node(bus_id: &str) -> Option<u32> {
+    let path = format!("/sys/bus/pci/devices/{}/numa_node", sysfs_bus_id(bus_id)?);
+    let node: i32 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    if node < 0 { None } else { Some(node as u32) }
+}
+
+impl DcgmLibSafe {
+    /// Builds the topology graph of every supported GPU, the NVSwitches and the GPUs' NUMA nodes.
+    pub fn topology_graph(&mut self) -> Result<TopologyGraph, DCGMError>{
+        let mut graph = TopologyGraph::new();
+        let gpus = self.getAllSupportedDevices()?;
+        for gpu in &gpus {
+            graph.add_node(TopologyNode::Gpu(*gpu));
+            let attributes = self.device_attributes(*gpu)?;
+            let bus_id = c_chars_to_string(&attributes.identifiers.pciBusId);
+            if let Some(numa) = read_numa_node(&bus_id) {
+                graph.add_edge(TopologyNode::Gpu(*gpu), TopologyNode::Cpu(numa), TopologyLink::Affinity);
+            }
+            graph.set_bus_id(*gpu, bus_id);
+            for link in self.getDeviceTopology(*gpu)? {
+                if link.gpu > *gpu {
+                    graph.add_edge(TopologyNode::Gpu(*gpu), TopologyNode::Gpu(link.gpu), TopologyLink::P2P(link.link));
+                }
+            }
+        }
+
+        // DCGM does not say which switch a GPU link lands on, so GPUs are attached to every switch
+        // of the fabric with the number of NVLinks they have up.
+        if let Ok(statuses) = self.getNvLinkLinkStatus() {
+            let mut up: BTreeMap<u32, u32> = BTreeMap::new();
+            let mut switches = BTreeSet::new();
+            for status in &statuses {
+                match status.parent_type {
+                    EntityGroup::Switch => { switches.insert(status.parent_id); }
+                    EntityGroup::Gpu if status.state == NvLinkState::Up => *up.entry(status.parent_id).or_default() += 1,
+                    _ => (),
+                }
+            }
+            for switch in &switches {
+                graph.add_node(TopologyNode::Switch(*switch));
+                for (gpu, links) in &up {
+                    graph.add_edge(TopologyNode::Gpu(*gpu), TopologyNode::Switch(*switch), TopologyLink::NvSwitch { links: *links });
+                }
+            }
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GPUs `0..gpus` joined by the direct `links`; GPUs without a link stay disconnected.
+    fn graph(gpus: u32, links: &[(u32, u32, P2PLinkType)]) -> TopologyGraph {
+        let mut graph = TopologyGraph::new();
+        for gpu in 0..gpus {
+            graph.add_node(TopologyNode::Gpu(gpu));
+        }
+        for (a, b, link) in links {
+            graph.add_edge(TopologyNode::Gpu(*a), TopologyNode::Gpu(*b), TopologyLink::P2P(*link));
+        }
+        graph
+    }
+
+    fn path(graph: &TopologyGraph, from: u32, to: u32) -> Option<Vec<u32>> {
+        graph.best_path(TopologyNode::Gpu(from), TopologyNode::Gpu(to)).map(|p| p.into_iter().map(|n| match n {
+            TopologyNode::Gpu(id) => id,
+            other => panic!("unexpected {other} on the path"),
+        }).collect())
+    }
+
+    #[test]
+    fn best_path_prefers_the_strongest_bottleneck() {
+        let graph = graph(3, &[(0, 1, P2PLinkType::System), (0, 2, P2PLinkType::NvLink(2)),
+                               (2, 1, P2PLinkType::NvLink(2))]);
+        assert_eq!(path(&graph, 0, 1), Some(vec![0, 2, 1]));
+        assert_eq!(path(&graph, 1, 1), Some(vec![1]));
+    }
+
+    #[test]
+    fn best_path_breaks_ties_by_hops_then_node() {
+        // both routes are limited by a single NVLink, the direct one is shorter
+        let shortcut = graph(3, &[(0, 1, P2PLinkType::NvLink(1)), (0, 2, P2PLinkType::NvLink(4)),
+                                  (2, 1, P2PLinkType::NvLink(1))]);
+        assert_eq!(path(&shortcut, 0, 1), Some(vec![0, 1]));
+
+        // two equal routes, the one through the lower GPU wins
+        let diamond = graph(4, &[(0, 2, P2PLinkType::NvLink(2)), (2, 1, P2PLinkType::NvLink(2)),
+                                 (0, 3, P2PLinkType::NvLink(2)), (3, 1, P2PLinkType::NvLink(2))]);
+        assert_eq!(path(&diamond, 0, 1), Some(vec![0, 2, 1]));
+    }
+
+    #[test]
+    fn best_path_needs_both_ends_connected() {
+        let graph = graph(3, &[(0, 1, P2PLinkType::Board)]);
+        assert_eq!(path(&graph, 0, 2), None);
+        assert_eq!(path(&graph, 0, 7), None);
+    }
+
+    /// GPUs 0-1 and 2-3 on NVLink, everything else across the system, GPU 4 without any link.
+    fn two_pairs() -> TopologyGraph {
+        graph(5, &[(0, 1, P2PLinkType::NvLink(4)), (2, 3, P2PLinkType::NvLink(4)),
+                   (0, 2, P2PLinkType::System), (0, 3, P2PLinkType::System),
+                   (1, 2, P2PLinkType::System), (1, 3, P2PLinkType::System)])
+    }
+
+    #[test]
+    fn equally_scored_subsets_rank_by_gpu_ids() {
+        let graph = two_pairs();
+        let ranked = graph.rank_gpu_subsets(&[3, 2, 1, 0], 2);
+        assert_eq!(ranked.len(), 6);
+        assert_eq!(ranked[0].0, vec![0, 1]);
+        assert_eq!(ranked[1].0, vec![2, 3]);
+        assert_eq!(ranked[0].1, ranked[1].1);
+        assert!(ranked[1].1 > ranked[2].1);
+        assert_eq!(graph.select_gpus(&[0, 1, 2, 3], 2), Some(vec![0, 1]));
+        assert_eq!(graph.select_gpus(&[1, 2, 3], 2), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn disconnected_gpus_rank_last() {
+        let graph = two_pairs();
+        assert_eq!(graph.select_gpus(&[0, 1, 2, 4], 3), Some(vec![0, 1, 2]));
+        let ranked = graph.rank_gpu_subsets(&[0, 1, 2, 4], 3);
+        assert!(ranked[1..].iter().all(|(set, score)| set.contains(&4) && score.weakest_pair == 0));
+        assert_eq!(graph.select_gpus(&[0, 4], 2), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn requesting_more_gpus_than_available_selects_nothing() {
+        let graph = two_pairs();
+        assert!(graph.rank_gpu_subsets(&[0, 1], 3).is_empty());
+        // duplicates count once
+        assert_eq!(graph.select_gpus(&[0, 0, 1], 3), None);
+        assert_eq!(graph.select_gpus(&[0, 1], 0), None);
+        assert_eq!(graph.select_gpus(&[0, 1], 2), Some(vec![0, 1]));
+    }
+}
@@ -0,0 +1,179 @@
+use super::bindings::*;
+use super::rates::{rate_source_field, DEFAULT_COUNTER_FIELDS};
+use super::samples::Sample;
+use serde::Serialize;
+use std::time::Duration;
+
+/// The unit DCGM reports a field's raw value in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum RawUnit {
+    Watts,
+    Millijoules,
+    Celsius,
+    Megahertz,
+    Mebibytes,
+    KilobytesPerSecond,
+    BytesPerSecond,
+    Microseconds,
+    /// 0-100.
+    Percent,
+    /// 0.0-1.0.
+    Ratio,
+    Count,
+}
+
+/// A raw value converted to an SI-friendly quantity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quantity {
+    Watts(f64),
+    Joules(f64),
+    Celsius(f64),
+    Hertz(f64),
+    Bytes(f64),
+    BytesPerSecond(f64),
+    Duration(Duration),
+    /// 0.0-1.0, percentages are divided by 100.
+    Ratio(f64),
+    Count(f64),
+    /// Rate of a counter, per second, in the counter's normalized unit.
+    PerSecond(f64),
+}
+
+impl Quantity {
+    /// The scalar in the quantity's unit; durations are given in seconds.
+    pub fn value(&self) -> f64 {
+        match *self {
+            Quantity::Watts(v) | Quantity::Joules(v) | Quantity::Celsius(v) | Quantity::Hertz(v)
+            | Quantity::Bytes(v) | Quantity::BytesPerSecond(v) | Quantity::Ratio(v) | Quantity::Count(v)
+            | Quantity::PerSecond(v) => v,
+            Quantity::Duration(d) => d.as_secs_f64(),
+        }
+    }
+
+    pub fn unit_symbol(&self) -> &'static str {
+        match self {
+            Quantity::Watts(_) => "W",
+            Quantity::Joules(_) => "J",
+            Quantity::Celsius(_) => "C",
+            Quantity::Hertz(_) => "Hz",
+            Quantity::Bytes(_) => "B",
+            Quantity::BytesPerSecond(_) => "B/s",
+            Quantity::Duration(_) => "s",
+            Quantity::Ratio(_) => "",
+            Quantity::Count(_) => "",
+            Quantity::PerSecond(_) => "/s",
+        }
+    }
+}
+
+/// Raw unit of a field, for the fields this crate knows about.
+pub fn field_unit(field_id: u16) -> Option<RawUnit> {
+    let unit = match field_id as u32 {
+        DCGM_FI_DEV_POWER_USAGE | DCGM_FI_DEV_POWER_USAGE_INSTANT | DCGM_FI_DEV_POWER_MGMT_LIMIT
+        | DCGM_FI_DEV_ENFORCED_POWER_LIMIT => RawUnit::Watts,
+        DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION => RawUnit::Millijoules,
+        DCGM_FI_DEV_GPU_TEMP | DCGM_FI_DEV_MEMORY_TEMP | DCGM_FI_DEV_SLOWDOWN_TEMP
+        | DCGM_FI_DEV_SHUTDOWN_TEMP => RawUnit::Celsius,
+        DCGM_FI_DEV_SM_CLOCK | DCGM_FI_DEV_MEM_CLOCK | DCGM_FI_DEV_VIDEO_CLOCK | DCGM_FI_DEV_APP_SM_CLOCK
+        | DCGM_FI_DEV_APP_MEM_CLOCK => RawUnit::Megahertz,
+        DCGM_FI_DEV_FB_TOTAL | DCGM_FI_DEV_FB_FREE | DCGM_FI_DEV_FB_USED | DCGM_FI_DEV_FB_RESERVED => RawUnit::Mebibytes,
+        DCGM_FI_DEV_PCIE_TX_THROUGHPUT | DCGM_FI_DEV_PCIE_RX_THROUGHPUT => RawUnit::KilobytesPerSecond,
+        DCGM_FI_PROF_PCIE_TX_BYTES | DCGM_FI_PROF_PCIE_RX_BYTES | DCGM_FI_PROF_NVLINK_TX_BYTES
+        | DCGM_FI_PROF_NVLINK_RX_BYTES => RawUnit::BytesPerSecond,
+        DCGM_FI_DEV_POWER_VIOLATION | DCGM_FI_DEV_THERMAL_VIOLATION | DCGM_FI_DEV_SYNC_BOOST_VIOLATION
+        | DCGM_FI_DEV_BOARD_LIMIT_VIOLATION | DCGM_FI_DEV_LOW_UTIL_VIOLATION | DCGM_FI_DEV_RELIABILITY_VIOLATION
+        | DCGM_FI_DEV_TOTAL_APP_CLOCKS_VIOLATION | DCGM_FI_DEV_TOTAL_BASE_CLOCKS_VIOLATION => RawUnit::Microseconds,
+        DCGM_FI_DEV_GPU_UTIL | DCGM_FI_DEV_MEM_COPY_UTIL | DCGM_FI_DEV_ENC_UTIL | DCGM_FI_DEV_DEC_UTIL => RawUnit::Percent,
+        DCGM_FI_PROF_GR_ENGINE_ACTIVE | DCGM_FI_PROF_SM_ACTIVE | DCGM_FI_PROF_SM_OCCUPANCY
+        | DCGM_FI_PROF_PIPE_TENSOR_ACTIVE | DCGM_FI_PROF_DRAM_ACTIVE | DCGM_FI_PROF_PIPE_TENSOR_IMMA_ACTIVE
+        | DCGM_FI_PROF_PIPE_TENSOR_HMMA_ACTIVE | DCGM_FI_PROF_PIPE_TENSOR_DFMA_ACTIVE
+        | DCGM_FI_PROF_PIPE_INT_ACTIVE => RawUnit::Ratio,
+        DCGM_FI_DEV_PCIE_REPLAY_COUNTER | DCGM_FI_DEV_XID_ERRORS | DCGM_FI_DEV_ECC_SBE_VOL_TOTAL
+        | DCGM_FI_DEV_ECC_DBE_VOL_TOTAL | DCGM_FI_DEV_ECC_SBE_AGG_TOTAL | DCGM_FI_DEV_ECC_DBE_AGG_TOTAL
+        | DCGM_FI_DEV_NVLINK_CRC_FLIT_ERROR_COUNT_TOTAL | DCGM_FI_DEV_NVLINK_CRC_DATA_ERROR_COUNT_TOTAL
+        | DCGM_FI_DEV_NVLINK_REPLAY_ERROR_COUNT_TOTAL | DCGM_FI_DEV_NVLINK_RECOVERY_ERROR_COUNT_TOTAL => RawUnit::Count,
+        _ => return None,
+    };
+    Some(unit)
+}
+
+/// How the values of a field behave over time, which decides the metric type it is exported as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldSemantics {
+    /// A level that goes up and down: temperatures, utilization, clocks, derived rates.
+    Gauge,
+    /// Only goes up, apart from resets: energy, error and violation totals.
+    Counter,
+    /// A mode, state or bitmask code; the number has no magnitude.
+    Enum,
+    /// Text or binary; not a metric.
+    String,
+}
+
+/// Semantics of a numeric field: a counter when it is one of `DEFAULT_COUNTER_FIELDS`, which are
+/// also the ones `RateComputer` derives rates from, an enum for the known mode and state fields, else
+/// a gauge. Text fields are told apart by their DCGM field type, see `catalog::FieldInfo::semantics`.
+pub fn field_semantics(field_id: u16) -> FieldSemantics {
+    if DEFAULT_COUNTER_FIELDS.contains(&field_id) {
+        return FieldSemantics::Counter;
+    }
+    match field_id as u32 {
+        DCGM_FI_DEV_COMPUTE_MODE | DCGM_FI_DEV_PERSISTENCE_MODE | DCGM_FI_DEV_VIRTUAL_MODE | DCGM_FI_DEV_MIG_MODE
+        | DCGM_FI_DEV_ECC_CURRENT | DCGM_FI_DEV_ECC_PENDING | DCGM_FI_DEV_AUTOBOOST | DCGM_FI_DEV_PSTATE
+        | DCGM_FI_DEV_CLOCKS_EVENT_REASONS | DCGM_FI_DEV_XID_ERRORS | DCGM_FI_DEV_RETIRED_PENDING
+        | DCGM_FI_DEV_ROW_REMAP_PENDING | DCGM_FI_DEV_ROW_REMAP_FAILURE | DCGM_FI_DEV_CUDA_COMPUTE_CAPABILITY
+        | DCGM_FI_DEV_FABRIC_MANAGER_STATUS => FieldSemantics::Enum,
+        _ => FieldSemantics::Gauge,
+    }
+}
+
+/// Converts a raw value in `unit` to its SI-friendly quantity.
+pub fn normalize(unit: RawUnit, raw: f64) -> Quantity {
+    match unit {
+        RawUnit::Watts => Quantity::Watts(raw),
+        RawUnit::Millijoules => Quantity::Joules(raw / 1000.0),
+        RawUnit::Celsius => Quantity::Celsius(raw),
+        RawUnit::Megahertz => Quantity::Hertz(raw * 1e6),
+        RawUnit::Mebibytes => Quantity::Bytes(raw * 1024.0 * 1024.0),
+        RawUnit::KilobytesPerSecond => Quantity::BytesPerSecond(raw * 1000.0),
+        RawUnit::BytesPerSecond => Quantity::BytesPerSecond(raw),
+        RawUnit::Microseconds => Quantity::Duration(Duration::from_micros(raw.max(0.0) as u64)),
+        RawUnit::Percent => Quantity::Ratio(raw / 100.0),
+        RawUnit::Ratio => Quantity::Ratio(raw),
+        RawUnit::Count => Quantity::Count(raw),
+    }
+}
+
+impl Sample {
+    /// The value as an SI-friendly quantity, or None for blank, non-numeric or unknown fields.
+    /// `value` keeps the raw reading. Derived rate fields are scaled like their source counter.
+    pub fn normalized(&self) -> Option<Quantity> {
+        let raw = self.value.as_f64()?;
+        if let Some(source) = rate_source_field(self.field_id) {
+            return Some(Quantity::PerSecond(normalize(field_unit(source)?, raw).value()));
+        }
+        Some(normalize(field_unit(self.field_id)?, raw))
+    }
+
+    pub fn watts(&self) -> Option<f64> {
+        match self.normalized()? {
+            Quantity::Watts(w) => Some(w),
+            _ => None,
+        }
+    }
+
+    pub fn celsius(&self) -> Option<f64> {
+        match self.normalized()? {
+            Quantity::Celsius(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        match self.normalized()? {
+            Quantity::Duration(d) => Some(d),
+            _ => None,
+        }
+    }
+}
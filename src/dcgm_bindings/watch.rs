@@ -0,0 +1,159 @@
+use super::bindings::*;
+use super::callbacks::catch_callback;
+use super::entity::{Entity, EntityGroup};
+use super::latest::LatestValuesQuery;
+use super::samples::{decode_field_value_v1, Sample};
+use super::{DCGMError, DcgmLibSafe};
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+static WATCH_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Sampling settings passed to `dcgmWatchFields`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchOptions {
+    pub update_interval: Duration,
+    pub max_keep_age: Duration,
+    /// 0 keeps as many samples as `max_keep_age` allows.
+    pub max_keep_samples: i32,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            update_interval: Duration::from_secs(1),
+            max_keep_age: Duration::from_secs(300),
+            max_keep_samples: 0,
+        }
+    }
+}
+
+/// A field group being watched on a GPU group, as returned by `watch_all_gpus`.
+#[derive(Clone, Debug)]
+pub struct WatchHandle {
+    pub group: dcgmGpuGrp_t,
+    pub field_group: dcgmFieldGrp_t,
+    pub fields: Vec<u16>,
+    pub options: WatchOptions,
+}
+
+impl WatchHandle {
+    /// Switches the watch to `fields`. Field groups cannot be changed in place, so a new one is created
+    /// and watched on the same GPU group before the old one is unwatched and destroyed: values keep
+    /// coming throughout, and on error the handle still describes the old, still watched field group.
+    pub fn update(&mut self, dcgm: &mut DcgmLibSafe, fields: &[u16]) -> Result<(), DCGMError>{
+        let mut fieldIds = fields.to_vec();
+        let fieldGroup = dcgm.fieldGroupCreate(&unique_name("watch"), &mut fieldIds)?;
+        let replacement = WatchHandle { group: self.group, field_group: fieldGroup, fields: fieldIds, options: self.options.clone() };
+        if let Err(e) = replacement.watch(dcgm) {
+            let _ = dcgm.fieldGroupDestroy(fieldGroup);
+            return Err(e);
+        }
+        let old = std::mem::replace(self, replacement);
+        let shared = old.fields.iter().any(|f| self.fields.contains(f));
+        if let Err(e) = dcgm.unwatch(old) {
+            tracing::warn!("Failed to remove the replaced field group: {e}");
+        }
+        // DCGM tracks watches per connection rather than per field group, so unwatching the old group
+        // also dropped the fields both have in common.
+        if shared {
+            self.watch(dcgm)?;
+        }
+        Ok(())
+    }
+
+    fn watch(&self, dcgm: &mut DcgmLibSafe) -> Result<(), DCGMError>{
+        dcgm.watchFields(self.field_group, self.group, self.options.update_interval.as_micros() as i64,
+                         self.options.max_keep_age.as_secs_f64(), self.options.max_keep_samples)
+    }
+}
+
+pub(crate) fn unique_name(prefix: &str) -> String {
+    format!("{prefix}-{}-{}", std::process::id(), WATCH_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+impl DcgmLibSafe {
+    /// Watches `fields` on the built-in all-GPUs group, so no GPU group has to be created or cleaned up.
+    pub fn watch_all_gpus(&mut self, fields: &[u16], options: &WatchOptions) -> Result<WatchHandle, DCGMError>{
+        let mut fieldIds = fields.to_vec();
+        let fieldGroup = self.fieldGroupCreate(&unique_name("all-gpus"), &mut fieldIds)?;
+        let group = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+        if let Err(e) = self.watchFields(fieldGroup, group, options.update_interval.as_micros() as i64,
+                                         options.max_keep_age.as_secs_f64(), options.max_keep_samples) {
+            let _ = self.fieldGroupDestroy(fieldGroup);
+            return Err(e);
+        }
+        Ok(WatchHandle { group, field_group: fieldGroup, fields: fieldIds, options: options.clone() })
+    }
+
+    /// Latest values of the watched fields for every GPU, in one batched call. Entries DCGM could not
+    /// provide are skipped.
+    pub fn watch_values(&mut self, watch: &WatchHandle) -> Result<Vec<Sample>, DCGMError>{
+        let gpus: Vec<Entity> = self.getAllSupportedDevices()?.into_iter().map(Entity::gpu).collect();
+        LatestValuesQuery::new(&gpus, &watch.fields).collect(self)
+    }
+
+    pub fn unwatch(&mut self, watch: WatchHandle) -> Result<(), DCGMError>{
+        match unsafe{self.lib()?.dcgmUnwatchFields(self.handle(), watch.group, watch.field_group)}{
+            dcgmReturn_enum_DCGM_ST_OK => self.resources().unwatched(watch.group, watch.field_group),
+            err_code => return Err(self.call_error(err_code, "dcgmUnwatchFields").arg("group", watch.group)
+                                    .arg("field_group", watch.field_group))
+        };
+        self.fieldGroupDestroy(watch.field_group)
+    }
+}
+
+struct ValuesSinceState<'a> {
+    on_value: &'a mut dyn FnMut(Sample) -> bool,
+    panic: Option<String>,
+}
+
+unsafe extern "C" fn values_since_trampoline(entityGroupId: dcgm_field_entity_group_t, entityId: dcgm_field_eid_t,
+                                             values: *mut dcgmFieldValue_v1, numValues: c_int,
+                                             userData: *mut c_void) -> c_int {
+    let state = &mut *(userData as *mut ValuesSinceState);
+    let Ok(group) = EntityGroup::try_from(entityGroupId) else { return 0 };
+    if values.is_null() || numValues <= 0 {
+        return 0;
+    }
+    let values = std::slice::from_raw_parts(values, numValues as usize);
+    let on_value = &mut state.on_value;
+    let keep_going = catch_callback(|| {
+        values.iter()
+            .filter_map(|v| decode_field_value_v1(group, entityId, v).ok())
+            .all(on_value)
+    });
+    match keep_going {
+        Ok(true) => 0,
+        Ok(false) => -1,
+        Err(msg) => {
+            state.panic = Some(msg);
+            -1
+        }
+    }
+}
+
+impl DcgmLibSafe {
+    /// Feeds every sample recorded since `since` (usec, 0 for all retained) to `on_value` until it returns
+    /// false. Returns the timestamp to pass as `since` next time. A panic in `on_value` stops the
+    /// enumeration and is returned as an error.
+    pub fn values_since<F>(&mut self, watch: &WatchHandle, since: i64, mut on_value: F) -> Result<i64, DCGMError>
+    where
+        F: FnMut(Sample) -> bool,
+    {
+        let mut state = ValuesSinceState { on_value: &mut on_value, panic: None };
+        let mut next_since: i64 = 0;
+        let res = unsafe{self.lib()?.dcgmGetValuesSince_v2(self.handle(), watch.group, watch.field_group, since,
+                                                        &raw mut next_since, Some(values_since_trampoline),
+                                                        &raw mut state as *mut c_void)};
+        if let Some(msg) = state.panic {
+            return Err(DCGMError::from(format!("values_since callback panicked: {msg}")));
+        }
+        match res {
+            dcgmReturn_enum_DCGM_ST_OK => Ok(next_since),
+            err_code => Err(self.call_error(err_code, "dcgmGetValuesSince_v2").arg("group", watch.group)
+                                .arg("field_group", watch.field_group).arg("since", since))
+        }
+    }
+}
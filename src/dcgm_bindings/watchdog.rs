@@ -0,0 +1,41 @@
+use super::{DCGMError, DcgmLibSafe};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::time::Duration;
+
+impl DcgmLibSafe {
+    /// Runs `call` on a separate thread and gives up after `deadline` with a `Timeout` error, marking the
+    /// connection suspect. A wedged call keeps its thread until the hostengine answers or the process exits.
+    pub fn with_deadline<T, F>(&self, deadline: Duration, call: F) -> Result<T, DCGMError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DcgmLibSafe) -> Result<T, DCGMError> + Send + 'static,
+    {
+        let mut client = self.share();
+        let (tx, rx) = mpsc::sync_channel(1);
+        std::thread::Builder::new()
+            .name("dcgm-watchdog-call".into())
+            .spawn(move || {
+                let _ = tx.send(call(&mut client));
+            })
+            .map_err(|e| DCGMError::from(format!("Failed to spawn watchdog thread: {e}")))?;
+        match rx.recv_timeout(deadline) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.suspect.store(true, Ordering::Relaxed);
+                tracing::error!("DCGM call did not return within {deadline:?}; marking connection suspect");
+                Err(DCGMError::timeout(format!("DCGM call did not return within {deadline:?}")))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(DCGMError::from("DCGM call panicked")),
+        }
+    }
+
+    /// True once any watchdog deadline on this connection has expired. Callers should reconnect.
+    pub fn is_suspect(&self) -> bool {
+        self.suspect.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_suspect(&self) {
+        self.suspect.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod dcgm_bindings;
+pub mod prelude;
@@ -1,5 +1,7 @@
-mod dcgm_bindings;
-use dcgm_bindings::*;
+mod cli;
+
+use clap::Parser;
+use cli::{Cli, Command};
 
 // use bindings::*;
 
@@ -401,11 +403,55 @@ use dcgm_bindings::*;
 // }
 
 fn main() {
-    
-    let mode = Mode::Standalone;
-    let args = ["127.0.0.1:5555", "0", "1"];
-    let mut dcgm = DcgmLibSafe::new(mode, &args).unwrap();
-   
-    println!("Devices: {:?}", dcgm.getAllSupportedDevices().unwrap())
-    
+    let cli = Cli::parse();
+    // These do not use the global connection: the daemon connects as its config says, the field
+    // catalog only needs the library and reports are built from files, both connecting by themselves
+    // when asked to probe or check health.
+    let unconnected = match &cli.command {
+        Some(Command::Daemon(args)) => Some(cli::daemon::run(args, cli.json)),
+        Some(Command::Fields(args)) => Some(cli::fields::run(&cli, args)),
+        Some(Command::Report(args)) => Some(cli::report::run(&cli, args)),
+        _ => None,
+    };
+    if let Some(result) = unconnected {
+        match result {
+            Ok(code) => std::process::exit(code),
+            Err(e) => {
+                cli::print_error(cli.json, "Error", &e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut dcgm = match cli.connect() {
+        Ok(dcgm) => dcgm,
+        Err(e) => {
+            cli::print_error(cli.json, "Failed to connect to DCGM", &e);
+            std::process::exit(2);
+        }
+    };
+    let _ = dcgm.install_signal_cleanup();
+
+    let result = match &cli.command {
+        Some(Command::Health(args)) => cli::health::run(&mut dcgm, args, cli.json),
+        Some(Command::Diag(args)) => cli::diag::run(&mut dcgm, args, cli.json),
+        Some(Command::BurnIn(args)) => cli::diag::run_burn_in(&mut dcgm, args, cli.json),
+        Some(Command::Stats(args)) => cli::stats::run(&mut dcgm, args, cli.json),
+        Some(Command::Daemon(_)) | Some(Command::Fields(_)) | Some(Command::Report(_)) => unreachable!(),
+        None => dcgm.getAllSupportedDevices().and_then(|devices| {
+            if cli.json {
+                cli::print_json(&serde_json::json!({ "devices": devices }))?;
+            } else {
+                println!("Devices: {devices:?}");
+            }
+            Ok(0)
+        }),
+    };
+    let _ = dcgm.shutdown();
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            cli::print_error(cli.json, "Error", &e);
+            std::process::exit(1);
+        }
+    }
 }
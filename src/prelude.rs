@@ -0,0 +1,18 @@
+//! The types most programs need, in one import:
+//!
+//! ```no_run
+//! use rust_dcgm::prelude::*;
+//!
+//! let mut dcgm: DcgmClient = Client::new().connect(&ConnectOptions::default())?;
+//! for gpu in dcgm.getAllSupportedDevices()? {
+//!     println!("{}", Entity::gpu(gpu));
+//! }
+//! # Ok::<(), DCGMError>(())
+//! ```
+
+pub use crate::dcgm_bindings::client::{Client, ConnectOptions, Connected, DcgmClient, Disconnected};
+pub use crate::dcgm_bindings::entity::{Entity, EntityGroup};
+pub use crate::dcgm_bindings::samples::{FieldId, FieldValue, Sample};
+pub use crate::dcgm_bindings::presets::WatchPreset;
+pub use crate::dcgm_bindings::watch::WatchOptions;
+pub use crate::dcgm_bindings::{DCGMError, DCGMErrorKind, DcgmLibSafe, DcgmResultExt};
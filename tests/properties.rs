@@ -0,0 +1,402 @@
+//! Property tests for value decoding, rate computation, rollups, histograms, the sample buffer and reports, driven by values encoded the way
+//! DCGM (and its injection API) hands them over. Run with `cargo test --features testing`.
+#![cfg(feature = "testing")]
+
+use proptest::prelude::*;
+use rust_dcgm::dcgm_bindings::bindings::*;
+use rust_dcgm::dcgm_bindings::daemon::{DaemonConfig, SinkConfig};
+use rust_dcgm::dcgm_bindings::entity::{Entity, EntityGroup, LinkId};
+use rust_dcgm::dcgm_bindings::exporter::{Exporter, ExporterConfig};
+use rust_dcgm::dcgm_bindings::histogram::{HistogramAggregator, HistogramConfig};
+use rust_dcgm::dcgm_bindings::import::parse_json_line;
+use rust_dcgm::dcgm_bindings::rates::{rate_field_id, RateComputer};
+use rust_dcgm::dcgm_bindings::report::ReportBuilder;
+use rust_dcgm::dcgm_bindings::rollup::RollupEngine;
+use rust_dcgm::dcgm_bindings::sample_buffer::SampleBuffer;
+use rust_dcgm::dcgm_bindings::samples::{decode_field_value_v1, decode_field_value_v2, FieldValue, Sample, Tags};
+use rust_dcgm::dcgm_bindings::testing::{field_type_of, field_value_v1, field_value_v2};
+use std::time::Duration;
+
+const COUNTER: u16 = DCGM_FI_DEV_TOTAL_ENERGY_CONSUMPTION as u16;
+const UTIL: u16 = DCGM_FI_DEV_GPU_UTIL as u16;
+
+fn int_value() -> impl Strategy<Value = FieldValue> {
+    (i64::MIN..DCGM_INT64_BLANK as i64).prop_map(FieldValue::Int64)
+}
+
+fn double_value() -> impl Strategy<Value = FieldValue> {
+    (-1e300..DCGM_FP64_BLANK).prop_map(FieldValue::Double)
+}
+
+fn string_value() -> impl Strategy<Value = FieldValue> {
+    "[ -~]{0,255}"
+        .prop_filter("blank sentinel", |s| !(s.starts_with("<<<") && s.ends_with(">>>")))
+        .prop_map(FieldValue::String)
+}
+
+/// A non-blank value with its field type.
+fn typed_value() -> impl Strategy<Value = (u8, FieldValue)> {
+    prop_oneof![
+        int_value().prop_map(|v| (DCGM_FT_INT64, v)),
+        int_value().prop_map(|v| (DCGM_FT_TIMESTAMP, v)),
+        double_value().prop_map(|v| (DCGM_FT_DOUBLE, v)),
+        string_value().prop_map(|v| (DCGM_FT_STRING, v)),
+    ]
+}
+
+/// Counter readings: mostly increasing, sometimes blank, sometimes reset to a smaller value.
+fn counter_series() -> impl Strategy<Value = Vec<(i64, FieldValue)>> {
+    prop::collection::vec((1i64..5_000_000, prop_oneof![8 => (0i64..1_000_000).prop_map(Some), 1 => Just(None)]), 1..64)
+        .prop_map(|steps| {
+            let mut ts = 1_700_000_000_000_000;
+            let mut total = 0i64;
+            steps.into_iter().map(|(dt, inc)| {
+                ts += dt;
+                match inc {
+                    Some(inc) => {
+                        total += inc;
+                        (ts, FieldValue::Int64(total))
+                    }
+                    None => (ts, FieldValue::Blank),
+                }
+            }).collect()
+        })
+}
+
+fn gpu_sample(field_id: u16, timestamp: i64, value: FieldValue) -> Sample {
+    Sample { entity_group: EntityGroup::Gpu, entity_id: 0, field_id, timestamp, value, tags: Tags::default() }
+}
+
+proptest! {
+    #[test]
+    fn v1_values_round_trip((field_type, value) in typed_value(), field_id in 1u16..1300, ts in any::<i64>()) {
+        let raw = field_value_v1(field_id, field_type, ts, &value);
+        let sample = decode_field_value_v1(EntityGroup::Gpu, 3, &raw).unwrap();
+        prop_assert_eq!(sample, Sample { entity_group: EntityGroup::Gpu, entity_id: 3, field_id, timestamp: ts, value, tags: Tags::default() });
+    }
+
+    #[test]
+    fn v2_values_round_trip((field_type, value) in typed_value(), gpu in 0u32..16, field_id in 1u16..1300, ts in any::<i64>()) {
+        let raw = field_value_v2(Entity::gpu(gpu), field_id, field_type, ts, &value);
+        let sample = decode_field_value_v2(&raw).unwrap();
+        prop_assert_eq!(sample, Sample { entity_group: EntityGroup::Gpu, entity_id: gpu, field_id, timestamp: ts, value, tags: Tags::default() });
+    }
+
+    #[test]
+    fn blobs_round_trip(bytes in prop::collection::vec(any::<u8>(), 0..4096)) {
+        let raw = field_value_v1(1, DCGM_FT_BINARY, 0, &FieldValue::Blob(bytes.clone()));
+        let FieldValue::Blob(decoded) = decode_field_value_v1(EntityGroup::Gpu, 0, &raw).unwrap().value else {
+            panic!("blob decoded as another type");
+        };
+        prop_assert_eq!(&decoded[..bytes.len()], &bytes[..]);
+        prop_assert!(decoded[bytes.len()..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn blank_sentinels_decode_as_blank(field_type in prop::sample::select(vec![DCGM_FT_INT64, DCGM_FT_TIMESTAMP, DCGM_FT_DOUBLE, DCGM_FT_STRING])) {
+        let raw = field_value_v1(1, field_type, 0, &FieldValue::Blank);
+        prop_assert_eq!(decode_field_value_v1(EntityGroup::Gpu, 0, &raw).unwrap().value, FieldValue::Blank);
+    }
+
+    #[test]
+    fn values_above_blank_decode_as_blank(int in DCGM_INT64_BLANK as i64..=i64::MAX, dbl in DCGM_FP64_BLANK..f64::MAX) {
+        let raw = field_value_v1(1, DCGM_FT_INT64, 0, &FieldValue::Int64(int));
+        prop_assert!(decode_field_value_v1(EntityGroup::Gpu, 0, &raw).unwrap().value.is_blank());
+        let raw = field_value_v1(1, DCGM_FT_DOUBLE, 0, &FieldValue::Double(dbl));
+        prop_assert!(decode_field_value_v1(EntityGroup::Gpu, 0, &raw).unwrap().value.is_blank());
+    }
+
+    #[test]
+    fn arbitrary_raw_values_never_panic(field_type in any::<u8>(), status in -60i32..1, bytes in prop::collection::vec(any::<u8>(), 4096)) {
+        let mut raw = field_value_v1(1, DCGM_FT_INT64, 0, &FieldValue::Int64(0));
+        raw.fieldType = field_type as u16;
+        raw.status = status;
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut raw.value as *mut _ as *mut u8, bytes.len());
+        }
+        let _ = decode_field_value_v1(EntityGroup::Gpu, 0, &raw);
+        let v2 = field_value_v2(Entity::gpu(0), 1, field_type, 0, &FieldValue::Int64(0));
+        let _ = decode_field_value_v2(&v2);
+    }
+
+    #[test]
+    fn link_ids_round_trip(switch in any::<bool>(), parent_id in any::<u16>(), index in any::<u8>()) {
+        let link = if switch { LinkId::switch(parent_id, index) } else { LinkId::gpu(parent_id, index) };
+        prop_assert_eq!(LinkId::from_raw(link.to_raw()).unwrap(), link);
+        prop_assert_eq!(Entity::link(link).as_link(), Some(link));
+    }
+
+    #[test]
+    fn link_ids_match_the_bindgen_layout(switch in any::<bool>(), parent_id in any::<u16>(), index in any::<u8>()) {
+        let link = if switch { LinkId::switch(parent_id, index) } else { LinkId::gpu(parent_id, index) };
+        let mut raw: dcgm_link_t = unsafe { std::mem::zeroed() };
+        let parsed = unsafe { &mut raw.__bindgen_anon_1.parsed };
+        parsed.set_type(link.parent.as_raw());
+        parsed.set_index(index as u32);
+        parsed.__bindgen_anon_1.set_gpuId(parent_id as u32);
+        // the 4.x headers widened index to 32 bits, so the parent id is no longer part of raw
+        prop_assert_eq!(unsafe { raw.__bindgen_anon_1.raw }, link.to_raw() & 0xffff);
+        prop_assert_eq!(unsafe { raw.__bindgen_anon_1.parsed.__bindgen_anon_1.gpuId() }, parent_id as u32);
+    }
+
+    #[test]
+    fn rates_match_counter_deltas(series in counter_series()) {
+        let mut rates = RateComputer::new([COUNTER]);
+        let mut previous: Option<(i64, i64)> = None;
+        for (ts, value) in series {
+            let rate = rates.observe(&gpu_sample(COUNTER, ts, value.clone()));
+            let FieldValue::Int64(v) = value else {
+                prop_assert!(rate.is_none());
+                continue;
+            };
+            match previous {
+                None => prop_assert!(rate.is_none()),
+                Some((prev_ts, prev_v)) => {
+                    let rate = rate.expect("a rate for every non-blank reading after the first");
+                    prop_assert_eq!(rate.field_id, rate_field_id(COUNTER));
+                    let expected = (v - prev_v) as f64 / ((ts - prev_ts) as f64 / 1e6);
+                    let FieldValue::Double(got) = rate.value else { panic!("rate is not a double") };
+                    prop_assert!((got - expected).abs() <= expected.abs() * 1e-9 + 1e-9);
+                }
+            }
+            previous = Some((ts, v));
+        }
+        prop_assert_eq!(rates.resets(), 0);
+    }
+
+    #[test]
+    fn rates_never_panic_or_go_negative(readings in prop::collection::vec((any::<i64>(), prop_oneof![int_value(), Just(FieldValue::Blank)]), 0..64)) {
+        let mut rates = RateComputer::new([COUNTER]);
+        for (ts, value) in readings {
+            if let Some(rate) = rates.observe(&gpu_sample(COUNTER, ts, value.clone())) {
+                let FieldValue::Double(r) = rate.value else { panic!("rate is not a double") };
+                // Only a reset to a negative reading can yield a negative rate.
+                prop_assert!(r >= 0.0 || matches!(value, FieldValue::Int64(v) if v < 0));
+            }
+        }
+    }
+
+    #[test]
+    fn report_counter_increase_matches_the_series(series in counter_series()) {
+        let mut report = ReportBuilder::new(0, i64::MAX).with_host("node");
+        for (ts, value) in &series {
+            report.add_sample(&gpu_sample(COUNTER, *ts, value.clone()));
+        }
+        let report = report.build();
+        let readings: Vec<f64> = series.iter().filter_map(|(_, v)| v.as_f64()).collect();
+        match (readings.first(), readings.last()) {
+            (Some(first), Some(last)) => prop_assert_eq!(report.entities[0].increase(COUNTER), Some(last - first)),
+            _ => prop_assert!(report.entities.is_empty()),
+        }
+    }
+
+    #[test]
+    fn json_lines_round_trip(value in prop_oneof![int_value(), string_value(), Just(FieldValue::Blank)], field_id in 1u16..1300,
+                             ts in any::<i64>(), tag in "[a-z]{0,8}") {
+        let mut sample = gpu_sample(field_id, ts, value);
+        if !tag.is_empty() {
+            sample.tags = Tags::new([("tenant".to_string(), tag)].into());
+        }
+        let line = serde_json::to_string(&sample).unwrap();
+        prop_assert_eq!(parse_json_line(&line).unwrap(), sample);
+    }
+
+    #[test]
+    fn rollups_bound_their_samples(mut readings in prop::collection::vec((0i64..600_000_000, prop_oneof![4 => double_value(), 1 => Just(FieldValue::Blank)]), 0..128),
+                                   window_secs in 1u64..120) {
+        readings.sort_by_key(|(ts, _)| *ts);
+        let mut engine = RollupEngine::new(Duration::from_secs(window_secs));
+        for (ts, value) in &readings {
+            engine.record(&gpu_sample(150, *ts, value.clone()));
+        }
+        let rollups = engine.flush_all();
+        let recorded = readings.iter().filter(|(_, v)| matches!(v.as_f64(), Some(x) if x.is_finite())).count() as u64;
+        prop_assert_eq!(rollups.iter().map(|r| r.count).sum::<u64>(), recorded);
+        for r in rollups {
+            prop_assert!(r.count > 0);
+            prop_assert!(r.min <= r.max);
+            prop_assert!(r.mean >= r.min - r.min.abs() * 1e-9 && r.mean <= r.max + r.max.abs() * 1e-9);
+            prop_assert!(r.window_start <= r.last_timestamp && r.last_timestamp < r.window_end);
+        }
+    }
+
+    #[test]
+    fn rollups_never_panic_out_of_order(readings in prop::collection::vec((any::<i64>(), prop_oneof![double_value(), int_value(), Just(FieldValue::Blank)]), 0..64),
+                                        window_us in 1u64..u32::MAX as u64) {
+        let mut engine = RollupEngine::new(Duration::from_micros(window_us));
+        for (ts, value) in readings {
+            engine.record(&gpu_sample(150, ts, value));
+        }
+        let _ = engine.flush_all();
+    }
+
+    #[test]
+    fn histograms_count_and_rank_their_window(values in prop::collection::vec(0.0f64..200.0, 1..128),
+                                              percentiles in prop::collection::vec(0.0f64..=100.0, 0..6)) {
+        let mut histograms = HistogramAggregator::new(HistogramConfig {
+            buckets: vec![100.0, 10.0, 50.0, 50.0, f64::INFINITY],
+            percentiles: percentiles.clone(),
+            ..HistogramConfig::default()
+        });
+        for (ts, v) in values.iter().enumerate() {
+            histograms.record(&gpu_sample(UTIL, ts as i64, FieldValue::Double(*v)));
+        }
+        let snapshot = histograms.snapshot(&gpu_sample(UTIL, 0, FieldValue::Blank).key()).unwrap();
+        let at_most = |bound: f64| values.iter().filter(|v| **v <= bound).count() as u64;
+        let bounds: Vec<f64> = snapshot.buckets.iter().map(|(b, _)| *b).collect();
+        prop_assert_eq!(bounds, vec![10.0, 50.0, 100.0, f64::INFINITY]);
+        for (bound, count) in &snapshot.buckets {
+            prop_assert_eq!(*count, at_most(*bound));
+        }
+        prop_assert_eq!(snapshot.count, values.len() as u64);
+        // nearest rank: the smallest value with at least p% of the window at or below it
+        for (p, value) in snapshot.percentiles {
+            let rank = ((p / 100.0 * values.len() as f64).ceil() as u64).max(1);
+            prop_assert!(values.contains(&value));
+            prop_assert!(at_most(value) >= rank);
+            prop_assert!(values.iter().filter(|v| **v < value).count() < rank as usize);
+        }
+    }
+}
+
+#[test]
+fn histogram_bucket_bounds_are_inclusive() {
+    let mut histograms = HistogramAggregator::new(HistogramConfig {
+        buckets: vec![50.0, 90.0],
+        percentiles: vec![0.0, 50.0, 90.0, 100.0],
+        ..HistogramConfig::default()
+    });
+    for (ts, v) in [20.0, 50.0, 90.0, 90.5].into_iter().enumerate() {
+        histograms.record(&gpu_sample(UTIL, ts as i64, FieldValue::Double(v)));
+    }
+    let snapshot = &histograms.snapshots()[0];
+    assert_eq!(snapshot.buckets, vec![(50.0, 2), (90.0, 3), (f64::INFINITY, 4)]);
+    assert_eq!(snapshot.percentiles, vec![(0.0, 20.0), (50.0, 50.0), (90.0, 90.5), (100.0, 90.5)]);
+    assert_eq!(snapshot.sum, 250.5);
+}
+
+#[test]
+fn histogram_window_follows_the_newest_sample() {
+    let mut histograms = HistogramAggregator::new(HistogramConfig { window: Duration::from_secs(10), ..HistogramConfig::default() });
+    for (secs, v) in [(0, 1), (2, 2), (12, 3)] {
+        histograms.record(&gpu_sample(UTIL, secs * 1_000_000, FieldValue::Int64(v)));
+    }
+    let key = gpu_sample(UTIL, 0, FieldValue::Blank).key();
+    // 12s is 10s after the sample at 2s, which stays on the edge of the window
+    assert_eq!(histograms.snapshot(&key).unwrap().count, 2);
+    assert_eq!(histograms.fraction_above(&key, 2.0), Some(0.5));
+}
+
+#[test]
+fn histograms_render_as_prometheus_histograms() {
+    let mut histograms = HistogramAggregator::new(HistogramConfig {
+        buckets: vec![50.0],
+        percentiles: vec![95.0],
+        ..HistogramConfig::default()
+    });
+    histograms.record_all(&[gpu_sample(UTIL, 0, FieldValue::Int64(40)), gpu_sample(UTIL, 1, FieldValue::Int64(60))]);
+    let exporter = Exporter::new(ExporterConfig { hostname: None, ..ExporterConfig::default() });
+    let name = format!("DCGM_FIELD_{UTIL}");
+    assert_eq!(exporter.render_histograms(&histograms.snapshots()), format!("\
+# TYPE {name}_histogram histogram
+{name}_histogram_bucket{{gpu=\"0\",le=\"50\"}} 1
+{name}_histogram_bucket{{gpu=\"0\",le=\"+Inf\"}} 2
+{name}_histogram_sum{{gpu=\"0\"}} 100
+{name}_histogram_count{{gpu=\"0\"}} 2
+# TYPE {name}_quantile gauge
+{name}_quantile{{gpu=\"0\",quantile=\"0.95\"}} 60
+"));
+}
+
+#[test]
+fn rollups_read_back_as_samples_of_their_mean() {
+    let mut engine = RollupEngine::new(Duration::from_secs(60));
+    let tagged = |ts: i64, v: i64, tenant: &str| Sample {
+        tags: Tags::new([("tenant".to_string(), tenant.to_string())].into()),
+        ..gpu_sample(UTIL, ts, FieldValue::Int64(v))
+    };
+    engine.record_all(&[tagged(1_000_000, 10, "a"), tagged(59_000_000, 30, "b"), tagged(61_000_000, 50, "b")]);
+    let rollups = engine.drain_completed();
+    assert_eq!(rollups.len(), 1);
+    assert_eq!((rollups[0].min, rollups[0].max, rollups[0].count), (10.0, 30.0, 2));
+
+    let line = serde_json::to_value(&rollups[0]).unwrap();
+    assert_eq!((line["window_end"].as_i64(), line["last"].as_f64()), (Some(60_000_000), Some(30.0)));
+    let sample = parse_json_line(&line.to_string()).unwrap();
+    assert_eq!(sample, Sample { value: FieldValue::Double(20.0), ..tagged(0, 0, "b") });
+}
+
+#[test]
+fn json_lines_rollups_cover_an_interval() {
+    let config = |rollup: &str| DaemonConfig::from_toml(&format!(r#"
+        interval = "10s"
+        groups = [{{ name = "util", fields = [{UTIL}] }}]
+        sinks = [{{ type = "json_lines", path = "/tmp/util.jsonl", rollup = "{rollup}" }}]
+    "#));
+    let sinks = config("1m").unwrap().sinks;
+    assert!(matches!(&sinks[..], [SinkConfig::JsonLines { rollup: Some(window), .. }] if *window == Duration::from_secs(60)));
+    let err = config("5s").unwrap_err();
+    assert!(err.to_string().contains("sinks[0]: rollup 5s is shorter"), "{err}");
+}
+
+#[test]
+fn sample_buffer_rings_evict_the_oldest() {
+    let mut buffer = SampleBuffer::with_retention(Duration::from_secs(2), Duration::from_secs(1), 8);
+    assert_eq!(buffer.capacity_per_series(), 3);
+    buffer.extend((0..5).map(|t| gpu_sample(UTIL, t * 1_000_000, FieldValue::Int64(t))));
+    let key = gpu_sample(UTIL, 0, FieldValue::Int64(0)).key();
+    let kept: Vec<i64> = buffer.range(&key, i64::MIN, i64::MAX).iter().map(|s| s.timestamp).collect();
+    assert_eq!(kept, [2_000_000, 3_000_000, 4_000_000]);
+    assert_eq!(buffer.latest(&key).map(|s| &s.value), Some(&FieldValue::Int64(4)));
+
+    // a late sample is placed by its timestamp and still evicts the oldest
+    buffer.push(gpu_sample(UTIL, 2_500_000, FieldValue::Int64(9)));
+    let kept: Vec<i64> = buffer.range(&key, i64::MIN, i64::MAX).iter().map(|s| s.timestamp).collect();
+    assert_eq!(kept, [2_500_000, 3_000_000, 4_000_000]);
+}
+
+#[test]
+fn sample_buffer_ranges_are_half_open() {
+    let mut buffer = SampleBuffer::new(16, 2);
+    buffer.extend((0..10).map(|t| gpu_sample(UTIL, t * 10, FieldValue::Int64(t))));
+    buffer.extend((0..10).map(|t| gpu_sample(COUNTER, t * 10, FieldValue::Int64(t))));
+    let util = gpu_sample(UTIL, 0, FieldValue::Int64(0)).key();
+    let times = |samples: Vec<Sample>| samples.iter().map(|s| s.timestamp).collect::<Vec<_>>();
+    assert_eq!(times(buffer.range(&util, 20, 50)), [20, 30, 40]);
+    assert_eq!(times(buffer.range(&util, 95, 200)), Vec::<i64>::new());
+
+    let all = buffer.range_all(80, 100);
+    assert_eq!(all.len(), 2);
+    assert!(all.values().all(|samples| times(samples.clone()) == [80, 90]));
+    assert!(buffer.range_all(100, 200).is_empty());
+
+    // a third series does not fit
+    buffer.push(gpu_sample(DCGM_FI_DEV_GPU_TEMP as u16, 0, FieldValue::Int64(40)));
+    assert_eq!((buffer.keys().len(), buffer.len(), buffer.dropped()), (2, 20, 1));
+}
+
+/// Round-trips values through a real hostengine with the injection API.
+#[test]
+#[ignore = "needs libdcgm and a GPU; run with --ignored"]
+fn injected_values_read_back() {
+    use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+    use rust_dcgm::dcgm_bindings::{DcgmLibSafe, Mode};
+
+    let dcgm = std::cell::RefCell::new(DcgmLibSafe::new(Mode::Embedded, &[]).expect("embedded hostengine"));
+    let field = DCGM_FI_DEV_GPU_TEMP as u16;
+    let watch = dcgm.borrow_mut().watch_all_gpus(&[field], &WatchOptions::default()).unwrap();
+    let mut runner = proptest::test_runner::TestRunner::new(ProptestConfig::with_cases(32));
+    runner.run(&(0i64..200), |temp| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_micros() as i64;
+        let value = FieldValue::Int64(temp);
+        let mut dcgm = dcgm.borrow_mut();
+        dcgm.inject_field_value(Entity::gpu(0), field, field_type_of(&value).unwrap(), now, &value).unwrap();
+        let latest = dcgm.entityGetLatestValues(0, EntityGroup::Gpu, &mut [field]).unwrap();
+        let sample = decode_field_value_v1(EntityGroup::Gpu, 0, &latest[0]).unwrap();
+        prop_assert_eq!(sample.value, value);
+        Ok(())
+    }).unwrap();
+    let mut dcgm = dcgm.into_inner();
+    dcgm.unwatch(watch).unwrap();
+    dcgm.shutdown().unwrap();
+}
@@ -0,0 +1,278 @@
+//! The client API against scripted scenarios. Run with `cargo test --features simulation`.
+#![cfg(feature = "simulation")]
+
+use rust_dcgm::dcgm_bindings::alerts::{AlertMonitor, Severity};
+use rust_dcgm::dcgm_bindings::bindings::*;
+use rust_dcgm::dcgm_bindings::client::{Client, ConnectOptions, DcgmClient};
+use rust_dcgm::dcgm_bindings::daemon::{Collector, DaemonConfig};
+use rust_dcgm::dcgm_bindings::entity::Entity;
+use rust_dcgm::dcgm_bindings::health::{HealthResult, HealthSystems};
+use rust_dcgm::dcgm_bindings::samples::FieldValue;
+use rust_dcgm::dcgm_bindings::simulation::{Scenario, Simulation};
+use rust_dcgm::dcgm_bindings::watch::WatchOptions;
+use rust_dcgm::dcgm_bindings::DCGMErrorKind;
+use std::time::{Duration, Instant};
+
+const TEMP: u16 = DCGM_FI_DEV_GPU_TEMP as u16;
+
+fn connect(simulation: &Simulation) -> DcgmClient {
+    Client::new().connect(&ConnectOptions::Simulated(simulation.clone())).expect("simulated connect")
+}
+
+#[test]
+fn watched_values_follow_the_clock() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(2)
+        .with_series(Entity::gpu(1), TEMP, Duration::ZERO, Duration::from_secs(10),
+                     [40, 55, 90].map(FieldValue::Int64)));
+    let mut dcgm = connect(&simulation);
+    assert_eq!(dcgm.getAllSupportedDevices().unwrap(), vec![0, 1]);
+    let watch = dcgm.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+
+    let values = |dcgm: &mut DcgmClient| -> Vec<(u32, FieldValue)> {
+        dcgm.watch_values(&watch).unwrap().into_iter().map(|s| (s.entity_id, s.value)).collect()
+    };
+    assert_eq!(values(&mut dcgm), vec![(1, FieldValue::Int64(40))]);
+    simulation.advance(Duration::from_secs(15));
+    assert_eq!(values(&mut dcgm), vec![(1, FieldValue::Int64(55))]);
+    assert_eq!(simulation.step(), Some(Duration::from_secs(20)));
+    assert_eq!(values(&mut dcgm), vec![(1, FieldValue::Int64(90))]);
+    assert_eq!(simulation.step(), None);
+
+    let mut seen = Vec::new();
+    dcgm.values_since(&watch, 0, |s| {
+        seen.push(s.value);
+        true
+    }).unwrap();
+    assert_eq!(seen, [40, 55, 90].map(FieldValue::Int64));
+}
+
+#[test]
+fn group_values_cover_every_entity_and_field() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(2)
+        .with_value(Duration::ZERO, Entity::gpu(1), TEMP, FieldValue::Int64(40)));
+    let mut dcgm = connect(&simulation);
+    let watch = dcgm.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+
+    let values = dcgm.get_group_values(watch.group, watch.field_group).unwrap();
+    assert_eq!(values.entities, vec![Entity::gpu(0), Entity::gpu(1)]);
+    assert_eq!(values.get(Entity::gpu(1), TEMP).map(|s| &s.value), Some(&FieldValue::Int64(40)));
+    assert_eq!(values.missing(), vec![(Entity::gpu(0), TEMP)]);
+}
+
+#[test]
+fn group_values_read_field_groups_made_by_other_connections() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_value(Duration::ZERO, Entity::gpu(0), TEMP, FieldValue::Int64(40)));
+    let mut owner = connect(&simulation);
+    let watch = owner.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+
+    // this connection did not create the field group, so its fields come from dcgmFieldGroupGetInfo
+    let mut dcgm = connect(&simulation);
+    assert_eq!(dcgm.getFieldGroupFields(watch.field_group).unwrap(), vec![TEMP]);
+    let values = dcgm.get_group_values(watch.group, watch.field_group).unwrap();
+    assert_eq!(values.fields, vec![TEMP]);
+    assert_eq!(values.get(Entity::gpu(0), TEMP).map(|s| &s.value), Some(&FieldValue::Int64(40)));
+    assert!(values.missing().is_empty());
+
+    owner.fieldGroupDestroy(watch.field_group).unwrap();
+    assert!(dcgm.get_group_values(watch.group, watch.field_group).is_err());
+    dcgm.disconnect().unwrap();
+    owner.disconnect().unwrap();
+}
+
+#[test]
+fn health_incidents_open_and_clear() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_health(Duration::from_secs(5), Entity::gpu(0), HealthSystems::THERMAL, HealthResult::Warn, "too hot")
+        .with_health(Duration::from_secs(9), Entity::gpu(0), HealthSystems::THERMAL, HealthResult::Pass, ""));
+    let mut dcgm = connect(&simulation);
+    let group = DCGM_GROUP_ALL_GPUS as dcgmGpuGrp_t;
+    dcgm.health_set(group, HealthSystems::all(), Duration::from_secs(1), Duration::from_secs(60)).unwrap();
+
+    assert_eq!(dcgm.health_check(group).unwrap().overall, HealthResult::Pass);
+    simulation.advance_to(Duration::from_secs(5));
+    let report = dcgm.health_check(group).unwrap();
+    assert_eq!(report.overall, HealthResult::Warn);
+    assert_eq!(report.unhealthy_gpus(), vec![0]);
+    assert_eq!(report.incidents[0].message, "too hot");
+    simulation.advance_to(Duration::from_secs(9));
+    assert!(dcgm.health_check(group).unwrap().incidents.is_empty());
+}
+
+#[test]
+fn health_transitions_raise_and_resolve_alerts() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_health(Duration::from_secs(5), Entity::gpu(0), HealthSystems::MEM, HealthResult::Fail, "DBE")
+        .with_health(Duration::from_secs(9), Entity::gpu(0), HealthSystems::MEM, HealthResult::Pass, ""));
+    let mut dcgm = connect(&simulation);
+    let mut monitor = AlertMonitor::start(&mut dcgm, false, Some(Duration::from_secs(1))).unwrap();
+
+    assert!(monitor.poll(&mut dcgm).unwrap().is_empty());
+    simulation.advance_to(Duration::from_secs(5));
+    let raised = monitor.poll(&mut dcgm).unwrap();
+    assert_eq!(raised.len(), 1);
+    assert_eq!((raised[0].severity, raised[0].resolved), (Severity::Critical, false));
+    assert!(monitor.poll(&mut dcgm).unwrap().is_empty());
+    simulation.advance_to(Duration::from_secs(9));
+    let resolved = monitor.poll(&mut dcgm).unwrap();
+    assert_eq!(resolved.len(), 1);
+    assert!(resolved[0].resolved);
+    assert_eq!(resolved[0].dedup_key, raised[0].dedup_key);
+}
+
+#[test]
+fn outages_fail_calls_until_they_end() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_value(Duration::ZERO, Entity::gpu(0), TEMP, FieldValue::Int64(40))
+        .with_outage(Duration::from_secs(10), Duration::from_secs(5)));
+    let mut dcgm = connect(&simulation);
+    let watch = dcgm.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+
+    simulation.advance_to(Duration::from_secs(10));
+    assert_eq!(dcgm.watch_values(&watch).unwrap_err().kind, DCGMErrorKind::Disconnected);
+    simulation.advance_to(Duration::from_secs(15));
+    assert_eq!(dcgm.watch_values(&watch).unwrap().len(), 1);
+    dcgm.disconnect().unwrap();
+}
+
+#[test]
+fn malformed_standalone_arguments_are_errors() {
+    let simulation = Simulation::new(Scenario::new().with_gpus(1));
+    let mut dcgm = connect(&simulation);
+
+    assert!(dcgm.connectStandalone(&["localhost", "yes"]).unwrap_err().message.contains("isUnixSocket"));
+    assert!(dcgm.connectStandalone(&["localhost", "0", "-1"]).unwrap_err().message.contains("persistAfterDisconnect"));
+    dcgm.disconnect().unwrap();
+}
+
+#[test]
+fn calls_that_are_not_modelled_are_not_supported() {
+    let simulation = Simulation::new(Scenario::new().with_gpus(1));
+    let mut dcgm = connect(&simulation);
+    let watch = dcgm.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+
+    assert_eq!(dcgm.job_start_stats(watch.group, "job").unwrap_err().kind, DCGMErrorKind::NotSupported);
+    dcgm.disconnect().unwrap();
+}
+
+#[test]
+fn only_persistent_connections_hand_off_watches() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_value(Duration::ZERO, Entity::gpu(0), TEMP, FieldValue::Int64(40)));
+    let mut dcgm = connect(&simulation);
+    let watch = dcgm.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+    let state = dcgm.remote_state();
+    assert!(!state.persist_after_disconnect);
+    assert_eq!((state.field_groups.len(), state.watches.len()), (1, 1));
+
+    assert_eq!(dcgm.can_keep_watches().unwrap_err().kind, DCGMErrorKind::NotSupported);
+    assert_eq!(dcgm.shutdown_with(true, Duration::from_secs(1)).unwrap_err().kind, DCGMErrorKind::NotSupported);
+    assert_eq!(dcgm.watch_values(&watch).unwrap().len(), 1);
+    let (_, report) = dcgm.disconnect_with(false, Duration::from_secs(1)).unwrap();
+    assert_eq!(report.unwatched, 1);
+    assert!(report.kept.is_none());
+}
+
+#[test]
+fn adopted_state_must_still_exist() {
+    let simulation = Simulation::new(Scenario::new().with_gpus(1));
+    let mut owner = connect(&simulation);
+    let watch = owner.watch_all_gpus(&[TEMP], &WatchOptions::default()).unwrap();
+    let state = owner.remote_state();
+
+    let mut successor = connect(&simulation);
+    successor.adopt(&state).unwrap();
+    assert_eq!(successor.remote_state().field_groups.len(), 1);
+
+    owner.fieldGroupDestroy(watch.field_group).unwrap();
+    let mut late = connect(&simulation);
+    let err = late.adopt(&state).unwrap_err();
+    assert!(err.to_string().contains(&format!("field group {} is gone", watch.field_group)), "{err}");
+    assert!(late.remote_state().is_empty());
+    late.disconnect().unwrap();
+    owner.disconnect().unwrap();
+}
+
+#[test]
+fn collected_samples_fill_the_buffer() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_series(Entity::gpu(0), TEMP, Duration::ZERO, Duration::from_secs(1), [40, 41, 42, 43].map(FieldValue::Int64)));
+    let config = DaemonConfig::from_toml(&format!(r#"
+        interval = "1s"
+        buffer = {{ retention = "2s" }}
+        [[groups]]
+        name = "temp"
+        fields = [{TEMP}]
+    "#)).unwrap();
+    let mut dcgm = connect(&simulation);
+    let mut collector = Collector::start(&mut dcgm, &config).unwrap();
+
+    let mut collected = Vec::new();
+    for _ in 0..4 {
+        let now = collector.next_due().unwrap();
+        let collection = collector.collect_due(&mut dcgm, now);
+        assert!(collection.failed.is_empty(), "{:?}", collection.failed);
+        collected.extend(collection.samples);
+        simulation.advance(Duration::from_secs(1));
+    }
+    let key = collected[0].key();
+    let buffered = |collector: &Collector| -> Vec<FieldValue> {
+        collector.buffer().unwrap().range(&key, i64::MIN, i64::MAX).into_iter().map(|s| s.value).collect()
+    };
+    assert_eq!(buffered(&collector), [41, 42, 43].map(FieldValue::Int64));
+
+    let reloaded = DaemonConfig::from_toml(&format!(r#"
+        interval = "1s"
+        buffer = {{ retention = "1s" }}
+        [[groups]]
+        name = "temp"
+        fields = [{TEMP}]
+    "#)).unwrap();
+    assert!(collector.reload(&mut dcgm, &reloaded).is_empty());
+    assert_eq!(buffered(&collector), [42, 43].map(FieldValue::Int64));
+    collector.stop(&mut dcgm);
+    dcgm.disconnect().unwrap();
+}
+
+#[test]
+fn failed_groups_are_reported_and_rescheduled() {
+    let simulation = Simulation::new(Scenario::new()
+        .with_gpus(1)
+        .with_value(Duration::ZERO, Entity::gpu(0), TEMP, FieldValue::Int64(40))
+        .with_outage(Duration::from_secs(10), Duration::from_secs(5)));
+    let config = DaemonConfig::from_toml(&format!(r#"
+        interval = "1s"
+        [[groups]]
+        name = "a"
+        fields = [{TEMP}]
+        [[groups]]
+        name = "b"
+        fields = [{TEMP}]
+    "#)).unwrap();
+    let mut dcgm = connect(&simulation);
+    let mut collector = Collector::start(&mut dcgm, &config).unwrap();
+
+    simulation.advance_to(Duration::from_secs(10));
+    // both groups due
+    let now = Instant::now() + Duration::from_secs(2);
+    let collection = collector.collect_due(&mut dcgm, now);
+    let failed: Vec<&str> = collection.failed.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(failed, ["a", "b"]);
+    assert!(collection.failed.iter().all(|(_, e)| e.kind == DCGMErrorKind::Disconnected));
+    assert!(collector.next_due().unwrap() > now);
+
+    simulation.advance_to(Duration::from_secs(15));
+    let collection = collector.collect_due(&mut dcgm, now + Duration::from_secs(2));
+    assert!(collection.failed.is_empty());
+    assert_eq!(collection.samples.len(), 2);
+    collector.stop(&mut dcgm);
+    dcgm.disconnect().unwrap();
+}